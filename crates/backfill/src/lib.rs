@@ -0,0 +1,151 @@
+//! Slot gap detection and backfill scheduling for `holaplex-indexer`.
+//!
+//! The coordinator watches the slot statuses published by one or more Geyser
+//! producers and schedules [`ReindexSlot`](job_runner::Message::ReindexSlot)
+//! jobs for rooted slots a producer never reported on.
+
+#![deny(
+    clippy::disallowed_methods,
+    clippy::suspicious,
+    clippy::style,
+    missing_debug_implementations,
+    missing_copy_implementations
+)]
+#![warn(clippy::pedantic, clippy::cargo, missing_docs)]
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use hashbrown::HashMap;
+use indexer_rabbitmq::{
+    geyser::{SlotStatus, SlotStatusUpdate, StartupType},
+    job_runner::{self, SlotReindex},
+};
+
+/// Gap detector for the slot statuses of a single producer
+#[derive(Debug, Default)]
+pub struct Detector {
+    /// Every slot reported since the last root, with its parent if known
+    seen: BTreeMap<u64, Option<u64>>,
+    last_root: Option<u64>,
+}
+
+impl Detector {
+    /// Process a slot status update, returning any slots on the newly-rooted
+    /// chain which were never reported by the producer
+    pub fn observe(&mut self, update: &SlotStatusUpdate) -> Vec<u64> {
+        let SlotStatusUpdate {
+            slot,
+            parent,
+            status,
+            ..
+        } = *update;
+
+        let entry = self.seen.entry(slot).or_default();
+        *entry = entry.or(parent);
+
+        if status != SlotStatus::Rooted {
+            return vec![];
+        }
+
+        let last = match self.last_root {
+            Some(l) if slot > l => l,
+            Some(_) => return vec![],
+            None => {
+                self.root(slot);
+                return vec![];
+            },
+        };
+
+        let mut missing = vec![];
+        let mut curr = slot;
+
+        loop {
+            match self.seen.get(&curr).copied().flatten() {
+                Some(p) if p <= last => break,
+                Some(p) => {
+                    if !self.seen.contains_key(&p) {
+                        missing.push(p);
+                    }
+
+                    curr = p;
+                },
+                None => {
+                    // We can't follow the chain any further, so assume any
+                    // unreported slot since the last root is a gap
+                    missing.extend(
+                        ((last + 1)..curr)
+                            .rev()
+                            .filter(|s| !self.seen.contains_key(s)),
+                    );
+                    break;
+                },
+            }
+        }
+
+        self.root(slot);
+        missing.reverse();
+        missing
+    }
+
+    fn root(&mut self, slot: u64) {
+        self.last_root = Some(slot);
+        self.seen = self.seen.split_off(&slot);
+    }
+}
+
+/// Gap detection and deduplicated job scheduling across multiple producers
+#[derive(Debug)]
+pub struct Coordinator {
+    detectors: HashMap<String, Detector>,
+    scheduled: BTreeSet<u64>,
+    dedup_window: u64,
+    startup: StartupType,
+}
+
+impl Coordinator {
+    /// Construct a new coordinator scheduling jobs for the given startup type.
+    /// A slot will not be scheduled more than once while it is within
+    /// `dedup_window` slots of the newest scheduled slot.
+    #[must_use]
+    pub fn new(startup: StartupType, dedup_window: u64) -> Self {
+        Self {
+            detectors: HashMap::new(),
+            scheduled: BTreeSet::new(),
+            dedup_window,
+            startup,
+        }
+    }
+
+    /// Process a slot status update from the given producer, returning the
+    /// reindex jobs that should be dispatched as a result
+    pub fn observe(
+        &mut self,
+        producer: &str,
+        update: &SlotStatusUpdate,
+    ) -> Vec<job_runner::Message> {
+        let missing = self
+            .detectors
+            .entry(producer.to_owned())
+            .or_default()
+            .observe(update);
+
+        let jobs = missing
+            .into_iter()
+            .filter(|s| self.scheduled.insert(*s))
+            .map(|slot| {
+                job_runner::Message::ReindexSlot(SlotReindex {
+                    slot,
+                    startup: self.startup,
+                })
+            })
+            .collect();
+
+        if let Some(&newest) = self.scheduled.iter().next_back() {
+            self.scheduled = self
+                .scheduled
+                .split_off(&newest.saturating_sub(self.dedup_window));
+        }
+
+        jobs
+    }
+}
@@ -0,0 +1,171 @@
+//! Binary entry point for the Geyser gap-backfill coordinator.
+//!
+//! Configuration is read from the environment:
+//!  - `AMQP_URL`: address of the RabbitMQ broker
+//!  - `NETWORK`: the network the Geyser producers publish for
+//!  - `STARTUP_TYPE`: the startup type of the Geyser exchange (default
+//!    `normal`)
+//!  - `JOB_SENDER`: the sender name of the job-runner exchange
+//!  - `DEBUG_SUFFIX`/`STAGING`: queue suffix configuration
+//!  - `SPLIT_EXCHANGES`: if set, consume from the dedicated slot status
+//!    exchange of producers publishing each message class separately
+//!  - `BACKFILL_RATE`: maximum number of jobs dispatched per second (default
+//!    10)
+//!  - `BACKFILL_DEDUP_WINDOW`: number of slots within which a slot will not be
+//!    rescheduled (default 10000)
+//!  - `DECRYPTION_KEYS`: comma-separated `<key ID>:<base64 key>` pairs for
+//!    producers publishing encrypted payloads
+//!  - `LAG_REPORT_SECS`: interval at which to log delivery lag percentiles
+//!    (default 60)
+
+#![deny(
+    clippy::disallowed_methods,
+    clippy::suspicious,
+    clippy::style,
+    missing_debug_implementations,
+    missing_copy_implementations
+)]
+#![warn(clippy::pedantic, clippy::cargo, missing_docs)]
+
+use std::{env, sync::Arc, time::Duration};
+
+use anyhow::{Context, Result};
+use holaplex_indexer_geyser_backfill::Coordinator;
+use indexer_rabbitmq::{
+    encryption::{Key, Keyring},
+    geyser::{self, Message, ProducerId},
+    job_runner,
+    lag::LagTracker,
+    lapin::{options::BasicAckOptions, Connection, ConnectionProperties},
+    suffix::Suffix,
+};
+use log::{debug, info};
+use tokio::time::MissedTickBehavior;
+
+fn var(name: &str) -> Result<String> {
+    env::var(name).with_context(|| format!("Missing environment variable {}", name))
+}
+
+fn var_or<T: std::str::FromStr>(name: &str, default: T) -> Result<T>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    env::var(name).ok().map_or(Ok(default), |v| {
+        v.parse()
+            .with_context(|| format!("Invalid value for {}", name))
+    })
+}
+
+fn keyring() -> Result<Option<Keyring>> {
+    let keys = match env::var("DECRYPTION_KEYS") {
+        Ok(k) => k,
+        Err(_) => return Ok(None),
+    };
+
+    let mut keyring = Keyring::new();
+
+    for pair in keys.split(',').filter(|p| !p.trim().is_empty()) {
+        let (id, material) = pair
+            .split_once(':')
+            .context("Invalid DECRYPTION_KEYS entry, expected <key ID>:<base64 key>")?;
+
+        keyring.insert(
+            Key::from_base64(id.trim(), material)
+                .with_context(|| format!("Invalid decryption key {:?}", id))?,
+        );
+    }
+
+    Ok(Some(keyring))
+}
+
+async fn run() -> Result<()> {
+    let addr = var("AMQP_URL")?;
+    let network: geyser::Network = var("NETWORK")?.parse().context("Invalid NETWORK")?;
+    let startup = var_or("STARTUP_TYPE", geyser::StartupType::Normal)?;
+    let sender = var("JOB_SENDER")?;
+    let rate: u32 = var_or("BACKFILL_RATE", 10)?;
+    let dedup_window = var_or("BACKFILL_DEDUP_WINDOW", 10_000)?;
+    let lag_interval = Duration::from_secs(var_or("LAG_REPORT_SECS", 60)?);
+
+    let suffix = match env::var("DEBUG_SUFFIX") {
+        Ok(s) => Suffix::Debug(s),
+        Err(_) if env::var_os("STAGING").is_some() => Suffix::Staging,
+        Err(_) => Suffix::Production,
+    };
+
+    let conn = Connection::connect(
+        &addr,
+        ConnectionProperties::default()
+            .with_executor(tokio_executor_trait::Tokio::current())
+            .with_reactor(tokio_reactor_trait::Tokio),
+    )
+    .await
+    .context("Failed to connect to the broker")?;
+
+    let queue_type = if env::var_os("SPLIT_EXCHANGES").is_some() {
+        geyser::QueueType::new_class(
+            network,
+            startup,
+            &suffix,
+            geyser::MessageClass::SlotStatuses,
+            "backfill",
+        )?
+    } else {
+        geyser::QueueType::new_named(network, startup, &suffix, "backfill")?
+    };
+
+    let mut consumer = geyser::Consumer::new(&conn, queue_type, "geyser-backfill")
+        .await
+        .context("Failed to create Geyser consumer")?;
+
+    if let Some(keyring) = keyring()? {
+        consumer = consumer.with_keyring(Arc::new(keyring));
+    }
+
+    let jobs = job_runner::Producer::new(&conn, job_runner::QueueType::new(&sender, &suffix)?)
+        .await
+        .context("Failed to create job producer")?;
+
+    let mut coordinator = Coordinator::new(startup, dedup_window);
+    let mut limiter = tokio::time::interval(Duration::from_secs(1) / rate.max(1));
+    limiter.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut lag = LagTracker::new(10_000);
+
+    info!("Watching {} slot statuses for gaps", network);
+
+    while let Some((msg, props, acker)) = consumer.read_with_properties().await? {
+        lag.observe(&props);
+
+        if let Message::SlotStatusUpdate(ref update) = msg {
+            // Messages from producers predating identity headers are grouped
+            // under the network name
+            let producer = ProducerId::from_properties(&props)
+                .map_or_else(|| network.to_string(), |i| i.to_string());
+
+            for job in coordinator.observe(&producer, update) {
+                debug!("Dispatching {:?}", job);
+
+                limiter.tick().await;
+                jobs.write(job).await?;
+            }
+        }
+
+        acker.ack(BasicAckOptions::default()).await?;
+
+        if let Some(report) = lag.report_every(lag_interval) {
+            info!("Slot status delivery lag: {}", report);
+        }
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .context("Failed to start async runtime")?
+        .block_on(run())
+}
@@ -0,0 +1,117 @@
+//! Binary entry point for training zstd dictionaries from the payload
+//! samples written by a Geyser producer configured with
+//! `amqp.compression.sample`.
+//!
+//! Each subdirectory of the samples directory holds the samples of one
+//! dictionary key, e.g. an account owner, and produces one dictionary named
+//! `<key>.dict` in the output directory.  Producers and consumers load the
+//! output directory with `Dictionaries::load_dir`.
+//!
+//! Configuration is read from the environment:
+//!  - `SAMPLES_DIR`: the directory samples were written to
+//!  - `DICTIONARY_DIR`: the directory to write dictionaries to
+//!  - `DICTIONARY_SIZE`: maximum size of each dictionary in bytes (default
+//!    112640)
+//!  - `MIN_SAMPLES`: number of samples below which a key is skipped (default
+//!    100)
+
+#![deny(
+    clippy::disallowed_methods,
+    clippy::suspicious,
+    clippy::style,
+    missing_debug_implementations,
+    missing_copy_implementations
+)]
+#![warn(clippy::pedantic, clippy::cargo, missing_docs)]
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use log::{info, warn};
+
+fn var(name: &str) -> Result<String> {
+    env::var(name).with_context(|| format!("Missing environment variable {}", name))
+}
+
+fn var_or<T: std::str::FromStr>(name: &str, default: T) -> Result<T>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    env::var(name).ok().map_or(Ok(default), |v| {
+        v.parse()
+            .with_context(|| format!("Invalid value for {}", name))
+    })
+}
+
+/// List the sample files of a single key
+fn samples(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {:?}", dir))? {
+        let entry = entry.with_context(|| format!("Failed to read {:?}", dir))?;
+
+        if entry.file_type()?.is_file() {
+            files.push(entry.path());
+        }
+    }
+
+    Ok(files)
+}
+
+fn main() -> Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let samples_dir = PathBuf::from(var("SAMPLES_DIR")?);
+    let out_dir = PathBuf::from(var("DICTIONARY_DIR")?);
+    let size: usize = var_or("DICTIONARY_SIZE", 112_640)?;
+    let min_samples: usize = var_or("MIN_SAMPLES", 100)?;
+
+    fs::create_dir_all(&out_dir).with_context(|| format!("Failed to create {:?}", out_dir))?;
+
+    for entry in
+        fs::read_dir(&samples_dir).with_context(|| format!("Failed to read {:?}", samples_dir))?
+    {
+        let entry = entry.with_context(|| format!("Failed to read {:?}", samples_dir))?;
+
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+
+        let key = entry.file_name();
+        let key = key.to_string_lossy();
+        let files = samples(&entry.path())?;
+
+        if files.len() < min_samples {
+            warn!(
+                "Skipping {:?} with only {} sample(s), fewer than {}",
+                key,
+                files.len(),
+                min_samples
+            );
+            continue;
+        }
+
+        let dict = match zstd::dict::from_files(&files, size) {
+            Ok(d) => d,
+            Err(e) => {
+                warn!("Failed to train dictionary for {:?}: {}", key, e);
+                continue;
+            },
+        };
+
+        let path = out_dir.join(format!("{}.dict", key));
+        fs::write(&path, &dict).with_context(|| format!("Failed to write {:?}", path))?;
+
+        info!(
+            "Trained {:?} from {} sample(s) ({} byte(s))",
+            path,
+            files.len(),
+            dict.len()
+        );
+    }
+
+    Ok(())
+}
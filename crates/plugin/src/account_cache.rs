@@ -0,0 +1,145 @@
+use hashbrown::HashMap;
+use parking_lot::Mutex;
+use solana_program::hash::{hashv, Hash};
+
+use crate::{config, prelude::*};
+
+/// Approximate LRU map made of two generations, with the older generation
+/// discarded whenever the newer one fills up
+#[derive(Debug)]
+pub(crate) struct Generations<K, V> {
+    cap: usize,
+    curr: HashMap<K, V>,
+    prev: HashMap<K, V>,
+}
+
+impl<K: std::hash::Hash + Eq, V> Generations<K, V> {
+    pub fn new(cap: usize) -> Self {
+        Self {
+            cap: (cap / 2).max(1),
+            curr: HashMap::new(),
+            prev: HashMap::new(),
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V>
+    where
+        K: Clone,
+    {
+        if !self.curr.contains_key(key) {
+            let val = self.prev.remove(key)?;
+            self.insert(key.clone(), val);
+        }
+
+        self.curr.get(key)
+    }
+
+    pub fn insert(&mut self, key: K, val: V) {
+        if self.curr.len() >= self.cap && !self.curr.contains_key(&key) {
+            self.prev = std::mem::take(&mut self.curr);
+        }
+
+        self.curr.insert(key, val);
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.curr.remove(key).or_else(|| self.prev.remove(key))
+    }
+
+    pub fn len(&self) -> usize {
+        self.curr.len() + self.prev.len()
+    }
+
+    /// Iterate over the keys of both generations, newest first
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.curr.keys().chain(self.prev.keys())
+    }
+}
+
+/// The state of an account as of its most recently published update
+#[derive(Debug, Clone, Copy)]
+pub struct LastPublished {
+    pub owner: Pubkey,
+    pub rent_epoch: u64,
+    /// Hash of every field except the rent epoch, if content hashing is
+    /// enabled
+    pub hash: Option<Hash>,
+}
+
+/// Bounded record of the last update published for each account
+#[derive(Debug)]
+pub struct AccountCache {
+    reassignments: bool,
+    drop_rent_epoch_only: bool,
+    accounts: Mutex<Generations<Pubkey, LastPublished>>,
+}
+
+impl AccountCache {
+    pub fn new(config: &config::AccountCache) -> Self {
+        Self {
+            reassignments: config.reassignments,
+            drop_rent_epoch_only: config.drop_rent_epoch_only,
+            accounts: Mutex::new(Generations::new(config.capacity)),
+        }
+    }
+
+    /// Check whether a previously-published account has moved to a new owner,
+    /// returning the old owner if so.  Returns `None` if reassignment tracking
+    /// is disabled.
+    pub fn reassigned(&self, key: &Pubkey, owner: &Pubkey) -> Option<Pubkey> {
+        if !self.reassignments {
+            return None;
+        }
+
+        let mut accounts = self.accounts.lock();
+        let last = accounts.get(key)?;
+
+        if last.owner == *owner {
+            return None;
+        }
+
+        let old = last.owner;
+        accounts.remove(key);
+
+        Some(old)
+    }
+
+    /// Hash the contents of an account update, excluding its rent epoch.
+    /// Returns `None` if no cache feature requires content hashes.
+    pub fn content_hash(
+        &self,
+        lamports: u64,
+        owner: &Pubkey,
+        executable: bool,
+        data: &[u8],
+    ) -> Option<Hash> {
+        if !self.drop_rent_epoch_only {
+            return None;
+        }
+
+        Some(hashv(&[
+            &lamports.to_le_bytes()[..],
+            owner.as_ref(),
+            &[u8::from(executable)],
+            data,
+        ]))
+    }
+
+    /// Returns true if an account update differs from the last one published
+    /// only in its rent epoch and such updates should be dropped
+    pub fn rent_epoch_only(&self, key: &Pubkey, hash: Option<&Hash>, rent_epoch: u64) -> bool {
+        let hash = match hash {
+            Some(h) if self.drop_rent_epoch_only => h,
+            _ => return false,
+        };
+
+        self.accounts.lock().get(key).map_or(false, |l| {
+            l.hash.as_ref() == Some(hash) && l.rent_epoch != rent_epoch
+        })
+    }
+
+    /// Record an account update as published
+    pub fn published(&self, key: Pubkey, last: LastPublished) {
+        self.accounts.lock().insert(key, last);
+    }
+}
@@ -0,0 +1,52 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::{clock::SharedClock, config, watermark::PendingSlots};
+
+/// Holds a slot's confirmed and rooted statuses until every account and
+/// instruction message of the slot has been published, so consumers can
+/// treat the status as the end of the slot
+#[derive(Debug)]
+#[allow(clippy::module_name_repetitions)]
+pub struct SlotBarrier {
+    pending: Arc<PendingSlots>,
+    timeout: Duration,
+    clock: SharedClock,
+}
+
+impl SlotBarrier {
+    pub fn new(
+        config: &config::SlotBarrier,
+        pending: Arc<PendingSlots>,
+        clock: SharedClock,
+    ) -> Self {
+        Self {
+            pending,
+            timeout: Duration::from_millis(config.timeout_ms),
+            clock,
+        }
+    }
+
+    /// Wait until no messages for the given slot remain in the pipeline,
+    /// returning false if the timeout elapsed first
+    pub async fn wait(&self, slot: u64) -> bool {
+        let deadline = self.clock.now() + self.timeout;
+
+        loop {
+            let drained = self.pending.drained();
+
+            if !self.pending.contains(slot) {
+                return true;
+            }
+
+            let left = deadline.saturating_duration_since(self.clock.now());
+
+            if left.is_zero() {
+                return false;
+            }
+
+            // The deadline is checked against the clock again after a
+            // timeout, so a clock running slow never ends the wait early
+            tokio::time::timeout(left, drained).await.ok();
+        }
+    }
+}
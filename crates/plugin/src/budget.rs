@@ -0,0 +1,95 @@
+use std::{
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::{clock::SharedClock, config, metrics::Metrics, prelude::*};
+
+/// Tracks time spent inside validator callbacks.  When the budget is
+/// exceeded by enough consecutive callbacks, expensive optional features
+/// (content hashing, enrichment, and inline data decoding for selection) are
+/// disabled until a recovery period passes.
+#[derive(Debug)]
+pub struct Budget {
+    budget: Duration,
+    strikes: u32,
+    recover_ms: u64,
+    clock: SharedClock,
+    epoch: Instant,
+    /// Consecutive callbacks over budget
+    over: AtomicU32,
+    /// Milliseconds since `epoch` until which features are degraded, or 0 if
+    /// they are not
+    degraded_until: AtomicU64,
+}
+
+impl Budget {
+    pub fn new(config: &config::LatencyBudget, clock: SharedClock) -> Self {
+        Self {
+            budget: Duration::from_micros(config.budget_us),
+            strikes: config.strikes.max(1),
+            recover_ms: config.recover_secs.saturating_mul(1000),
+            epoch: clock.now(),
+            clock,
+            over: AtomicU32::new(0),
+            degraded_until: AtomicU64::new(0),
+        }
+    }
+
+    #[inline]
+    fn now_ms(&self) -> u64 {
+        let elapsed = self.clock.now().saturating_duration_since(self.epoch);
+
+        u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX)
+    }
+
+    /// Returns true if expensive features should currently be skipped
+    #[inline]
+    pub fn is_degraded(&self) -> bool {
+        let until = self.degraded_until.load(Ordering::Relaxed);
+
+        until != 0 && self.now_ms() < until
+    }
+
+    /// Record the time taken by a callback
+    pub fn observe(&self, elapsed: Duration, metrics: &Metrics) {
+        let until = self.degraded_until.load(Ordering::Relaxed);
+
+        if until != 0
+            && self.now_ms() >= until
+            && self
+                .degraded_until
+                .compare_exchange(until, 0, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            metrics.degraded.set(0);
+            info!("Callback latency recovery period elapsed, re-enabling degraded features");
+        }
+
+        if elapsed <= self.budget {
+            self.over.store(0, Ordering::Relaxed);
+            return;
+        }
+
+        metrics.budget_overruns.log(1);
+
+        if self.over.fetch_add(1, Ordering::Relaxed) + 1 < self.strikes {
+            return;
+        }
+
+        self.over.store(0, Ordering::Relaxed);
+        let until = self.now_ms().saturating_add(self.recover_ms).max(1);
+
+        if self.degraded_until.swap(until, Ordering::Relaxed) == 0 {
+            metrics.budget_trips.log(1);
+            metrics.degraded.set(1);
+            warn!(
+                "{} consecutive callbacks exceeded the {:?} latency budget, disabling hashing, \
+                 enrichment, and inline decoding for {}s",
+                self.strikes,
+                self.budget,
+                self.recover_ms / 1000,
+            );
+        }
+    }
+}
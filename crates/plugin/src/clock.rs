@@ -0,0 +1,100 @@
+//! Time sources for the plugin's timing-dependent bookkeeping, such as
+//! coalescing windows, the callback latency budget, and slot time estimates.
+//! The plugin reads the real clock; tests can substitute a [`TestClock`] and
+//! advance it explicitly to drive these deterministically.
+
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use parking_lot::Mutex;
+
+/// A source of monotonic and wall-clock time
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// The current monotonic time
+    fn now(&self) -> Instant;
+
+    /// The current wall-clock time
+    fn system_now(&self) -> SystemTime;
+
+    /// The current wall-clock time as milliseconds since the Unix epoch
+    fn unix_ms(&self) -> i64 {
+        self.system_now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis().try_into().unwrap_or(i64::MAX))
+    }
+}
+
+/// A shareable handle to a clock
+#[allow(clippy::module_name_repetitions)]
+pub type SharedClock = Arc<dyn Clock>;
+
+/// The real system clock
+#[derive(Debug, Clone, Copy, Default)]
+#[allow(clippy::module_name_repetitions)]
+pub struct SystemClock;
+
+impl SystemClock {
+    /// Construct a shareable handle to the system clock
+    #[must_use]
+    pub fn shared() -> SharedClock {
+        Arc::new(Self)
+    }
+}
+
+impl Clock for SystemClock {
+    #[inline]
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    #[inline]
+    fn system_now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock which only moves when advanced, starting from the time it was
+/// constructed
+#[derive(Debug)]
+#[allow(clippy::module_name_repetitions)]
+pub struct TestClock {
+    start: Instant,
+    system_start: SystemTime,
+    elapsed: Mutex<Duration>,
+}
+
+impl Default for TestClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TestClock {
+    /// Construct a new stopped clock
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            system_start: SystemTime::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    /// Move the clock forward by the given duration
+    pub fn advance(&self, by: Duration) {
+        *self.elapsed.lock() += by;
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> Instant {
+        self.start + *self.elapsed.lock()
+    }
+
+    fn system_now(&self) -> SystemTime {
+        self.system_start + *self.elapsed.lock()
+    }
+}
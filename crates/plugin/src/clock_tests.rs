@@ -0,0 +1,168 @@
+//! Timing-dependent bookkeeping driven by a [`TestClock`], so expiry and
+//! time estimates are checked without sleeping.
+
+use std::{sync::Arc, time::Duration};
+
+use indexer_rabbitmq::{
+    control::{Reply, Request, Subscription},
+    geyser::{AccountUpdate, ProducerId, ProducerLifecycle},
+};
+
+use crate::{
+    clock::{Clock, TestClock},
+    coalesce::{Coalescer, Commitment},
+    config,
+    metrics::Metrics,
+    prelude::*,
+    record::Recorder,
+    slot_clock::SlotClock,
+    stats::Summarizer,
+    subscriptions::Subscriptions,
+};
+
+fn key(n: u8) -> Pubkey {
+    Pubkey::new_from_array([n; 32])
+}
+
+fn update(slot: u64) -> AccountUpdate {
+    AccountUpdate {
+        key: key(1),
+        lamports: 1,
+        owner: key(2),
+        executable: false,
+        rent_epoch: 0,
+        data: vec![1, 2, 3],
+        write_version: 1,
+        slot,
+        is_startup: false,
+        on_demand: false,
+        mint_info: None,
+        data_hash: None,
+    }
+}
+
+#[test]
+fn coalescer_releases_buffers_past_max_age() {
+    let clock = Arc::new(TestClock::new());
+    let coalescer = Coalescer::new(
+        &config::Coalesce {
+            commitment: Commitment::default(),
+            max_age_ms: 1_000,
+        },
+        clock.clone(),
+    );
+
+    coalescer.push(update(10));
+    clock.advance(Duration::from_millis(999));
+    assert!(coalescer.flush_expired().is_empty());
+
+    clock.advance(Duration::from_millis(1));
+    assert_eq!(coalescer.flush_expired().len(), 1);
+    assert_eq!(coalescer.pending(), 0);
+}
+
+#[test]
+fn subscriptions_expire_after_ttl() {
+    let clock = Arc::new(TestClock::new());
+    let subs = Subscriptions::new(
+        &config::Control {
+            max_subscriptions: 4,
+            max_ttl_secs: 60,
+        },
+        clock.clone(),
+    );
+
+    let reply = subs.handle(Request::Subscribe(Subscription {
+        id: "test".into(),
+        accounts: vec![key(3)],
+        owners: vec![],
+        programs: vec![],
+        ttl_secs: 10,
+    }));
+    let expected = clock.unix_ms() / 1_000 + 10;
+    assert!(matches!(
+        reply,
+        Reply::Subscribed { expires_at, .. } if expires_at == expected
+    ));
+
+    clock.advance(Duration::from_secs(9));
+    subs.expire();
+    assert!(subs.is_account_selected(key(3).as_ref(), key(4).as_ref()));
+
+    clock.advance(Duration::from_secs(1));
+    subs.expire();
+    assert!(!subs.is_account_selected(key(3).as_ref(), key(4).as_ref()));
+}
+
+#[test]
+fn slot_clock_places_late_slots_by_cadence() {
+    let clock = Arc::new(TestClock::new());
+    let slots = SlotClock::new(clock.clone());
+
+    let first = slots.observe(100);
+    assert_eq!(first, clock.unix_ms());
+
+    clock.advance(Duration::from_millis(800));
+    assert_eq!(slots.observe(102), first + 800);
+
+    // Observed late, but placed between its neighbours
+    clock.advance(Duration::from_secs(5));
+    assert_eq!(slots.observe(101), first + 400);
+    assert_eq!(slots.observe(100), first);
+}
+
+#[test]
+fn recording_stops_at_time_limit() {
+    let dir = std::env::temp_dir().join(format!("geyser-clock-tests-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let clock = Arc::new(TestClock::new());
+    let recorder = Recorder::new(
+        &config::Recording {
+            dir: dir.clone(),
+            max_duration_secs: 60,
+            max_bytes: 1 << 20,
+        },
+        clock.clone(),
+    );
+
+    let (path, until, _) = recorder.start(3_600, 1 << 30).unwrap();
+    assert_eq!(until, clock.unix_ms() / 1_000 + 60);
+
+    clock.advance(Duration::from_secs(59));
+    recorder.expire();
+    assert!(recorder.is_active());
+
+    clock.advance(Duration::from_secs(1));
+    recorder.expire();
+    assert!(!recorder.is_active());
+
+    std::fs::remove_file(path).ok();
+    std::fs::remove_dir(dir).ok();
+}
+
+#[test]
+fn stats_are_stamped_by_the_clock() {
+    let clock = Arc::new(TestClock::new());
+    let summary = Summarizer::new(Duration::from_secs(30), vec![], clock.clone());
+    let metrics = Metrics::new_rc();
+    let lifecycle = ProducerLifecycle {
+        id: ProducerId {
+            host: "validator-1".into(),
+            epoch: "1".into(),
+        },
+        version: String::new(),
+        build: None,
+    };
+
+    metrics.acct_sends.log(3);
+    let first = summary.sample(&metrics, &lifecycle, 1);
+    assert_eq!(first.timestamp, clock.unix_ms() / 1_000);
+    assert_eq!(first.account_msgs, 3);
+
+    clock.advance(Duration::from_secs(30));
+    metrics.acct_sends.log(2);
+    let second = summary.sample(&metrics, &lifecycle, 2);
+    assert_eq!(second.timestamp, first.timestamp + 30);
+    assert_eq!(second.account_msgs, 2);
+}
@@ -0,0 +1,172 @@
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use hashbrown::{hash_map::Entry, HashMap};
+use indexer_rabbitmq::geyser::{AccountUpdate, SlotStatus};
+use parking_lot::Mutex;
+use serde::Deserialize;
+
+use crate::{clock::SharedClock, config, memory, prelude::*};
+
+/// The slot status at which a slot's buffered account updates are published
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Commitment {
+    Processed,
+    Confirmed,
+    Rooted,
+}
+
+impl Default for Commitment {
+    fn default() -> Self {
+        Self::Confirmed
+    }
+}
+
+impl Commitment {
+    pub fn is_met_by(self, status: SlotStatus) -> bool {
+        let status = match status {
+            SlotStatus::Processed => Self::Processed,
+            SlotStatus::Confirmed => Self::Confirmed,
+            SlotStatus::Rooted => Self::Rooted,
+            SlotStatus::Unknown => return false,
+        };
+
+        status >= self
+    }
+}
+
+#[derive(Debug)]
+struct SlotBuffer {
+    created: Instant,
+    accounts: HashMap<Pubkey, AccountUpdate>,
+}
+
+impl SlotBuffer {
+    fn size(&self) -> usize {
+        self.accounts.values().map(memory::account_size).sum()
+    }
+
+    fn into_updates(self) -> impl Iterator<Item = AccountUpdate> {
+        self.accounts.into_values()
+    }
+}
+
+/// Per-slot buffer collapsing repeated writes to an account into the latest
+/// one, released once the slot reaches the configured commitment
+#[derive(Debug)]
+pub struct Coalescer {
+    commitment: Commitment,
+    max_age: Duration,
+    clock: SharedClock,
+    slots: Mutex<BTreeMap<u64, SlotBuffer>>,
+    /// Approximate memory held by the buffered updates
+    bytes: AtomicUsize,
+}
+
+impl Coalescer {
+    pub fn new(config: &config::Coalesce, clock: SharedClock) -> Self {
+        let config::Coalesce {
+            commitment,
+            max_age_ms,
+        } = *config;
+
+        Self {
+            commitment,
+            max_age: Duration::from_millis(max_age_ms),
+            clock,
+            slots: Mutex::new(BTreeMap::new()),
+            bytes: AtomicUsize::new(0),
+        }
+    }
+
+    #[inline]
+    pub fn commitment(&self) -> Commitment {
+        self.commitment
+    }
+
+    #[inline]
+    pub fn max_age(&self) -> Duration {
+        self.max_age
+    }
+
+    /// The number of account updates currently buffered
+    pub fn pending(&self) -> usize {
+        self.slots.lock().values().map(|b| b.accounts.len()).sum()
+    }
+
+    /// The approximate memory held by the buffered updates
+    pub fn bytes(&self) -> usize {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Buffer an account update, returning true if it superseded an update
+    /// already buffered for the same account and slot
+    pub fn push(&self, update: AccountUpdate) -> bool {
+        let mut slots = self.slots.lock();
+        let size = memory::account_size(&update);
+        let buf = slots.entry(update.slot).or_insert_with(|| SlotBuffer {
+            created: self.clock.now(),
+            accounts: HashMap::new(),
+        });
+
+        match buf.accounts.entry(update.key) {
+            Entry::Occupied(mut e) => {
+                if e.get().write_version <= update.write_version {
+                    let old = e.insert(update);
+                    self.bytes.fetch_add(size, Ordering::Relaxed);
+                    self.bytes
+                        .fetch_sub(memory::account_size(&old), Ordering::Relaxed);
+                }
+
+                true
+            },
+            Entry::Vacant(e) => {
+                e.insert(update);
+                self.bytes.fetch_add(size, Ordering::Relaxed);
+
+                false
+            },
+        }
+    }
+
+    /// Release the buffered updates for a slot if the given status satisfies
+    /// the configured commitment
+    pub fn on_status(&self, slot: u64, status: SlotStatus) -> Vec<AccountUpdate> {
+        if !self.commitment.is_met_by(status) {
+            return vec![];
+        }
+
+        self.slots.lock().remove(&slot).map_or_else(Vec::new, |b| {
+            self.bytes.fetch_sub(b.size(), Ordering::Relaxed);
+            b.into_updates().collect()
+        })
+    }
+
+    /// Release every buffer older than the configured maximum age, regardless
+    /// of its slot's status
+    pub fn flush_expired(&self) -> Vec<AccountUpdate> {
+        let now = self.clock.now();
+        let mut slots = self.slots.lock();
+
+        let expired: Vec<_> = slots
+            .iter()
+            .filter(|(_, b)| now.saturating_duration_since(b.created) >= self.max_age)
+            .map(|(s, _)| *s)
+            .collect();
+
+        let mut updates = vec![];
+
+        for slot in expired {
+            if let Some(buf) = slots.remove(&slot) {
+                self.bytes.fetch_sub(buf.size(), Ordering::Relaxed);
+                updates.extend(buf.into_updates());
+            }
+        }
+
+        updates
+    }
+}
@@ -1,76 +1,1521 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use hashbrown::HashSet;
 use selector::{
     config::{Accounts, Instructions},
     AccountSelector, InstructionSelector,
 };
 use serde::Deserialize;
 
-use crate::prelude::*;
+use crate::{
+    coalesce::Commitment,
+    guardrails,
+    middleware::Field,
+    migrate::{self, Deprecation},
+    prelude::*,
+};
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct Config {
     amqp: Amqp,
+
+    #[serde(default)]
     jobs: Jobs,
 
     #[serde(default)]
     metrics: Metrics,
 
+    #[serde(default)]
+    coalesce: Option<Coalesce>,
+
+    #[serde(default)]
+    watermark: Option<Watermark>,
+
+    #[serde(default)]
+    leader: Option<Leader>,
+
+    #[serde(default)]
+    account_cache: Option<AccountCache>,
+
+    #[serde(default)]
+    token_registry: TokenRegistry,
+
+    #[serde(default)]
+    http: Http,
+
+    #[serde(default)]
+    publish: Publish,
+
+    #[serde(default)]
+    retention: Option<Retention>,
+
+    #[serde(default)]
+    diff: Option<Diff>,
+
+    #[serde(default)]
+    rpc: Rpc,
+
+    #[serde(default)]
+    snapshots: Option<Snapshots>,
+
+    #[serde(default)]
+    mint_info: Option<MintInfo>,
+
+    #[serde(default)]
+    scrub: Vec<Scrub>,
+
+    #[serde(default)]
+    middleware: Vec<Middleware>,
+
+    #[serde(default)]
+    control: Option<Control>,
+
+    #[serde(default)]
+    stats: Option<Stats>,
+
+    #[serde(default)]
+    token_shards: Option<TokenShards>,
+
+    #[serde(default)]
+    readiness: Option<Readiness>,
+
+    #[serde(default)]
+    offload: Option<Offload>,
+
+    #[serde(default)]
+    latency_budget: Option<LatencyBudget>,
+
+    #[serde(default)]
+    tombstones: Option<Tombstones>,
+
+    #[serde(default)]
+    trace: Trace,
+
+    #[serde(default)]
+    slot_barrier: Option<SlotBarrier>,
+
+    #[serde(default)]
+    wallet_digest: Option<WalletDigest>,
+
+    #[serde(default)]
+    recording: Option<Recording>,
+
+    #[serde(default)]
+    features: Features,
+
+    #[serde(default)]
+    startup_check: StartupCheck,
+
+    #[serde(default)]
+    memory: Option<Memory>,
+
+    #[serde(default)]
+    first_write: Option<FirstWrite>,
+
+    #[serde(default)]
+    config_usage: Option<ConfigUsage>,
+
+    /// Load configurations refused by the network guardrails, logging a
+    /// warning for each instead
+    #[serde(default)]
+    override_guardrails: bool,
+
+    #[serde(default)]
     accounts: Accounts,
+
+    #[serde(default)]
     instructions: Instructions,
 
+    /// Additional files whose `accounts` and `instructions` blocks are merged
+    /// into this config's, resolved relative to this file.  Consumed by
+    /// [`Config::read`].
+    #[serde(default)]
+    #[allow(dead_code)]
+    include: Vec<PathBuf>,
+
     /// Unused but required by the validator to load the plugin
     #[allow(dead_code)]
     libpath: String,
+
+    /// Deprecated fields migrated by [`Config::read`]
+    #[serde(skip)]
+    deprecations: Vec<Deprecation>,
 }
 
 #[serde_with::serde_as]
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Amqp {
     pub address: String,
 
     #[serde_as(as = "serde_with::DisplayFromStr")]
     pub network: indexer_rabbitmq::geyser::Network,
+
+    /// Publish account updates, instruction notifications, and slot statuses
+    /// to separate exchanges
+    #[serde(default)]
+    pub split_exchanges: bool,
+
+    /// Message priority given to updates for accounts in the `pubkeys`
+    /// allowlist.  Consumer queues must be declared with a maximum priority
+    /// for this to take effect.
+    #[serde(default)]
+    pub allowlist_priority: Option<u8>,
+
+    /// The overflow policy consumer queues are declared with.  If messages
+    /// are rejected under this policy, publisher confirms are enabled to
+    /// count them.
+    #[serde(default)]
+    pub overflow: Option<indexer_rabbitmq::Overflow>,
+
+    /// Publish with the `mandatory` flag to detect messages which are not
+    /// routed to any queue
+    #[serde(default)]
+    pub mandatory: bool,
+
+    /// Queue to divert unroutable messages to.  Has no effect unless
+    /// `mandatory` is set.
+    #[serde(default)]
+    pub fallback_queue: Option<String>,
+
+    /// Publisher confirm policy of each sender, trading latency for
+    /// durability per message class
+    #[serde(default)]
+    pub confirms: Confirms,
+
+    /// Encrypt message payloads before publishing them
+    #[serde(default)]
+    pub encryption: Option<Encryption>,
+
+    /// Compress account updates with per-owner zstd dictionaries, or sample
+    /// them to train new dictionaries
+    #[serde(default)]
+    pub compression: Option<Compression>,
+
+    /// Encode account updates of the given owners with a different layout
+    /// than the rest, e.g. `json` for accounts read by scripts.  Payloads
+    /// carry their encoding in the AMQP content type.
+    #[serde(default)]
+    pub owner_encodings: HashMap<String, indexer_rabbitmq::Encoding>,
+
+    /// Additionally publish every message to the exchanges of another
+    /// network name for a limited time, to migrate consumers between
+    /// exchange names without a hard cutover
+    #[serde(default)]
+    pub migration: Option<Migration>,
+
+    /// Additionally publish every message to a second set of exchanges, with
+    /// its own connection and payload encoding, e.g. to test a protocol
+    /// change against production traffic
+    #[serde(default)]
+    pub secondary: Option<Secondary>,
+
+    /// If an exchange already exists with arguments differing from the ones
+    /// it is declared with, declare a versioned exchange name (e.g.
+    /// `mainnet.accounts.v2`) instead of failing to load
+    #[serde(default)]
+    pub exchange_fallback: bool,
+
+    /// Stamp every message with a deterministic AMQP `message_id`, so
+    /// consumers of exchanges shared by several producers can discard
+    /// duplicates.  Identifying an account update hashes its data, so this
+    /// is off by default.
+    #[serde(default)]
+    pub message_ids: bool,
+}
+
+#[serde_with::serde_as]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Secondary {
+    /// The broker to publish to, if different from the primary broker
+    #[serde(default)]
+    pub address: Option<String>,
+
+    /// The network name of the secondary exchanges
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub network: indexer_rabbitmq::geyser::Network,
+
+    /// The layout payloads are encoded with
+    #[serde(default)]
+    pub encoding: indexer_rabbitmq::Encoding,
 }
 
-#[derive(Debug, Deserialize)]
+/// Whether each sender waits for publisher confirms.  Senders with confirms
+/// republish messages the broker nacks, a few times with backoff before
+/// dropping them, and a message whose publish fails with the connection is
+/// republished once after reconnecting; senders without confirms give
+/// at-most-once delivery at lower latency.  Messages carry their guarantee in the `x-delivery-guarantee`
+/// header.  Unset entries use confirms only if `overflow` rejects publishes,
+/// and `mandatory` always requires them.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Confirms {
+    /// Account updates, and every other message class unless
+    /// `splitExchanges` is set
+    #[serde(default)]
+    pub accounts: Option<bool>,
+
+    /// Instruction notifications, if `splitExchanges` is set
+    #[serde(default)]
+    pub instructions: Option<bool>,
+
+    /// Slot statuses
+    #[serde(default)]
+    pub slot_statuses: Option<bool>,
+
+    /// Sharded token account updates, if `tokenShards` is configured
+    #[serde(default)]
+    pub token_accounts: Option<bool>,
+
+    /// Health reports, if `stats` is configured
+    #[serde(default)]
+    pub stats: Option<bool>,
+}
+
+#[serde_with::serde_as]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Migration {
+    /// The network name of the exchanges being migrated from
+    #[serde_as(as = "serde_with::DisplayFromStr")]
+    pub network: indexer_rabbitmq::geyser::Network,
+
+    /// How long after startup to keep publishing to both sets of exchanges
+    pub window_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Encryption {
+    /// ID published with each message, identifying the key to consumers
+    pub key_id: String,
+
+    /// Base64-encoded 256-bit key
+    #[serde(default)]
+    pub key: Option<String>,
+
+    /// Environment variable to read the base64-encoded key from instead,
+    /// e.g. as injected by a KMS
+    #[serde(default)]
+    pub key_env: Option<String>,
+}
+
+impl Encryption {
+    pub fn load(&self) -> Result<indexer_rabbitmq::encryption::Key> {
+        let material = match (&self.key, &self.key_env) {
+            (Some(key), None) => key.clone(),
+            (None, Some(var)) => std::env::var(var)
+                .with_context(|| format!("Failed to read encryption key from {}", var))?,
+            _ => bail!("Exactly one of key and keyEnv must be set for encryption"),
+        };
+
+        indexer_rabbitmq::encryption::Key::from_base64(self.key_id.clone(), &material)
+            .context("Invalid encryption key")
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Compression {
+    /// Directory of dictionaries trained by `geyser-train-dict`, each named
+    /// `<owner>.dict` after the account owner it applies to
+    #[serde(default)]
+    pub dictionaries: Option<PathBuf>,
+
+    /// zstd compression level
+    #[serde(default = "Compression::default_level")]
+    pub level: i32,
+
+    /// Write serialized account updates of each owner to a directory, as
+    /// training input for `geyser-train-dict`
+    #[serde(default)]
+    pub sample: Option<Sampling>,
+}
+
+impl Compression {
+    fn default_level() -> i32 {
+        3
+    }
+
+    pub fn load(&self) -> Result<indexer_rabbitmq::compression::Dictionaries> {
+        let dicts = match self.dictionaries {
+            Some(ref dir) => indexer_rabbitmq::compression::Dictionaries::load_dir(dir, self.level)
+                .with_context(|| format!("Failed to load dictionaries from {:?}", dir))?,
+            None => indexer_rabbitmq::compression::Dictionaries::new(),
+        };
+
+        Ok(match self.sample {
+            Some(ref s) => dicts
+                .with_sampling(s.dir.clone(), s.max_per_owner, s.max_owners)
+                .context("Failed to start payload sampling")?,
+            None => dicts,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Sampling {
+    /// Directory to write samples to, one subdirectory per owner
+    pub dir: PathBuf,
+
+    /// Number of samples to write for each owner
+    #[serde(default = "Sampling::default_max_per_owner")]
+    pub max_per_owner: usize,
+
+    /// Number of owners to write samples for; updates of owners seen after
+    /// this many are not sampled
+    #[serde(default = "Sampling::default_max_owners")]
+    pub max_owners: usize,
+}
+
+impl Sampling {
+    fn default_max_per_owner() -> usize {
+        1000
+    }
+
+    fn default_max_owners() -> usize {
+        64
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct Jobs {
-    pub limit: usize,
+    /// Number of worker threads to spawn.  If omitted this is sized from the
+    /// number of available cores.
+    #[serde(default)]
+    pub limit: Option<usize>,
 
     #[serde(default)]
     pub blocking: Option<usize>,
 }
 
+impl Jobs {
+    /// Fraction of the host's cores given to the plugin when no limit is set
+    const AUTO_CORE_DIVISOR: usize = 4;
+    /// Upper bound for automatically-sized worker pools
+    const AUTO_MAX_WORKERS: usize = 16;
+
+    pub fn worker_threads(&self) -> usize {
+        self.limit.unwrap_or_else(|| {
+            let cores = std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get);
+
+            (cores / Self::AUTO_CORE_DIVISOR).clamp(1, Self::AUTO_MAX_WORKERS)
+        })
+    }
+
+    pub fn blocking_threads(&self) -> usize {
+        self.blocking.unwrap_or_else(|| self.worker_threads())
+    }
+}
+
 #[derive(Debug, Default, Deserialize)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct Metrics {
     pub config: Option<String>,
 }
 
-impl Config {
-    pub fn read(path: &str) -> Result<Self> {
-        let f = std::fs::File::open(path).context("Failed to open config file")?;
-        let cfg = serde_json::from_reader(f).context("Failed to parse config file")?;
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Coalesce {
+    /// The slot status at which buffered account updates are published
+    #[serde(default)]
+    pub commitment: Commitment,
+
+    /// Buffers older than this are published even if their slot never reaches
+    /// the configured commitment
+    #[serde(default = "Coalesce::default_max_age_ms")]
+    pub max_age_ms: u64,
+}
 
-        Ok(cfg)
+impl Coalesce {
+    fn default_max_age_ms() -> u64 {
+        30_000
     }
+}
 
-    pub fn into_parts(self) -> Result<(Amqp, Jobs, Metrics, AccountSelector, InstructionSelector)> {
-        let Self {
-            amqp,
-            jobs,
-            metrics,
-            accounts,
-            instructions,
-            libpath: _,
-        } = self;
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Watermark {
+    /// How often to publish the rooted-slot watermark
+    #[serde(default = "Watermark::default_interval_ms")]
+    pub interval_ms: u64,
+}
 
-        let acct =
-            AccountSelector::from_config(accounts).context("Failed to create account selector")?;
-        let ins = InstructionSelector::from_config(instructions)
-            .context("Failed to create instruction selector")?;
+impl Watermark {
+    fn default_interval_ms() -> u64 {
+        1000
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Leader {
+    /// Name of the exclusive queue used as the lease.  Defaults to a name
+    /// derived from the network.
+    #[serde(default)]
+    pub lease: Option<String>,
+
+    /// How often a standby producer retries acquiring the lease
+    #[serde(default = "Leader::default_retry_ms")]
+    pub retry_ms: u64,
+}
+
+impl Leader {
+    fn default_retry_ms() -> u64 {
+        5000
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct AccountCache {
+    /// Approximate maximum number of accounts to remember
+    #[serde(default = "AccountCache::default_capacity")]
+    pub capacity: usize,
+
+    /// Publish a message when a previously-published account is assigned to a
+    /// new owner
+    #[serde(default)]
+    pub reassignments: bool,
+
+    /// Drop account updates that differ from the last published update only
+    /// in their rent epoch
+    #[serde(default)]
+    pub drop_rent_epoch_only: bool,
+}
+
+impl AccountCache {
+    fn default_capacity() -> usize {
+        100_000
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct TokenRegistry {
+    /// Local file to cache the fetched token registry in.  The cached copy is
+    /// used if the registry cannot be fetched.
+    #[serde(default)]
+    pub cache_path: Option<PathBuf>,
+
+    /// Cached registries younger than this are used without refetching
+    #[serde(default = "TokenRegistry::default_cache_ttl_secs")]
+    pub cache_ttl_secs: u64,
+}
+
+impl Default for TokenRegistry {
+    fn default() -> Self {
+        Self {
+            cache_path: None,
+            cache_ttl_secs: Self::default_cache_ttl_secs(),
+        }
+    }
+}
+
+impl TokenRegistry {
+    fn default_cache_ttl_secs() -> u64 {
+        24 * 60 * 60
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Http {
+    /// Forbid all outbound HTTP, requiring local file sources instead
+    #[serde(default)]
+    pub disabled: bool,
+
+    /// Timeout for a single request attempt
+    #[serde(default = "Http::default_timeout_ms")]
+    pub timeout_ms: u64,
+
+    /// Number of times to retry a failed request
+    #[serde(default = "Http::default_retries")]
+    pub retries: u32,
+
+    /// Delay before the first retry, doubled for each subsequent retry
+    #[serde(default = "Http::default_backoff_ms")]
+    pub backoff_ms: u64,
+
+    /// Proxy URL to send all requests through
+    #[serde(default)]
+    pub proxy: Option<String>,
+
+    /// Path to an additional PEM-encoded root certificate to trust
+    #[serde(default)]
+    pub ca_cert: Option<PathBuf>,
+}
+
+impl Default for Http {
+    fn default() -> Self {
+        Self {
+            disabled: false,
+            timeout_ms: Self::default_timeout_ms(),
+            retries: Self::default_retries(),
+            backoff_ms: Self::default_backoff_ms(),
+            proxy: None,
+            ca_cert: None,
+        }
+    }
+}
+
+impl Http {
+    fn default_timeout_ms() -> u64 {
+        30_000
+    }
+
+    fn default_retries() -> u32 {
+        3
+    }
+
+    fn default_backoff_ms() -> u64 {
+        500
+    }
+}
+
+/// Switches for each class of message published by the plugin
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Publish {
+    #[serde(default = "Publish::default_enabled")]
+    pub accounts: bool,
+
+    #[serde(default = "Publish::default_enabled")]
+    pub instructions: bool,
 
-        Ok((amqp, jobs, metrics, acct, ins))
+    #[serde(default = "Publish::default_enabled")]
+    pub slot_status: bool,
+
+    /// Publish a signature notification for every successful transaction
+    /// invoking a selected program
+    #[serde(default)]
+    pub signatures: bool,
+
+    /// Publish per-slot fee-market statistics for transactions invoking
+    /// selected programs
+    #[serde(default)]
+    pub fees: bool,
+
+    /// Publish a structured sale message for every successful transaction
+    /// recognized as an NFT sale on a known marketplace, regardless of the
+    /// instruction selector
+    #[serde(default)]
+    pub nft_sales: bool,
+
+    /// Publish the data of executable accounts.  By default their data is
+    /// replaced with its hash, so consumers can still detect upgrades.
+    #[serde(default)]
+    pub include_program_data: bool,
+
+    /// How to publish updates for accounts holding no data, such as wallets
+    /// receiving lamport transfers.  Closed accounts (those left with no
+    /// lamports) are always published in full.
+    #[serde(default)]
+    pub zero_data: ZeroData,
+}
+
+impl Default for Publish {
+    fn default() -> Self {
+        Self {
+            accounts: true,
+            instructions: true,
+            slot_status: true,
+            signatures: false,
+            fees: false,
+            nft_sales: false,
+            include_program_data: false,
+            zero_data: ZeroData::default(),
+        }
+    }
+}
+
+/// Treatment of updates for accounts holding no data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ZeroData {
+    /// Publish them as ordinary account updates
+    Publish,
+    /// Do not publish them
+    Drop,
+    /// Publish them as balance updates, which omit the data and fields
+    /// describing it
+    Compact,
+}
+
+impl Default for ZeroData {
+    fn default() -> Self {
+        Self::Publish
+    }
+}
+
+impl Publish {
+    fn default_enabled() -> bool {
+        true
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Retention {
+    /// Account updates for slots older than this are discarded by the broker
+    /// instead of being delivered
+    pub max_age_secs: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Diff {
+    /// Accounts to publish as byte-level deltas instead of full updates.
+    /// Deltas are published one at a time in order, without the retention
+    /// TTL, so the broker never expires a link out of a chain.
+    pub accounts: HashSet<String>,
+
+    /// Number of updates in a delta chain, after which a new keyframe is
+    /// published
+    #[serde(default = "Diff::default_keyframe_interval")]
+    pub keyframe_interval: u64,
+}
+
+impl Diff {
+    fn default_keyframe_interval() -> u64 {
+        100
+    }
+}
+
+/// Byte ranges of account data to zero before publishing accounts owned by
+/// a program
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Scrub {
+    /// The owning program
+    pub owner: String,
+
+    /// Ranges to zero, clamped to the length of the account's data
+    pub ranges: Vec<ScrubRange>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ScrubRange {
+    pub offset: usize,
+    pub len: usize,
+}
+
+/// A stage of the middleware chain applied to account and transaction
+/// messages before they are published
+#[derive(Debug, Deserialize)]
+#[serde(tag = "stage", rename_all = "camelCase", deny_unknown_fields)]
+pub enum Middleware {
+    /// Truncate account and instruction data to at most the given length
+    TruncateData {
+        #[serde(rename = "maxLen")]
+        max_len: usize,
+    },
+    /// Strip a field from every message containing it
+    DropField { field: Field },
+    /// Publish messages of the given kind (e.g. `accountUpdate`) with the
+    /// given routing key
+    RenameRoutingKey {
+        kind: String,
+        #[serde(rename = "routingKey")]
+        routing_key: String,
+    },
+    /// Publish only the given fraction of messages of the given kinds, or of
+    /// all messages if none are listed
+    Sample {
+        rate: f64,
+        #[serde(default)]
+        kinds: HashSet<String>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Control {
+    /// Maximum number of subscriptions active at once
+    #[serde(default = "Control::default_max_subscriptions")]
+    pub max_subscriptions: usize,
+
+    /// Upper bound on the TTL requested by a subscription
+    #[serde(default = "Control::default_max_ttl_secs")]
+    pub max_ttl_secs: u64,
+}
+
+impl Control {
+    fn default_max_subscriptions() -> usize {
+        64
+    }
+
+    fn default_max_ttl_secs() -> u64 {
+        3600
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Stats {
+    /// How often to publish a health summary to the stats exchange
+    #[serde(default = "Stats::default_interval_secs")]
+    pub interval_secs: u64,
+}
+
+impl Stats {
+    fn default_interval_secs() -> u64 {
+        60
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct TokenShards {
+    /// Number of leading bytes of the mint used to form the routing key of
+    /// token account updates published to the token account exchange
+    #[serde(default = "TokenShards::default_bytes")]
+    pub bytes: usize,
+}
+
+impl TokenShards {
+    fn default_bytes() -> usize {
+        2
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Readiness {
+    /// Name of the consumer queue which must have a consumer before startup
+    /// updates are published
+    #[serde(default = "Readiness::default_consumer")]
+    pub consumer: String,
+
+    /// Maximum time to wait for a consumer, after which startup proceeds
+    /// anyway
+    #[serde(default = "Readiness::default_timeout_secs")]
+    pub timeout_secs: u64,
+
+    #[serde(default = "Readiness::default_poll_ms")]
+    pub poll_ms: u64,
+}
+
+impl Readiness {
+    fn default_consumer() -> String {
+        "indexer".into()
+    }
+
+    fn default_timeout_secs() -> u64 {
+        300
+    }
+
+    fn default_poll_ms() -> u64 {
+        1000
+    }
+}
+
+/// Verification, before a validator configured to publish startup updates
+/// loads its snapshot, that a consumer queue is there to receive them
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct StartupCheck {
+    /// Skip the check, publishing startup updates whether or not anyone
+    /// will receive them
+    #[serde(default)]
+    pub disabled: bool,
+
+    /// Name of the consumer queue which must be bound to the startup
+    /// exchange
+    #[serde(default = "Readiness::default_consumer")]
+    pub consumer: String,
+
+    /// Maximum number of messages the queue may already hold
+    #[serde(default = "StartupCheck::default_max_backlog")]
+    pub max_backlog: u32,
+}
+
+impl Default for StartupCheck {
+    fn default() -> Self {
+        Self {
+            disabled: false,
+            consumer: Readiness::default_consumer(),
+            max_backlog: Self::default_max_backlog(),
+        }
+    }
+}
+
+impl StartupCheck {
+    fn default_max_backlog() -> u32 {
+        100_000
+    }
+}
+
+/// Evaluation of account selection on the runtime rather than in the
+/// validator's callback, for owners whose selection decodes account data
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Offload {
+    /// Average time, in microseconds, inline selection may take before
+    /// selection of data-inspecting owners is moved off the callback
+    #[serde(default = "Offload::default_budget_us")]
+    pub budget_us: u64,
+}
+
+impl Offload {
+    fn default_budget_us() -> u64 {
+        20
+    }
+}
+
+/// Detection of accounts deleted while the validator was offline.  Accounts
+/// published by the previous run but absent from the startup snapshot are
+/// published as deletions, including accounts which merely stopped matching
+/// the selector after a configuration change.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Tombstones {
+    /// File to keep the index of published accounts in between runs
+    pub index_path: PathBuf,
+}
+
+/// Publication of each slot's confirmed and rooted statuses only after every
+/// account and instruction message of the slot
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct SlotBarrier {
+    /// Maximum time, in milliseconds, to hold a status while its slot's
+    /// messages are published before publishing it anyway
+    #[serde(default = "SlotBarrier::default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+impl SlotBarrier {
+    fn default_timeout_ms() -> u64 {
+        10_000
+    }
+}
+
+/// Switches for experimental subsystems, all off by default.  A subsystem
+/// whose switch is off is not started even if its block is configured.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Features {
+    /// Enrich outgoing messages as configured by `mintInfo`
+    #[serde(default)]
+    pub enrichment: bool,
+
+    /// Coalesce account updates as configured by `coalesce`
+    #[serde(default)]
+    pub coalescing: bool,
+
+    /// Publish account diffs as configured by `diff`
+    #[serde(default)]
+    pub diffs: bool,
+
+    /// Elect a publishing leader as configured by `leader`
+    #[serde(default)]
+    pub leader_election: bool,
+
+    /// Offload account updates to worker lanes as configured by `offload`
+    #[serde(default)]
+    pub offload: bool,
+
+    /// Publish account tombstones as configured by `tombstones`
+    #[serde(default)]
+    pub tombstones: bool,
+
+    /// Record traffic on request as configured by `recording`
+    #[serde(default)]
+    pub recording: bool,
+
+    /// Serve control-exchange subscriptions as configured by `control`
+    #[serde(default)]
+    pub subscriptions: bool,
+
+    /// Mirror messages to other exchanges as configured by `amqp.migration`
+    /// and `amqp.secondary`
+    #[serde(default)]
+    pub mirroring: bool,
+}
+
+impl Features {
+    /// Names of the enabled features
+    pub fn enabled(self) -> Vec<String> {
+        [
+            ("enrichment", self.enrichment),
+            ("coalescing", self.coalescing),
+            ("diffs", self.diffs),
+            ("leaderElection", self.leader_election),
+            ("offload", self.offload),
+            ("tombstones", self.tombstones),
+            ("recording", self.recording),
+            ("subscriptions", self.subscriptions),
+            ("mirroring", self.mirroring),
+        ]
+        .into_iter()
+        .filter_map(|(name, on)| on.then(|| name.to_owned()))
+        .collect()
+    }
+
+    /// Discard the configuration block of a disabled feature
+    fn gate<T>(on: bool, feature: &str, key: &str, conf: Option<T>) -> Option<T> {
+        if conf.is_some() && !on {
+            warn!(
+                "Ignoring {:?} because the {:?} feature is disabled",
+                key, feature
+            );
+
+            return None;
+        }
+
+        conf
+    }
+}
+
+/// Limits and location of recordings started with a control request.
+/// Recording requires `control` to be configured.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Recording {
+    /// Directory to write recordings to
+    pub dir: PathBuf,
+
+    /// Upper bound on the duration requested for a recording
+    #[serde(default = "Recording::default_max_duration_secs")]
+    pub max_duration_secs: u64,
+
+    /// Upper bound on the size requested for a recording, in bytes
+    #[serde(default = "Recording::default_max_bytes")]
+    pub max_bytes: u64,
+}
+
+impl Recording {
+    fn default_max_duration_secs() -> u64 {
+        300
+    }
+
+    fn default_max_bytes() -> u64 {
+        1 << 30
+    }
+}
+
+/// Per-slot digests of the activity of a set of wallets, computed from
+/// successful transactions
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct WalletDigest {
+    /// Base58-encoded addresses of the wallets to summarize
+    pub wallets: Vec<String>,
+}
+
+/// Sampled logging of selector decisions.  The sampling interval can be
+/// changed at runtime with a control request.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Trace {
+    /// Log one in this many account and instruction decisions.  Defaults to
+    /// 0, disabling tracing.
+    #[serde(default)]
+    pub sample_every: u64,
+}
+
+/// Limits on the time spent inside each validator callback
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct LatencyBudget {
+    /// Maximum time, in microseconds, a single callback should take
+    #[serde(default = "LatencyBudget::default_budget_us")]
+    pub budget_us: u64,
+
+    /// Number of consecutive callbacks over budget after which expensive
+    /// features are disabled
+    #[serde(default = "LatencyBudget::default_strikes")]
+    pub strikes: u32,
+
+    /// Time after which disabled features are re-enabled
+    #[serde(default = "LatencyBudget::default_recover_secs")]
+    pub recover_secs: u64,
+}
+
+impl LatencyBudget {
+    fn default_budget_us() -> u64 {
+        200
+    }
+
+    fn default_strikes() -> u32 {
+        50
+    }
+
+    fn default_recover_secs() -> u64 {
+        60
+    }
+}
+
+/// Global cap on the approximate memory held by messages accepted but not
+/// yet published.  Account updates are shed once usage reaches
+/// `accountsPct` percent of the cap; instructions, signatures, and sales
+/// once it reaches the cap.  Slot statuses are never shed.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Memory {
+    /// Maximum memory, in bytes, to hold for unpublished messages
+    pub cap_bytes: u64,
+
+    /// Percentage of the cap beyond which account updates are shed
+    #[serde(default = "Memory::default_accounts_pct")]
+    pub accounts_pct: u8,
+}
+
+impl Memory {
+    fn default_accounts_pct() -> u8 {
+        80
+    }
+}
+
+/// Publication of only the first selected update of each account, for
+/// consumers discovering new accounts rather than tracking their state
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct FirstWrite {
+    /// Approximate number of accounts remembered.  Accounts forgotten once
+    /// the set is full are published again when next updated.
+    #[serde(default = "FirstWrite::default_capacity")]
+    pub capacity: usize,
+
+    /// File to keep the set of published accounts in between runs.  If
+    /// unset, each account is published once per run.
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+
+    /// Interval between checkpoints of the set to `path`
+    #[serde(default = "FirstWrite::default_checkpoint_secs")]
+    pub checkpoint_secs: u64,
+}
+
+/// Reporting of selector entries which never matched anything, so stale
+/// entries can be pruned.  Unmatched `owners`, `pubkeys`, and `programs`
+/// entries are logged and counted in gauges at the end of each window,
+/// cumulatively since the plugin was loaded.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+#[allow(clippy::module_name_repetitions)]
+pub struct ConfigUsage {
+    /// Interval between reports
+    #[serde(default = "ConfigUsage::default_window_secs")]
+    pub window_secs: u64,
+}
+
+impl ConfigUsage {
+    fn default_window_secs() -> u64 {
+        86_400
+    }
+}
+
+impl FirstWrite {
+    fn default_capacity() -> usize {
+        1_000_000
+    }
+
+    fn default_checkpoint_secs() -> u64 {
+        60
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Rpc {
+    /// Address of the validator's RPC service
+    #[serde(default = "Rpc::default_url")]
+    pub url: String,
+
+    /// Timeout for RPC requests
+    #[serde(default = "Rpc::default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+impl Default for Rpc {
+    fn default() -> Self {
+        Self {
+            url: Self::default_url(),
+            timeout_ms: Self::default_timeout_ms(),
+        }
+    }
+}
+
+impl Rpc {
+    fn default_url() -> String {
+        "http://127.0.0.1:8899".into()
+    }
+
+    fn default_timeout_ms() -> u64 {
+        10_000
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Snapshots {
+    /// Sender name of the snapshot request exchange to receive requests on,
+    /// e.g. `my-sender` for `my-sender.snapshots`
+    pub sender: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct MintInfo {
+    /// Also attach the raw Metaplex metadata account of each mint
+    #[serde(default)]
+    pub metadata: bool,
+
+    /// Approximate maximum number of mints to remember
+    #[serde(default = "MintInfo::default_capacity")]
+    pub capacity: usize,
+
+    /// Cached mint info older than this is refetched
+    #[serde(default = "MintInfo::default_ttl_secs")]
+    pub ttl_secs: u64,
+
+    /// Mints which could not be fetched, or which do not exist, are not
+    /// requested again for this long
+    #[serde(default = "MintInfo::default_negative_ttl_secs")]
+    pub negative_ttl_secs: u64,
+
+    /// Updates are published without mint details if they can't be fetched
+    /// within this time
+    #[serde(default = "MintInfo::default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+impl MintInfo {
+    fn default_capacity() -> usize {
+        100_000
+    }
+
+    fn default_ttl_secs() -> u64 {
+        300
+    }
+
+    fn default_negative_ttl_secs() -> u64 {
+        10
+    }
+
+    fn default_timeout_ms() -> u64 {
+        2_000
+    }
+}
+
+#[derive(Debug)]
+pub struct Parts {
+    pub amqp: Amqp,
+    pub jobs: Jobs,
+    pub metrics: Metrics,
+    pub coalesce: Option<Coalesce>,
+    pub watermark: Option<Watermark>,
+    pub leader: Option<Leader>,
+    pub account_cache: Option<AccountCache>,
+    pub token_registry: TokenRegistry,
+    pub http: Http,
+    pub publish: Publish,
+    pub retention: Option<Retention>,
+    pub diff: Option<Diff>,
+    pub rpc: Rpc,
+    pub snapshots: Option<Snapshots>,
+    pub mint_info: Option<MintInfo>,
+    pub scrub: Vec<Scrub>,
+    pub middleware: Vec<Middleware>,
+    pub control: Option<Control>,
+    pub stats: Option<Stats>,
+    pub token_shards: Option<TokenShards>,
+    pub readiness: Option<Readiness>,
+    pub offload: Option<Offload>,
+    pub latency_budget: Option<LatencyBudget>,
+    pub tombstones: Option<Tombstones>,
+    pub trace: Trace,
+    pub slot_barrier: Option<SlotBarrier>,
+    pub wallet_digest: Option<WalletDigest>,
+    pub recording: Option<Recording>,
+    pub features: Features,
+    pub startup_check: StartupCheck,
+    pub memory: Option<Memory>,
+    pub first_write: Option<FirstWrite>,
+    pub config_usage: Option<ConfigUsage>,
+    pub deprecations: Vec<Deprecation>,
+    pub acct_sel: AccountSelector,
+    pub ins_sel: InstructionSelector,
+}
+
+/// Top-level keys an included file may contain
+const INCLUDE_KEYS: &[&str] = &["accounts", "instructions", "include"];
+
+/// Read a config file as JSON, recursively merging in the selector blocks of
+/// any files it includes and migrating deprecated fields of each into
+/// `deprecations`.  `stack` holds the canonical paths of the files currently
+/// being read, to detect include cycles.
+fn read_value(
+    path: &Path,
+    stack: &mut Vec<PathBuf>,
+    deprecations: &mut Vec<Deprecation>,
+) -> Result<serde_json::Value> {
+    let canon = path
+        .canonicalize()
+        .with_context(|| format!("Failed to resolve config file {:?}", path))?;
+
+    if let Some(pos) = stack.iter().position(|p| *p == canon) {
+        let cycle = stack[pos..]
+            .iter()
+            .chain(std::iter::once(&canon))
+            .map(|p| p.display().to_string())
+            .collect::<Vec<_>>()
+            .join(" -> ");
+
+        bail!("Config include cycle detected: {}", cycle);
+    }
+
+    let f = std::fs::File::open(&canon)
+        .with_context(|| format!("Failed to open config file {:?}", path))?;
+    let mut value: serde_json::Value = serde_json::from_reader(std::io::BufReader::new(f))
+        .with_context(|| format!("Failed to parse config file {:?}", path))?;
+
+    deprecations.extend(migrate::migrate(&mut value, path)?);
+
+    let obj = value
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("Config file {:?} is not a JSON object", path))?;
+
+    if !stack.is_empty() {
+        if let Some(key) = obj.keys().find(|k| !INCLUDE_KEYS.contains(&k.as_str())) {
+            bail!(
+                "Included config file {:?} contains {:?}, only selector blocks may be included",
+                path,
+                key
+            );
+        }
+
+        // Validate fragments on their own so errors name the offending file
+        if let Some(accts) = obj.get("accounts") {
+            Accounts::deserialize(accts)
+                .with_context(|| format!("Invalid accounts block in {:?}", path))?;
+        }
+
+        if let Some(ins) = obj.get("instructions") {
+            Instructions::deserialize(ins)
+                .with_context(|| format!("Invalid instructions block in {:?}", path))?;
+        }
+    }
+
+    let includes: Vec<PathBuf> = match obj.get("include") {
+        Some(i) => {
+            Vec::deserialize(i).with_context(|| format!("Invalid include list in {:?}", path))?
+        },
+        None => vec![],
+    };
+
+    if includes.is_empty() {
+        return Ok(value);
+    }
+
+    let dir = canon.parent().unwrap_or_else(|| Path::new("."));
+    stack.push(canon.clone());
+
+    for include in includes {
+        let file = dir.join(include);
+        let mut fragment = read_value(&file, stack, deprecations)
+            .with_context(|| format!("Failed to include config file from {:?}", path))?;
+
+        if let Some(obj) = fragment.as_object_mut() {
+            obj.remove("include");
+        }
+
+        merge(&mut value, fragment, "").with_context(|| {
+            format!("Included config file {:?} conflicts with {:?}", file, path)
+        })?;
+    }
+
+    stack.pop();
+
+    Ok(value)
+}
+
+/// Merge `other` into `base`, taking the union of arrays.  Any other value
+/// set by both must be equal, so an include never silently changes a setting
+/// of the file including it.
+fn merge(base: &mut serde_json::Value, other: serde_json::Value, at: &str) -> Result<()> {
+    use serde_json::Value;
+
+    match (base, other) {
+        (Value::Object(base), Value::Object(other)) => {
+            for (key, val) in other {
+                let path = if at.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", at, key)
+                };
+
+                match base.get_mut(&key) {
+                    Some(b) => merge(b, val, &path)?,
+                    None => {
+                        base.insert(key, val);
+                    },
+                }
+            }
+        },
+        (Value::Array(base), Value::Array(other)) => {
+            let mut seen: HashSet<String> = base.iter().map(Value::to_string).collect();
+
+            base.extend(other.into_iter().filter(|v| seen.insert(v.to_string())));
+        },
+        (base, other) => {
+            if *base != other {
+                bail!("{} is set to both {} and {}", at, base, other);
+            }
+        },
+    }
+
+    Ok(())
+}
+
+impl Config {
+    pub fn read(path: &str) -> Result<Self> {
+        let mut deprecations = vec![];
+        let value = read_value(Path::new(path), &mut vec![], &mut deprecations)?;
+        let cfg: Self = serde_json::from_value(value).context("Failed to parse config file")?;
+
+        Ok(Self {
+            deprecations,
+            ..cfg
+        })
+    }
+
+    #[allow(clippy::too_many_lines)]
+    pub fn into_parts(self) -> Result<Parts> {
+        let Self {
+            amqp,
+            jobs,
+            metrics,
+            coalesce,
+            watermark,
+            leader,
+            account_cache,
+            token_registry,
+            http,
+            publish,
+            retention,
+            diff,
+            rpc,
+            snapshots,
+            mint_info,
+            scrub,
+            middleware,
+            control,
+            stats,
+            token_shards,
+            readiness,
+            offload,
+            latency_budget,
+            tombstones,
+            trace,
+            slot_barrier,
+            wallet_digest,
+            recording,
+            features,
+            startup_check,
+            memory,
+            first_write,
+            config_usage,
+            override_guardrails,
+            accounts,
+            instructions,
+            include: _,
+            libpath: _,
+            deprecations,
+        } = self;
+
+        guardrails::check(&amqp, &accounts, &instructions, override_guardrails)?;
+
+        let mut amqp = amqp;
+        amqp.owner_encodings = amqp
+            .owner_encodings
+            .into_iter()
+            .map(|(owner, encoding)| {
+                let owner = owner
+                    .parse::<Pubkey>()
+                    .with_context(|| format!("Invalid owner {:?} in ownerEncodings", owner))?;
+
+                Ok((owner.to_string(), encoding))
+            })
+            .collect::<Result<_>>()?;
+
+        let coalesce = Features::gate(features.coalescing, "coalescing", "coalesce", coalesce);
+        let mint_info = Features::gate(features.enrichment, "enrichment", "mintInfo", mint_info);
+        let diff = Features::gate(features.diffs, "diffs", "diff", diff);
+        let leader = Features::gate(features.leader_election, "leaderElection", "leader", leader);
+        let offload = Features::gate(features.offload, "offload", "offload", offload);
+        let tombstones =
+            Features::gate(features.tombstones, "tombstones", "tombstones", tombstones);
+        let recording = Features::gate(features.recording, "recording", "recording", recording);
+        let control = Features::gate(features.subscriptions, "subscriptions", "control", control);
+        amqp.migration = Features::gate(
+            features.mirroring,
+            "mirroring",
+            "amqp.migration",
+            amqp.migration.take(),
+        );
+        amqp.secondary = Features::gate(
+            features.mirroring,
+            "mirroring",
+            "amqp.secondary",
+            amqp.secondary.take(),
+        );
+
+        let acct =
+            AccountSelector::from_config(accounts).context("Failed to create account selector")?;
+        let ins = InstructionSelector::from_config(instructions)
+            .context("Failed to create instruction selector")?;
+
+        Ok(Parts {
+            amqp,
+            jobs,
+            metrics,
+            coalesce,
+            watermark,
+            leader,
+            account_cache,
+            token_registry,
+            http,
+            publish,
+            retention,
+            diff,
+            rpc,
+            snapshots,
+            mint_info,
+            scrub,
+            middleware,
+            control,
+            stats,
+            token_shards,
+            readiness,
+            offload,
+            latency_budget,
+            tombstones,
+            trace,
+            slot_barrier,
+            wallet_digest,
+            recording,
+            features,
+            startup_check,
+            memory,
+            first_write,
+            config_usage,
+            deprecations,
+            acct_sel: acct,
+            ins_sel: ins,
+        })
     }
 }
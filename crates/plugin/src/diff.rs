@@ -0,0 +1,185 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use hashbrown::{HashMap, HashSet};
+use indexer_rabbitmq::geyser::{AccountDiff, AccountUpdate, DataPatch, ProducerId};
+use parking_lot::Mutex;
+use solana_program::hash::hashv;
+
+use crate::{config, prelude::*};
+
+/// Runs of unchanged bytes shorter than this are folded into the surrounding
+/// patches to save per-patch overhead
+const MERGE_GAP: usize = 16;
+
+#[derive(Debug)]
+struct Chain {
+    id: u64,
+    version: u64,
+    data: Vec<u8>,
+}
+
+/// Producer of byte-level deltas for large tracked accounts
+#[derive(Debug)]
+pub struct Differ {
+    accounts: HashSet<Pubkey>,
+    keyframe_interval: u64,
+    epoch: String,
+    chains: Mutex<HashMap<Pubkey, Chain>>,
+    next_chain: AtomicU64,
+}
+
+/// Compute the patches required to turn `old` into `new`, which must be of
+/// equal length
+fn patches(old: &[u8], new: &[u8]) -> Vec<DataPatch> {
+    debug_assert_eq!(old.len(), new.len());
+
+    let mut patches: Vec<DataPatch> = vec![];
+    // End of the last patch pushed
+    let mut end = None;
+    let mut i = 0;
+
+    while i < new.len() {
+        if old[i] == new[i] {
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        while i < new.len() && old[i] != new[i] {
+            i += 1;
+        }
+
+        match (patches.last_mut(), end) {
+            (Some(last), Some(e)) if start - e < MERGE_GAP => {
+                last.bytes.extend_from_slice(&new[e..i]);
+            },
+            _ => patches.push(DataPatch {
+                offset: start as u64,
+                bytes: new[start..i].to_vec(),
+            }),
+        }
+
+        end = Some(i);
+    }
+
+    patches
+}
+
+impl Differ {
+    pub fn new(config: config::Diff, producer_id: &ProducerId) -> Result<Self> {
+        let config::Diff {
+            accounts,
+            keyframe_interval,
+        } = config;
+
+        let accounts = accounts
+            .into_iter()
+            .map(|s| s.parse())
+            .collect::<StdResult<_, _>>()
+            .context("Failed to parse diff account list")?;
+
+        Ok(Self {
+            accounts,
+            keyframe_interval: keyframe_interval.max(1),
+            epoch: producer_id.epoch.clone(),
+            chains: Mutex::new(HashMap::new()),
+            next_chain: AtomicU64::new(0),
+        })
+    }
+
+    #[inline]
+    pub fn tracks(&self, key: &Pubkey) -> bool {
+        self.accounts.contains(key)
+    }
+
+    fn new_chain_id(&self, key: &Pubkey) -> u64 {
+        let n = self.next_chain.fetch_add(1, Ordering::Relaxed);
+
+        // Mix in the producer epoch so chains never collide across restarts
+        let hash = hashv(&[self.epoch.as_bytes(), key.as_ref(), &n.to_le_bytes()]);
+        let mut id = [0_u8; 8];
+        id.copy_from_slice(&hash.as_ref()[..8]);
+
+        u64::from_le_bytes(id)
+    }
+
+    /// Discard the delta chain of an account, so its next update is published
+    /// as a keyframe.  Called when a delta fails to reach the broker, since
+    /// consumers cannot apply later deltas past the missing one, and when a
+    /// snapshot of the account is published in full.
+    pub fn reset(&self, key: &Pubkey) {
+        self.chains.lock().remove(key);
+    }
+
+    /// Convert an update for a tracked account into a delta against the last
+    /// delta produced for it, or a keyframe if a new chain must be started
+    pub fn diff(&self, update: AccountUpdate) -> AccountDiff {
+        let AccountUpdate {
+            key,
+            lamports,
+            owner,
+            executable,
+            rent_epoch,
+            data,
+            write_version,
+            slot,
+            is_startup,
+            on_demand: _,
+            mint_info: _,
+            data_hash: _,
+        } = update;
+
+        let mut chains = self.chains.lock();
+
+        let delta = chains.get_mut(&key).and_then(|chain| {
+            if chain.version + 1 >= self.keyframe_interval || chain.data.len() != data.len() {
+                return None;
+            }
+
+            let patches = patches(&chain.data, &data);
+
+            // Don't bother with deltas that aren't much smaller than the data
+            if patches.iter().map(|p| p.bytes.len()).sum::<usize>() > data.len() / 2 {
+                return None;
+            }
+
+            chain.version += 1;
+            chain.data.copy_from_slice(&data);
+
+            Some((chain.id, chain.version, patches))
+        });
+
+        let (chain, version, patches) = delta.unwrap_or_else(|| {
+            let id = self.new_chain_id(&key);
+            let patches = vec![DataPatch {
+                offset: 0,
+                bytes: data.clone(),
+            }];
+
+            chains.insert(
+                key,
+                Chain {
+                    id,
+                    version: 0,
+                    data,
+                },
+            );
+
+            (id, 0, patches)
+        });
+
+        AccountDiff {
+            key,
+            lamports,
+            owner,
+            executable,
+            rent_epoch,
+            write_version,
+            slot,
+            is_startup,
+            chain,
+            version,
+            patches,
+        }
+    }
+}
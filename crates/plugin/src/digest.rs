@@ -0,0 +1,190 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use hashbrown::{HashMap, HashSet};
+use indexer_rabbitmq::geyser::{TokenDelta, WalletDigest};
+use parking_lot::Mutex;
+use solana_program::message::AccountKeys;
+use solana_transaction_status::TransactionStatusMeta;
+
+use crate::{config, prelude::*};
+
+#[derive(Debug, Default)]
+struct Activity {
+    txn_count: u64,
+    lamport_delta: i64,
+    /// Total (received, sent) per mint
+    tokens: BTreeMap<Pubkey, (u64, u64)>,
+    programs: BTreeSet<Pubkey>,
+}
+
+/// Per-slot accumulator of the activity of a configured set of wallets
+#[derive(Debug)]
+#[allow(clippy::module_name_repetitions)]
+pub struct DigestTracker {
+    wallets: HashSet<Pubkey>,
+    /// Base58 forms of `wallets`, for matching token balance owners
+    owners: HashSet<String>,
+    slots: Mutex<BTreeMap<u64, HashMap<Pubkey, Activity>>>,
+}
+
+/// Parse the raw amount of a token balance, treating malformed amounts as 0
+fn token_amount(amount: &str) -> u64 {
+    amount.parse().unwrap_or(0)
+}
+
+impl DigestTracker {
+    pub fn new(config: config::WalletDigest) -> Result<Self> {
+        let wallets: HashSet<Pubkey> = config
+            .wallets
+            .into_iter()
+            .map(|s| s.parse())
+            .collect::<StdResult<_, _>>()
+            .context("Failed to parse digest wallet list")?;
+        let owners = wallets.iter().map(ToString::to_string).collect();
+
+        Ok(Self {
+            wallets,
+            owners,
+            slots: Mutex::default(),
+        })
+    }
+
+    /// Returns true if any tracked wallet is among the given account keys or
+    /// owns a token account whose balance is recorded in the given metadata
+    pub fn involves(&self, keys: &AccountKeys, meta: &TransactionStatusMeta) -> bool {
+        keys.iter().any(|k| self.wallets.contains(k))
+            || meta
+                .pre_token_balances
+                .iter()
+                .chain(&meta.post_token_balances)
+                .flatten()
+                .any(|b| self.owners.contains(&b.owner))
+    }
+
+    /// Record the balance changes of a successful transaction involving any
+    /// tracked wallet
+    pub fn record(
+        &self,
+        slot: u64,
+        keys: &AccountKeys,
+        meta: &TransactionStatusMeta,
+        programs: &[Pubkey],
+    ) {
+        let mut slots = self.slots.lock();
+        let wallets = slots.entry(slot).or_default();
+        let mut touched = HashSet::new();
+
+        for (i, key) in keys.iter().enumerate() {
+            if !self.wallets.contains(key) {
+                continue;
+            }
+
+            let pre = meta.pre_balances.get(i).copied().unwrap_or(0);
+            let post = meta.post_balances.get(i).copied().unwrap_or(0);
+            let delta = i128::from(post) - i128::from(pre);
+            let activity = wallets.entry(*key).or_default();
+
+            activity.lamport_delta = activity
+                .lamport_delta
+                .saturating_add(delta.try_into().unwrap_or(0));
+            touched.insert(*key);
+        }
+
+        // Token account balances keyed by account index, as (owner, mint,
+        // pre, post)
+        let mut balances: BTreeMap<u8, (&str, &str, u64, u64)> = BTreeMap::new();
+
+        for bal in meta.pre_token_balances.iter().flatten() {
+            balances.insert(
+                bal.account_index,
+                (
+                    bal.owner.as_str(),
+                    bal.mint.as_str(),
+                    token_amount(&bal.ui_token_amount.amount),
+                    0,
+                ),
+            );
+        }
+
+        for bal in meta.post_token_balances.iter().flatten() {
+            let entry = balances.entry(bal.account_index).or_insert((
+                bal.owner.as_str(),
+                bal.mint.as_str(),
+                0,
+                0,
+            ));
+            entry.3 = token_amount(&bal.ui_token_amount.amount);
+        }
+
+        for (owner, mint, pre, post) in balances.into_values() {
+            if pre == post {
+                continue;
+            }
+
+            if !self.owners.contains(owner) {
+                continue;
+            }
+
+            let (owner, mint) = match (owner.parse::<Pubkey>(), mint.parse::<Pubkey>()) {
+                (Ok(o), Ok(m)) => (o, m),
+                _ => continue,
+            };
+
+            let (received, sent) = wallets
+                .entry(owner)
+                .or_default()
+                .tokens
+                .entry(mint)
+                .or_default();
+
+            if post > pre {
+                *received = received.saturating_add(post - pre);
+            } else {
+                *sent = sent.saturating_add(pre - post);
+            }
+
+            touched.insert(owner);
+        }
+
+        for wallet in touched {
+            let activity = wallets.entry(wallet).or_default();
+
+            activity.txn_count += 1;
+            activity.programs.extend(programs.iter().copied());
+        }
+    }
+
+    /// Summarize and discard the activity for every slot up to and including
+    /// the given slot
+    pub fn flush(&self, slot: u64) -> Vec<WalletDigest> {
+        let done = {
+            let mut slots = self.slots.lock();
+            let rest = slots.split_off(&(slot + 1));
+
+            std::mem::replace(&mut *slots, rest)
+        };
+
+        done.into_iter()
+            .flat_map(|(slot, wallets)| {
+                wallets
+                    .into_iter()
+                    .map(move |(wallet, activity)| WalletDigest {
+                        wallet,
+                        slot,
+                        txn_count: activity.txn_count,
+                        lamport_delta: activity.lamport_delta,
+                        token_deltas: activity
+                            .tokens
+                            .into_iter()
+                            .map(|(mint, (received, sent))| TokenDelta {
+                                mint,
+                                received,
+                                sent,
+                            })
+                            .collect(),
+                        programs: activity.programs.into_iter().collect(),
+                    })
+            })
+            .collect()
+    }
+}
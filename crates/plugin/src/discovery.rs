@@ -0,0 +1,119 @@
+use std::{
+    fs, io,
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use parking_lot::Mutex;
+
+use crate::{account_cache::Generations, config, prelude::*};
+
+/// Bounded, optionally persisted set of accounts already published, used to
+/// publish only the first selected update of each account.  Accounts evicted
+/// from the set once it is full are published again when next updated.
+#[derive(Debug)]
+pub struct Discovery {
+    path: Option<PathBuf>,
+    seen: Mutex<Generations<Pubkey, ()>>,
+    /// True if accounts were recorded since the last checkpoint
+    dirty: AtomicBool,
+}
+
+impl Discovery {
+    pub fn new(config: &config::FirstWrite) -> Result<Self> {
+        let mut seen = Generations::new(config.capacity);
+
+        if let Some(ref path) = config.path {
+            match fs::read(path) {
+                Ok(b) => {
+                    if b.len() % 32 != 0 {
+                        bail!("Discovered account set {:?} is truncated", path);
+                    }
+
+                    let mut n = 0_usize;
+
+                    for key in b.chunks_exact(32) {
+                        let mut arr = [0; 32];
+                        arr.copy_from_slice(key);
+                        seen.insert(Pubkey::new_from_array(arr), ());
+                        n += 1;
+                    }
+
+                    info!("Loaded {} discovered account(s) from {:?}", n, path);
+                },
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    info!("No discovered account set at {:?}, starting empty", path);
+                },
+                Err(e) => {
+                    return Err(e).with_context(|| {
+                        format!("Failed to read discovered account set {:?}", path)
+                    })
+                },
+            }
+        }
+
+        Ok(Self {
+            path: config.path.clone(),
+            seen: Mutex::new(seen),
+            dirty: AtomicBool::new(false),
+        })
+    }
+
+    /// Record an account as published, returning true if it had not been
+    /// published before
+    pub fn first_write(&self, key: Pubkey) -> bool {
+        let mut seen = self.seen.lock();
+
+        if seen.get(&key).is_some() {
+            return false;
+        }
+
+        seen.insert(key, ());
+        self.dirty.store(true, Ordering::Relaxed);
+
+        true
+    }
+
+    /// The number of accounts currently remembered
+    pub fn len(&self) -> usize {
+        self.seen.lock().len()
+    }
+
+    /// Write the set of published accounts for the next run, if it is
+    /// persisted and has changed since the last checkpoint
+    pub fn checkpoint(&self) -> Result<()> {
+        let path = match self.path {
+            Some(ref p) => p,
+            None => return Ok(()),
+        };
+
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let buf = {
+            let seen = self.seen.lock();
+            let mut buf = Vec::with_capacity(seen.len() * 32);
+
+            for key in seen.keys() {
+                buf.extend_from_slice(key.as_ref());
+            }
+
+            buf
+        };
+
+        // Write-then-rename so a crash never leaves a truncated set
+        let tmp = path.with_extension("tmp");
+        let res = fs::write(&tmp, buf)
+            .context("Failed to write discovered account set")
+            .and_then(|()| {
+                fs::rename(&tmp, path).context("Failed to replace discovered account set")
+            });
+
+        if res.is_err() {
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+
+        res
+    }
+}
@@ -0,0 +1,121 @@
+use std::{
+    sync::atomic::{AtomicI64, Ordering},
+    time::{Duration, Instant},
+};
+
+use futures_util::future::BoxFuture;
+use indexer_rabbitmq::geyser::Message;
+use log::Level;
+use solana_metrics::datapoint::DataPoint;
+
+use crate::prelude::*;
+
+/// A stage of the enrichment pipeline, able to augment messages before they
+/// are published
+///
+/// Enrichers run in the order they were added to the [`Pipeline`].  An
+/// enricher which times out is cancelled and the message is published
+/// without its changes, so implementations should only mutate the message
+/// once all of their own awaits have completed.
+pub trait Enricher: std::fmt::Debug + Send + Sync {
+    /// A short name identifying this enricher in logs and metrics
+    fn name(&self) -> &'static str;
+
+    /// Augment a message in-place
+    ///
+    /// # Errors
+    /// Implementations should fail if they could not enrich a message they
+    /// apply to.  The message is still published.
+    fn enrich<'a>(&'a self, msg: &'a mut Message) -> BoxFuture<'a, Result<()>>;
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+    runs: AtomicI64,
+    errs: AtomicI64,
+    timeouts: AtomicI64,
+    micros: AtomicI64,
+}
+
+#[derive(Debug)]
+struct Stage {
+    enricher: Box<dyn Enricher>,
+    timeout: Duration,
+    stats: Stats,
+}
+
+/// An ordered list of enrichers applied to every outgoing message
+#[derive(Debug, Default)]
+pub struct Pipeline(Vec<Stage>);
+
+impl Pipeline {
+    /// Append an enricher to the pipeline
+    pub fn push(&mut self, enricher: impl Enricher + 'static, timeout: Duration) {
+        info!(
+            "Enabling {:?} enricher with a timeout of {:?}",
+            enricher.name(),
+            timeout
+        );
+
+        self.0.push(Stage {
+            enricher: Box::new(enricher),
+            timeout,
+            stats: Stats::default(),
+        });
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Run every enricher over a message in order
+    pub async fn run(&self, msg: &mut Message) {
+        for Stage {
+            enricher,
+            timeout,
+            stats,
+        } in &self.0
+        {
+            let start = Instant::now();
+            let res = tokio::time::timeout(*timeout, enricher.enrich(msg)).await;
+
+            stats.runs.fetch_add(1, Ordering::Relaxed);
+            stats.micros.fetch_add(
+                start.elapsed().as_micros().try_into().unwrap_or(i64::MAX),
+                Ordering::Relaxed,
+            );
+
+            match res {
+                Ok(Ok(())) => (),
+                Ok(Err(e)) => {
+                    warn!("Enricher {:?} failed: {:?}", enricher.name(), e);
+                    stats.errs.fetch_add(1, Ordering::Relaxed);
+                },
+                Err(_) => {
+                    debug!("Enricher {:?} timed out", enricher.name());
+                    stats.timeouts.fetch_add(1, Ordering::Relaxed);
+                },
+            }
+        }
+    }
+
+    /// Report and reset the counters of every enricher
+    pub fn submit_metrics(&self) {
+        for Stage {
+            enricher, stats, ..
+        } in &self.0
+        {
+            solana_metrics::submit(
+                DataPoint::new("geyser_enricher")
+                    .add_field_str("name", enricher.name())
+                    .add_field_i64("runs", stats.runs.swap(0, Ordering::Relaxed))
+                    .add_field_i64("errs", stats.errs.swap(0, Ordering::Relaxed))
+                    .add_field_i64("timeouts", stats.timeouts.swap(0, Ordering::Relaxed))
+                    .add_field_i64("micros", stats.micros.swap(0, Ordering::Relaxed))
+                    .clone(),
+                Level::Info,
+            );
+        }
+    }
+}
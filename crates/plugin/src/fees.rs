@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+
+use indexer_rabbitmq::geyser::FeeStats;
+use parking_lot::Mutex;
+use solana_program::{instruction::CompiledInstruction, message::AccountKeys, pubkey};
+
+use crate::prelude::*;
+
+const COMPUTE_BUDGET_ID: Pubkey = pubkey!("ComputeBudget111111111111111111111111111111");
+/// Compute unit limit of an instruction which does not request one
+const DEFAULT_INSTRUCTION_LIMIT: u64 = 200_000;
+/// Maximum compute unit limit of a transaction
+const MAX_COMPUTE_LIMIT: u64 = 1_400_000;
+
+/// Fee data extracted from a single transaction
+#[derive(Debug, Clone, Copy)]
+pub struct Sample {
+    /// Prioritization fee in micro-lamports per compute unit
+    unit_price: u64,
+    /// Requested compute unit limit
+    compute_limit: u64,
+    /// Total fee paid, in lamports
+    fee: u64,
+}
+
+impl Sample {
+    pub fn new(instructions: &[CompiledInstruction], keys: &AccountKeys, fee: u64) -> Self {
+        let mut unit_price = 0;
+        let mut compute_limit = None;
+        let mut other = 0;
+
+        for ins in instructions {
+            if keys.get(ins.program_id_index as usize) != Some(&COMPUTE_BUDGET_ID) {
+                other += 1;
+                continue;
+            }
+
+            match ins.data.split_first() {
+                // SetComputeUnitLimit
+                Some((2, rest)) => {
+                    if let Ok(b) = rest.try_into() {
+                        compute_limit = Some(u32::from_le_bytes(b).into());
+                    }
+                },
+                // SetComputeUnitPrice
+                Some((3, rest)) => {
+                    if let Ok(b) = rest.try_into() {
+                        unit_price = u64::from_le_bytes(b);
+                    }
+                },
+                _ => (),
+            }
+        }
+
+        Self {
+            unit_price,
+            compute_limit: compute_limit
+                .unwrap_or(other * DEFAULT_INSTRUCTION_LIMIT)
+                .min(MAX_COMPUTE_LIMIT),
+            fee,
+        }
+    }
+}
+
+/// Per-slot accumulator for fee-market statistics
+#[derive(Debug, Default)]
+pub struct FeeTracker(Mutex<BTreeMap<u64, Vec<Sample>>>);
+
+impl FeeTracker {
+    pub fn record(&self, slot: u64, sample: Sample) {
+        self.0.lock().entry(slot).or_default().push(sample);
+    }
+
+    /// Summarize and discard the samples for every slot up to and including
+    /// the given slot
+    pub fn flush(&self, slot: u64) -> Vec<FeeStats> {
+        let done = {
+            let mut slots = self.0.lock();
+            let rest = slots.split_off(&(slot + 1));
+
+            std::mem::replace(&mut *slots, rest)
+        };
+
+        done.into_iter()
+            .map(|(slot, samples)| {
+                let mut prices: Vec<_> = samples.iter().map(|s| s.unit_price).collect();
+                prices.sort_unstable();
+
+                let pct = |p: usize| prices[(prices.len() - 1) * p / 100];
+
+                FeeStats {
+                    slot,
+                    txn_count: samples.len().try_into().unwrap_or(u64::MAX),
+                    unit_price_p50: pct(50),
+                    unit_price_p75: pct(75),
+                    unit_price_p90: pct(90),
+                    unit_price_p99: pct(99),
+                    unit_price_max: pct(100),
+                    total_compute_limit: samples.iter().map(|s| s.compute_limit).sum(),
+                    total_fees: samples.iter().map(|s| s.fee).sum(),
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,94 @@
+use indexer_rabbitmq::geyser::Network;
+use selector::config::{Accounts, Instructions};
+
+use crate::{config::Amqp, prelude::*};
+
+/// IDs of the spl-token and Token-2022 programs, as given in configs
+const TOKEN_PROGRAMS: [&str; 2] = [
+    "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA",
+    "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb",
+];
+
+/// A combination of settings which is almost certainly a mistake on the
+/// configured network
+#[derive(Debug)]
+struct Violation {
+    /// True if loading should be refused, false if it only warrants a warning
+    refuse: bool,
+    message: String,
+}
+
+fn violations(amqp: &Amqp, accounts: &Accounts, instructions: &Instructions) -> Vec<Violation> {
+    let mainnet = amqp.network == Network::Mainnet;
+    let mut ret = vec![];
+
+    for program in TOKEN_PROGRAMS {
+        let all_tokens = accounts
+            .token_programs
+            .get(program)
+            .map_or(accounts.all_tokens, |p| p.all_tokens);
+
+        if accounts.owners.contains(program) && all_tokens && accounts.exclude_mints.is_empty() {
+            ret.push(Violation {
+                refuse: mainnet,
+                message: format!(
+                    "allTokens selects every token account of {} on {} with no excludeMints",
+                    program, amqp.network
+                ),
+            });
+        }
+    }
+
+    let spl_token = spl_token::id().to_string();
+
+    if mainnet
+        && instructions.all_token_calls
+        && instructions.programs.contains(&spl_token)
+        && !instructions.screens.contains_key(&spl_token)
+    {
+        ret.push(Violation {
+            refuse: true,
+            message: format!(
+                "allTokenCalls selects every spl-token instruction on {}",
+                amqp.network
+            ),
+        });
+    }
+
+    ret
+}
+
+/// Check the selector configuration against the network it publishes for,
+/// refusing combinations known to overwhelm the broker unless
+/// `override_guardrails` is set
+///
+/// # Errors
+/// This function fails if any refused combination is configured and
+/// `override_guardrails` is not set.
+pub fn check(
+    amqp: &Amqp,
+    accounts: &Accounts,
+    instructions: &Instructions,
+    override_guardrails: bool,
+) -> Result<()> {
+    let mut refused = vec![];
+
+    for Violation { refuse, message } in violations(amqp, accounts, instructions) {
+        if refuse && !override_guardrails {
+            refused.push(message);
+        } else if refuse {
+            warn!("Guardrail overridden: {}", message);
+        } else {
+            warn!("Possible misconfiguration: {}", message);
+        }
+    }
+
+    if !refused.is_empty() {
+        bail!(
+            "Refusing to load configuration: {} (set overrideGuardrails to load it anyway)",
+            refused.join("; ")
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+
+use crate::{config, prelude::*};
+
+/// HTTP client shared by all outbound requests made by the plugin
+#[derive(Debug)]
+pub struct Client {
+    /// The underlying client, or `None` if outbound HTTP is disabled
+    client: Option<reqwest::Client>,
+    retries: u32,
+    backoff: Duration,
+}
+
+impl Client {
+    pub fn new(config: &config::Http) -> Result<Self> {
+        let config::Http {
+            disabled,
+            timeout_ms,
+            retries,
+            backoff_ms,
+            ref proxy,
+            ref ca_cert,
+        } = *config;
+
+        let client = if disabled {
+            None
+        } else {
+            let mut builder = reqwest::Client::builder().timeout(Duration::from_millis(timeout_ms));
+
+            if let Some(proxy) = proxy {
+                builder = builder
+                    .proxy(reqwest::Proxy::all(proxy).context("Invalid HTTP proxy address")?);
+            }
+
+            if let Some(path) = ca_cert {
+                let pem = std::fs::read(path).context("Failed to read HTTP CA certificate")?;
+
+                builder = builder.add_root_certificate(
+                    reqwest::Certificate::from_pem(&pem)
+                        .context("Failed to parse HTTP CA certificate")?,
+                );
+            }
+
+            Some(builder.build().context("Failed to create HTTP client")?)
+        };
+
+        Ok(Self {
+            client,
+            retries,
+            backoff: Duration::from_millis(backoff_ms),
+        })
+    }
+
+    /// Returns false if outbound HTTP has been disabled
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        self.client.is_some()
+    }
+
+    async fn try_get_json<T: DeserializeOwned>(client: &reqwest::Client, url: &str) -> Result<T> {
+        client
+            .get(url)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .context("HTTP request failed")?
+            .json()
+            .await
+            .context("Failed to parse response JSON")
+    }
+
+    /// Fetch and parse a JSON document, retrying with exponential backoff on
+    /// failure
+    pub async fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        let client = self
+            .client
+            .as_ref()
+            .ok_or_else(|| anyhow!("Outbound HTTP is disabled, cannot fetch {:?}", url))?;
+
+        let mut backoff = self.backoff;
+        let mut attempt = 0;
+
+        loop {
+            match Self::try_get_json(client, url).await {
+                Ok(v) => break Ok(v),
+                Err(e) if attempt < self.retries => {
+                    warn!(
+                        "Fetching {:?} failed, retrying in {:?}: {:?}",
+                        url, backoff, e
+                    );
+
+                    tokio::time::sleep(backoff).await;
+                    backoff = backoff.saturating_mul(2);
+                    attempt += 1;
+                },
+                Err(e) => break Err(e),
+            }
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use indexer_rabbitmq::lapin::{
+    options::QueueDeclareOptions, types::FieldTable, Connection, ConnectionProperties,
+};
+
+use crate::{config, metrics::Metrics, prelude::*};
+
+/// Active/standby election between redundant producers.
+///
+/// The lease is an exclusive queue on the broker: only one connection can hold
+/// it at a time, and the broker deletes it as soon as the holder's connection
+/// dies, allowing a standby to take over.
+#[derive(Debug)]
+pub struct Leader {
+    address: String,
+    lease: String,
+    retry: Duration,
+    active: AtomicBool,
+    metrics: Arc<Metrics>,
+}
+
+impl Leader {
+    pub fn new(amqp: &config::Amqp, config: config::Leader, metrics: Arc<Metrics>) -> Self {
+        let config::Leader { lease, retry_ms } = config;
+
+        Self {
+            address: amqp.address.clone(),
+            lease: lease.unwrap_or_else(|| format!("{}.geyser.leader", amqp.network)),
+            retry: Duration::from_millis(retry_ms),
+            active: AtomicBool::new(false),
+            metrics,
+        }
+    }
+
+    /// Returns true if this producer currently holds the lease
+    #[inline]
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Acquire)
+    }
+
+    fn set_active(&self, active: bool) {
+        self.active.store(active, Ordering::Release);
+        self.metrics.leader.set(active.into());
+    }
+
+    /// Attempt to acquire the lease, returning the connection holding it on
+    /// success
+    pub async fn try_acquire(&self) -> Option<Connection> {
+        let conn = match Connection::connect(
+            &self.address,
+            ConnectionProperties::default()
+                .with_connection_name(format!("{}/lease", self.lease).into())
+                .with_executor(tokio_executor_trait::Tokio::current())
+                .with_reactor(tokio_reactor_trait::Tokio),
+        )
+        .await
+        {
+            Ok(c) => c,
+            Err(e) => {
+                warn!("Failed to connect for producer lease: {:?}", e);
+                self.metrics.errs.log(1);
+                return None;
+            },
+        };
+
+        let res = async {
+            conn.create_channel()
+                .await?
+                .queue_declare(
+                    &self.lease,
+                    QueueDeclareOptions {
+                        exclusive: true,
+                        auto_delete: true,
+                        ..QueueDeclareOptions::default()
+                    },
+                    FieldTable::default(),
+                )
+                .await
+        }
+        .await;
+
+        match res {
+            Ok(_) => {
+                info!(
+                    "Acquired producer lease {:?}, publishing as leader",
+                    self.lease
+                );
+                self.set_active(true);
+
+                Some(conn)
+            },
+            Err(e) => {
+                debug!("Producer lease {:?} is held elsewhere: {:?}", self.lease, e);
+                conn.close(0, "standby").await.ok();
+
+                None
+            },
+        }
+    }
+
+    /// Hold the lease for as long as its connection lives, and keep trying to
+    /// take it over while in standby
+    pub async fn run(self: Arc<Self>, mut held: Option<Connection>) {
+        loop {
+            match held {
+                Some(ref conn) if conn.status().connected() => (),
+                Some(_) => {
+                    warn!("Lost producer lease {:?}, entering standby", self.lease);
+                    self.set_active(false);
+                    held = None;
+                },
+                None => held = self.try_acquire().await,
+            }
+
+            tokio::time::sleep(self.retry).await;
+        }
+    }
+}
@@ -22,11 +22,44 @@ pub(crate) mod prelude {
     pub type Result<T, E = Error> = StdResult<T, E>;
 }
 
+pub(crate) mod account_cache;
+pub(crate) mod barrier;
+pub(crate) mod budget;
+pub mod clock;
+#[cfg(test)]
+mod clock_tests;
+pub(crate) mod coalesce;
 pub(crate) mod config;
+pub(crate) mod diff;
+pub(crate) mod digest;
+pub(crate) mod discovery;
+pub(crate) mod enrich;
+pub(crate) mod fees;
+pub(crate) mod guardrails;
+pub(crate) mod http;
+pub(crate) mod leader;
+pub(crate) mod memory;
 pub(crate) mod metrics;
+pub(crate) mod middleware;
+pub(crate) mod migrate;
+pub(crate) mod mint_info;
+pub(crate) mod offload;
 mod plugin;
+pub(crate) mod readiness;
+pub(crate) mod record;
+pub(crate) mod rpc;
+pub(crate) mod sales;
+pub(crate) mod scrub;
 pub(crate) mod selector;
 pub(crate) mod sender;
+pub(crate) mod slot_clock;
+pub(crate) mod snapshot;
+pub(crate) mod stats;
+pub(crate) mod subscriptions;
+pub(crate) mod token_registry;
+pub(crate) mod tombstone;
+pub(crate) mod trace;
+pub(crate) mod watermark;
 
 pub(crate) use plugin::GeyserPluginRabbitMq;
 
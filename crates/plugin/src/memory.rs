@@ -0,0 +1,64 @@
+use indexer_rabbitmq::geyser::{AccountUpdate, Message, MessageClass};
+
+use crate::{config, metrics::Metrics};
+
+/// Allowance for the allocations and bookkeeping of each message beyond its
+/// variable-length fields
+const OVERHEAD: usize = 256;
+
+/// Approximate memory held by a buffered account update
+pub fn account_size(update: &AccountUpdate) -> usize {
+    OVERHEAD + update.data.len()
+}
+
+/// Approximate memory held by an outgoing message
+pub fn message_size(msg: &Message) -> usize {
+    OVERHEAD
+        + match msg {
+            Message::AccountUpdate(u) => u.data.len(),
+            Message::AccountDiff(d) => d.patches.iter().map(|p| p.bytes.len() + 16).sum(),
+            Message::InstructionNotify(i) => i.data.len() + i.accounts.len() * 32,
+            Message::SignatureNotify(s) => s.program_ids.len() * 32,
+            _ => 0,
+        }
+}
+
+/// Global cap on the approximate memory held by messages accepted but not
+/// yet published, either in flight on the runtime or buffered for
+/// coalescing.  Messages are shed in a fixed order as usage grows: account
+/// updates first, then instructions, signatures, and sales.  Slot statuses
+/// and producer lifecycle messages are small and never shed.
+#[derive(Debug)]
+#[allow(clippy::module_name_repetitions)]
+pub struct MemoryCap {
+    cap: i64,
+    accounts: i64,
+}
+
+impl MemoryCap {
+    pub fn new(config: &config::Memory) -> Self {
+        let cap = config.cap_bytes.try_into().unwrap_or(i64::MAX);
+
+        Self {
+            cap,
+            accounts: cap / 100 * i64::from(config.accounts_pct.min(100)),
+        }
+    }
+
+    #[inline]
+    pub fn cap(&self) -> i64 {
+        self.cap
+    }
+
+    /// Returns true if a message of the given class may be accepted at the
+    /// current usage
+    pub fn admits(&self, class: MessageClass, metrics: &Metrics) -> bool {
+        let used = metrics.mem_in_flight.get() + metrics.mem_coalesced.get();
+
+        match class {
+            MessageClass::AccountUpdates | MessageClass::TokenAccounts => used < self.accounts,
+            MessageClass::Instructions => used < self.cap,
+            MessageClass::SlotStatuses | MessageClass::Stats => true,
+        }
+    }
+}
@@ -1,8 +1,12 @@
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicI64, Ordering},
+    Arc,
+};
 
+use indexer_rabbitmq::geyser::{Message, MessageClass};
 use log::Level;
 use parking_lot::Mutex;
-use solana_metrics::counter::Counter as CounterInner;
+use solana_metrics::{counter::Counter as CounterInner, datapoint::DataPoint};
 
 // Despite being entirely atomic, Solana's counter still requires a mutable
 // borrow for the inc() method.  So we have to do this awful Mutex<Atomic>
@@ -38,13 +42,158 @@ impl Counter {
     pub fn log(&self, n: usize) {
         self.0.lock().inc(self.1, n);
     }
+
+    /// The total count logged since startup
+    pub fn count(&self) -> usize {
+        self.0.lock().counts.load(Ordering::Relaxed)
+    }
+}
+
+/// A point-in-time value, reported periodically by
+/// [`Metrics::submit_gauges`]
+#[derive(Debug)]
+pub struct Gauge(&'static str, AtomicI64);
+
+impl Gauge {
+    #[inline]
+    fn new(name: &'static str) -> Self {
+        Self(name, AtomicI64::new(0))
+    }
+
+    pub fn get(&self) -> i64 {
+        self.1.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, val: i64) {
+        self.1.store(val, Ordering::Relaxed);
+    }
+
+    pub fn inc(&self) {
+        self.1.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dec(&self) {
+        self.1.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn add(&self, n: usize) {
+        self.1
+            .fetch_add(n.try_into().unwrap_or(i64::MAX), Ordering::Relaxed);
+    }
+
+    pub fn sub(&self, n: usize) {
+        self.1
+            .fetch_sub(n.try_into().unwrap_or(i64::MAX), Ordering::Relaxed);
+    }
+
+    fn submit(&self) {
+        solana_metrics::submit(
+            DataPoint::new(self.0)
+                .add_field_i64("value", self.get())
+                .clone(),
+            Level::Info,
+        );
+    }
+}
+
+/// The exchange class a message is published under when exchanges are not
+/// split, for metrics purposes
+pub fn class_of(msg: &Message) -> MessageClass {
+    match msg {
+        Message::AccountUpdate(_)
+        | Message::AccountReassigned(_)
+        | Message::AccountDiff(_)
+        | Message::AccountDeleted(_)
+        | Message::AccountBalance(_) => MessageClass::AccountUpdates,
+        Message::InstructionNotify(_) | Message::SignatureNotify(_) | Message::NftSale(_) => {
+            MessageClass::Instructions
+        },
+        Message::SlotStatusUpdate(_)
+        | Message::RootedWatermark(_)
+        | Message::FeeStats(_)
+        | Message::WalletDigest(_) => MessageClass::SlotStatuses,
+        Message::ProducerStarted(_)
+        | Message::ProducerStopped(_)
+        | Message::Stats(_)
+        | Message::Unknown { .. } => MessageClass::Stats,
+    }
+}
+
+/// Running delivery totals for one message class, reported periodically as
+/// a single data point by [`Metrics::submit_gauges`].  Totals only increase,
+/// so rates can be derived from any two samples.
+#[derive(Debug)]
+#[allow(clippy::module_name_repetitions)]
+pub struct ClassMetrics {
+    name: &'static str,
+    /// Messages accepted by the broker
+    delivered: AtomicI64,
+    /// Messages discarded or refused before reaching a queue
+    dropped: AtomicI64,
+    /// Messages currently held back for later publication
+    deferred: AtomicI64,
+    /// Payload bytes accepted by the broker
+    bytes: AtomicI64,
+}
+
+impl ClassMetrics {
+    fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            delivered: AtomicI64::new(0),
+            dropped: AtomicI64::new(0),
+            deferred: AtomicI64::new(0),
+            bytes: AtomicI64::new(0),
+        }
+    }
+
+    pub fn delivered(&self, bytes: usize) {
+        self.delivered.fetch_add(1, Ordering::Relaxed);
+        self.bytes
+            .fetch_add(bytes.try_into().unwrap_or(i64::MAX), Ordering::Relaxed);
+    }
+
+    pub fn dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn set_deferred(&self, n: usize) {
+        self.deferred
+            .store(n.try_into().unwrap_or(i64::MAX), Ordering::Relaxed);
+    }
+
+    fn submit(&self) {
+        solana_metrics::submit(
+            DataPoint::new(self.name)
+                .add_field_i64("delivered", self.delivered.load(Ordering::Relaxed))
+                .add_field_i64("dropped", self.dropped.load(Ordering::Relaxed))
+                .add_field_i64("deferred", self.deferred.load(Ordering::Relaxed))
+                .add_field_i64("bytes", self.bytes.load(Ordering::Relaxed))
+                .clone(),
+            Level::Info,
+        );
+    }
 }
 
 #[derive(Debug)]
 pub struct Metrics {
     pub acct_sends: Counter,
     pub acct_recvs: Counter,
+    pub acct_coalesced: Counter,
+    pub acct_force_flushes: Counter,
+    pub acct_reassigns: Counter,
+    pub acct_rent_epoch_drops: Counter,
+    pub acct_expired: Counter,
+    pub acct_scrubs: Counter,
+    pub acct_zero_drops: Counter,
+    pub acct_elisions: Counter,
+    pub acct_offloads: Counter,
+    pub acct_tombstones: Counter,
+    pub acct_repeat_drops: Counter,
+    pub middleware_drops: Counter,
     pub ins_sends: Counter,
+    pub sig_sends: Counter,
+    pub sale_sends: Counter,
     pub txn_sends: Counter,
     pub txn_recvs: Counter,
     pub txn_errs: Counter,
@@ -52,6 +201,34 @@ pub struct Metrics {
     pub status_recvs: Counter,
     pub errs: Counter,
     pub reconnects: Counter,
+    pub standby_drops: Counter,
+    pub publish_rejects: Counter,
+    pub republishes: Counter,
+    pub unroutable: Counter,
+    pub bytes_sent: Counter,
+    pub mirror_sends: Counter,
+    pub mirror_errs: Counter,
+    pub mirror_drops: Counter,
+    pub budget_overruns: Counter,
+    pub budget_trips: Counter,
+    pub barrier_timeouts: Counter,
+    pub mem_sheds: Counter,
+    pub worker_threads: Gauge,
+    pub tasks_in_flight: Gauge,
+    pub leader: Gauge,
+    pub degraded: Gauge,
+    /// Payload bytes accepted by the broker since startup, across all classes
+    pub bytes_total: Gauge,
+    /// Approximate memory held by messages waiting to be published
+    pub mem_in_flight: Gauge,
+    /// Approximate memory held by coalescing buffers
+    pub mem_coalesced: Gauge,
+    /// Selector entries which have not matched since the plugin was loaded
+    pub unused_owners: Gauge,
+    pub unused_pubkeys: Gauge,
+    pub unused_programs: Gauge,
+    pub config_deprecations: Gauge,
+    classes: [ClassMetrics; 5],
 }
 
 impl Metrics {
@@ -59,7 +236,21 @@ impl Metrics {
         Arc::new(Self {
             acct_sends: Counter::new("geyser_acct_sends", Level::Info),
             acct_recvs: Counter::new("geyser_acct_recvs", Level::Info),
+            acct_coalesced: Counter::new("geyser_acct_coalesced", Level::Info),
+            acct_force_flushes: Counter::new("geyser_acct_force_flushes", Level::Warn),
+            acct_reassigns: Counter::new("geyser_acct_reassigns", Level::Info),
+            acct_rent_epoch_drops: Counter::new("geyser_acct_rent_epoch_drops", Level::Info),
+            acct_expired: Counter::new("geyser_acct_expired", Level::Info),
+            acct_scrubs: Counter::new("geyser_acct_scrubs", Level::Info),
+            acct_zero_drops: Counter::new("geyser_acct_zero_drops", Level::Info),
+            acct_elisions: Counter::new("geyser_acct_elisions", Level::Info),
+            acct_offloads: Counter::new("geyser_acct_offloads", Level::Info),
+            acct_tombstones: Counter::new("geyser_acct_tombstones", Level::Info),
+            acct_repeat_drops: Counter::new("geyser_acct_repeat_drops", Level::Info),
+            middleware_drops: Counter::new("geyser_middleware_drops", Level::Info),
             ins_sends: Counter::new("geyser_ins_sends", Level::Info),
+            sig_sends: Counter::new("geyser_sig_sends", Level::Info),
+            sale_sends: Counter::new("geyser_sale_sends", Level::Info),
             txn_sends: Counter::new("geyser_txn_sends", Level::Info),
             txn_recvs: Counter::new("geyser_txn_recvs", Level::Info),
             txn_errs: Counter::new("geyser_txn_errs", Level::Info),
@@ -67,6 +258,65 @@ impl Metrics {
             status_recvs: Counter::new("geyser_status_recvs", Level::Info),
             errs: Counter::new("geyser_errs", Level::Error),
             reconnects: Counter::new("geyser_reconnects", Level::Error),
+            standby_drops: Counter::new("geyser_standby_drops", Level::Info),
+            publish_rejects: Counter::new("geyser_publish_rejects", Level::Warn),
+            republishes: Counter::new("geyser_republishes", Level::Warn),
+            unroutable: Counter::new("geyser_unroutable", Level::Warn),
+            bytes_sent: Counter::new("geyser_bytes_sent", Level::Info),
+            mirror_sends: Counter::new("geyser_mirror_sends", Level::Info),
+            mirror_errs: Counter::new("geyser_mirror_errs", Level::Info),
+            mirror_drops: Counter::new("geyser_mirror_drops", Level::Info),
+            budget_overruns: Counter::new("geyser_budget_overruns", Level::Info),
+            budget_trips: Counter::new("geyser_budget_trips", Level::Warn),
+            barrier_timeouts: Counter::new("geyser_barrier_timeouts", Level::Warn),
+            mem_sheds: Counter::new("geyser_mem_sheds", Level::Warn),
+            worker_threads: Gauge::new("geyser_worker_threads"),
+            tasks_in_flight: Gauge::new("geyser_tasks_in_flight"),
+            leader: Gauge::new("geyser_leader"),
+            degraded: Gauge::new("geyser_degraded"),
+            bytes_total: Gauge::new("geyser_bytes_total"),
+            mem_in_flight: Gauge::new("geyser_mem_in_flight"),
+            mem_coalesced: Gauge::new("geyser_mem_coalesced"),
+            unused_owners: Gauge::new("geyser_unused_owners"),
+            unused_pubkeys: Gauge::new("geyser_unused_pubkeys"),
+            unused_programs: Gauge::new("geyser_unused_programs"),
+            config_deprecations: Gauge::new("geyser_config_deprecations"),
+            classes: [
+                ClassMetrics::new("geyser_class_account_updates"),
+                ClassMetrics::new("geyser_class_instructions"),
+                ClassMetrics::new("geyser_class_slot_statuses"),
+                ClassMetrics::new("geyser_class_stats"),
+                ClassMetrics::new("geyser_class_token_accounts"),
+            ],
         })
     }
+
+    pub fn submit_gauges(&self) {
+        self.worker_threads.submit();
+        self.tasks_in_flight.submit();
+        self.leader.submit();
+        self.degraded.submit();
+        self.bytes_total.submit();
+        self.mem_in_flight.submit();
+        self.mem_coalesced.submit();
+        self.unused_owners.submit();
+        self.unused_pubkeys.submit();
+        self.unused_programs.submit();
+        self.config_deprecations.submit();
+
+        for class in &self.classes {
+            class.submit();
+        }
+    }
+
+    /// Delivery totals for a message class
+    pub fn class(&self, class: MessageClass) -> &ClassMetrics {
+        &self.classes[match class {
+            MessageClass::AccountUpdates => 0,
+            MessageClass::Instructions => 1,
+            MessageClass::SlotStatuses => 2,
+            MessageClass::Stats => 3,
+            MessageClass::TokenAccounts => 4,
+        }]
+    }
 }
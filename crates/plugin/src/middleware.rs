@@ -0,0 +1,133 @@
+use hashbrown::HashSet;
+use indexer_rabbitmq::geyser::{AccountUpdate, InstructionNotify, Message};
+use serde::Deserialize;
+
+use crate::{config, prelude::*, sender::SendOpts};
+
+/// A message field which can be stripped before publishing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Field {
+    /// Account data of account updates and instruction data of instructions
+    Data,
+    /// Account inputs of instructions
+    Accounts,
+    /// Mint details attached to token account updates
+    MintInfo,
+}
+
+#[derive(Debug)]
+enum Stage {
+    TruncateData(usize),
+    DropField(Field),
+    RenameRoutingKey {
+        kind: String,
+        routing_key: String,
+    },
+    Sample {
+        threshold: u64,
+        kinds: HashSet<String>,
+    },
+}
+
+impl Stage {
+    /// Apply this stage to a message, returning false if it should be
+    /// dropped
+    fn apply(&self, msg: &mut Message, opts: &mut SendOpts) -> bool {
+        match *self {
+            Self::TruncateData(max_len) => match msg {
+                Message::AccountUpdate(AccountUpdate { data, .. })
+                | Message::InstructionNotify(InstructionNotify { data, .. }) => {
+                    data.truncate(max_len);
+                },
+                _ => (),
+            },
+            Self::DropField(field) => match (field, msg) {
+                (
+                    Field::Data,
+                    Message::AccountUpdate(AccountUpdate { data, .. })
+                    | Message::InstructionNotify(InstructionNotify { data, .. }),
+                ) => {
+                    *data = vec![];
+                },
+                (
+                    Field::Accounts,
+                    Message::InstructionNotify(InstructionNotify { accounts, .. }),
+                ) => {
+                    *accounts = vec![];
+                },
+                (Field::MintInfo, Message::AccountUpdate(AccountUpdate { mint_info, .. })) => {
+                    *mint_info = None;
+                },
+                _ => (),
+            },
+            Self::RenameRoutingKey {
+                ref kind,
+                ref routing_key,
+            } => {
+                if msg.kind() == kind.as_str() {
+                    opts.routing_key = Some(routing_key.clone());
+                }
+            },
+            Self::Sample {
+                threshold,
+                ref kinds,
+            } => {
+                if kinds.is_empty() || kinds.contains(msg.kind()) {
+                    // Sample by message ID so redundant producers keep the
+                    // same messages
+                    let hash = solana_program::hash::hash(msg.message_id().as_bytes());
+                    let mut bytes = [0_u8; 8];
+                    bytes.copy_from_slice(&hash.as_ref()[..8]);
+
+                    return u64::from_le_bytes(bytes) <= threshold;
+                }
+            },
+        }
+
+        true
+    }
+}
+
+/// An ordered chain of transformations and filters applied to outgoing
+/// messages
+#[derive(Debug, Default)]
+pub struct Chain(Vec<Stage>);
+
+impl Chain {
+    pub fn new(config: Vec<config::Middleware>) -> Result<Self> {
+        config
+            .into_iter()
+            .map(|stage| {
+                Ok(match stage {
+                    config::Middleware::TruncateData { max_len } => Stage::TruncateData(max_len),
+                    config::Middleware::DropField { field } => Stage::DropField(field),
+                    config::Middleware::RenameRoutingKey { kind, routing_key } => {
+                        Stage::RenameRoutingKey { kind, routing_key }
+                    },
+                    config::Middleware::Sample { rate, kinds } => {
+                        if !(0.0..=1.0).contains(&rate) {
+                            bail!("Sample rate {} is not between 0 and 1", rate);
+                        }
+
+                        #[allow(
+                            clippy::cast_possible_truncation,
+                            clippy::cast_precision_loss,
+                            clippy::cast_sign_loss
+                        )]
+                        let threshold = (rate * u64::MAX as f64) as u64;
+
+                        Stage::Sample { threshold, kinds }
+                    },
+                })
+            })
+            .collect::<Result<_>>()
+            .map(Self)
+    }
+
+    /// Run every stage over a message in order, returning false if the
+    /// message should not be published
+    pub fn apply(&self, msg: &mut Message, opts: &mut SendOpts) -> bool {
+        self.0.iter().all(|s| s.apply(msg, opts))
+    }
+}
@@ -0,0 +1,121 @@
+//! Migration of deprecated config fields.  Fields renamed or moved as the
+//! config schema evolves are still accepted at their old paths: each is
+//! moved to its replacement before the config is parsed, and reported so
+//! configs can be upgraded gradually across a fleet.
+
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::prelude::*;
+
+/// A deprecated field and its replacement, as paths of JSON keys
+#[derive(Debug, Clone, Copy)]
+struct Alias {
+    old: &'static [&'static str],
+    new: &'static [&'static str],
+}
+
+const ALIASES: &[Alias] = &[
+    // RPC settings were moved out of the snapshot block when mint info began
+    // sharing the RPC client
+    Alias {
+        old: &["snapshots", "rpc"],
+        new: &["rpc", "url"],
+    },
+    Alias {
+        old: &["snapshots", "timeoutMs"],
+        new: &["rpc", "timeoutMs"],
+    },
+];
+
+/// A deprecated field found in a config file
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Deprecation {
+    /// The file containing the field
+    pub file: PathBuf,
+    /// The dotted path of the deprecated field
+    pub field: String,
+    /// The dotted path of the field replacing it
+    pub replacement: String,
+}
+
+fn get<'a>(value: &'a Value, path: &[&str]) -> Option<&'a Value> {
+    path.iter().try_fold(value, Value::get)
+}
+
+fn take(value: &mut Value, path: &[&str]) -> Option<Value> {
+    let (last, parents) = path.split_last()?;
+    let mut obj = value.as_object_mut()?;
+
+    for key in parents {
+        obj = obj.get_mut(*key)?.as_object_mut()?;
+    }
+
+    obj.remove(*last)
+}
+
+fn insert(value: &mut Value, path: &[&str], field: Value) -> Result<()> {
+    let (last, parents) = path
+        .split_last()
+        .ok_or_else(|| anyhow!("Empty config field path"))?;
+    let mut obj = value
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("Config is not a JSON object"))?;
+
+    for key in parents {
+        obj = obj
+            .entry(*key)
+            .or_insert_with(|| Value::Object(Map::new()))
+            .as_object_mut()
+            .ok_or_else(|| anyhow!("Config field {:?} is not an object", key))?;
+    }
+
+    obj.insert((*last).to_owned(), field);
+
+    Ok(())
+}
+
+/// Move the deprecated fields of a config file to their replacements,
+/// returning the fields moved
+pub fn migrate(value: &mut Value, file: &Path) -> Result<Vec<Deprecation>> {
+    let mut found = vec![];
+
+    for Alias { old, new } in ALIASES {
+        let field = old.join(".");
+        let replacement = new.join(".");
+
+        if get(value, old).is_none() {
+            continue;
+        }
+
+        if get(value, new).is_some() {
+            bail!(
+                "Config file {:?} sets both {} and its replacement {}, remove {}",
+                file,
+                field,
+                replacement,
+                field
+            );
+        }
+
+        if let Some(v) = take(value, old) {
+            insert(value, new, v)?;
+        }
+
+        warn!(
+            "Config field {} in {:?} is deprecated, replace it with {}",
+            field, file, replacement
+        );
+
+        found.push(Deprecation {
+            file: file.to_owned(),
+            field,
+            replacement,
+        });
+    }
+
+    Ok(found)
+}
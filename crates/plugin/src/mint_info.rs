@@ -0,0 +1,204 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use futures_util::future::BoxFuture;
+use hashbrown::HashMap;
+use indexer_rabbitmq::geyser::{AccountUpdate, Message, MintInfo};
+use parking_lot::Mutex;
+use solana_program::{program_pack::Pack, pubkey};
+use spl_token::state::Mint;
+use tokio::sync::OnceCell;
+
+use crate::{
+    account_cache::Generations, clock::SharedClock, config, enrich::Enricher, prelude::*, rpc::Rpc,
+};
+
+const METADATA_ID: Pubkey = pubkey!("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+
+/// The outcome of fetching a mint, cached until it goes stale
+#[derive(Debug, Clone)]
+struct Entry {
+    fetched: Instant,
+    /// The mint's details, or `None` if it could not be fetched
+    info: Option<MintInfo>,
+}
+
+/// Attaches mint details fetched from the validator's RPC service to updates
+/// for token accounts
+#[derive(Debug)]
+pub struct MintEnricher {
+    rpc: Rpc,
+    metadata: bool,
+    ttl: Duration,
+    negative_ttl: Duration,
+    clock: SharedClock,
+    cache: Mutex<Generations<Pubkey, Entry>>,
+    /// Fetches in progress, shared by every update waiting on the same mint
+    in_flight: Mutex<HashMap<Pubkey, Arc<OnceCell<Option<MintInfo>>>>>,
+}
+
+impl MintEnricher {
+    pub fn new(config: &config::MintInfo, rpc: Rpc, clock: SharedClock) -> Self {
+        let config::MintInfo {
+            metadata,
+            capacity,
+            ttl_secs,
+            negative_ttl_secs,
+            ..
+        } = *config;
+
+        Self {
+            rpc,
+            metadata,
+            ttl: Duration::from_secs(ttl_secs),
+            negative_ttl: Duration::from_secs(negative_ttl_secs),
+            clock,
+            cache: Mutex::new(Generations::new(capacity)),
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the mint of an spl-token or Token-2022 account, or `None` if
+    /// the update is not for a token account
+    pub fn mint_of(update: &AccountUpdate) -> Option<Pubkey> {
+        selector::token_info(update.owner.as_ref(), &update.data).map(|t| t.mint)
+    }
+
+    /// Returns the cached outcome of fetching a mint, if it is still fresh
+    fn cached(&self, mint: &Pubkey) -> Option<Entry> {
+        let now = self.clock.now();
+        let mut cache = self.cache.lock();
+        let fresh = cache
+            .get(mint)
+            .filter(|e| {
+                let ttl = if e.info.is_some() {
+                    self.ttl
+                } else {
+                    self.negative_ttl
+                };
+
+                now.saturating_duration_since(e.fetched) < ttl
+            })
+            .cloned();
+
+        if fresh.is_none() {
+            cache.remove(mint);
+        }
+
+        fresh
+    }
+
+    async fn fetch(&self, mint: Pubkey) -> Result<Option<MintInfo>> {
+        let acct = match self.rpc.get_account(&mint).await? {
+            (_, Some(a))
+                if a.owner
+                    .parse::<Pubkey>()
+                    .map_or(false, |o| selector::is_token_program(o.as_ref())) =>
+            {
+                a
+            },
+            _ => return Ok(None),
+        };
+
+        // Token-2022 mints carry their extensions after the base layout
+        let data = acct.decode_data()?;
+        let Mint {
+            decimals, supply, ..
+        } = Mint::unpack(data.get(..Mint::LEN).unwrap_or(&data))
+            .context("Failed to parse mint account")?;
+
+        let metadata = if self.metadata {
+            let (addr, _) = Pubkey::find_program_address(
+                &[b"metadata", METADATA_ID.as_ref(), mint.as_ref()],
+                &METADATA_ID,
+            );
+
+            match self.rpc.get_account(&addr).await? {
+                (_, Some(a)) => Some(a.decode_data()?),
+                (_, None) => None,
+            }
+        } else {
+            None
+        };
+
+        Ok(Some(MintInfo {
+            mint,
+            decimals,
+            supply,
+            metadata,
+        }))
+    }
+
+    /// Attach mint details to an update if it is for a token account
+    async fn enrich_update(&self, update: &mut AccountUpdate) -> Result<()> {
+        let mint = match Self::mint_of(update) {
+            Some(m) => m,
+            None => return Ok(()),
+        };
+
+        if let Some(Entry { info, .. }) = self.cached(&mint) {
+            update.mint_info = info;
+            return Ok(());
+        }
+
+        let cell = Arc::clone(self.in_flight.lock().entry(mint).or_default());
+        let mut res = Ok(());
+
+        // Only the update that starts the fetch reports its error, the rest
+        // see the mint as missing
+        let info = cell
+            .get_or_init(|| async {
+                let info = match self.fetch(mint).await {
+                    Ok(info) => info,
+                    Err(e) => {
+                        res = Err(e);
+                        None
+                    },
+                };
+
+                self.cache.lock().insert(
+                    mint,
+                    Entry {
+                        fetched: self.clock.now(),
+                        info: info.clone(),
+                    },
+                );
+
+                info
+            })
+            .await
+            .clone();
+
+        {
+            let mut in_flight = self.in_flight.lock();
+
+            if in_flight
+                .get(&mint)
+                .map_or(false, |c| Arc::ptr_eq(c, &cell))
+            {
+                in_flight.remove(&mint);
+            }
+        }
+
+        update.mint_info = info;
+
+        res.with_context(|| format!("Failed to fetch mint info for {}", mint))
+    }
+}
+
+impl Enricher for MintEnricher {
+    fn name(&self) -> &'static str {
+        "mint_info"
+    }
+
+    fn enrich<'a>(&'a self, msg: &'a mut Message) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            match msg {
+                Message::AccountUpdate(update) => self.enrich_update(update).await,
+                _ => Ok(()),
+            }
+        })
+    }
+}
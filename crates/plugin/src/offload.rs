@@ -0,0 +1,124 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use tokio::{runtime::Handle, sync::mpsc};
+
+use crate::config;
+
+/// One in this many offloadable updates is still selected inline, so the
+/// cost estimate tracks changes in load
+const SAMPLE_EVERY: u64 = 64;
+
+/// Number of ordered queues offloaded updates are spread over
+const LANES: usize = 16;
+
+/// Tracks the cost of inline account selection for owners whose selection
+/// decodes account data, deciding when to move it off the validator's
+/// callback
+#[derive(Debug)]
+pub struct Offload {
+    budget_ns: u64,
+    /// Exponentially-weighted moving average of inline selection time
+    avg_ns: AtomicU64,
+    seen: AtomicU64,
+}
+
+impl Offload {
+    pub fn new(config: &config::Offload) -> Self {
+        Self {
+            budget_ns: config.budget_us.saturating_mul(1000),
+            avg_ns: AtomicU64::new(0),
+            seen: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns true if the next update should be selected on the runtime
+    #[inline]
+    pub fn should_offload(&self) -> bool {
+        let n = self.seen.fetch_add(1, Ordering::Relaxed);
+
+        n % SAMPLE_EVERY != 0 && self.avg_ns.load(Ordering::Relaxed) > self.budget_ns
+    }
+
+    /// Record the time taken by an inline selection
+    #[inline]
+    pub fn record(&self, elapsed: Duration) {
+        let ns = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+
+        self.avg_ns
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |avg| {
+                Some(avg - avg / 8 + ns / 8)
+            })
+            .ok();
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+#[derive(Debug)]
+struct Lane {
+    tx: mpsc::UnboundedSender<Job>,
+    queued: Arc<AtomicUsize>,
+}
+
+/// Ordered queues for work moved off the validator's callback.  Work for the
+/// same account always shares a lane, so updates to it are handled in the
+/// order the validator reported them.
+#[derive(Debug)]
+pub struct Lanes(Vec<Lane>);
+
+impl Lanes {
+    pub fn new(rt: &Handle) -> Self {
+        Self(
+            (0..LANES)
+                .map(|_| {
+                    let (tx, mut rx) = mpsc::unbounded_channel::<Job>();
+                    let queued = Arc::new(AtomicUsize::new(0));
+                    let done = Arc::clone(&queued);
+
+                    rt.spawn(async move {
+                        while let Some(job) = rx.recv().await {
+                            job();
+                            done.fetch_sub(1, Ordering::AcqRel);
+                        }
+                    });
+
+                    Lane { tx, queued }
+                })
+                .collect(),
+        )
+    }
+
+    fn lane(&self, key: &[u8]) -> &Lane {
+        let mut bytes = [0_u8; 8];
+        let len = key.len().min(8);
+        bytes[..len].copy_from_slice(&key[..len]);
+
+        #[allow(clippy::cast_possible_truncation)]
+        &self.0[(u64::from_le_bytes(bytes) % self.0.len() as u64) as usize]
+    }
+
+    /// Returns true if work for the given account is still queued, in which
+    /// case its next update must be queued behind it rather than handled
+    /// inline
+    #[inline]
+    pub fn is_busy(&self, key: &[u8]) -> bool {
+        self.lane(key).queued.load(Ordering::Acquire) > 0
+    }
+
+    /// Queue work for the given account behind any already queued for it
+    pub fn push(&self, key: &[u8], job: impl FnOnce() + Send + 'static) {
+        let lane = self.lane(key);
+
+        lane.queued.fetch_add(1, Ordering::AcqRel);
+
+        if lane.tx.send(Box::new(job)).is_err() {
+            lane.queued.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
+}
@@ -1,30 +1,73 @@
-use std::{env, sync::Arc};
+use std::{
+    env,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Weak,
+    },
+    time::{Duration, Instant},
+};
 
-use anyhow::Context;
-use hashbrown::HashSet;
-use indexer_rabbitmq::geyser::{
-    AccountUpdate, InstructionIndex, InstructionNotify, Message, SlotStatus as RmqSlotStatus,
-    SlotStatusUpdate,
+use indexer_rabbitmq::{
+    control::{Reply, Request},
+    geyser::{
+        token_shard_key, AccountBalance, AccountDeleted, AccountReassigned, AccountUpdate,
+        BuildInfo, InstructionIndex, InstructionNotify, Message, MessageClass, NftSale, ProducerId,
+        ProducerLifecycle, RootedWatermark, SignatureNotify, SlotStatus as RmqSlotStatus,
+        SlotStatusUpdate, StartupType,
+    },
 };
-use selector::{AccountSelector, InstructionSelector};
+use selector::{AccountInfo, AccountSelector, Decision, InstructionSelector, Unused};
 use solana_geyser_plugin_interface::geyser_plugin_interface::SlotStatus;
-use solana_program::{instruction::CompiledInstruction, message::AccountKeys};
+use solana_program::{hash::hash, instruction::CompiledInstruction, message::AccountKeys};
 
-use serde::Deserialize;
+use tokio::sync::mpsc;
 
 use crate::{
-    config::Config,
+    account_cache::{AccountCache, LastPublished},
+    barrier::SlotBarrier,
+    budget::Budget,
+    clock::{SharedClock, SystemClock},
+    coalesce::Coalescer,
+    config::{self, Config, Parts, ZeroData},
+    diff::Differ,
+    digest::DigestTracker,
+    discovery::Discovery,
+    enrich::Pipeline,
+    fees::{self, FeeTracker},
+    http,
     interface::{
         GeyserPlugin, GeyserPluginError, ReplicaAccountInfo, ReplicaAccountInfoVersions,
         ReplicaTransactionInfoVersions, Result,
     },
-    metrics::{Counter, Metrics},
+    leader::Leader,
+    memory::{self, MemoryCap},
+    metrics::{self, Counter, Metrics},
+    middleware::Chain,
+    migrate::Deprecation,
+    mint_info::MintEnricher,
+    offload::{Lanes, Offload},
     prelude::*,
-    selector::{AccountShim, CompiledInstructionShim},
-    sender::Sender,
+    readiness,
+    record::Recorder,
+    rpc::Rpc,
+    sales,
+    scrub::Scrubber,
+    selector::{AccountShim, CompiledInstructionShim, UpdateShim},
+    sender::{SendOpts, Sender, Sink},
+    slot_clock::SlotClock,
+    snapshot,
+    stats::{Reporter, Summarizer},
+    subscriptions::{self, Subscriptions},
+    token_registry,
+    tombstone::Tombstones,
+    trace::Tracer,
+    watermark::{PendingSlots, Watermark},
 };
 
 const UNINIT: &str = "RabbitMQ plugin not initialized yet!";
+const GAUGE_INTERVAL: Duration = Duration::from_secs(10);
+/// Nominal duration of a slot, used to estimate the age of a slot
+const SLOT_DURATION_MS: u64 = 400;
 
 #[inline]
 fn custom_err<E: Into<Box<dyn std::error::Error + Send + Sync + 'static>>>(
@@ -36,10 +79,118 @@ fn custom_err<E: Into<Box<dyn std::error::Error + Send + Sync + 'static>>>(
     }
 }
 
+/// Details of this build, embedded by the build script
+fn build_info() -> BuildInfo {
+    BuildInfo {
+        rustc: env!("META_RUSTC_VERSION").into(),
+        profile: env!("META_BUILD_PROFILE").into(),
+        target: env!("META_BUILD_TARGET").into(),
+        host: env!("META_BUILD_HOST").into(),
+        platform: env!("META_BUILD_PLATFORM").into(),
+        git_head: option_env!("META_GIT_HEAD").map(Into::into),
+        git_remote: option_env!("META_GIT_REMOTE")
+            .filter(|r| !r.is_empty())
+            .map(Into::into),
+    }
+}
+
+/// Report this build to the metrics backend so fleet audits can see which
+/// build each validator runs
+fn submit_build_info(build: &BuildInfo) {
+    let mut point = solana_metrics::datapoint::DataPoint::new("geyser_build");
+    point
+        .add_field_str("version", env!("CARGO_PKG_VERSION"))
+        .add_field_str("rustc", &build.rustc)
+        .add_field_str("profile", &build.profile)
+        .add_field_str("target", &build.target)
+        .add_field_str("host", &build.host)
+        .add_field_str("platform", &build.platform)
+        .add_field_str("git_head", build.git_head.as_deref().unwrap_or(""))
+        .add_field_str("git_remote", build.git_remote.as_deref().unwrap_or(""));
+
+    solana_metrics::submit(point, log::Level::Info);
+}
+
+/// Returns every program invoked by the given instructions, sorted and
+/// deduplicated
+fn touched_programs(
+    instructions: &[(InstructionIndex, &CompiledInstruction)],
+    keys: &AccountKeys,
+) -> Vec<Pubkey> {
+    let mut program_ids: Vec<_> = instructions
+        .iter()
+        .filter_map(|(_, ins)| keys.get(ins.program_id_index as usize).copied())
+        .collect();
+    program_ids.sort_unstable();
+    program_ids.dedup();
+
+    program_ids
+}
+
+/// An update for a diff-tracked account, queued to be diffed and published
+/// after the ones before it
+#[derive(Debug)]
+struct QueuedDiff {
+    update: AccountUpdate,
+    opts: SendOpts,
+    shard: Option<String>,
+    size: usize,
+}
+
 #[derive(Debug)]
 pub(crate) struct Inner {
     rt: tokio::runtime::Runtime,
     producer: Sender,
+    /// Dedicated sender for instructions if exchanges are split by message
+    /// class
+    ins_producer: Option<Sender>,
+    /// Sender for token account updates and the number of mint bytes to
+    /// shard them by, if they are routed by mint
+    token_producer: Option<(Sender, usize)>,
+    // Slot statuses get their own runtime and connection so they are never
+    // queued behind large account payloads
+    _status_rt: tokio::runtime::Runtime,
+    status_tx: mpsc::UnboundedSender<Message>,
+    coalesce: Option<Coalescer>,
+    acct_cache: Option<AccountCache>,
+    /// Messages in flight per slot, tracked if the watermark or slot barrier
+    /// is enabled
+    pending: Option<Arc<PendingSlots>>,
+    watermark: Option<Arc<Watermark>>,
+    lifecycle: ProducerLifecycle,
+    publish: config::Publish,
+    allowlist_priority: Option<u8>,
+    /// Maximum age of account updates, if they should expire
+    max_age: Option<Duration>,
+    /// Newest slot observed by the plugin
+    latest_slot: AtomicU64,
+    clock: SharedClock,
+    slot_clock: SlotClock,
+    fees: Option<FeeTracker>,
+    differ: Option<Differ>,
+    diff_tx: Option<mpsc::UnboundedSender<QueuedDiff>>,
+    scrubber: Scrubber,
+    offload: Option<Offload>,
+    /// Ordered queues for account updates selected off the callback
+    lanes: Lanes,
+    budget: Option<Budget>,
+    tombstones: Option<Arc<Tombstones>>,
+    discovery: Option<Discovery>,
+    /// Deprecated config fields migrated at load
+    deprecations: Vec<Deprecation>,
+    tracer: Tracer,
+    barrier: Option<Arc<SlotBarrier>>,
+    digest: Option<DigestTracker>,
+    recorder: Option<Recorder>,
+    memory: Option<MemoryCap>,
+    /// Names of the enabled experimental features
+    features: Vec<String>,
+    /// Enrichers and middleware applied to account and transaction messages.
+    /// Slot statuses bypass them so they are never delayed or dropped.
+    enrichers: Pipeline,
+    middleware: Chain,
+    subscriptions: Option<Subscriptions>,
+    stats: Option<Reporter>,
     acct_sel: AccountSelector,
     ins_sel: InstructionSelector,
     metrics: Arc<Metrics>,
@@ -50,43 +201,753 @@ impl Inner {
         self: &Arc<Self>,
         f: impl FnOnce(Arc<Self>) -> F,
     ) {
-        self.rt.spawn(f(Arc::clone(self)));
+        let fut = f(Arc::clone(self));
+        let metrics = Arc::clone(&self.metrics);
+
+        metrics.tasks_in_flight.inc();
+        self.rt.spawn(async move {
+            let res = fut.await;
+            metrics.tasks_in_flight.dec();
+            res
+        });
     }
-}
 
-/// An instance of the plugin
-#[derive(Debug, Default)]
-#[repr(transparent)]
-pub struct GeyserPluginRabbitMq(Option<Arc<Inner>>);
+    /// Run a callback on an interval for as long as the plugin is alive
+    pub fn spawn_periodic(
+        self: &Arc<Self>,
+        period: Duration,
+        f: impl Fn(&Arc<Self>) + Send + 'static,
+    ) {
+        self.spawn_periodic_after(Duration::ZERO, period, f);
+    }
 
-#[derive(Deserialize)]
-struct TokenItem {
-    address: String,
-}
+    /// Run a callback on an interval, starting after an initial delay, for as
+    /// long as the plugin is alive
+    pub fn spawn_periodic_after(
+        self: &Arc<Self>,
+        delay: Duration,
+        period: Duration,
+        f: impl Fn(&Arc<Self>) + Send + 'static,
+    ) {
+        let this = Arc::downgrade(self);
 
-#[derive(Deserialize)]
-struct TokenList {
-    tokens: Vec<TokenItem>,
-}
+        self.rt.spawn(async move {
+            let mut interval =
+                tokio::time::interval_at(tokio::time::Instant::now() + delay, period);
 
-impl GeyserPluginRabbitMq {
-    const TOKEN_REG_URL: &'static str = "https://raw.githubusercontent.com/solana-labs/token-list/main/src/tokens/solana.tokenlist.json";
+            loop {
+                interval.tick().await;
 
-    async fn load_token_reg() -> anyhow::Result<HashSet<Pubkey>> {
-        let res: TokenList = reqwest::get(Self::TOKEN_REG_URL)
-            .await
-            .context("HTTP request failed")?
-            .json()
-            .await
-            .context("Failed to parse response JSON")?;
+                match this.upgrade() {
+                    Some(this) => f(&this),
+                    None => break,
+                }
+            }
+        });
+    }
+
+    #[inline]
+    fn begin_slot(&self, slot: u64) {
+        if let Some(ref pending) = self.pending {
+            pending.begin(slot);
+        }
+    }
+
+    #[inline]
+    fn end_slot(&self, slot: u64) {
+        if let Some(ref pending) = self.pending {
+            pending.end(slot);
+        }
+    }
+
+    /// Run the enrichers and middleware over an outgoing message, returning
+    /// `None` if it should not be published
+    async fn prepare(&self, mut msg: Message, mut opts: SendOpts) -> Option<(Message, SendOpts)> {
+        if !self.is_degraded() {
+            self.enrichers.run(&mut msg).await;
+        }
+
+        if self.middleware.apply(&mut msg, &mut opts) {
+            Some((msg, opts))
+        } else {
+            self.metrics.middleware_drops.log(1);
+            self.metrics.class(metrics::class_of(&msg)).dropped();
+            None
+        }
+    }
+
+    /// Returns false, counting the message as shed, if the memory cap does
+    /// not admit another message of the given class
+    fn admits(&self, class: MessageClass) -> bool {
+        match self.memory {
+            Some(ref cap) if !cap.admits(class, &self.metrics) => {
+                self.metrics.mem_sheds.log(1);
+                self.metrics.class(class).dropped();
+                false
+            },
+            _ => true,
+        }
+    }
+
+    #[inline]
+    fn track_coalesced(&self, coalesce: &Coalescer) {
+        self.metrics
+            .mem_coalesced
+            .set(coalesce.bytes().try_into().unwrap_or(i64::MAX));
+    }
+
+    /// Returns true if a program's instructions are selected by the
+    /// configured selector or an active subscription
+    fn is_program_selected(&self, program: &Pubkey) -> bool {
+        self.ins_sel.contains_program(program)
+            || self
+                .subscriptions
+                .as_ref()
+                .map_or(false, |s| s.is_program_selected(program))
+    }
+
+    /// Returns true if expensive features are disabled because callbacks
+    /// have been exceeding their latency budget
+    #[inline]
+    fn is_degraded(&self) -> bool {
+        self.budget.as_ref().map_or(false, Budget::is_degraded)
+    }
+
+    #[inline]
+    fn ins_sender(&self) -> &Sender {
+        self.ins_producer.as_ref().unwrap_or(&self.producer)
+    }
+
+    /// Remaining lifetime of an account update for the given slot if a
+    /// maximum age is configured, estimated from the newest slot seen
+    fn account_ttl(&self, slot: u64) -> Option<Duration> {
+        let max_age = self.max_age?;
+        let behind = self
+            .latest_slot
+            .load(Ordering::Relaxed)
+            .saturating_sub(slot);
+
+        Some(max_age.saturating_sub(Duration::from_millis(
+            behind.saturating_mul(SLOT_DURATION_MS),
+        )))
+    }
+
+    fn send_account(self: &Arc<Self>, mut update: AccountUpdate) {
+        let slot = update.slot;
+        let ttl = self.account_ttl(slot);
+
+        if ttl == Some(Duration::ZERO) {
+            self.metrics.acct_expired.log(1);
+            self.metrics.class(MessageClass::AccountUpdates).dropped();
+            self.end_slot(slot);
+            return;
+        }
+
+        // Closed accounts are never dropped or compacted, so their closure
+        // is always visible to consumers
+        let zero_data = update.data.is_empty() && update.lamports > 0;
+
+        if zero_data && self.publish.zero_data == ZeroData::Drop {
+            self.metrics.acct_zero_drops.log(1);
+            self.metrics.class(MessageClass::AccountUpdates).dropped();
+            self.end_slot(slot);
+            return;
+        }
+
+        let opts = SendOpts {
+            priority: self
+                .allowlist_priority
+                .filter(|_| self.acct_sel.is_allowlisted(update.key.as_ref())),
+            ttl,
+            routing_key: None,
+        };
+
+        let shard = self.token_producer.as_ref().and_then(|(_, bytes)| {
+            MintEnricher::mint_of(&update).map(|m| token_shard_key(&m, *bytes))
+        });
+
+        // Scrub before diffing so deltas never carry scrubbed bytes
+        if self.scrubber.scrub(&update.owner, &mut update.data) {
+            self.metrics.acct_scrubs.log(1);
+        }
+
+        if update.executable && !update.data.is_empty() && !self.publish.include_program_data {
+            update.data_hash = Some(hash(&update.data));
+            update.data = vec![];
+            self.metrics.acct_elisions.log(1);
+        }
+
+        let compact = zero_data && self.publish.zero_data == ZeroData::Compact;
+        let tracked = !compact
+            && update.data_hash.is_none()
+            && self
+                .differ
+                .as_ref()
+                .map_or(false, |d| d.tracks(&update.key));
+
+        // A consumer requesting a snapshot has lost its state, so it gets the
+        // full account and the chain restarts from a keyframe
+        let tracked = if tracked && update.on_demand {
+            if let Some(ref differ) = self.differ {
+                differ.reset(&update.key);
+            }
+
+            false
+        } else {
+            tracked
+        };
+
+        if let Some(tx) = self.diff_tx.as_ref().filter(|_| tracked) {
+            let size = memory::account_size(&update);
+            self.metrics.mem_in_flight.add(size);
+
+            // A delta expiring in the broker would break its chain
+            let opts = SendOpts { ttl: None, ..opts };
+
+            if tx
+                .send(QueuedDiff {
+                    update,
+                    opts,
+                    shard,
+                    size,
+                })
+                .is_err()
+            {
+                self.metrics.mem_in_flight.sub(size);
+                self.metrics.class(MessageClass::AccountUpdates).dropped();
+                self.end_slot(slot);
+            }
+
+            return;
+        }
+
+        let msg = if compact {
+            Message::AccountBalance(AccountBalance {
+                key: update.key,
+                lamports: update.lamports,
+                owner: update.owner,
+                write_version: update.write_version,
+                slot,
+                is_startup: update.is_startup,
+            })
+        } else {
+            Message::AccountUpdate(update)
+        };
+
+        let size = memory::message_size(&msg);
+        self.metrics.mem_in_flight.add(size);
+
+        self.spawn(move |this| async move {
+            if let Some((msg, opts)) = this.prepare(msg, opts).await {
+                this.send_routed(msg, opts, shard).await;
+                this.metrics.acct_sends.log(1);
+            }
+
+            this.metrics.mem_in_flight.sub(size);
+            this.end_slot(slot);
+
+            Ok(())
+        });
+    }
+
+    /// Publish an account message, to the token exchange if it was given a
+    /// shard key, returning true if the broker accepted it
+    async fn send_routed(&self, msg: Message, mut opts: SendOpts, shard: Option<String>) -> bool {
+        match (shard, &self.token_producer) {
+            (Some(key), Some((producer, _))) => {
+                opts.routing_key = Some(key);
+                producer.send_with(msg, opts).await
+            },
+            _ => self.producer.send_with(msg, opts).await,
+        }
+    }
+
+    /// Diff and publish updates for tracked accounts one at a time, so every
+    /// delta reaches the broker after the one it builds on
+    async fn run_diffs(this: Weak<Self>, mut rx: mpsc::UnboundedReceiver<QueuedDiff>) {
+        while let Some(queued) = rx.recv().await {
+            let this = match this.upgrade() {
+                Some(t) => t,
+                None => break,
+            };
+
+            if let Some(ref differ) = this.differ {
+                this.send_diff(differ, queued).await;
+            }
+        }
+    }
+
+    async fn send_diff(&self, differ: &Differ, queued: QueuedDiff) {
+        let QueuedDiff {
+            update,
+            opts,
+            shard,
+            size,
+        } = queued;
+        let key = update.key;
+        let slot = update.slot;
+        let msg = Message::AccountDiff(differ.diff(update));
+
+        let sent = match self.prepare(msg, opts).await {
+            Some((msg, opts)) => {
+                let sent = self.send_routed(msg, opts, shard).await;
+                self.metrics.acct_sends.log(1);
+                sent
+            },
+            None => false,
+        };
+
+        // Consumers cannot apply deltas past a missing one, so start a new
+        // chain with the next update
+        if !sent {
+            differ.reset(&key);
+        }
 
-        res.tokens
+        self.metrics.mem_in_flight.sub(size);
+        self.end_slot(slot);
+    }
+
+    /// Copy an account out of the validator's callback
+    fn copy_account(
+        acct: &ReplicaAccountInfo,
+        slot: u64,
+        is_startup: bool,
+    ) -> anyhow::Result<AccountUpdate> {
+        let ReplicaAccountInfo {
+            pubkey,
+            lamports,
+            owner,
+            executable,
+            rent_epoch,
+            data,
+            write_version,
+        } = *acct;
+
+        Ok(AccountUpdate {
+            key: Pubkey::new_from_array(pubkey.try_into()?),
+            lamports,
+            owner: Pubkey::new_from_array(owner.try_into()?),
+            executable,
+            rent_epoch,
+            data: data.to_owned(),
+            write_version,
+            slot,
+            is_startup,
+            on_demand: false,
+            mint_info: None,
+            data_hash: None,
+        })
+    }
+
+    #[inline]
+    fn is_account_selected(&self, acct: &impl AccountInfo, is_startup: bool, slot: u64) -> bool {
+        let decision = self.acct_sel.decide(acct, is_startup);
+        let subscribed = !decision.is_selected()
+            && self.subscriptions.as_ref().map_or(false, |s| {
+                s.is_account_selected(acct.pubkey(), acct.owner())
+            });
+
+        if self.tracer.sample() {
+            info!(
+                "Trace: account {} (owner {}, {} byte(s), slot {}, startup {}): {}{}",
+                bs58::encode(acct.pubkey()).into_string(),
+                bs58::encode(acct.owner()).into_string(),
+                acct.data().len(),
+                slot,
+                is_startup,
+                decision,
+                if subscribed {
+                    ", selected by subscription"
+                } else {
+                    ""
+                },
+            );
+        }
+
+        decision.is_selected() || subscribed
+    }
+
+    /// Report a previously-published account which has moved to a new owner
+    fn check_reassigned(self: &Arc<Self>, key: Pubkey, owner: Pubkey, slot: u64) {
+        if let Some(ref cache) = self.acct_cache {
+            if let Some(old_owner) = cache.reassigned(&key, &owner) {
+                self.send_reassigned(AccountReassigned {
+                    key,
+                    old_owner,
+                    new_owner: owner,
+                    slot,
+                });
+            }
+        }
+    }
+
+    /// Publish or coalesce a selected account update
+    fn publish_account(self: &Arc<Self>, update: AccountUpdate) {
+        let AccountUpdate {
+            key,
+            lamports,
+            owner,
+            executable,
+            rent_epoch,
+            slot,
+            is_startup,
+            ..
+        } = update;
+
+        if let Some(ref tombstones) = self.tombstones {
+            tombstones.record(key, owner, lamports, is_startup, slot);
+        }
+
+        if !self.admits(MessageClass::AccountUpdates) {
+            return;
+        }
+
+        if let Some(ref discovery) = self.discovery {
+            if !discovery.first_write(key) {
+                self.metrics.acct_repeat_drops.log(1);
+                return;
+            }
+        }
+
+        let hash = self
+            .acct_cache
+            .as_ref()
+            .filter(|_| !self.is_degraded())
+            .and_then(|c| c.content_hash(lamports, &owner, executable, &update.data));
+
+        if let Some(ref cache) = self.acct_cache {
+            if cache.rent_epoch_only(&key, hash.as_ref(), rent_epoch) {
+                self.metrics.acct_rent_epoch_drops.log(1);
+                return;
+            }
+
+            cache.published(
+                key,
+                LastPublished {
+                    owner,
+                    rent_epoch,
+                    hash,
+                },
+            );
+        }
+
+        self.begin_slot(slot);
+
+        // Startup updates have no slot statuses to flush them
+        match self.coalesce {
+            Some(ref coalesce) if !is_startup => {
+                if coalesce.push(update) {
+                    self.metrics.acct_coalesced.log(1);
+                    self.end_slot(slot);
+                }
+
+                self.track_coalesced(coalesce);
+            },
+            _ => self.send_account(update),
+        }
+    }
+
+    /// Publish tombstones for accounts missing from the startup snapshot and
+    /// persist the account index
+    fn end_startup(self: &Arc<Self>) {
+        let tombstones = match self.tombstones {
+            Some(ref t) => Arc::clone(t),
+            None => return,
+        };
+
+        let deleted = tombstones.end_startup();
+
+        if !deleted.is_empty() {
+            info!(
+                "Publishing tombstones for {} account(s) deleted while offline",
+                deleted.len()
+            );
+            self.metrics.acct_tombstones.log(deleted.len());
+        }
+
+        for deleted in deleted {
+            self.send_deleted(deleted);
+        }
+
+        self.rt.spawn_blocking(move || {
+            if let Err(e) = tombstones.persist() {
+                error!("Failed to persist account index: {:?}", e);
+            }
+        });
+    }
+
+    fn send_deleted(self: &Arc<Self>, deleted: AccountDeleted) {
+        let slot = deleted.slot;
+
+        self.begin_slot(slot);
+        self.spawn(|this| async move {
+            let msg = Message::AccountDeleted(deleted);
+
+            if let Some((msg, opts)) = this.prepare(msg, SendOpts::default()).await {
+                this.producer.send_with(msg, opts).await;
+            }
+
+            this.end_slot(slot);
+
+            Ok(())
+        });
+    }
+
+    fn send_reassigned(self: &Arc<Self>, reassigned: AccountReassigned) {
+        let slot = reassigned.slot;
+
+        self.begin_slot(slot);
+        self.spawn(|this| async move {
+            let msg = Message::AccountReassigned(reassigned);
+
+            if let Some((msg, opts)) = this.prepare(msg, SendOpts::default()).await {
+                this.producer.send_with(msg, opts).await;
+                this.metrics.acct_reassigns.log(1);
+            }
+
+            this.end_slot(slot);
+
+            Ok(())
+        });
+    }
+
+    fn send_signature(
+        self: &Arc<Self>,
+        program_ids: Vec<Pubkey>,
+        keys: &AccountKeys,
+        slot: u64,
+        txn_signature: &[u8],
+    ) {
+        let fee_payer = if let Some(k) = keys.get(0) {
+            *k
+        } else {
+            warn!("Couldn't get fee payer for transaction");
+            self.metrics.errs.log(1);
+            return;
+        };
+
+        if !self.admits(MessageClass::Instructions) {
+            return;
+        }
+
+        let msg = Message::SignatureNotify(SignatureNotify {
+            txn_signature: txn_signature.to_vec(),
+            slot,
+            fee_payer,
+            program_ids,
+        });
+        let size = memory::message_size(&msg);
+        self.metrics.mem_in_flight.add(size);
+
+        self.begin_slot(slot);
+        self.spawn(move |this| async move {
+            if let Some((msg, opts)) = this.prepare(msg, SendOpts::default()).await {
+                this.ins_sender().send_with(msg, opts).await;
+                this.metrics.sig_sends.log(1);
+            }
+
+            this.metrics.mem_in_flight.sub(size);
+            this.end_slot(slot);
+
+            Ok(())
+        });
+    }
+
+    fn send_sale(self: &Arc<Self>, sale: NftSale) {
+        if !self.admits(MessageClass::Instructions) {
+            return;
+        }
+
+        let slot = sale.slot;
+        let msg = Message::NftSale(sale);
+        let size = memory::message_size(&msg);
+        self.metrics.mem_in_flight.add(size);
+
+        self.begin_slot(slot);
+        self.spawn(move |this| async move {
+            if let Some((msg, opts)) = this.prepare(msg, SendOpts::default()).await {
+                this.ins_sender().send_with(msg, opts).await;
+                this.metrics.sale_sends.log(1);
+            }
+
+            this.metrics.mem_in_flight.sub(size);
+            this.end_slot(slot);
+
+            Ok(())
+        });
+    }
+
+    /// Returns true if any configured output is computed from transactions
+    fn wants_transactions(&self) -> bool {
+        let selected = (self.publish.instructions || self.publish.signatures || self.publish.fees)
+            && (!self.ins_sel.is_empty() || self.subscriptions.is_some());
+
+        selected || self.publish.nft_sales || self.digest.is_some()
+    }
+
+    fn publish_watermark(self: &Arc<Self>) {
+        let slot = match self.watermark.as_ref().and_then(|w| w.advance()) {
+            Some(s) => s,
+            None => return,
+        };
+
+        if self
+            .status_tx
+            .send(Message::RootedWatermark(RootedWatermark { slot }))
+            .is_err()
+        {
+            error!("Slot status worker has shut down, dropping watermark");
+            self.metrics.errs.log(1);
+        }
+    }
+
+    /// Describe the plugin's configuration and state for operators
+    fn plugin_status(&self) -> serde_json::Value {
+        let amqp = self.producer.amqp();
+
+        serde_json::json!({
+            "producer": self.lifecycle.id,
+            "version": self.lifecycle.version,
+            "build": self.lifecycle.build,
+            "config": {
+                "network": amqp.network,
+                "splitExchanges": amqp.split_exchanges,
+                "encrypted": amqp.encryption.is_some(),
+                "compressed": amqp.compression.is_some(),
+                "migrationNetwork": amqp.migration.as_ref().map(|m| m.network),
+                "secondaryNetwork": amqp.secondary.as_ref().map(|s| s.network),
+                "publish": {
+                    "accounts": self.publish.accounts,
+                    "instructions": self.publish.instructions,
+                    "slotStatus": self.publish.slot_status,
+                    "signatures": self.publish.signatures,
+                    "fees": self.publish.fees,
+                    "nftSales": self.publish.nft_sales,
+                },
+                "coalesce": self.coalesce.is_some(),
+                "diff": self.differ.is_some(),
+                "subscriptions": self.subscriptions.is_some(),
+                "offload": self.offload.is_some(),
+                "latencyBudget": self.budget.is_some(),
+                "slotBarrier": self.barrier.is_some(),
+                "walletDigest": self.digest.is_some(),
+                "firstWrite": self.discovery.as_ref().map(Discovery::len),
+                "recording": self.recorder.as_ref().map(Recorder::is_active),
+                "features": self.features,
+                "deprecations": self.deprecations,
+            },
+            "connections": {
+                "default": self.producer.is_connected(),
+                "instructions": self.ins_producer.as_ref().map(Sender::is_connected),
+                "tokenAccounts": self.token_producer.as_ref().map(|(s, _)| s.is_connected()),
+                "stats": self.stats.as_ref().map(|s| s.sender().is_connected()),
+            },
+            "leader": self.metrics.leader.get(),
+            "degraded": self.is_degraded(),
+            "lastSlot": self.latest_slot.load(Ordering::Relaxed),
+            "backlog": {
+                "tasks": self.metrics.tasks_in_flight.get(),
+                "coalesced": self.coalesce.as_ref().map(Coalescer::pending),
+            },
+            "memory": {
+                "inFlight": self.metrics.mem_in_flight.get(),
+                "coalesced": self.metrics.mem_coalesced.get(),
+                "cap": self.memory.as_ref().map(MemoryCap::cap),
+            },
+        })
+    }
+
+    /// Log and count the selector entries which have not matched anything
+    /// since the plugin was loaded
+    fn report_unused(self: &Arc<Self>) {
+        let unused = self
+            .acct_sel
+            .unused()
             .into_iter()
-            .map(|TokenItem { address }| address.parse())
-            .collect::<StdResult<_, _>>()
-            .context("Failed to convert token list")
+            .chain(self.ins_sel.unused());
+
+        for Unused { field, keys } in unused {
+            let gauge = match field {
+                "owners" => &self.metrics.unused_owners,
+                "pubkeys" => &self.metrics.unused_pubkeys,
+                "programs" => &self.metrics.unused_programs,
+                _ => continue,
+            };
+            gauge.set(keys.len().try_into().unwrap_or(i64::MAX));
+
+            if keys.is_empty() {
+                continue;
+            }
+
+            warn!(
+                "{} {} entr{} never matched: {}",
+                keys.len(),
+                field,
+                if keys.len() == 1 { "y" } else { "ies" },
+                keys.iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            );
+        }
+    }
+
+    fn publish_stats(self: &Arc<Self>) {
+        let stats = match self.stats {
+            Some(ref s) => s,
+            None => return,
+        };
+
+        let msg = Message::Stats(stats.sample(
+            &self.metrics,
+            &self.lifecycle,
+            self.latest_slot.load(Ordering::Relaxed),
+        ));
+
+        self.spawn(|this| async move {
+            if let Some(ref stats) = this.stats {
+                stats.sender().send(msg).await;
+            }
+
+            Ok(())
+        });
+    }
+
+    fn flush_expired(self: &Arc<Self>) {
+        let coalesce = match self.coalesce {
+            Some(ref c) => c,
+            None => return,
+        };
+
+        let updates = coalesce.flush_expired();
+
+        if !updates.is_empty() {
+            warn!(
+                "Force-flushing {} buffered account update(s) that never reached {:?} \
+                 commitment",
+                updates.len(),
+                coalesce.commitment()
+            );
+            self.metrics.acct_force_flushes.log(updates.len());
+        }
+
+        for update in updates {
+            self.send_account(update);
+        }
+
+        self.track_coalesced(coalesce);
+        self.metrics
+            .class(MessageClass::AccountUpdates)
+            .set_deferred(coalesce.pending());
     }
+}
 
+/// An instance of the plugin
+#[derive(Debug, Default)]
+#[repr(transparent)]
+pub struct GeyserPluginRabbitMq(Option<Arc<Inner>>);
+
+impl GeyserPluginRabbitMq {
     fn expect_inner(&self) -> &Arc<Inner> {
         self.0.as_ref().expect(UNINIT)
     }
@@ -98,7 +959,17 @@ impl GeyserPluginRabbitMq {
         f: impl FnOnce(&Arc<Inner>) -> anyhow::Result<T>,
     ) -> Result<T> {
         match self.0 {
-            Some(ref inner) => f(inner).map_err(custom_err(&inner.metrics.errs)),
+            Some(ref inner) => {
+                let start = inner.budget.as_ref().map(|_| inner.clock.now());
+                let res = f(inner).map_err(custom_err(&inner.metrics.errs));
+
+                if let Some((budget, start)) = inner.budget.as_ref().zip(start) {
+                    let elapsed = inner.clock.now().saturating_duration_since(start);
+                    budget.observe(elapsed, &inner.metrics);
+                }
+
+                res
+            },
             None => Err(uninit()),
         }
     }
@@ -109,6 +980,7 @@ impl GeyserPlugin for GeyserPluginRabbitMq {
         "GeyserPluginRabbitMq"
     }
 
+    #[allow(clippy::too_many_lines)]
     fn on_load(&mut self, cfg: &str) -> Result<()> {
         solana_logger::setup_with_default("info");
 
@@ -120,8 +992,6 @@ impl GeyserPlugin for GeyserPluginRabbitMq {
         {
             let ver = env!("CARGO_PKG_VERSION");
             let git = option_env!("META_GIT_HEAD");
-            // TODO
-            // let rem = option_env!("META_GIT_REMOTE");
 
             {
                 use std::fmt::Write;
@@ -135,13 +1005,6 @@ impl GeyserPlugin for GeyserPluginRabbitMq {
                 version = s;
             }
 
-            // TODO
-            // let rustc_ver = env!("META_RUSTC_VERSION");
-            // let build_host = env!("META_BUILD_HOST");
-            // let target = env!("META_BUILD_TARGET");
-            // let profile = env!("META_BUILD_PROFILE");
-            // let platform = env!("META_BUILD_PLATFORM");
-
             host = hostname::get()
                 .map_err(custom_err(&metrics.errs))?
                 .into_string()
@@ -149,12 +1012,72 @@ impl GeyserPlugin for GeyserPluginRabbitMq {
                 .map_err(custom_err(&metrics.errs))?;
         }
 
-        let (amqp, jobs, metrics_conf, mut acct_sel, ins_sel) = Config::read(cfg)
+        let Parts {
+            amqp,
+            jobs,
+            metrics: metrics_conf,
+            coalesce,
+            watermark: watermark_conf,
+            leader: leader_conf,
+            account_cache,
+            token_registry,
+            http: http_conf,
+            publish,
+            retention,
+            diff: diff_conf,
+            rpc: rpc_conf,
+            snapshots,
+            mint_info,
+            scrub,
+            middleware,
+            control,
+            stats: stats_conf,
+            token_shards,
+            readiness: readiness_conf,
+            offload,
+            latency_budget,
+            tombstones,
+            trace,
+            slot_barrier,
+            wallet_digest,
+            recording,
+            features,
+            startup_check,
+            memory,
+            first_write,
+            config_usage,
+            deprecations,
+            mut acct_sel,
+            mut ins_sel,
+        } = Config::read(cfg)
             .and_then(Config::into_parts)
             .map_err(custom_err(&metrics.errs))?;
 
         let startup_type = acct_sel.startup();
 
+        metrics
+            .config_deprecations
+            .set(deprecations.len().try_into().unwrap_or(i64::MAX));
+
+        if config_usage.is_some() {
+            acct_sel.track_usage();
+            ins_sel.track_usage();
+        }
+
+        let tombstones = tombstones
+            .as_ref()
+            .map(Tombstones::new)
+            .transpose()
+            .map_err(custom_err(&metrics.errs))?
+            .map(Arc::new);
+        let discovery = first_write
+            .as_ref()
+            .map(Discovery::new)
+            .transpose()
+            .map_err(custom_err(&metrics.errs))?;
+        let http = http::Client::new(&http_conf).map_err(custom_err(&metrics.errs))?;
+        let rpc = Rpc::new(rpc_conf).map_err(custom_err(&metrics.errs))?;
+
         if let Some(config) = metrics_conf.config {
             const VAR: &str = "SOLANA_METRICS_CONFIG";
 
@@ -165,46 +1088,521 @@ impl GeyserPlugin for GeyserPluginRabbitMq {
             env::set_var(VAR, config);
         }
 
+        info!("Enabled features: {:?}", features.enabled());
+
+        let worker_threads = jobs.worker_threads();
+        info!(
+            "Starting {} worker thread(s){}",
+            worker_threads,
+            if jobs.limit.is_some() {
+                ""
+            } else {
+                " (auto-detected)"
+            }
+        );
+
         let rt = tokio::runtime::Builder::new_multi_thread()
             .enable_all()
             .thread_name("geyser-rabbitmq")
-            .worker_threads(jobs.limit)
-            .max_blocking_threads(jobs.blocking.unwrap_or(jobs.limit))
+            .worker_threads(worker_threads)
+            .max_blocking_threads(jobs.blocking_threads())
+            .build()
+            .map_err(custom_err(&metrics.errs))?;
+
+        metrics
+            .worker_threads
+            .set(worker_threads.try_into().unwrap_or(i64::MAX));
+
+        rt.spawn({
+            let metrics = Arc::clone(&metrics);
+
+            async move {
+                let mut interval = tokio::time::interval(GAUGE_INTERVAL);
+
+                loop {
+                    interval.tick().await;
+                    metrics.submit_gauges();
+                }
+            }
+        });
+
+        let name = format!("geyser-rabbitmq-{}@{}", version, host);
+        let build = build_info();
+        info!(
+            "Built by {} for {} ({}, {}) from {} of {}",
+            build.rustc,
+            build.target,
+            build.profile,
+            build.platform,
+            build.git_head.as_deref().unwrap_or("unknown commit"),
+            build.git_remote.as_deref().unwrap_or("unknown remote"),
+        );
+        submit_build_info(&build);
+
+        let lifecycle = ProducerLifecycle {
+            id: ProducerId::new(host),
+            version,
+            build: Some(build),
+        };
+        info!("Starting producer {}", lifecycle.id);
+
+        let split = amqp.split_exchanges;
+        let snapshots = snapshots.map(|c| (amqp.clone(), c));
+        let control_amqp = control.is_some().then(|| amqp.clone());
+        let allowlist_priority = amqp.allowlist_priority;
+        let status_runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .thread_name("geyser-rabbitmq-status")
+            .worker_threads(1)
+            .max_blocking_threads(1)
             .build()
             .map_err(custom_err(&metrics.errs))?;
 
-        let producer = rt.block_on(async {
+        let leader = if let Some(conf) = leader_conf {
+            let leader = Arc::new(Leader::new(&amqp, conf, Arc::clone(&metrics)));
+            let held = rt.block_on(leader.try_acquire());
+
+            if held.is_none() {
+                info!("Producer lease is held elsewhere, starting in standby");
+            }
+
+            rt.spawn(Arc::clone(&leader).run(held));
+
+            Some(leader)
+        } else {
+            None
+        };
+
+        let clock = SystemClock::shared();
+        let status_producer = status_runtime
+            .block_on(Sender::new(
+                amqp.clone(),
+                format!("{}/status", name),
+                startup_type,
+                Sink::SlotStatuses,
+                &lifecycle.id,
+                leader.clone(),
+                Arc::clone(&metrics),
+                Arc::clone(&clock),
+            ))
+            .map_err(custom_err(&metrics.errs))?;
+
+        let (status_tx, mut status_rx) = mpsc::unbounded_channel();
+        let pending = (watermark_conf.is_some() || slot_barrier.is_some())
+            .then(|| Arc::new(PendingSlots::default()));
+        let watermark = watermark_conf
+            .as_ref()
+            .zip(pending.as_ref())
+            .map(|(_, p)| Arc::new(Watermark::new(Arc::clone(p))));
+        let barrier = slot_barrier
+            .as_ref()
+            .zip(pending.as_ref())
+            .map(|(c, p)| Arc::new(SlotBarrier::new(c, Arc::clone(p), Arc::clone(&clock))));
+        let digest = wallet_digest
+            .map(DigestTracker::new)
+            .transpose()
+            .map_err(custom_err(&metrics.errs))?;
+
+        // Coalesced updates are held until their slot meets the coalescing
+        // commitment, so waiting for them on an earlier status would always
+        // time out
+        let barrier_commitment = coalesce.as_ref().map(|c| c.commitment);
+
+        status_runtime.spawn({
+            let metrics = Arc::clone(&metrics);
+            let watermark = watermark.clone();
+            let barrier = barrier.clone();
+
+            async move {
+                while let Some(msg) = status_rx.recv().await {
+                    if let (
+                        Some(barrier),
+                        Message::SlotStatusUpdate(SlotStatusUpdate {
+                            slot,
+                            status: status @ (RmqSlotStatus::Confirmed | RmqSlotStatus::Rooted),
+                            ..
+                        }),
+                    ) = (&barrier, &msg)
+                    {
+                        let awaited = barrier_commitment.map_or(true, |c| c.is_met_by(*status));
+
+                        if awaited && !barrier.wait(*slot).await {
+                            metrics.barrier_timeouts.log(1);
+                            warn!(
+                                "Timed out waiting for messages of slot {}, publishing its \
+                                 status anyway",
+                                slot
+                            );
+                        }
+                    }
+
+                    let rooted = match msg {
+                        Message::SlotStatusUpdate(SlotStatusUpdate {
+                            slot,
+                            status: RmqSlotStatus::Rooted,
+                            ..
+                        }) => Some(slot),
+                        _ => None,
+                    };
+                    let is_status = matches!(msg, Message::SlotStatusUpdate(_));
+
+                    status_producer.send(msg).await;
+
+                    if is_status {
+                        metrics.status_sends.log(1);
+                    }
+
+                    if let (Some(watermark), Some(slot)) = (&watermark, rooted) {
+                        watermark.rooted(slot);
+                    }
+                }
+            }
+        });
+
+        // Startup updates are published as soon as this returns, so hold them
+        // until someone is there to read them
+        if let Some(conf) = readiness_conf.filter(|_| startup_type != StartupType::Normal) {
+            let ready = rt
+                .block_on(readiness::wait(&amqp, &conf, startup_type, &*clock))
+                .map_err(custom_err(&metrics.errs))?;
+
+            if !ready {
+                warn!(
+                    "No consumer appeared within {}s, publishing startup updates anyway",
+                    conf.timeout_secs
+                );
+            }
+        }
+
+        if startup_type != StartupType::Normal && !startup_check.disabled {
+            rt.block_on(readiness::verify(&amqp, &startup_check, startup_type))
+                .map_err(custom_err(&metrics.errs))?;
+        }
+
+        let (producer, ins_producer, token_producer, stats) = rt.block_on(async {
+            let ins_producer = if split {
+                Some(
+                    Sender::new(
+                        amqp.clone(),
+                        format!("{}/instructions", name),
+                        startup_type,
+                        Sink::Instructions,
+                        &lifecycle.id,
+                        leader.clone(),
+                        Arc::clone(&metrics),
+                        Arc::clone(&clock),
+                    )
+                    .await
+                    .map_err(custom_err(&metrics.errs))?,
+                )
+            } else {
+                None
+            };
+
+            let token_producer = if let Some(conf) = token_shards {
+                let sender = Sender::new(
+                    amqp.clone(),
+                    format!("{}/tokens", name),
+                    startup_type,
+                    Sink::TokenAccounts,
+                    &lifecycle.id,
+                    leader.clone(),
+                    Arc::clone(&metrics),
+                    Arc::clone(&clock),
+                )
+                .await
+                .map_err(custom_err(&metrics.errs))?;
+
+                Some((sender, conf.bytes.clamp(1, 32)))
+            } else {
+                None
+            };
+
+            let stats = if let Some(conf) = stats_conf {
+                let sender = Sender::new(
+                    amqp.clone(),
+                    format!("{}/stats", name),
+                    startup_type,
+                    Sink::Stats,
+                    &lifecycle.id,
+                    // Standby producers report their health too
+                    None,
+                    Arc::clone(&metrics),
+                    Arc::clone(&clock),
+                )
+                .await
+                .map_err(custom_err(&metrics.errs))?;
+
+                Some(Reporter::new(
+                    sender,
+                    Summarizer::new(
+                        Duration::from_secs(conf.interval_secs.max(1)),
+                        features.enabled(),
+                        Arc::clone(&clock),
+                    ),
+                ))
+            } else {
+                None
+            };
+
             let producer = Sender::new(
                 amqp,
-                format!("geyser-rabbitmq-{}@{}", version, host),
+                name,
                 startup_type,
+                Sink::Accounts,
+                &lifecycle.id,
+                leader,
                 Arc::clone(&metrics),
+                Arc::clone(&clock),
             )
             .await
             .map_err(custom_err(&metrics.errs))?;
 
             if acct_sel.screen_token_registry() {
-                acct_sel.init_token_registry(
-                    Self::load_token_reg()
-                        .await
-                        .map_err(custom_err(&metrics.errs))?,
-                );
+                acct_sel
+                    .set_token_registry(
+                        token_registry::load(&token_registry, &http)
+                            .await
+                            .map_err(custom_err(&metrics.errs))?,
+                    )
+                    .map_err(custom_err(&metrics.errs))?;
             }
 
-            Result::<_>::Ok(producer)
+            producer
+                .send(Message::ProducerStarted(lifecycle.clone()))
+                .await;
+
+            Result::<_>::Ok((producer, ins_producer, token_producer, stats))
         })?;
 
-        self.0 = Some(Arc::new(Inner {
+        let differ = diff_conf
+            .map(|c| Differ::new(c, &lifecycle.id))
+            .transpose()
+            .map_err(custom_err(&metrics.errs))?;
+
+        let scrubber = Scrubber::new(scrub).map_err(custom_err(&metrics.errs))?;
+        let middleware = Chain::new(middleware).map_err(custom_err(&metrics.errs))?;
+        let mut enrichers = Pipeline::default();
+
+        if let Some(conf) = mint_info {
+            enrichers.push(
+                MintEnricher::new(&conf, rpc.clone(), Arc::clone(&clock)),
+                Duration::from_millis(conf.timeout_ms),
+            );
+        }
+
+        let lanes = Lanes::new(rt.handle());
+        let (diff_tx, diff_rx) = if differ.is_some() {
+            let (tx, rx) = mpsc::unbounded_channel();
+
+            (Some(tx), Some(rx))
+        } else {
+            (None, None)
+        };
+        let inner = Arc::new(Inner {
             rt,
             producer,
+            ins_producer,
+            token_producer,
+            _status_rt: status_runtime,
+            status_tx,
+            coalesce: coalesce.map(|c| Coalescer::new(&c, Arc::clone(&clock))),
+            acct_cache: account_cache.as_ref().map(AccountCache::new),
+            pending,
+            watermark,
+            lifecycle,
+            publish,
+            allowlist_priority,
+            max_age: retention.map(|r| Duration::from_secs(r.max_age_secs)),
+            latest_slot: AtomicU64::new(0),
+            clock: Arc::clone(&clock),
+            slot_clock: SlotClock::new(Arc::clone(&clock)),
+            fees: publish.fees.then(FeeTracker::default),
+            differ,
+            diff_tx,
+            scrubber,
+            offload: offload.as_ref().map(Offload::new),
+            lanes,
+            budget: latency_budget
+                .as_ref()
+                .map(|b| Budget::new(b, Arc::clone(&clock))),
+            tombstones,
+            discovery,
+            deprecations,
+            tracer: Tracer::new(trace.sample_every),
+            barrier,
+            digest,
+            recorder: recording
+                .as_ref()
+                .map(|r| Recorder::new(r, Arc::clone(&clock))),
+            memory: memory.as_ref().map(MemoryCap::new),
+            features: features.enabled(),
+            enrichers,
+            middleware,
+            subscriptions: control
+                .as_ref()
+                .map(|c| Subscriptions::new(c, Arc::clone(&clock))),
+            stats,
             acct_sel,
             ins_sel,
             metrics,
-        }));
+        });
+
+        if let Some(rx) = diff_rx {
+            inner.rt.spawn(Inner::run_diffs(Arc::downgrade(&inner), rx));
+        }
+
+        if let Some(ref coalesce) = inner.coalesce {
+            inner.spawn_periodic(
+                (coalesce.max_age() / 4).max(Duration::from_millis(10)),
+                Inner::flush_expired,
+            );
+        }
+
+        if !inner.enrichers.is_empty() {
+            inner.spawn_periodic(GAUGE_INTERVAL, |i| i.enrichers.submit_metrics());
+        }
+
+        if let Some(conf) = watermark_conf {
+            inner.spawn_periodic(
+                Duration::from_millis(conf.interval_ms.max(10)),
+                Inner::publish_watermark,
+            );
+        }
+
+        if let Some(interval) = inner.stats.as_ref().map(Reporter::interval) {
+            inner.spawn_periodic(interval, Inner::publish_stats);
+        }
+
+        if let Some(conf) = config_usage {
+            let window = Duration::from_secs(conf.window_secs.max(1));
+
+            inner.spawn_periodic_after(window, window, Inner::report_unused);
+        }
+
+        if let Some(conf) = first_write {
+            inner.spawn_periodic(Duration::from_secs(conf.checkpoint_secs.max(1)), |i| {
+                if let Some(ref discovery) = i.discovery {
+                    if let Err(e) = discovery.checkpoint() {
+                        error!("Failed to checkpoint discovered accounts: {:?}", e);
+                    }
+                }
+            });
+        }
+
+        if let Some(amqp) = control_amqp {
+            let this = Arc::downgrade(&inner);
+
+            inner.spawn_periodic(Duration::from_secs(1), |i| {
+                if let Some(ref subs) = i.subscriptions {
+                    subs.expire();
+                }
+
+                if let Some(ref recorder) = i.recorder {
+                    recorder.expire();
+                }
+            });
+
+            inner.rt.spawn(subscriptions::run(
+                amqp,
+                inner.lifecycle.id.to_string(),
+                Arc::clone(&inner.metrics),
+                move |req| match (this.upgrade(), req) {
+                    (Some(this), Request::Status) => {
+                        Reply::Status(this.plugin_status().to_string())
+                    },
+                    (Some(this), Request::Trace { sample_every }) => {
+                        this.tracer.set(sample_every);
+
+                        Reply::Tracing {
+                            sample_every: this.tracer.every(),
+                        }
+                    },
+                    (
+                        Some(this),
+                        Request::Record {
+                            duration_secs,
+                            max_bytes,
+                        },
+                    ) => match this.recorder {
+                        Some(ref recorder) => match recorder.start(duration_secs, max_bytes) {
+                            Ok((path, until, max_bytes)) => Reply::Recording {
+                                path: path.display().to_string(),
+                                until,
+                                max_bytes,
+                            },
+                            Err(e) => {
+                                warn!("Failed to start recording: {:?}", e);
+
+                                Reply::Rejected(format!("Failed to start recording: {}", e))
+                            },
+                        },
+                        None => Reply::Rejected("Recording is disabled".into()),
+                    },
+                    (Some(this), req) => this.subscriptions.as_ref().map_or_else(
+                        || Reply::Rejected("Subscriptions are disabled".into()),
+                        |s| s.handle(req),
+                    ),
+                    (None, _) => Reply::Rejected("Producer is shutting down".into()),
+                },
+            ));
+        }
+
+        if let Some((amqp, conf)) = snapshots {
+            let this = Arc::downgrade(&inner);
+
+            inner.rt.spawn(snapshot::run(
+                amqp,
+                conf,
+                rpc,
+                Arc::clone(&inner.metrics),
+                move |update| {
+                    if let Some(this) = this.upgrade() {
+                        this.begin_slot(update.slot);
+                        this.send_account(update);
+                    }
+                },
+            ));
+        }
+
+        self.0 = Some(inner);
 
         Ok(())
     }
 
+    fn on_unload(&mut self) {
+        if let Some(ref inner) = self.0 {
+            if let Some(ref tombstones) = inner.tombstones {
+                if let Err(e) = tombstones.persist() {
+                    error!("Failed to persist account index: {:?}", e);
+                }
+            }
+
+            if let Some(ref discovery) = inner.discovery {
+                if let Err(e) = discovery.checkpoint() {
+                    error!("Failed to checkpoint discovered accounts: {:?}", e);
+                }
+            }
+
+            inner.rt.block_on(
+                inner
+                    .producer
+                    .send(Message::ProducerStopped(inner.lifecycle.clone())),
+            );
+        }
+    }
+
+    fn notify_end_of_startup(&mut self) -> Result<()> {
+        self.with_inner(
+            || GeyserPluginError::Custom(anyhow!(UNINIT).into()),
+            |this| {
+                this.end_startup();
+
+                Ok(())
+            },
+        )
+    }
+
     fn update_account(
         &mut self,
         account: ReplicaAccountInfoVersions,
@@ -216,44 +1614,87 @@ impl GeyserPlugin for GeyserPluginRabbitMq {
             |this| {
                 this.metrics.acct_recvs.log(1);
 
+                if let Some(ref recorder) = this.recorder {
+                    match account {
+                        ReplicaAccountInfoVersions::V0_0_1(acct) => {
+                            recorder.account(acct, slot, is_startup);
+                        },
+                    }
+                }
+
+                if !this.publish.accounts {
+                    return Ok(());
+                }
+
                 match account {
                     ReplicaAccountInfoVersions::V0_0_1(acct) => {
-                        if !this.acct_sel.is_selected(&AccountShim(acct), is_startup) {
+                        let offload = this
+                            .offload
+                            .as_ref()
+                            .filter(|_| this.acct_sel.inspects_data(acct.owner));
+
+                        // Decoding moves off the callback while degraded
+                        let offload_now = if this.is_degraded() {
+                            this.acct_sel.inspects_data(acct.owner)
+                        } else {
+                            offload.map_or(false, Offload::should_offload)
+                        };
+
+                        // Tombstones are computed when startup ends, so every
+                        // startup account must be recorded before then
+                        let offload_now = offload_now && !(is_startup && this.tombstones.is_some());
+
+                        // Updates queued behind an offloaded one for the same
+                        // account must not overtake it
+                        if offload_now || this.lanes.is_busy(acct.pubkey) {
+                            let update = Inner::copy_account(acct, slot, is_startup)?;
+                            this.metrics.acct_offloads.log(1);
+
+                            // Hold the slot open until selection finishes
+                            this.begin_slot(slot);
+                            let inner = Arc::clone(this);
+                            this.lanes.push(acct.pubkey, move || {
+                                let selected = inner.is_account_selected(
+                                    &UpdateShim(&update),
+                                    is_startup,
+                                    slot,
+                                );
+                                inner.check_reassigned(update.key, update.owner, slot);
+
+                                if selected {
+                                    inner.publish_account(update);
+                                }
+
+                                inner.end_slot(slot);
+                            });
+
                             return Ok(());
                         }
 
-                        let ReplicaAccountInfo {
-                            pubkey,
-                            lamports,
-                            owner,
-                            executable,
-                            rent_epoch,
-                            data,
-                            write_version,
-                        } = *acct;
-
-                        let key = Pubkey::new_from_array(pubkey.try_into()?);
-                        let owner = Pubkey::new_from_array(owner.try_into()?);
-                        let data = data.to_owned();
-
-                        this.spawn(|this| async move {
-                            this.producer
-                                .send(Message::AccountUpdate(AccountUpdate {
-                                    key,
-                                    lamports,
-                                    owner,
-                                    executable,
-                                    rent_epoch,
-                                    data,
-                                    write_version,
-                                    slot,
-                                    is_startup,
-                                }))
-                                .await;
-                            this.metrics.acct_sends.log(1);
+                        let start = Instant::now();
+                        let selected =
+                            this.is_account_selected(&AccountShim(acct), is_startup, slot);
+
+                        if let Some(offload) = offload {
+                            offload.record(start.elapsed());
+                        }
+
+                        // Unselected accounts are still checked against the
+                        // cache, since moving to an untracked owner is exactly
+                        // the kind of reassignment we want to report
+                        if !selected && this.acct_cache.is_none() {
+                            return Ok(());
+                        }
+
+                        let key = Pubkey::new_from_array(acct.pubkey.try_into()?);
+                        let owner = Pubkey::new_from_array(acct.owner.try_into()?);
+                        this.check_reassigned(key, owner, slot);
+
+                        if !selected {
+                            return Ok(());
+                        }
 
-                            Ok(())
-                        });
+                        this.publish_account(Inner::copy_account(acct, slot, is_startup)?);
                     },
                 };
 
@@ -273,28 +1714,65 @@ impl GeyserPlugin for GeyserPluginRabbitMq {
             |this| {
                 this.metrics.status_recvs.log(1);
 
-                this.spawn(|this| async move {
-                    this.producer
-                        .send(Message::SlotStatusUpdate(SlotStatusUpdate {
-                            slot,
-                            parent,
-                            status: match status {
-                                SlotStatus::Processed => RmqSlotStatus::Processed,
-                                SlotStatus::Rooted => RmqSlotStatus::Rooted,
-                                SlotStatus::Confirmed => RmqSlotStatus::Confirmed,
-                            },
-                        }))
-                        .await;
-                    this.metrics.status_sends.log(1);
+                if let Some(ref recorder) = this.recorder {
+                    recorder.slot_status(slot, parent, status);
+                }
+                this.latest_slot.fetch_max(slot, Ordering::Relaxed);
 
-                    Ok(())
-                });
+                let status = match status {
+                    SlotStatus::Processed => RmqSlotStatus::Processed,
+                    SlotStatus::Rooted => RmqSlotStatus::Rooted,
+                    SlotStatus::Confirmed => RmqSlotStatus::Confirmed,
+                };
+
+                if let Some(ref coalesce) = this.coalesce {
+                    for update in coalesce.on_status(slot, status) {
+                        this.send_account(update);
+                    }
+
+                    this.track_coalesced(coalesce);
+                }
+
+                if let Some(ref fees) = this.fees {
+                    for stats in fees.flush(slot) {
+                        this.status_tx
+                            .send(Message::FeeStats(stats))
+                            .map_err(|_| anyhow!("Slot status worker has shut down"))?;
+                    }
+                }
+
+                if let Some(ref digest) = this.digest {
+                    for digest in digest.flush(slot) {
+                        this.status_tx
+                            .send(Message::WalletDigest(digest))
+                            .map_err(|_| anyhow!("Slot status worker has shut down"))?;
+                    }
+                }
+
+                if !this.publish.slot_status {
+                    if let (Some(ref watermark), RmqSlotStatus::Rooted) = (&this.watermark, status)
+                    {
+                        watermark.rooted(slot);
+                    }
+
+                    return Ok(());
+                }
+
+                this.status_tx
+                    .send(Message::SlotStatusUpdate(SlotStatusUpdate {
+                        slot,
+                        parent,
+                        status,
+                        estimated_time: Some(this.slot_clock.observe(slot)),
+                    }))
+                    .map_err(|_| anyhow!("Slot status worker has shut down"))?;
 
                 Ok(())
             },
         )
     }
 
+    #[allow(clippy::too_many_lines)]
     fn notify_transaction(
         &mut self,
         transaction: ReplicaTransactionInfoVersions,
@@ -302,20 +1780,50 @@ impl GeyserPlugin for GeyserPluginRabbitMq {
     ) -> Result<()> {
         #[inline]
         fn process_instruction(
-            sel: &InstructionSelector,
+            this: &Inner,
             (index, ins): (InstructionIndex, &CompiledInstruction),
             keys: &AccountKeys,
+            signer: Option<Decision>,
             slot: u64,
             txn_signature: &[u8],
         ) -> anyhow::Result<Option<Message>> {
-            if !sel.is_selected(|i| keys.get(i as usize), &CompiledInstructionShim(ins))? {
-                return Ok(None);
-            }
-
             let program = *keys
                 .get(ins.program_id_index as usize)
                 .ok_or_else(|| anyhow!("Couldn't get program ID for instruction"))?;
 
+            let subscribed = this
+                .subscriptions
+                .as_ref()
+                .map_or(false, |s| s.is_program_selected(&program));
+
+            let decision = match signer {
+                Some(d) => d,
+                None => this
+                    .ins_sel
+                    .decide(|i| keys.get(i as usize), &CompiledInstructionShim(ins))?,
+            };
+
+            if this.tracer.sample() {
+                info!(
+                    "Trace: instruction {:?} of {} (program {}, {} byte(s), slot {}): {}{}",
+                    index,
+                    bs58::encode(txn_signature).into_string(),
+                    program,
+                    ins.data.len(),
+                    slot,
+                    decision,
+                    if subscribed {
+                        ", selected by subscription"
+                    } else {
+                        ""
+                    },
+                );
+            }
+
+            if !subscribed && !decision.is_selected() {
+                return Ok(None);
+            }
+
             let accounts = ins
                 .accounts
                 .iter()
@@ -342,7 +1850,15 @@ impl GeyserPlugin for GeyserPluginRabbitMq {
         self.with_inner(
             || GeyserPluginError::Custom(anyhow!(UNINIT).into()),
             |this| {
-                if this.ins_sel.is_empty() {
+                if let Some(ref recorder) = this.recorder {
+                    match transaction {
+                        ReplicaTransactionInfoVersions::V0_0_1(tx) => {
+                            recorder.transaction(tx, slot);
+                        },
+                    }
+                }
+
+                if !this.wants_transactions() {
                     return Ok(());
                 }
 
@@ -360,8 +1876,7 @@ impl GeyserPlugin for GeyserPluginRabbitMq {
 
                         let txn_signature = tx.signature.as_ref();
 
-                        let mut any_sent = false;
-                        for ins in msg
+                        let instructions: Vec<_> = msg
                             .instructions()
                             .iter()
                             .enumerate()
@@ -377,19 +1892,83 @@ impl GeyserPlugin for GeyserPluginRabbitMq {
                                         })
                                     }),
                             )
-                        {
-                            match process_instruction(
-                                &this.ins_sel,
-                                ins,
+                            .collect();
+
+                        if let Some(ref digest) = this.digest {
+                            let meta = tx.transaction_status_meta;
+
+                            if digest.involves(&keys, meta) {
+                                let programs = touched_programs(&instructions, &keys);
+                                digest.record(slot, &keys, meta, &programs);
+                            }
+                        }
+
+                        if this.publish.nft_sales {
+                            for sale in sales::detect(
+                                &instructions,
                                 &keys,
+                                tx.transaction_status_meta,
                                 slot,
                                 txn_signature,
                             ) {
+                                this.send_sale(sale);
+                            }
+                        }
+
+                        if this.publish.signatures || this.fees.is_some() {
+                            let program_ids = touched_programs(&instructions, &keys);
+
+                            if program_ids.iter().any(|p| this.is_program_selected(p)) {
+                                if let Some(ref fees) = this.fees {
+                                    fees.record(
+                                        slot,
+                                        fees::Sample::new(
+                                            msg.instructions(),
+                                            &keys,
+                                            tx.transaction_status_meta.fee,
+                                        ),
+                                    );
+                                }
+
+                                if this.publish.signatures {
+                                    this.send_signature(program_ids, &keys, slot, txn_signature);
+                                }
+                            }
+                        }
+
+                        if !this.publish.instructions {
+                            return Ok(());
+                        }
+
+                        let signer = this.ins_sel.decide_signers(
+                            keys.iter()
+                                .take(msg.header().num_required_signatures.into()),
+                        );
+
+                        let mut any_sent = false;
+                        for &ins in &instructions {
+                            match process_instruction(this, ins, &keys, signer, slot, txn_signature)
+                            {
                                 Ok(Some(m)) => {
+                                    if !this.admits(MessageClass::Instructions) {
+                                        continue;
+                                    }
+
+                                    let size = memory::message_size(&m);
+                                    this.metrics.mem_in_flight.add(size);
+
                                     any_sent = true;
-                                    this.spawn(|this| async move {
-                                        this.producer.send(m).await;
-                                        this.metrics.ins_sends.log(1);
+                                    this.begin_slot(slot);
+                                    this.spawn(move |this| async move {
+                                        if let Some((m, opts)) =
+                                            this.prepare(m, SendOpts::default()).await
+                                        {
+                                            this.ins_sender().send_with(m, opts).await;
+                                            this.metrics.ins_sends.log(1);
+                                        }
+
+                                        this.metrics.mem_in_flight.sub(size);
+                                        this.end_slot(slot);
 
                                         Ok(())
                                     });
@@ -414,11 +1993,12 @@ impl GeyserPlugin for GeyserPluginRabbitMq {
     }
 
     fn account_data_notifications_enabled(&self) -> bool {
-        true
+        let this = self.expect_inner();
+        this.publish.accounts || this.recorder.is_some()
     }
 
     fn transaction_notifications_enabled(&self) -> bool {
         let this = self.expect_inner();
-        !this.ins_sel.is_empty()
+        this.wants_transactions() || this.recorder.is_some()
     }
 }
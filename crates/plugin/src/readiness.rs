@@ -0,0 +1,120 @@
+use std::time::Duration;
+
+use indexer_rabbitmq::{
+    geyser::{MessageClass, QueueType, StartupType},
+    lapin::{Connection, ConnectionProperties},
+    suffix::Suffix,
+};
+
+use crate::{clock::Clock, config, prelude::*};
+
+async fn connect(amqp: &config::Amqp, name: &str) -> Result<Connection> {
+    Connection::connect(
+        &amqp.address,
+        ConnectionProperties::default()
+            .with_connection_name(name.into())
+            .with_executor(tokio_executor_trait::Tokio::current())
+            .with_reactor(tokio_reactor_trait::Tokio),
+    )
+    .await
+    .map_err(Into::into)
+}
+
+/// The queue the given consumer reads startup account updates from
+fn queue_type(amqp: &config::Amqp, startup_type: StartupType, consumer: &str) -> Result<QueueType> {
+    let suffix = Suffix::ProductionUnchecked;
+
+    Ok(if amqp.split_exchanges {
+        QueueType::new_class(
+            amqp.network,
+            startup_type,
+            &suffix,
+            MessageClass::AccountUpdates,
+            consumer,
+        )?
+    } else {
+        QueueType::new_named(amqp.network, startup_type, &suffix, consumer)?
+    })
+}
+
+/// Block until a consumer is reading the queue startup account updates will
+/// be published for, or the configured timeout elapses.  Returns true if a
+/// consumer was found.
+pub async fn wait(
+    amqp: &config::Amqp,
+    config: &config::Readiness,
+    startup_type: StartupType,
+    clock: &dyn Clock,
+) -> Result<bool> {
+    let conn = connect(amqp, "geyser-readiness").await?;
+    let queue_type = queue_type(amqp, startup_type, &config.consumer)?;
+
+    let deadline = clock.now() + Duration::from_secs(config.timeout_secs);
+    let mut interval = tokio::time::interval(Duration::from_millis(config.poll_ms.max(10)));
+
+    info!(
+        "Waiting up to {}s for a {:?} consumer before publishing startup updates",
+        config.timeout_secs, config.consumer
+    );
+
+    while clock.now() < deadline {
+        interval.tick().await;
+
+        match queue_type.consumer_count(&conn).await {
+            Ok(Some(n)) if n > 0 => {
+                info!("Found {} consumer(s), starting", n);
+                return Ok(true);
+            },
+            Ok(_) => (),
+            Err(e) => debug!("Failed to query consumer count: {:?}", e),
+        }
+    }
+
+    Ok(false)
+}
+
+/// Check that the queue startup account updates will be published for has
+/// been declared, and therefore bound to the startup exchange, and that its
+/// backlog leaves room for the snapshot.  Fails if either check does not
+/// pass, so a snapshot is never streamed to an exchange nobody reads.
+pub async fn verify(
+    amqp: &config::Amqp,
+    config: &config::StartupCheck,
+    startup_type: StartupType,
+) -> Result<()> {
+    let conn = connect(amqp, "geyser-startup-check").await?;
+    let queue_type = queue_type(amqp, startup_type, &config.consumer)?;
+    let info = indexer_rabbitmq::QueueType::info(&queue_type);
+
+    let status = queue_type
+        .status(&conn)
+        .await
+        .with_context(|| format!("Failed to query startup queue {:?}", info.queue()))?
+        .ok_or_else(|| {
+            anyhow!(
+                "No queue {:?} is bound to startup exchange {:?}; start its consumer before \
+                 the validator, or set startupCheck.disabled",
+                info.queue(),
+                info.exchange()
+            )
+        })?;
+
+    if status.messages > config.max_backlog {
+        bail!(
+            "Startup queue {:?} already holds {} message(s), more than the {} allowed by \
+             startupCheck.maxBacklog",
+            info.queue(),
+            status.messages,
+            config.max_backlog
+        );
+    }
+
+    info!(
+        "Startup queue {:?} is declared with {} consumer(s) and {} message(s) waiting",
+        info.queue(),
+        status.consumers,
+        status.messages
+    );
+
+    Ok(())
+}
@@ -0,0 +1,298 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use solana_geyser_plugin_interface::geyser_plugin_interface::SlotStatus;
+
+use crate::{
+    clock::SharedClock,
+    config,
+    interface::{ReplicaAccountInfo, ReplicaTransactionInfo},
+    prelude::*,
+};
+
+/// A single recorded callback, written as one line of JSON
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum Entry {
+    #[serde(rename_all = "camelCase")]
+    Account {
+        slot: u64,
+        is_startup: bool,
+        pubkey: String,
+        owner: String,
+        lamports: u64,
+        executable: bool,
+        rent_epoch: u64,
+        write_version: u64,
+        /// Base64-encoded account data
+        data: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    Transaction {
+        slot: u64,
+        signature: String,
+        is_vote: bool,
+        failed: bool,
+        account_keys: Vec<String>,
+        instructions: Vec<Instruction>,
+    },
+    #[serde(rename_all = "camelCase")]
+    SlotStatus {
+        slot: u64,
+        parent: Option<u64>,
+        status: &'static str,
+    },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Instruction {
+    /// Index of the top-level instruction, or of the instruction invoking
+    /// this one followed by its position among the inner instructions
+    index: String,
+    program_id_index: u8,
+    accounts: Vec<u8>,
+    /// Base64-encoded instruction data
+    data: String,
+}
+
+#[derive(Debug)]
+struct Session {
+    path: PathBuf,
+    out: BufWriter<File>,
+    until: Instant,
+    max_bytes: u64,
+    written: u64,
+}
+
+/// Time-boxed recording of every callback received by the plugin, before
+/// any filtering, for replaying against selectors offline.  Recording
+/// writes from the callback threads, so it is intended for short repro
+/// windows rather than continuous capture.
+#[derive(Debug)]
+pub struct Recorder {
+    dir: PathBuf,
+    max_duration: Duration,
+    max_bytes: u64,
+    clock: SharedClock,
+    active: AtomicBool,
+    session: Mutex<Option<Session>>,
+}
+
+impl Recorder {
+    pub fn new(config: &config::Recording, clock: SharedClock) -> Self {
+        Self {
+            dir: config.dir.clone(),
+            max_duration: Duration::from_secs(config.max_duration_secs),
+            max_bytes: config.max_bytes,
+            clock,
+            active: AtomicBool::new(false),
+            session: Mutex::new(None),
+        }
+    }
+
+    /// Start a new recording, replacing any recording in progress.  Returns
+    /// the path of the recording, its end time as a Unix timestamp, and its
+    /// maximum size after capping the requested limits.
+    pub fn start(&self, duration_secs: u64, max_bytes: u64) -> Result<(PathBuf, i64, u64)> {
+        let duration = Duration::from_secs(duration_secs).min(self.max_duration);
+        let max_bytes = max_bytes.min(self.max_bytes);
+        let now = self
+            .clock
+            .system_now()
+            .duration_since(UNIX_EPOCH)
+            .context("System clock is before the Unix epoch")?;
+
+        let path = self.dir.join(format!("geyser-{}.jsonl", now.as_millis()));
+        let file = File::create(&path)
+            .with_context(|| format!("Failed to create recording {:?}", path))?;
+
+        let until = (now + duration).as_secs().try_into().unwrap_or(i64::MAX);
+
+        let mut session = self.session.lock();
+
+        if let Some(prev) = session.take() {
+            Self::finish(prev, "replaced by a new recording");
+        }
+
+        info!(
+            "Recording to {:?} for up to {}s or {} byte(s)",
+            path,
+            duration.as_secs(),
+            max_bytes
+        );
+
+        *session = Some(Session {
+            path: path.clone(),
+            out: BufWriter::new(file),
+            until: self.clock.now() + duration,
+            max_bytes,
+            written: 0,
+        });
+        self.active.store(true, Ordering::Release);
+
+        Ok((path, until, max_bytes))
+    }
+
+    #[inline]
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Acquire)
+    }
+
+    fn finish(mut session: Session, reason: &str) {
+        if let Err(e) = session.out.flush() {
+            warn!("Failed to flush recording {:?}: {:?}", session.path, e);
+        }
+
+        info!(
+            "Recording {:?} stopped after {} byte(s): {}",
+            session.path, session.written, reason
+        );
+    }
+
+    /// Stop the recording in progress if its time limit has passed
+    pub fn expire(&self) {
+        if !self.is_active() {
+            return;
+        }
+
+        let mut session = self.session.lock();
+
+        if session
+            .as_ref()
+            .map_or(false, |s| self.clock.now() >= s.until)
+        {
+            self.active.store(false, Ordering::Release);
+
+            if let Some(s) = session.take() {
+                Self::finish(s, "time limit reached");
+            }
+        }
+    }
+
+    fn write(&self, entry: &Entry) {
+        let mut line = match serde_json::to_vec(entry) {
+            Ok(l) => l,
+            Err(e) => {
+                warn!("Failed to serialize recorded entry: {:?}", e);
+                return;
+            },
+        };
+        line.push(b'\n');
+
+        let mut guard = self.session.lock();
+        let session = match guard.as_mut() {
+            Some(s) => s,
+            None => return,
+        };
+
+        let len = line.len().try_into().unwrap_or(u64::MAX);
+        let stop = if self.clock.now() >= session.until {
+            Some("time limit reached")
+        } else if session.written.saturating_add(len) > session.max_bytes {
+            Some("size limit reached")
+        } else if let Err(e) = session.out.write_all(&line) {
+            warn!("Failed to write recording {:?}: {:?}", session.path, e);
+
+            Some("write failed")
+        } else {
+            session.written += len;
+
+            None
+        };
+
+        if let Some(reason) = stop {
+            self.active.store(false, Ordering::Release);
+
+            if let Some(s) = guard.take() {
+                Self::finish(s, reason);
+            }
+        }
+    }
+
+    pub fn account(&self, acct: &ReplicaAccountInfo, slot: u64, is_startup: bool) {
+        if !self.is_active() {
+            return;
+        }
+
+        self.write(&Entry::Account {
+            slot,
+            is_startup,
+            pubkey: bs58::encode(acct.pubkey).into_string(),
+            owner: bs58::encode(acct.owner).into_string(),
+            lamports: acct.lamports,
+            executable: acct.executable,
+            rent_epoch: acct.rent_epoch,
+            write_version: acct.write_version,
+            data: base64::encode(acct.data),
+        });
+    }
+
+    pub fn transaction(&self, tx: &ReplicaTransactionInfo, slot: u64) {
+        if !self.is_active() {
+            return;
+        }
+
+        let msg = tx.transaction.message();
+        let ins =
+            |index: String, ins: &solana_program::instruction::CompiledInstruction| Instruction {
+                index,
+                program_id_index: ins.program_id_index,
+                accounts: ins.accounts.clone(),
+                data: base64::encode(&ins.data),
+            };
+
+        let instructions = msg
+            .instructions()
+            .iter()
+            .enumerate()
+            .map(|(i, c)| ins(i.to_string(), c))
+            .chain(
+                tx.transaction_status_meta
+                    .inner_instructions
+                    .iter()
+                    .flatten()
+                    .flat_map(|inner| {
+                        inner
+                            .instructions
+                            .iter()
+                            .enumerate()
+                            .map(move |(i, c)| (format!("{}.{}", inner.index, i), c))
+                    })
+                    .map(|(i, c)| ins(i, c)),
+            )
+            .collect();
+
+        self.write(&Entry::Transaction {
+            slot,
+            signature: tx.signature.to_string(),
+            is_vote: tx.is_vote,
+            failed: tx.transaction_status_meta.status.is_err(),
+            account_keys: msg.account_keys().iter().map(ToString::to_string).collect(),
+            instructions,
+        });
+    }
+
+    pub fn slot_status(&self, slot: u64, parent: Option<u64>, status: SlotStatus) {
+        if !self.is_active() {
+            return;
+        }
+
+        self.write(&Entry::SlotStatus {
+            slot,
+            parent,
+            status: match status {
+                SlotStatus::Processed => "processed",
+                SlotStatus::Confirmed => "confirmed",
+                SlotStatus::Rooted => "rooted",
+            },
+        });
+    }
+}
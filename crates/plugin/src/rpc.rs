@@ -0,0 +1,108 @@
+use std::time::Duration;
+
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::{config, prelude::*};
+
+#[derive(Deserialize)]
+struct Response<T> {
+    result: Option<T>,
+    error: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct RpcContext {
+    slot: u64,
+}
+
+#[derive(Deserialize)]
+struct WithContext<T> {
+    context: RpcContext,
+    value: T,
+}
+
+/// An account as returned by the `getAccountInfo` RPC method
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Account {
+    pub lamports: u64,
+    pub owner: String,
+    pub executable: bool,
+    pub rent_epoch: u64,
+    /// The account data and its encoding
+    pub data: (String, String),
+}
+
+/// Minimal JSON-RPC client for the validator's local RPC service
+#[derive(Debug, Clone)]
+pub struct Rpc {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl Rpc {
+    pub fn new(config: config::Rpc) -> Result<Self> {
+        let config::Rpc { url, timeout_ms } = config;
+
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(timeout_ms))
+            .build()
+            .context("Failed to create RPC client")?;
+
+        Ok(Self { client, url })
+    }
+
+    pub async fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<T> {
+        let res: Response<T> = self
+            .client
+            .post(&self.url)
+            .json(&serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": params,
+            }))
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .with_context(|| format!("RPC request {:?} failed", method))?
+            .json()
+            .await
+            .with_context(|| format!("Failed to parse RPC response for {:?}", method))?;
+
+        match (res.result, res.error) {
+            (Some(r), _) => Ok(r),
+            (None, Some(e)) => Err(anyhow!("RPC error for {:?}: {}", method, e)),
+            (None, None) => Err(anyhow!("Empty RPC response for {:?}", method)),
+        }
+    }
+
+    /// Fetch an account, returning the slot it was read at along with the
+    /// account, if it exists
+    pub async fn get_account(&self, key: &Pubkey) -> Result<(u64, Option<Account>)> {
+        let WithContext { context, value } = self
+            .call(
+                "getAccountInfo",
+                serde_json::json!([key.to_string(), { "encoding": "base64" }]),
+            )
+            .await?;
+
+        Ok((context.slot, value))
+    }
+}
+
+impl Account {
+    /// Decode the account data
+    pub fn decode_data(&self) -> Result<Vec<u8>> {
+        match self.data {
+            (ref data, ref enc) if enc == "base64" => {
+                base64::decode(data).context("Invalid base64 account data")
+            },
+            (_, ref enc) => Err(anyhow!("Unsupported account data encoding {:?}", enc)),
+        }
+    }
+}
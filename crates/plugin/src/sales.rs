@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+
+use hashbrown::HashMap;
+use indexer_rabbitmq::geyser::{InstructionIndex, NftSale};
+use solana_program::{instruction::CompiledInstruction, message::AccountKeys, pubkey};
+use solana_transaction_status::TransactionStatusMeta;
+
+use crate::prelude::*;
+
+/// The instruction shape of a marketplace's sale instruction
+struct Marketplace {
+    program: Pubkey,
+    /// Anchor discriminator of the sale instruction
+    discriminator: [u8; 8],
+    /// Offset of the little-endian lamport price in the instruction data
+    price_offset: usize,
+}
+
+const MARKETPLACES: &[Marketplace] = &[
+    // Metaplex Auction House execute_sale(escrow_payment_bump,
+    // free_trade_state_bump, program_as_signer_bump, buyer_price, token_size)
+    Marketplace {
+        program: pubkey!("hausS13jsjafwWwGqZTUQRmWyvyxn9EQpqMwV1PBBmk"),
+        discriminator: [37, 74, 217, 157, 79, 49, 35, 6],
+        price_offset: 11,
+    },
+    // Magic Eden v2 execute_sale_v2(escrow_payment_bump,
+    // program_as_signer_bump, buyer_price, maker_fee_bp, taker_fee_bp)
+    Marketplace {
+        program: pubkey!("M2mx93ekt1fmXSVkTrUL9xVFHkmME8HTUi5Cyc5aF7K"),
+        discriminator: [91, 220, 49, 223, 204, 129, 53, 193],
+        price_offset: 10,
+    },
+];
+
+/// Returns the marketplace and price of a sale instruction, if the given
+/// instruction is one
+fn parse_sale(ins: &CompiledInstruction, keys: &AccountKeys) -> Option<(Pubkey, u64)> {
+    let program = keys.get(ins.program_id_index as usize)?;
+    let market = MARKETPLACES.iter().find(|m| m.program == *program)?;
+
+    if !ins.data.starts_with(&market.discriminator) {
+        return None;
+    }
+
+    let price = ins
+        .data
+        .get(market.price_offset..market.price_offset + 8)?
+        .try_into()
+        .ok()?;
+
+    Some((market.program, u64::from_le_bytes(price)))
+}
+
+/// A single token moved between owners
+#[derive(Debug, Default)]
+struct Transfer<'a> {
+    seller: Option<&'a str>,
+    buyer: Option<&'a str>,
+}
+
+/// Find the tokens with no decimals moved in their entirety from one owner to
+/// another, keyed by mint
+fn nft_transfers(meta: &TransactionStatusMeta) -> HashMap<&str, Transfer> {
+    // Balances of each token account by account index, as (owner, mint, pre,
+    // post)
+    let mut balances: BTreeMap<u8, (&str, &str, u64, u64)> = BTreeMap::new();
+
+    for bal in meta.pre_token_balances.iter().flatten() {
+        if bal.ui_token_amount.decimals != 0 {
+            continue;
+        }
+
+        balances.insert(
+            bal.account_index,
+            (
+                bal.owner.as_str(),
+                bal.mint.as_str(),
+                bal.ui_token_amount.amount.parse().unwrap_or(0),
+                0,
+            ),
+        );
+    }
+
+    for bal in meta.post_token_balances.iter().flatten() {
+        if bal.ui_token_amount.decimals != 0 {
+            continue;
+        }
+
+        let entry = balances.entry(bal.account_index).or_insert((
+            bal.owner.as_str(),
+            bal.mint.as_str(),
+            0,
+            0,
+        ));
+        entry.3 = bal.ui_token_amount.amount.parse().unwrap_or(0);
+    }
+
+    let mut transfers: HashMap<&str, Transfer> = HashMap::new();
+
+    for (owner, mint, pre, post) in balances.into_values() {
+        match (pre, post) {
+            (1, 0) => transfers.entry(mint).or_default().seller = Some(owner),
+            (0, 1) => transfers.entry(mint).or_default().buyer = Some(owner),
+            _ => (),
+        }
+    }
+
+    transfers
+}
+
+/// Recognize the NFT sales performed by a successful transaction.  A sale is
+/// a recognized marketplace's sale instruction, invoked directly or by CPI,
+/// which references the mint of a token transferred whole from one owner to
+/// another within the same transaction.
+pub fn detect(
+    instructions: &[(InstructionIndex, &CompiledInstruction)],
+    keys: &AccountKeys,
+    meta: &TransactionStatusMeta,
+    slot: u64,
+    txn_signature: &[u8],
+) -> Vec<NftSale> {
+    let sales: Vec<_> = instructions
+        .iter()
+        .filter_map(|(_, ins)| parse_sale(ins, keys).map(|s| (*ins, s)))
+        .collect();
+
+    if sales.is_empty() {
+        return vec![];
+    }
+
+    let transfers = nft_transfers(meta);
+
+    sales
+        .into_iter()
+        .filter_map(|(ins, (marketplace, price))| {
+            ins.accounts.iter().find_map(|i| {
+                let mint = keys.get(*i as usize)?;
+                let transfer = transfers.get(mint.to_string().as_str())?;
+
+                Some(NftSale {
+                    mint: *mint,
+                    price,
+                    buyer: transfer.buyer?.parse().ok()?,
+                    seller: transfer.seller?.parse().ok()?,
+                    marketplace,
+                    slot,
+                    txn_signature: txn_signature.to_vec(),
+                })
+            })
+        })
+        .collect()
+}
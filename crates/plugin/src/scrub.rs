@@ -0,0 +1,56 @@
+use std::ops::Range;
+
+use hashbrown::HashMap;
+
+use crate::{config, prelude::*};
+
+/// Zeroes configured byte ranges of account data before it is published, so
+/// fields which must not leave the validator never reach the broker
+#[derive(Debug, Default)]
+pub struct Scrubber(HashMap<Pubkey, Vec<Range<usize>>>);
+
+impl Scrubber {
+    pub fn new(config: Vec<config::Scrub>) -> Result<Self> {
+        let mut rules: HashMap<Pubkey, Vec<Range<usize>>> = HashMap::new();
+
+        for config::Scrub { owner, ranges } in config {
+            let owner: Pubkey = owner
+                .parse()
+                .with_context(|| format!("Failed to parse scrub owner {:?}", owner))?;
+
+            let ranges = ranges
+                .into_iter()
+                .map(|config::ScrubRange { offset, len }| {
+                    let end = offset
+                        .checked_add(len)
+                        .ok_or_else(|| anyhow!("Scrub range for {} overflows", owner))?;
+
+                    Ok(offset..end)
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            rules.entry(owner).or_default().extend(ranges);
+        }
+
+        Ok(Self(rules))
+    }
+
+    /// Zero the ranges configured for an account's owner, clamped to the
+    /// length of its data.  Returns true if any rule applied.
+    pub fn scrub(&self, owner: &Pubkey, data: &mut [u8]) -> bool {
+        let ranges = match self.0.get(owner) {
+            Some(r) => r,
+            None => return false,
+        };
+
+        for range in ranges {
+            let end = range.end.min(data.len());
+
+            if let Some(bytes) = data.get_mut(range.start..end) {
+                bytes.fill(0);
+            }
+        }
+
+        true
+    }
+}
@@ -1,3 +1,5 @@
+use indexer_rabbitmq::geyser::AccountUpdate;
+
 use crate::interface::ReplicaAccountInfo;
 use selector::prelude::*;
 use solana_program::instruction::CompiledInstruction;
@@ -22,6 +24,27 @@ impl<'a> AccountInfo for AccountShim<'a> {
     }
 }
 
+/// Selector view of an account copied out of the validator's callback
+#[repr(transparent)]
+pub struct UpdateShim<'a>(pub &'a AccountUpdate);
+
+impl<'a> AccountInfo for UpdateShim<'a> {
+    #[inline]
+    fn owner(&self) -> &[u8] {
+        self.0.owner.as_ref()
+    }
+
+    #[inline]
+    fn pubkey(&self) -> &[u8] {
+        self.0.key.as_ref()
+    }
+
+    #[inline]
+    fn data(&self) -> &[u8] {
+        &self.0.data
+    }
+}
+
 #[repr(transparent)]
 pub struct CompiledInstructionShim<'a>(pub &'a CompiledInstruction);
 
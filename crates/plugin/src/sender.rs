@@ -1,51 +1,389 @@
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use indexer_rabbitmq::{
-    geyser::{Message, Producer, QueueType, StartupType},
-    lapin::{Connection, ConnectionProperties},
+    compression::Dictionaries,
+    encryption::Key,
+    geyser::{
+        self, DeliveryGuarantee, Message, MessageClass, Network, Producer, ProducerId, QueueType,
+        StartupType,
+    },
+    lapin::{BasicProperties, Connection, ConnectionProperties},
     suffix::Suffix,
+    Encoding, Overflow,
 };
-use tokio::sync::{RwLock, RwLockReadGuard};
+use tokio::sync::{mpsc, RwLock, RwLockReadGuard};
 
 use crate::{
+    clock::SharedClock,
     config,
-    metrics::{Counter, Metrics},
+    leader::Leader,
+    metrics::{self, ClassMetrics, Counter, Metrics},
 };
 
+/// Number of times a message nacked by the broker is republished before it
+/// is dropped
+const MAX_REPUBLISHES: u32 = 3;
+
+/// Delay before republishing a nacked message, multiplied by the number of
+/// nacks received for it so far
+const REPUBLISH_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Highest exchange version declared when falling back from an exchange
+/// with mismatched arguments
+const MAX_EXCHANGE_VERSION: u32 = 9;
+
+/// Number of messages queued for each mirror before further messages are
+/// dropped
+const MIRROR_QUEUE_LEN: usize = 4096;
+
+/// Initial delay between attempts to reconnect a mirror, doubled after each
+/// failed attempt
+const MIRROR_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Longest delay between attempts to reconnect a mirror
+const MAX_MIRROR_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Per-message publishing options
+#[derive(Debug, Default, Clone)]
+pub struct SendOpts {
+    /// AMQP priority of the message
+    pub priority: Option<u8>,
+    /// Time after which the broker should discard the message if it has not
+    /// been delivered
+    pub ttl: Option<Duration>,
+    /// Routing key to publish with instead of the exchange binding's
+    pub routing_key: Option<String>,
+}
+
+/// The message streams published by separate senders
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sink {
+    Accounts,
+    Instructions,
+    SlotStatuses,
+    TokenAccounts,
+    Stats,
+}
+
+impl Sink {
+    /// The exchange class this sink publishes to, or `None` if it publishes
+    /// every class to the shared exchange
+    fn class(self, amqp: &config::Amqp) -> Option<MessageClass> {
+        match self {
+            Self::Accounts => amqp.split_exchanges.then_some(MessageClass::AccountUpdates),
+            Self::SlotStatuses => amqp.split_exchanges.then_some(MessageClass::SlotStatuses),
+            Self::Instructions => Some(MessageClass::Instructions),
+            Self::TokenAccounts => Some(MessageClass::TokenAccounts),
+            Self::Stats => Some(MessageClass::Stats),
+        }
+    }
+
+    /// The configured confirm policy of this sink, if any
+    fn policy(self, confirms: config::Confirms) -> Option<bool> {
+        let config::Confirms {
+            accounts,
+            instructions,
+            slot_statuses,
+            token_accounts,
+            stats,
+        } = confirms;
+
+        match self {
+            Self::Accounts => accounts,
+            Self::Instructions => instructions,
+            Self::SlotStatuses => slot_statuses,
+            Self::TokenAccounts => token_accounts,
+            Self::Stats => stats,
+        }
+    }
+
+    /// Returns true if this sink's producers wait for publisher confirms
+    fn confirms(self, amqp: &config::Amqp) -> bool {
+        amqp.mandatory
+            || self
+                .policy(amqp.confirms)
+                .unwrap_or_else(|| amqp.overflow.map_or(false, Overflow::rejects_publish))
+    }
+}
+
+/// The broker, network name, and payload encoding of a set of exchanges
+#[derive(Debug, Clone)]
+struct Target {
+    address: String,
+    network: Network,
+    encoding: Encoding,
+}
+
+impl Target {
+    fn primary(amqp: &config::Amqp) -> Self {
+        Self {
+            address: amqp.address.clone(),
+            network: amqp.network,
+            encoding: Encoding::default(),
+        }
+    }
+}
+
+/// A message queued for publishing to a mirror
+#[derive(Debug)]
+struct Mirrored {
+    msg: Message,
+    routing_key: Option<String>,
+    props: BasicProperties,
+}
+
+/// Handle to a task publishing every message to another set of exchanges,
+/// independently of the primary producer.  Messages are queued so a slow or
+/// unreachable mirror never delays the primary producer, and are dropped
+/// while the queue is full.
+#[derive(Debug)]
+struct Mirror {
+    /// Describes the mirror in logs and connection names
+    label: &'static str,
+    /// Closed by the task once the mirror's migration window closes
+    tx: mpsc::Sender<Mirrored>,
+}
+
+/// The publishing task of a [`Mirror`], owning its producer
+struct MirrorTask {
+    label: &'static str,
+    target: Target,
+    /// End of the migration window, if the mirror has one
+    until: Option<Instant>,
+    amqp: config::Amqp,
+    name: String,
+    startup_type: StartupType,
+    sink: Sink,
+    key: Option<Arc<Key>>,
+    dictionaries: Option<Arc<Dictionaries>>,
+    metrics: Arc<Metrics>,
+    clock: SharedClock,
+}
+
+impl MirrorTask {
+    async fn connect(&self) -> Option<Producer> {
+        Sender::create_producer(
+            &self.amqp,
+            &self.target,
+            format!("{}/{}", self.name, self.label),
+            self.startup_type,
+            self.sink,
+            self.key.as_ref(),
+            self.dictionaries.as_ref(),
+        )
+        .await
+        .map_err(|e| {
+            self.metrics.mirror_errs.log(1);
+            log::error!("Failed to connect {} producer: {:?}", self.label, e);
+        })
+        .ok()
+    }
+
+    /// Publish queued messages until the migration window closes or the
+    /// sender is dropped.  A failed publish drops its producer, and
+    /// reconnection is retried with each later message, backing off after
+    /// every failed attempt.  Messages received while disconnected are
+    /// dropped.
+    async fn run(self, mut rx: mpsc::Receiver<Mirrored>) {
+        let metrics = &self.metrics;
+        let mut producer = self.connect().await;
+        let mut backoff = MIRROR_BACKOFF;
+
+        while let Some(Mirrored {
+            msg,
+            routing_key,
+            props,
+        }) = rx.recv().await
+        {
+            if self.until.map_or(false, |u| self.clock.now() >= u) {
+                log::info!(
+                    "Migration window closed, no longer publishing to {} exchanges",
+                    self.target.network
+                );
+
+                return;
+            }
+
+            if producer.is_none() {
+                producer = self.connect().await;
+
+                if producer.is_none() {
+                    metrics.mirror_drops.log(1);
+                    tokio::time::sleep(backoff).await;
+                    backoff = backoff.saturating_mul(2).min(MAX_MIRROR_BACKOFF);
+
+                    continue;
+                }
+
+                backoff = MIRROR_BACKOFF;
+            }
+
+            let res = match producer {
+                Some(ref p) => p.write_routed(&msg, routing_key.as_deref(), props).await,
+                None => continue,
+            };
+
+            match res {
+                Ok(_) => metrics.mirror_sends.log(1),
+                Err(e) => {
+                    metrics.mirror_errs.log(1);
+                    log::error!("Failed to publish to {} exchange: {:?}", self.label, e);
+                    producer = None;
+                },
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Sender {
     amqp: config::Amqp,
     name: String,
     startup_type: StartupType,
+    sink: Sink,
+    class: Option<MessageClass>,
+    properties: BasicProperties,
+    key: Option<Arc<Key>>,
+    dictionaries: Option<Arc<Dictionaries>>,
     producer: RwLock<Producer>,
+    mirrors: Vec<Mirror>,
+    leader: Option<Arc<Leader>>,
     metrics: Arc<Metrics>,
 }
 
 impl Sender {
+    #[allow(clippy::too_many_arguments)]
     pub async fn new(
         amqp: config::Amqp,
         name: String,
         startup_type: StartupType,
+        sink: Sink,
+        producer_id: &ProducerId,
+        leader: Option<Arc<Leader>>,
         metrics: Arc<Metrics>,
-    ) -> Result<Self, indexer_rabbitmq::Error> {
-        let producer = Self::create_producer(&amqp, name.as_ref(), startup_type).await?;
+        clock: SharedClock,
+    ) -> anyhow::Result<Self> {
+        let key = amqp
+            .encryption
+            .as_ref()
+            .map(config::Encryption::load)
+            .transpose()?
+            .map(Arc::new);
+        let dictionaries = amqp
+            .compression
+            .as_ref()
+            .map(config::Compression::load)
+            .transpose()?
+            .map(Arc::new);
+        if amqp.mandatory && sink.policy(amqp.confirms) == Some(false) {
+            log::warn!(
+                "Enabling publisher confirms for {:?} anyway, mandatory publishing requires them",
+                sink
+            );
+        }
+
+        let producer = Self::create_producer(
+            &amqp,
+            &Target::primary(&amqp),
+            name.as_ref(),
+            startup_type,
+            sink,
+            key.as_ref(),
+            dictionaries.as_ref(),
+        )
+        .await?;
+
+        let migration = amqp.migration.as_ref().map(|m| {
+            let target = Target {
+                network: m.network,
+                ..Target::primary(&amqp)
+            };
+            let until = clock.now() + Duration::from_secs(m.window_secs);
+
+            ("migration", target, Some(until))
+        });
+
+        let secondary = amqp.secondary.as_ref().map(|s| {
+            let target = Target {
+                address: s.address.clone().unwrap_or_else(|| amqp.address.clone()),
+                network: s.network,
+                encoding: s.encoding,
+            };
+
+            ("secondary", target, None)
+        });
+
+        let mut mirrors = vec![];
+
+        for (label, target, until) in migration.into_iter().chain(secondary) {
+            let (tx, rx) = mpsc::channel(MIRROR_QUEUE_LEN);
+            let task = MirrorTask {
+                label,
+                target,
+                until,
+                amqp: amqp.clone(),
+                name: name.clone(),
+                startup_type,
+                sink,
+                key: key.clone(),
+                dictionaries: dictionaries.clone(),
+                metrics: Arc::clone(&metrics),
+                clock: Arc::clone(&clock),
+            };
+
+            tokio::spawn(task.run(rx));
+            mirrors.push(Mirror { label, tx });
+        }
+
+        let guarantee = if sink.confirms(&amqp) {
+            DeliveryGuarantee::AtLeastOnce
+        } else {
+            DeliveryGuarantee::AtMostOnce
+        };
+        let mut headers = producer_id.to_headers();
+        guarantee.stamp(&mut headers);
 
         Ok(Self {
+            class: sink.class(&amqp),
             amqp,
             name,
             startup_type,
+            sink,
+            properties: BasicProperties::default().with_headers(headers),
+            key,
+            dictionaries,
             producer: RwLock::new(producer),
+            mirrors,
+            leader,
             metrics,
         })
     }
 
+    #[inline]
+    pub fn amqp(&self) -> &config::Amqp {
+        &self.amqp
+    }
+
+    /// Returns true if the producer's channel is open.  A producer in the
+    /// middle of reconnecting is reported as disconnected.
+    pub fn is_connected(&self) -> bool {
+        self.producer.try_read().map_or(false, |p| p.is_connected())
+    }
+
     async fn create_producer(
         amqp: &config::Amqp,
+        target: &Target,
         name: impl Into<indexer_rabbitmq::lapin::types::LongString>,
         startup_type: StartupType,
+        sink: Sink,
+        key: Option<&Arc<Key>>,
+        dictionaries: Option<&Arc<Dictionaries>>,
     ) -> Result<Producer, indexer_rabbitmq::Error> {
         let conn = Connection::connect(
-            &amqp.address,
+            &target.address,
             ConnectionProperties::default()
                 .with_connection_name(name.into())
                 .with_executor(tokio_executor_trait::Tokio::current())
@@ -53,11 +391,75 @@ impl Sender {
         )
         .await?;
 
-        Producer::new(
-            &conn,
-            QueueType::new(amqp.network, startup_type, &Suffix::ProductionUnchecked)?,
-        )
-        .await
+        let network = target.network;
+        let suffix = Suffix::ProductionUnchecked;
+        let queue_type = match sink.class(amqp) {
+            Some(class) => QueueType::new_class(network, startup_type, &suffix, class, "indexer")?,
+            None => QueueType::new(network, startup_type, &suffix)?,
+        };
+
+        let queue_type = if sink.confirms(amqp) {
+            queue_type.with_confirms()
+        } else {
+            queue_type
+        };
+
+        let queue_type = if amqp.mandatory {
+            queue_type.with_mandatory(amqp.fallback_queue.clone())
+        } else {
+            queue_type
+        };
+
+        let producer = match Producer::new(&conn, queue_type.clone()).await {
+            Err(e @ indexer_rabbitmq::Error::DeclarationMismatch { .. })
+                if amqp.exchange_fallback =>
+            {
+                log::error!("{}", e);
+                Self::declare_fallback(&conn, queue_type).await?
+            },
+            res => res?,
+        };
+
+        let producer = producer
+            .with_encoding(target.encoding)
+            .with_encodings(amqp.owner_encodings.clone());
+
+        let producer = match dictionaries {
+            Some(dicts) => producer.with_compression(Arc::clone(dicts)),
+            None => producer,
+        };
+
+        Ok(match key {
+            Some(key) => producer.with_encryption(Arc::clone(key)),
+            None => producer,
+        })
+    }
+
+    /// Declare successive versions of an exchange whose default declaration
+    /// was rejected, until one is accepted by the broker.  A rejected
+    /// declaration closes its channel, so each attempt opens a new one.
+    async fn declare_fallback(
+        conn: &Connection,
+        queue_type: QueueType,
+    ) -> Result<Producer, indexer_rabbitmq::Error> {
+        let mut version = 2;
+
+        loop {
+            match Producer::new(conn, queue_type.clone().with_exchange_version(version)).await {
+                Ok(producer) => {
+                    log::warn!("Declared fallback exchange version {}", version);
+
+                    return Ok(producer);
+                },
+                Err(e @ indexer_rabbitmq::Error::DeclarationMismatch { .. })
+                    if version < MAX_EXCHANGE_VERSION =>
+                {
+                    log::error!("{}", e);
+                    version += 1;
+                },
+                Err(e) => return Err(e),
+            }
+        }
     }
 
     async fn connect<'a>(
@@ -69,12 +471,60 @@ impl Sender {
         std::mem::drop(prod);
         let mut prod = self.producer.write().await;
 
-        *prod = Self::create_producer(&self.amqp, self.name.as_ref(), self.startup_type).await?;
+        *prod = Self::create_producer(
+            &self.amqp,
+            &Target::primary(&self.amqp),
+            self.name.as_ref(),
+            self.startup_type,
+            self.sink,
+            self.key.as_ref(),
+            self.dictionaries.as_ref(),
+        )
+        .await?;
 
         Ok(prod.downgrade())
     }
 
+    fn log_unroutable(metrics: &Metrics, class: &ClassMetrics, diverted: bool) {
+        metrics.unroutable.log(1);
+
+        if !diverted {
+            class.dropped();
+            log::warn!("Message could not be routed to any queue, is a binding missing?");
+        }
+    }
+
+    fn log_delivered(metrics: &Metrics, class: &ClassMetrics, len: usize) {
+        metrics.bytes_sent.log(len);
+        metrics.bytes_total.add(len);
+        class.delivered(len);
+    }
+
+    /// Queue a message for each mirror's exchanges, unless its migration
+    /// window has closed
+    fn send_mirrors(&self, msg: &Message, opts: &SendOpts, props: &BasicProperties) {
+        for mirror in &self.mirrors {
+            let res = mirror.tx.try_send(Mirrored {
+                msg: msg.clone(),
+                routing_key: opts.routing_key.clone(),
+                props: props.clone(),
+            });
+
+            if let Err(mpsc::error::TrySendError::Full(_)) = res {
+                self.metrics.mirror_drops.log(1);
+                log::warn!("{} queue full, dropping message", mirror.label);
+            }
+        }
+    }
+
+    #[inline]
     pub async fn send(&self, msg: Message) {
+        self.send_with(msg, SendOpts::default()).await;
+    }
+
+    /// Publish a message, returning true if the broker accepted it for its
+    /// destination exchange
+    pub async fn send_with(&self, msg: Message, opts: SendOpts) -> bool {
         #[inline]
         fn log_err<E: std::fmt::Debug>(counter: &'_ Counter) -> impl FnOnce(E) + '_ {
             |err| {
@@ -84,25 +534,79 @@ impl Sender {
         }
 
         let metrics = &self.metrics;
-        let prod = self.producer.read().await;
+        let class = metrics.class(self.class.unwrap_or_else(|| metrics::class_of(&msg)));
 
-        if prod
-            .write(&msg)
-            .await
-            .map_err(log_err(&metrics.errs))
-            .is_ok()
-        {
-            return;
+        if self.leader.as_ref().map_or(false, |l| !l.is_active()) {
+            metrics.standby_drops.log(1);
+            class.dropped();
+            return false;
         }
 
-        metrics.reconnects.log(1);
-        let prod = match self.connect(prod).await.map_err(log_err(&metrics.errs)) {
-            Ok(p) => p,
-            Err(()) => return,
-        };
+        let mut props = self.properties.clone();
+
+        if self.amqp.message_ids {
+            props = props.with_message_id(msg.message_id().into());
+        }
+
+        props = geyser::stamp_publish_time(props);
+
+        if let Some(priority) = opts.priority {
+            props = props.with_priority(priority);
+        }
+
+        if let Some(ttl) = opts.ttl {
+            props = props.with_expiration(ttl.as_millis().to_string().into());
+        }
+
+        self.send_mirrors(&msg, &opts, &props);
+        let mut prod = self.producer.read().await;
+        let mut nacks = 0;
+        let mut reconnected = false;
 
-        match prod.write(&msg).await.map_err(log_err(&metrics.errs)) {
-            Ok(()) | Err(()) => (), // Type-level assertion that we consumed the error
+        loop {
+            match prod
+                .write_routed(&msg, opts.routing_key.as_deref(), props.clone())
+                .await
+            {
+                Ok(len) => {
+                    Self::log_delivered(metrics, class, len);
+                    return true;
+                },
+                Err(indexer_rabbitmq::Error::PublishNacked) => {
+                    // The broker is up but refusing messages, so reconnecting
+                    // won't help; give it time to drain instead
+                    if nacks >= MAX_REPUBLISHES {
+                        metrics.publish_rejects.log(1);
+                        class.dropped();
+                        return false;
+                    }
+
+                    nacks += 1;
+                    metrics.republishes.log(1);
+                    tokio::time::sleep(REPUBLISH_BACKOFF * nacks).await;
+                },
+                Err(indexer_rabbitmq::Error::Unroutable { diverted }) => {
+                    Self::log_unroutable(metrics, class, diverted);
+                    return false;
+                },
+                Err(e) => {
+                    log_err(&metrics.errs)(e);
+
+                    if reconnected {
+                        class.dropped();
+                        return false;
+                    }
+
+                    reconnected = true;
+                    metrics.reconnects.log(1);
+                    prod = if let Ok(p) = self.connect(prod).await.map_err(log_err(&metrics.errs)) {
+                        p
+                    } else {
+                        class.dropped();
+                        return false;
+                    };
+                },
+            }
         }
     }
 }
@@ -0,0 +1,63 @@
+use std::collections::BTreeMap;
+
+use parking_lot::Mutex;
+
+use crate::clock::SharedClock;
+
+/// Estimator for the wall-clock time of slots, based on when their statuses
+/// were first observed
+#[derive(Debug)]
+pub struct SlotClock {
+    clock: SharedClock,
+    seen: Mutex<BTreeMap<u64, i64>>,
+}
+
+impl SlotClock {
+    /// Number of recent slots to remember
+    const WINDOW: usize = 512;
+    /// Fallback cadence used before enough slots have been observed
+    const DEFAULT_SLOT_MS: i64 = 400;
+
+    pub fn new(clock: SharedClock) -> Self {
+        Self {
+            clock,
+            seen: Mutex::default(),
+        }
+    }
+
+    /// Record a status for the given slot, returning its estimated Unix
+    /// timestamp in milliseconds
+    pub fn observe(&self, slot: u64) -> i64 {
+        let mut seen = self.seen.lock();
+
+        if let Some(&time) = seen.get(&slot) {
+            return time;
+        }
+
+        let time = match (seen.iter().next(), seen.iter().next_back()) {
+            // A slot first observed after newer slots is placed according to
+            // the recent slot cadence rather than the time it arrived
+            (Some((&first, &first_time)), Some((&last, &last_time))) if slot < last => {
+                let cadence = if last > first {
+                    (last_time - first_time) / i64::try_from(last - first).unwrap_or(i64::MAX)
+                } else {
+                    Self::DEFAULT_SLOT_MS
+                };
+                let behind = i64::try_from(last - slot).unwrap_or(i64::MAX);
+
+                last_time.saturating_sub(behind.saturating_mul(cadence))
+            },
+            _ => self.clock.unix_ms(),
+        };
+
+        seen.insert(slot, time);
+
+        if seen.len() > Self::WINDOW {
+            if let Some(&oldest) = seen.keys().next() {
+                seen.remove(&oldest);
+            }
+        }
+
+        time
+    }
+}
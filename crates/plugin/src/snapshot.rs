@@ -0,0 +1,116 @@
+use std::time::Duration;
+
+use indexer_rabbitmq::{
+    geyser::AccountUpdate,
+    job_runner::{self, AccountSnapshotRequest},
+    lapin::{
+        options::{BasicAckOptions, BasicRejectOptions},
+        Connection, ConnectionProperties,
+    },
+    suffix::Suffix,
+};
+
+use crate::{config, metrics::Metrics, prelude::*, rpc::Rpc};
+
+/// Delay before reconnecting after the job consumer fails
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+async fn snapshot(rpc: &Rpc, key: Pubkey) -> Result<Option<AccountUpdate>> {
+    let (slot, acct) = rpc.get_account(&key).await?;
+    let acct = match acct {
+        Some(a) => a,
+        None => return Ok(None),
+    };
+
+    Ok(Some(AccountUpdate {
+        key,
+        lamports: acct.lamports,
+        owner: acct.owner.parse().context("Invalid account owner")?,
+        executable: acct.executable,
+        rent_epoch: acct.rent_epoch,
+        data: acct.decode_data()?,
+        // RPC doesn't expose write versions, and an on-demand update
+        // shouldn't be ordered after live ones anyway
+        write_version: 0,
+        slot,
+        is_startup: false,
+        on_demand: true,
+        mint_info: None,
+        data_hash: None,
+    }))
+}
+
+async fn consume(
+    amqp: &config::Amqp,
+    queue_type: job_runner::QueueType,
+    rpc: &Rpc,
+    publish: &(impl Fn(AccountUpdate) + Sync),
+) -> Result<()> {
+    let conn = Connection::connect(
+        &amqp.address,
+        ConnectionProperties::default()
+            .with_connection_name("geyser-snapshots".into())
+            .with_executor(tokio_executor_trait::Tokio::current())
+            .with_reactor(tokio_reactor_trait::Tokio),
+    )
+    .await?;
+
+    let mut consumer = job_runner::Consumer::new(&conn, queue_type, "geyser-snapshots").await?;
+
+    while let Some((msg, acker)) = consumer.read().await? {
+        let key = match msg {
+            job_runner::Message::RequestAccountSnapshot(AccountSnapshotRequest { pubkey }) => {
+                pubkey
+            },
+            m => {
+                debug!("Ignoring unsupported job {:?}", m);
+                acker.ack(BasicAckOptions::default()).await?;
+                continue;
+            },
+        };
+
+        match snapshot(rpc, key).await {
+            Ok(Some(update)) => publish(update),
+            Ok(None) => debug!("Snapshot requested for nonexistent account {}", key),
+            Err(e) => {
+                // Let the dead-letter queue retry the request
+                warn!("Failed to snapshot account {}: {:?}", key, e);
+                acker.reject(BasicRejectOptions::default()).await?;
+                continue;
+            },
+        }
+
+        acker.ack(BasicAckOptions::default()).await?;
+    }
+
+    Ok(())
+}
+
+/// Answer account snapshot requests posted to the configured sender's
+/// snapshot request exchange until the plugin is unloaded
+pub async fn run(
+    amqp: config::Amqp,
+    config: config::Snapshots,
+    rpc: Rpc,
+    metrics: std::sync::Arc<Metrics>,
+    publish: impl Fn(AccountUpdate) + Send + Sync,
+) {
+    let config::Snapshots { sender } = config;
+
+    loop {
+        let res = async {
+            let queue_type =
+                job_runner::QueueType::snapshot_requests(&sender, &Suffix::ProductionUnchecked)?;
+
+            consume(&amqp, queue_type, &rpc, &publish).await
+        }
+        .await;
+
+        if let Err(e) = res {
+            error!("Snapshot request consumer failed: {:?}", e);
+            metrics.errs.log(1);
+        }
+
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
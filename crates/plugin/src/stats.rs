@@ -0,0 +1,120 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use indexer_rabbitmq::geyser::{ProducerLifecycle, Stats};
+use parking_lot::Mutex;
+
+use crate::{clock::SharedClock, metrics::Metrics, sender::Sender};
+
+/// Cumulative counts as of the last summary
+#[derive(Debug, Default, Clone, Copy)]
+struct Totals {
+    accounts: usize,
+    instructions: usize,
+    statuses: usize,
+    bytes: usize,
+    drops: usize,
+    reconnects: usize,
+}
+
+impl Totals {
+    fn read(metrics: &Metrics) -> Self {
+        Self {
+            accounts: metrics.acct_sends.count() + metrics.acct_reassigns.count(),
+            instructions: metrics.ins_sends.count()
+                + metrics.sig_sends.count()
+                + metrics.sale_sends.count(),
+            statuses: metrics.status_sends.count(),
+            bytes: metrics.bytes_sent.count(),
+            drops: metrics.standby_drops.count()
+                + metrics.publish_rejects.count()
+                + metrics.unroutable.count()
+                + metrics.middleware_drops.count()
+                + metrics.acct_expired.count(),
+            reconnects: metrics.reconnects.count(),
+        }
+    }
+}
+
+/// Summarizer of the counts logged between successive health summaries
+#[derive(Debug)]
+pub struct Summarizer {
+    interval: Duration,
+    features: Vec<String>,
+    clock: SharedClock,
+    last: Mutex<Totals>,
+}
+
+impl Summarizer {
+    pub fn new(interval: Duration, features: Vec<String>, clock: SharedClock) -> Self {
+        Self {
+            interval,
+            features,
+            clock,
+            last: Mutex::default(),
+        }
+    }
+
+    #[inline]
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Summarize the counts logged since the last call
+    pub fn sample(&self, metrics: &Metrics, lifecycle: &ProducerLifecycle, slot: u64) -> Stats {
+        fn delta(curr: usize, prev: usize) -> u64 {
+            curr.saturating_sub(prev).try_into().unwrap_or(u64::MAX)
+        }
+
+        let curr = Totals::read(metrics);
+        let prev = std::mem::replace(&mut *self.last.lock(), curr);
+
+        Stats {
+            producer: lifecycle.id.clone(),
+            timestamp: self
+                .clock
+                .system_now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs().try_into().unwrap_or(i64::MAX)),
+            interval_secs: self.interval.as_secs(),
+            slot,
+            account_msgs: delta(curr.accounts, prev.accounts),
+            instruction_msgs: delta(curr.instructions, prev.instructions),
+            slot_status_msgs: delta(curr.statuses, prev.statuses),
+            bytes: delta(curr.bytes, prev.bytes),
+            drops: delta(curr.drops, prev.drops),
+            reconnects: delta(curr.reconnects, prev.reconnects),
+            backlog: metrics.tasks_in_flight.get().try_into().unwrap_or(0),
+            build: lifecycle.build.clone(),
+            features: self.features.clone(),
+        }
+    }
+}
+
+/// Periodic publisher of producer health summaries
+#[derive(Debug)]
+pub struct Reporter {
+    sender: Sender,
+    summary: Summarizer,
+}
+
+impl Reporter {
+    pub fn new(sender: Sender, summary: Summarizer) -> Self {
+        Self { sender, summary }
+    }
+
+    #[inline]
+    pub fn interval(&self) -> Duration {
+        self.summary.interval()
+    }
+
+    /// Summarize the counts logged since the last call
+    #[inline]
+    pub fn sample(&self, metrics: &Metrics, lifecycle: &ProducerLifecycle, slot: u64) -> Stats {
+        self.summary.sample(metrics, lifecycle, slot)
+    }
+
+    #[inline]
+    pub fn sender(&self) -> &Sender {
+        &self.sender
+    }
+}
@@ -0,0 +1,206 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+
+use hashbrown::{HashMap, HashSet};
+use indexer_rabbitmq::{
+    control::{self, Reply, Request, Subscription},
+    lapin::{options::BasicAckOptions, Connection, ConnectionProperties},
+    suffix::Suffix,
+};
+use parking_lot::RwLock;
+
+use crate::{clock::SharedClock, config, metrics::Metrics, prelude::*};
+
+/// Delay before reconnecting after the control consumer fails
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Default)]
+struct State {
+    subs: HashMap<String, (Instant, Subscription)>,
+    /// Union of every active subscription
+    accounts: HashSet<Pubkey>,
+    owners: HashSet<Pubkey>,
+    programs: HashSet<Pubkey>,
+}
+
+impl State {
+    fn rebuild(&mut self) {
+        self.accounts.clear();
+        self.owners.clear();
+        self.programs.clear();
+
+        for (_, sub) in self.subs.values() {
+            self.accounts.extend(&sub.accounts);
+            self.owners.extend(&sub.owners);
+            self.programs.extend(&sub.programs);
+        }
+    }
+}
+
+/// Ephemeral selections registered by consumers over the control queue,
+/// unioned with the configured selectors
+#[derive(Debug)]
+pub struct Subscriptions {
+    max_subscriptions: usize,
+    max_ttl: Duration,
+    clock: SharedClock,
+    /// Fast path for the common case of no active subscriptions
+    any: AtomicBool,
+    state: RwLock<State>,
+}
+
+impl Subscriptions {
+    pub fn new(config: &config::Control, clock: SharedClock) -> Self {
+        Self {
+            max_subscriptions: config.max_subscriptions,
+            max_ttl: Duration::from_secs(config.max_ttl_secs),
+            clock,
+            any: AtomicBool::new(false),
+            state: RwLock::new(State::default()),
+        }
+    }
+
+    fn key(bytes: &[u8]) -> Option<Pubkey> {
+        bytes.try_into().ok().map(Pubkey::new_from_array)
+    }
+
+    /// Returns true if an account is selected by any active subscription
+    pub fn is_account_selected(&self, key: &[u8], owner: &[u8]) -> bool {
+        if !self.any.load(Ordering::Acquire) {
+            return false;
+        }
+
+        let state = self.state.read();
+
+        Self::key(key).map_or(false, |k| state.accounts.contains(&k))
+            || Self::key(owner).map_or(false, |o| state.owners.contains(&o))
+    }
+
+    /// Returns true if a program's instructions are selected by any active
+    /// subscription
+    pub fn is_program_selected(&self, program: &Pubkey) -> bool {
+        self.any.load(Ordering::Acquire) && self.state.read().programs.contains(program)
+    }
+
+    fn subscribe(&self, mut sub: Subscription) -> Reply {
+        let ttl = Duration::from_secs(sub.ttl_secs).min(self.max_ttl);
+        sub.ttl_secs = ttl.as_secs();
+
+        let mut state = self.state.write();
+
+        if state.subs.len() >= self.max_subscriptions && !state.subs.contains_key(&sub.id) {
+            return Reply::Rejected(format!(
+                "Subscription limit of {} reached",
+                self.max_subscriptions
+            ));
+        }
+
+        let id = sub.id.clone();
+        state.subs.insert(id.clone(), (self.clock.now() + ttl, sub));
+        state.rebuild();
+        self.any.store(true, Ordering::Release);
+
+        let expires_at = (self.clock.system_now() + ttl)
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs().try_into().unwrap_or(i64::MAX));
+
+        info!("Subscription {:?} registered until {}", id, expires_at);
+
+        Reply::Subscribed { id, expires_at }
+    }
+
+    fn unsubscribe(&self, id: String) -> Reply {
+        let mut state = self.state.write();
+
+        if state.subs.remove(&id).is_some() {
+            info!("Subscription {:?} removed", id);
+            state.rebuild();
+            self.any.store(!state.subs.is_empty(), Ordering::Release);
+        }
+
+        Reply::Unsubscribed(id)
+    }
+
+    pub fn handle(&self, req: Request) -> Reply {
+        match req {
+            Request::Subscribe(sub) => self.subscribe(sub),
+            Request::Unsubscribe(id) => self.unsubscribe(id),
+            Request::Status | Request::Trace { .. } | Request::Record { .. } => {
+                Reply::Rejected("Not a subscription request".into())
+            },
+        }
+    }
+
+    /// Remove any subscriptions past their TTL
+    pub fn expire(&self) {
+        if !self.any.load(Ordering::Acquire) {
+            return;
+        }
+
+        let now = self.clock.now();
+        let mut state = self.state.write();
+        let len = state.subs.len();
+        state.subs.retain(|id, (expires, _)| {
+            let live = *expires > now;
+
+            if !live {
+                info!("Subscription {:?} expired", id);
+            }
+
+            live
+        });
+
+        if state.subs.len() != len {
+            state.rebuild();
+            self.any.store(!state.subs.is_empty(), Ordering::Release);
+        }
+    }
+}
+
+async fn consume(
+    amqp: &config::Amqp,
+    name: &str,
+    handle: &(impl Fn(Request) -> Reply + Sync),
+) -> Result<()> {
+    let conn = Connection::connect(
+        &amqp.address,
+        ConnectionProperties::default()
+            .with_connection_name("geyser-control".into())
+            .with_executor(tokio_executor_trait::Tokio::current())
+            .with_reactor(tokio_reactor_trait::Tokio),
+    )
+    .await?;
+
+    let queue_type = control::QueueType::new(amqp.network, &Suffix::ProductionUnchecked, name)?;
+    let mut consumer = control::Consumer::new(&conn, queue_type, "geyser-control").await?;
+    let chan = conn.create_channel().await?;
+
+    while let Some((req, props, acker)) = consumer.read_with_properties().await? {
+        debug!("Received control request {:?}", req);
+
+        control::reply(&chan, &props, &handle(req)).await?;
+        acker.ack(BasicAckOptions::default()).await?;
+    }
+
+    Ok(())
+}
+
+/// Answer control requests addressed to this producer until the plugin is
+/// unloaded
+pub async fn run(
+    amqp: config::Amqp,
+    name: String,
+    metrics: std::sync::Arc<Metrics>,
+    handle: impl Fn(Request) -> Reply + Send + Sync,
+) {
+    loop {
+        if let Err(e) = consume(&amqp, &name, &handle).await {
+            error!("Control request consumer failed: {:?}", e);
+            metrics.errs.log(1);
+        }
+
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
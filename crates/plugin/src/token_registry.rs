@@ -0,0 +1,108 @@
+use std::{fs, path::Path, time::Duration};
+
+use hashbrown::HashSet;
+use serde::Deserialize;
+
+use crate::{config, http, prelude::*};
+
+const URL: &str =
+    "https://raw.githubusercontent.com/solana-labs/token-list/main/src/tokens/solana.tokenlist.json";
+
+#[derive(Deserialize)]
+struct TokenItem {
+    address: String,
+}
+
+#[derive(Deserialize)]
+struct TokenList {
+    tokens: Vec<TokenItem>,
+}
+
+fn parse(addrs: impl IntoIterator<Item = String>) -> Result<HashSet<Pubkey>> {
+    addrs
+        .into_iter()
+        .map(|a| a.parse())
+        .collect::<StdResult<_, _>>()
+        .context("Failed to convert token list")
+}
+
+async fn fetch(http: &http::Client) -> Result<HashSet<Pubkey>> {
+    let res: TokenList = http.get_json(URL).await?;
+
+    parse(res.tokens.into_iter().map(|TokenItem { address }| address))
+}
+
+/// Read a cached registry, returning it along with its age
+fn read_cache(path: &Path) -> Result<(HashSet<Pubkey>, Duration)> {
+    let age = fs::metadata(path)
+        .and_then(|m| m.modified())
+        .context("Failed to stat token registry cache")?
+        .elapsed()
+        .unwrap_or_default();
+
+    let f = fs::File::open(path).context("Failed to open token registry cache")?;
+    let addrs: Vec<String> =
+        serde_json::from_reader(f).context("Failed to parse token registry cache")?;
+
+    Ok((parse(addrs)?, age))
+}
+
+fn write_cache(path: &Path, addrs: &HashSet<Pubkey>) -> Result<()> {
+    let addrs: Vec<_> = addrs.iter().map(ToString::to_string).collect();
+    let json = serde_json::to_vec(&addrs).context("Failed to serialize token registry")?;
+
+    // Write-then-rename so a crash never leaves a truncated cache
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, json).context("Failed to write token registry cache")?;
+    fs::rename(&tmp, path).context("Failed to replace token registry cache")
+}
+
+/// Load the Solana token registry, using the local cache if one is configured
+/// and falling back to it if the registry cannot be fetched.  If outbound HTTP
+/// is disabled the cache is always used.
+pub async fn load(config: &config::TokenRegistry, http: &http::Client) -> Result<HashSet<Pubkey>> {
+    let path = match config.cache_path {
+        Some(ref p) => p,
+        None if http.is_enabled() => return fetch(http).await,
+        None => bail!("Outbound HTTP is disabled and no token registry cache path is set"),
+    };
+
+    if !http.is_enabled() {
+        return read_cache(path).map(|(addrs, _)| addrs);
+    }
+
+    let cached = match read_cache(path) {
+        Ok((addrs, age)) if age < Duration::from_secs(config.cache_ttl_secs) => {
+            info!("Using cached token registry from {:?}", path);
+
+            return Ok(addrs);
+        },
+        Ok((addrs, _)) => Some(addrs),
+        Err(e) => {
+            debug!("No usable token registry cache at {:?}: {:?}", path, e);
+
+            None
+        },
+    };
+
+    match fetch(http).await {
+        Ok(addrs) => {
+            if let Err(e) = write_cache(path, &addrs) {
+                warn!("Failed to cache token registry at {:?}: {:?}", path, e);
+            }
+
+            Ok(addrs)
+        },
+        Err(e) => match cached {
+            Some(addrs) => {
+                warn!(
+                    "Failed to fetch token registry, falling back to stale cache: {:?}",
+                    e
+                );
+
+                Ok(addrs)
+            },
+            None => Err(e),
+        },
+    }
+}
@@ -0,0 +1,152 @@
+use std::{fs, io, mem, path::PathBuf};
+
+use hashbrown::HashMap;
+use indexer_rabbitmq::geyser::AccountDeleted;
+use parking_lot::Mutex;
+
+use crate::{config, prelude::*};
+
+/// Length of an index entry, an account key followed by its owner
+const ENTRY_LEN: usize = 64;
+
+#[derive(Debug, Default)]
+struct State {
+    /// Accounts published by the previous run which have not yet reappeared
+    /// in the startup snapshot
+    prev: HashMap<Pubkey, Pubkey>,
+    /// Owners of every live account published by this run
+    index: HashMap<Pubkey, Pubkey>,
+    startup_accounts: u64,
+    startup_slot: u64,
+    startup_done: bool,
+}
+
+/// Persisted index of published accounts, used to detect accounts deleted
+/// while the validator was offline.  Any account published by the previous
+/// run but absent from the startup snapshot is reported as deleted.
+#[derive(Debug)]
+pub struct Tombstones {
+    path: PathBuf,
+    state: Mutex<State>,
+}
+
+impl Tombstones {
+    pub fn new(config: &config::Tombstones) -> Result<Self> {
+        let prev = match fs::read(&config.index_path) {
+            Ok(b) => parse(&b).with_context(|| {
+                format!("Failed to parse account index {:?}", config.index_path)
+            })?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                info!(
+                    "No account index at {:?}, tombstones will start with the next run",
+                    config.index_path
+                );
+
+                HashMap::new()
+            },
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!("Failed to read account index {:?}", config.index_path)
+                })
+            },
+        };
+
+        Ok(Self {
+            path: config.index_path.clone(),
+            state: Mutex::new(State {
+                prev,
+                ..State::default()
+            }),
+        })
+    }
+
+    /// Record a published account update
+    pub fn record(&self, key: Pubkey, owner: Pubkey, lamports: u64, is_startup: bool, slot: u64) {
+        let mut state = self.state.lock();
+
+        if lamports == 0 {
+            state.index.remove(&key);
+        } else {
+            state.index.insert(key, owner);
+        }
+
+        if is_startup {
+            state.prev.remove(&key);
+            state.startup_accounts += 1;
+            state.startup_slot = state.startup_slot.max(slot);
+        }
+    }
+
+    /// Finish the startup snapshot, returning a tombstone for each account
+    /// published by the previous run which did not appear in it
+    pub fn end_startup(&self) -> Vec<AccountDeleted> {
+        let mut state = self.state.lock();
+
+        if mem::replace(&mut state.startup_done, true) {
+            return vec![];
+        }
+
+        let prev = mem::take(&mut state.prev);
+
+        // Without a snapshot every account would look deleted
+        if state.startup_accounts == 0 {
+            if !prev.is_empty() {
+                warn!(
+                    "No startup accounts were published, not emitting tombstones for {} \
+                     previously-published account(s)",
+                    prev.len()
+                );
+            }
+
+            for (key, owner) in prev {
+                state.index.entry(key).or_insert(owner);
+            }
+
+            return vec![];
+        }
+
+        let slot = state.startup_slot;
+
+        prev.into_iter()
+            .map(|(key, owner)| AccountDeleted { key, owner, slot })
+            .collect()
+    }
+
+    /// Write the index of live published accounts for the next run
+    pub fn persist(&self) -> Result<()> {
+        let buf = {
+            let state = self.state.lock();
+            let mut buf = Vec::with_capacity(state.index.len() * ENTRY_LEN);
+
+            for (key, owner) in &state.index {
+                buf.extend_from_slice(key.as_ref());
+                buf.extend_from_slice(owner.as_ref());
+            }
+
+            buf
+        };
+
+        // Write-then-rename so a crash never leaves a truncated index
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, buf).context("Failed to write account index")?;
+        fs::rename(&tmp, &self.path).context("Failed to replace account index")
+    }
+}
+
+fn parse(bytes: &[u8]) -> Result<HashMap<Pubkey, Pubkey>> {
+    if bytes.len() % ENTRY_LEN != 0 {
+        bail!("Account index is truncated");
+    }
+
+    Ok(bytes
+        .chunks_exact(ENTRY_LEN)
+        .map(|e| {
+            let mut key = [0; 32];
+            let mut owner = [0; 32];
+            key.copy_from_slice(&e[..32]);
+            owner.copy_from_slice(&e[32..]);
+
+            (Pubkey::new_from_array(key), Pubkey::new_from_array(owner))
+        })
+        .collect())
+}
@@ -0,0 +1,44 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::prelude::*;
+
+/// Sampled logging of the selector rule deciding each account and
+/// instruction, adjustable at runtime through the control queue
+#[derive(Debug)]
+pub struct Tracer {
+    /// Log one in this many decisions, or none if 0
+    every: AtomicU64,
+    seen: AtomicU64,
+}
+
+impl Tracer {
+    pub fn new(every: u64) -> Self {
+        Self {
+            every: AtomicU64::new(every),
+            seen: AtomicU64::new(0),
+        }
+    }
+
+    pub fn set(&self, every: u64) {
+        self.every.store(every, Ordering::Relaxed);
+
+        if every == 0 {
+            info!("Decision tracing disabled");
+        } else {
+            info!("Tracing one in {} selector decision(s)", every);
+        }
+    }
+
+    pub fn every(&self) -> u64 {
+        self.every.load(Ordering::Relaxed)
+    }
+
+    /// Returns true if the next decision should be logged
+    #[inline]
+    pub fn sample(&self) -> bool {
+        match self.every.load(Ordering::Relaxed) {
+            0 => false,
+            n => self.seen.fetch_add(1, Ordering::Relaxed) % n == 0,
+        }
+    }
+}
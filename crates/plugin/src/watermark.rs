@@ -0,0 +1,103 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+use parking_lot::Mutex;
+use tokio::sync::Notify;
+
+/// Number of messages still being published, keyed by slot, shared by the
+/// watermark and the slot barrier
+#[derive(Debug, Default)]
+pub struct PendingSlots {
+    counts: Mutex<BTreeMap<u64, usize>>,
+    drained: Notify,
+}
+
+impl PendingSlots {
+    /// Record that a message for the given slot has entered the pipeline
+    pub fn begin(&self, slot: u64) {
+        *self.counts.lock().entry(slot).or_default() += 1;
+    }
+
+    /// Record that a message for the given slot has left the pipeline
+    pub fn end(&self, slot: u64) {
+        let mut counts = self.counts.lock();
+
+        if let Some(count) = counts.get_mut(&slot) {
+            *count -= 1;
+
+            if *count == 0 {
+                counts.remove(&slot);
+                self.drained.notify_waiters();
+            }
+        }
+    }
+
+    /// Returns true if messages for the given slot are still being published
+    pub fn contains(&self, slot: u64) -> bool {
+        self.counts.lock().contains_key(&slot)
+    }
+
+    /// The lowest slot with messages still being published
+    fn lowest(&self) -> Option<u64> {
+        self.counts.lock().keys().next().copied()
+    }
+
+    /// Wait until any slot's messages have all been published.  The future
+    /// must be created before checking [`contains`](Self::contains) so a
+    /// drain in between is not missed.
+    pub fn drained(&self) -> tokio::sync::futures::Notified {
+        self.drained.notified()
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Slots whose rooted status has been published but which have not yet
+    /// been emitted as a watermark
+    rooted: BTreeSet<u64>,
+    last: Option<u64>,
+}
+
+/// Tracker for the highest rooted slot whose messages have all been published
+#[derive(Debug)]
+pub struct Watermark {
+    pending: Arc<PendingSlots>,
+    state: Mutex<State>,
+}
+
+impl Watermark {
+    pub fn new(pending: Arc<PendingSlots>) -> Self {
+        Self {
+            pending,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Record that the rooted status for the given slot has been published
+    pub fn rooted(&self, slot: u64) {
+        let mut state = self.state.lock();
+
+        if state.last.map_or(true, |l| slot > l) {
+            state.rooted.insert(slot);
+        }
+    }
+
+    /// Returns a new watermark if one has become available since the last call
+    pub fn advance(&self) -> Option<u64> {
+        let lowest = self.pending.lowest();
+        let mut state = self.state.lock();
+
+        let next = match lowest {
+            Some(lowest) => state.rooted.range(..lowest).next_back(),
+            None => state.rooted.iter().next_back(),
+        }
+        .copied()?;
+
+        state.rooted = state.rooted.split_off(&(next + 1));
+        state.last = Some(next);
+
+        Some(next)
+    }
+}
@@ -0,0 +1,315 @@
+//! Optional zstd compression of message payloads with trained dictionaries,
+//! for small and highly similar messages such as token account updates.
+//!
+//! Each dictionary is identified by a key derived from the message by its
+//! [`QueueType`](crate::QueueType), e.g. the owner of an updated account.
+//! Only payloads with a matching dictionary are compressed; the ID of the
+//! dictionary used is carried in the [`DICTIONARY_HEADER`] header and the
+//! content encoding is set to [`CONTENT_ENCODING`].  Producers can also
+//! sample the serialized payloads of each key to a directory, from which the
+//! `geyser-train-dict` tool trains new dictionaries.
+
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, SyncSender},
+        Mutex,
+    },
+    thread,
+};
+
+use lapin::{
+    types::{AMQPValue, FieldTable, ShortString},
+    BasicProperties,
+};
+use zstd::dict::{DecoderDictionary, EncoderDictionary};
+
+use crate::{Error, Result};
+
+/// The message header carrying the ID of the dictionary a payload was
+/// compressed with
+pub const DICTIONARY_HEADER: &str = "x-holaplex-zstd-dict";
+
+/// The AMQP content encoding of compressed payloads
+pub const CONTENT_ENCODING: &str = "zstd";
+
+/// The file extension of dictionaries loaded by
+/// [`Dictionaries::load_dir`]
+pub const DICTIONARY_EXTENSION: &str = "dict";
+
+/// A named, prepared zstd dictionary
+pub struct Dictionary {
+    id: String,
+    encoder: EncoderDictionary<'static>,
+    decoder: DecoderDictionary<'static>,
+}
+
+impl fmt::Debug for Dictionary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Dictionary")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Dictionary {
+    /// Prepare a dictionary from its ID and trained contents, compressing at
+    /// the given zstd level
+    #[must_use]
+    pub fn new(id: impl Into<String>, data: &[u8], level: i32) -> Self {
+        Self {
+            id: id.into(),
+            encoder: EncoderDictionary::copy(data, level),
+            decoder: DecoderDictionary::copy(data),
+        }
+    }
+
+    /// The ID of this dictionary
+    #[must_use]
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+/// The number of payload samples waiting to be written before further
+/// samples are dropped
+const SAMPLE_QUEUE_LEN: usize = 1024;
+
+/// Writes the serialized payloads of each dictionary key to a directory,
+/// up to a fixed number of samples per key and a fixed number of keys.
+/// Samples are written by a dedicated thread so compressing a payload never
+/// waits on the filesystem.
+#[derive(Debug)]
+struct Sampler {
+    dir: PathBuf,
+    max_per_key: usize,
+    max_keys: usize,
+    counts: Mutex<HashMap<String, usize>>,
+    tx: SyncSender<(String, usize, Vec<u8>)>,
+}
+
+impl Sampler {
+    fn new(dir: PathBuf, max_per_key: usize, max_keys: usize) -> Result<Self> {
+        let (tx, rx) = mpsc::sync_channel::<(String, usize, Vec<u8>)>(SAMPLE_QUEUE_LEN);
+        let out = dir.clone();
+
+        thread::Builder::new()
+            .name("zstd-sampler".into())
+            .spawn(move || {
+                for (key, n, data) in rx {
+                    let dir = out.join(&key);
+                    let res = fs::create_dir_all(&dir)
+                        .and_then(|()| fs::write(dir.join(format!("{}.bin", n)), data));
+
+                    if let Err(e) = res {
+                        log::warn!("Failed to write payload sample for {:?}: {:?}", key, e);
+                    }
+                }
+            })
+            .map_err(Error::Compression)?;
+
+        Ok(Self {
+            dir,
+            max_per_key,
+            max_keys,
+            counts: Mutex::default(),
+            tx,
+        })
+    }
+
+    fn sample(&self, key: &str, data: &[u8]) {
+        let n = {
+            let mut counts = self
+                .counts
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+            if !counts.contains_key(key) && counts.len() >= self.max_keys {
+                return;
+            }
+
+            let count = counts.entry(key.to_owned()).or_default();
+
+            if *count >= self.max_per_key {
+                return;
+            }
+
+            *count += 1;
+            *count
+        };
+
+        if self
+            .tx
+            .try_send((key.to_owned(), n, data.to_vec()))
+            .is_err()
+        {
+            log::debug!("Payload sample queue for {:?} is full", self.dir);
+        }
+    }
+}
+
+/// The set of dictionaries a producer compresses with or a consumer
+/// decompresses with
+#[derive(Debug, Default)]
+pub struct Dictionaries {
+    dictionaries: HashMap<String, Dictionary>,
+    sampler: Option<Sampler>,
+}
+
+impl Dictionaries {
+    /// Construct an empty set of dictionaries
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load every `.dict` file in a directory, using each file's name without
+    /// its extension as the dictionary ID
+    ///
+    /// # Errors
+    /// This function fails if the directory or a dictionary cannot be read.
+    pub fn load_dir(dir: impl AsRef<Path>, level: i32) -> Result<Self> {
+        let mut dicts = Self::new();
+
+        for entry in fs::read_dir(dir).map_err(Error::Compression)? {
+            let path = entry.map_err(Error::Compression)?.path();
+
+            if path.extension().and_then(std::ffi::OsStr::to_str) != Some(DICTIONARY_EXTENSION) {
+                continue;
+            }
+
+            let id = match path.file_stem().and_then(std::ffi::OsStr::to_str) {
+                Some(s) => s.to_owned(),
+                None => continue,
+            };
+            let data = fs::read(&path).map_err(Error::Compression)?;
+
+            dicts.insert(Dictionary::new(id, &data, level));
+        }
+
+        Ok(dicts)
+    }
+
+    /// Add a dictionary, replacing any existing dictionary with the same ID
+    pub fn insert(&mut self, dict: Dictionary) {
+        self.dictionaries.insert(dict.id.clone(), dict);
+    }
+
+    /// Returns true if no dictionaries are loaded
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.dictionaries.is_empty()
+    }
+
+    /// Write up to `max_per_key` serialized payloads of each of the first
+    /// `max_keys` dictionary keys seen to `dir/<key>/`, as training input for
+    /// new dictionaries.  Samples are written in the background and dropped
+    /// if the writer falls behind.
+    ///
+    /// # Errors
+    /// This function fails if the writer thread cannot be spawned.
+    pub fn with_sampling(
+        self,
+        dir: impl Into<PathBuf>,
+        max_per_key: usize,
+        max_keys: usize,
+    ) -> Result<Self> {
+        Ok(Self {
+            sampler: Some(Sampler::new(dir.into(), max_per_key, max_keys)?),
+            ..self
+        })
+    }
+
+    /// Compress a payload with the dictionary for the given key, if there is
+    /// one, tagging the given properties with its ID.  Payloads without a
+    /// dictionary are returned unchanged.
+    ///
+    /// # Errors
+    /// This function fails if the payload cannot be compressed.
+    pub fn compress(
+        &self,
+        key: Option<&str>,
+        data: Vec<u8>,
+        props: BasicProperties,
+    ) -> Result<(Vec<u8>, BasicProperties)> {
+        let key = match key {
+            Some(k) => k,
+            None => return Ok((data, props)),
+        };
+
+        if let Some(ref sampler) = self.sampler {
+            sampler.sample(key, &data);
+        }
+
+        let dict = match self.dictionaries.get(key) {
+            Some(d) => d,
+            None => return Ok((data, props)),
+        };
+
+        let mut enc = zstd::stream::write::Encoder::with_prepared_dictionary(
+            Vec::with_capacity(data.len()),
+            &dict.encoder,
+        )
+        .map_err(Error::Compression)?;
+        enc.write_all(&data).map_err(Error::Compression)?;
+        let compressed = enc.finish().map_err(Error::Compression)?;
+
+        let mut headers = props.headers().clone().unwrap_or_default();
+        headers.insert(
+            DICTIONARY_HEADER.into(),
+            AMQPValue::LongString(dict.id.clone().into()),
+        );
+
+        Ok((
+            compressed,
+            props
+                .with_content_encoding(CONTENT_ENCODING.into())
+                .with_headers(headers),
+        ))
+    }
+
+    /// Read the dictionary ID a delivery was compressed with, if any
+    #[must_use]
+    pub fn dictionary_id(props: &BasicProperties) -> Option<String> {
+        if props.content_encoding().as_ref().map(ShortString::as_str) != Some(CONTENT_ENCODING) {
+            return None;
+        }
+
+        props
+            .headers()
+            .as_ref()
+            .map(FieldTable::inner)?
+            .get(DICTIONARY_HEADER)?
+            .as_long_string()
+            .map(ToString::to_string)
+    }
+
+    /// Decompress a delivered payload.  Uncompressed payloads are returned
+    /// unchanged.
+    ///
+    /// # Errors
+    /// This function fails if the payload was compressed with a dictionary
+    /// not in this set or cannot be decompressed.
+    pub fn decompress(&self, data: Vec<u8>, props: &BasicProperties) -> Result<Vec<u8>> {
+        let id = match Self::dictionary_id(props) {
+            Some(i) => i,
+            None => return Ok(data),
+        };
+
+        let dict = self
+            .dictionaries
+            .get(&id)
+            .ok_or(Error::UnknownDictionary(id))?;
+
+        let mut dec =
+            zstd::stream::read::Decoder::with_prepared_dictionary(data.as_slice(), &dict.decoder)
+                .map_err(Error::Compression)?;
+        let mut out = Vec::with_capacity(data.len() * 4);
+        dec.read_to_end(&mut out).map_err(Error::Compression)?;
+
+        Ok(out)
+    }
+}
@@ -2,26 +2,46 @@
 
 use std::marker::PhantomData;
 
-use futures_util::StreamExt;
-use lapin::{acker::Acker, Connection};
+use lapin::{BasicProperties, Connection};
 
-use crate::{serialize::deserialize, QueueType, Result};
+use crate::{
+    serialize::deserialize_content,
+    transport::{Ack, Delivery, Subscriber},
+    QueueType, Result,
+};
 
-/// A consumer consisting of a configured AMQP consumer and queue config
+/// A consumer consisting of a configured subscriber and queue config.
+/// Consumes over AMQP unless constructed with
+/// [`from_subscriber`](Self::from_subscriber).
 #[derive(Debug)]
-pub struct Consumer<Q> {
+pub struct Consumer<Q, S = lapin::Consumer> {
     // chan: Channel,
-    consumer: lapin::Consumer,
+    subscriber: S,
     // ty: Q,
+    #[cfg(feature = "compression")]
+    dictionaries: Option<std::sync::Arc<crate::compression::Dictionaries>>,
+    #[cfg(feature = "encryption")]
+    keyring: Option<std::sync::Arc<crate::encryption::Keyring>>,
     _p: PhantomData<Q>,
 }
 
-impl<Q> Clone for Consumer<Q> {
+impl<Q, S: Clone> Clone for Consumer<Q, S> {
     fn clone(&self) -> Self {
-        let Self { consumer, .. } = self;
+        let Self {
+            subscriber,
+            #[cfg(feature = "compression")]
+            dictionaries,
+            #[cfg(feature = "encryption")]
+            keyring,
+            ..
+        } = self;
 
         Self {
-            consumer: consumer.clone(),
+            subscriber: subscriber.clone(),
+            #[cfg(feature = "compression")]
+            dictionaries: dictionaries.clone(),
+            #[cfg(feature = "encryption")]
+            keyring: keyring.clone(),
             ..*self
         }
     }
@@ -41,12 +61,53 @@ where
 
         let consumer = ty.info().init_consumer(&chan, tag).await?;
 
-        Ok(Self {
+        Ok(Self::from_subscriber(consumer))
+    }
+}
+
+impl<Q: QueueType, S: Subscriber> Consumer<Q, S>
+where
+    Q::Message: for<'a> serde::Deserialize<'a>,
+{
+    /// Construct a new consumer from any transport backend.  The subscriber
+    /// is expected to already be receiving from the queue described by `Q`.
+    #[must_use]
+    pub fn from_subscriber(subscriber: S) -> Self {
+        Self {
             // chan,
-            consumer,
+            subscriber,
             // ty,
+            #[cfg(feature = "compression")]
+            dictionaries: None,
+            #[cfg(feature = "encryption")]
+            keyring: None,
             _p: PhantomData::default(),
-        })
+        }
+    }
+
+    /// Decrypt payloads encrypted with any key in the given keyring.
+    /// Unencrypted payloads are still accepted.
+    #[cfg(feature = "encryption")]
+    #[must_use]
+    pub fn with_keyring(self, keyring: std::sync::Arc<crate::encryption::Keyring>) -> Self {
+        Self {
+            keyring: Some(keyring),
+            ..self
+        }
+    }
+
+    /// Decompress payloads compressed with any of the given dictionaries.
+    /// Uncompressed payloads are still accepted.
+    #[cfg(feature = "compression")]
+    #[must_use]
+    pub fn with_dictionaries(
+        self,
+        dictionaries: std::sync::Arc<crate::compression::Dictionaries>,
+    ) -> Self {
+        Self {
+            dictionaries: Some(dictionaries),
+            ..self
+        }
     }
 
     /// Receive a single message from this consumer
@@ -54,14 +115,64 @@ where
     /// # Errors
     /// This function fails if the delivery cannot be successfully performed or
     /// the payload cannot be deserialized.
-    pub async fn read(&mut self) -> Result<Option<(Q::Message, Acker)>> {
-        let delivery = match self.consumer.next().await {
-            Some(d) => d?,
+    pub async fn read(&mut self) -> Result<Option<(Q::Message, S::Acker)>> {
+        Ok(self
+            .read_with_properties()
+            .await?
+            .map(|(data, _, acker)| (data, acker)))
+    }
+
+    /// Receive a single message from this consumer, along with the AMQP
+    /// properties it was published with.  A delivery whose payload cannot be
+    /// decrypted, decompressed, or deserialized is rejected without
+    /// requeueing, dead-lettering it, before its error is returned.
+    ///
+    /// # Errors
+    /// This function fails if the delivery cannot be successfully performed or
+    /// the payload cannot be deserialized.
+    pub async fn read_with_properties(
+        &mut self,
+    ) -> Result<Option<(Q::Message, BasicProperties, S::Acker)>> {
+        let Delivery {
+            data,
+            properties,
+            acker,
+        } = match self.subscriber.next_delivery().await? {
+            Some(d) => d,
             None => return Ok(None),
         };
 
-        let data = deserialize(std::io::Cursor::new(delivery.data))?;
+        match self.decode(data, &properties) {
+            Ok(msg) => Ok(Some((msg, properties, acker))),
+            Err(e) => {
+                if let Err(e) = acker.reject_delivery(false).await {
+                    log::error!("Failed to reject undecodable delivery: {:?}", e);
+                }
+
+                Err(e)
+            },
+        }
+    }
+
+    fn decode(&self, data: Vec<u8>, props: &BasicProperties) -> Result<Q::Message> {
+        #[cfg(feature = "encryption")]
+        let data = match self.keyring {
+            Some(ref k) => k.open(data, props)?,
+            None => match crate::encryption::Keyring::key_id(props) {
+                Some(id) => return Err(crate::Error::UnknownKey(id)),
+                None => data,
+            },
+        };
+
+        #[cfg(feature = "compression")]
+        let data = match self.dictionaries {
+            Some(ref d) => d.decompress(data, props)?,
+            None => match crate::compression::Dictionaries::dictionary_id(props) {
+                Some(id) => return Err(crate::Error::UnknownDictionary(id)),
+                None => data,
+            },
+        };
 
-        Ok(Some((data, delivery.acker)))
+        deserialize_content(std::io::Cursor::new(data), props)
     }
 }
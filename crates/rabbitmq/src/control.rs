@@ -0,0 +1,275 @@
+//! Request/reply protocol for registering ephemeral subscriptions with a
+//! running Geyser producer.
+//!
+//! Requests are published to a per-network fanout exchange consumed by every
+//! producer for that network.  Each producer answers on the queue named by
+//! the request's `reply_to` property, tagging the reply with the request's
+//! correlation ID.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    geyser::{Network, Pubkey},
+    queue_type::{Binding, QueueProps},
+    suffix::Suffix,
+    Result,
+};
+
+/// The pseudo-queue used for RabbitMQ direct reply-to
+pub const DIRECT_REPLY_TO: &str = "amq.rabbitmq.reply-to";
+
+/// An ephemeral selection of accounts and instructions to publish in addition
+/// to a producer's configured selection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Subscription {
+    /// A caller-chosen identifier for this subscription.  Subscribing again
+    /// with the same ID replaces the existing subscription.
+    pub id: String,
+    /// Accounts to publish updates for
+    #[serde(default)]
+    pub accounts: Vec<Pubkey>,
+    /// Programs whose accounts should be published
+    #[serde(default)]
+    pub owners: Vec<Pubkey>,
+    /// Programs whose instructions should be published
+    #[serde(default)]
+    pub programs: Vec<Pubkey>,
+    /// Number of seconds after which the subscription expires unless renewed
+    pub ttl_secs: u64,
+}
+
+/// A control request sent to Geyser producers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Request {
+    /// Register or renew a subscription
+    Subscribe(Subscription),
+    /// Remove the subscription with the given ID
+    Unsubscribe(String),
+    /// Describe the producer's configuration and state
+    Status,
+    /// Log one in every `sample_every` selector decisions, explaining which
+    /// rule selected or rejected each account and instruction.  A value of 0
+    /// disables tracing.
+    Trace {
+        /// The sampling interval
+        sample_every: u64,
+    },
+    /// Write every account update, transaction, and slot status received by
+    /// the producer, before any filtering, to a local file until either
+    /// limit is reached.  Both limits are capped by the producer's
+    /// configuration.
+    Record {
+        /// Maximum number of seconds to record for
+        duration_secs: u64,
+        /// Maximum size of the recording, in bytes
+        max_bytes: u64,
+    },
+}
+
+/// A Geyser producer's reply to a control request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Reply {
+    /// The subscription with the given ID is active until the given Unix
+    /// timestamp, in seconds
+    Subscribed {
+        /// The ID of the subscription
+        id: String,
+        /// The time the subscription will expire unless renewed
+        expires_at: i64,
+    },
+    /// The subscription with the given ID was removed, or did not exist
+    Unsubscribed(String),
+    /// The request was refused for the given reason
+    Rejected(String),
+    /// A JSON document describing the producer's configuration, connection
+    /// state, newest slot, and backlog
+    Status(String),
+    /// Selector decisions are now traced at the given sampling interval
+    Tracing {
+        /// The sampling interval, or 0 if tracing is disabled
+        sample_every: u64,
+    },
+    /// A recording was started with the given limits, after capping
+    Recording {
+        /// The path of the recording on the producer's host
+        path: String,
+        /// The time the recording will stop, as a Unix timestamp in seconds
+        until: i64,
+        /// Maximum size of the recording, in bytes
+        max_bytes: u64,
+    },
+}
+
+/// AMQP configuration for Geyser control requests
+#[derive(Debug, Clone)]
+pub struct QueueType {
+    props: QueueProps,
+}
+
+impl QueueType {
+    /// Construct a new queue configuration for the given network.  `name`
+    /// should uniquely identify the consuming producer, since every producer
+    /// must see every request.
+    ///
+    /// # Errors
+    /// This function fails if the given queue suffix is invalid.
+    pub fn new(network: Network, suffix: &Suffix, name: &str) -> Result<Self> {
+        let exchange = format!("{}.control", network);
+        let queue = suffix.format(format!("{}.{}", exchange, name))?;
+
+        Ok(Self {
+            props: QueueProps {
+                exchange,
+                queue,
+                binding: Binding::Fanout,
+                prefetch: 16,
+                // Subscriptions don't outlive the producer holding them, so
+                // neither should its requests
+                auto_delete: true,
+                max_len: None,
+                max_priority: None,
+                overflow: None,
+                confirm: false,
+                mandatory: false,
+                fallback_queue: None,
+                retry: None,
+            },
+        })
+    }
+}
+
+impl crate::QueueType for QueueType {
+    type Message = Request;
+
+    #[inline]
+    fn info(&self) -> crate::queue_type::QueueInfo {
+        (&self.props).into()
+    }
+}
+
+/// The type of a control request consumer
+#[cfg(feature = "consumer")]
+pub type Consumer = crate::consumer::Consumer<QueueType>;
+
+#[cfg(all(feature = "consumer", feature = "producer"))]
+mod client {
+    use futures_util::StreamExt;
+    use lapin::{
+        options::{BasicConsumeOptions, BasicPublishOptions},
+        types::{FieldTable, ShortString},
+        BasicProperties, Channel, Connection,
+    };
+    use rand::Rng;
+
+    use super::{QueueType, Reply, Request, DIRECT_REPLY_TO};
+    use crate::{
+        serialize::{deserialize, serialize},
+        QueueType as _, Result,
+    };
+
+    /// Send a reply to a control request, if the request asked for one
+    ///
+    /// # Errors
+    /// This function fails if the reply cannot be serialized or published.
+    pub async fn reply(chan: &Channel, request: &BasicProperties, reply: &Reply) -> Result<()> {
+        let reply_to = match request.reply_to() {
+            Some(r) => r.as_str(),
+            None => return Ok(()),
+        };
+
+        let mut vec = Vec::new();
+        serialize(&mut vec, reply)?;
+
+        let mut props = BasicProperties::default();
+
+        if let Some(id) = request.correlation_id() {
+            props = props.with_correlation_id(id.clone());
+        }
+
+        chan.basic_publish("", reply_to, BasicPublishOptions::default(), &vec, props)
+            .await?
+            .await?;
+
+        Ok(())
+    }
+
+    /// A client for sending control requests to Geyser producers
+    #[derive(Debug)]
+    pub struct Client {
+        chan: Channel,
+        ty: QueueType,
+        replies: lapin::Consumer,
+    }
+
+    impl Client {
+        /// Construct a new client for the given queue configuration
+        ///
+        /// # Errors
+        /// This function fails if the channel cannot be created and
+        /// configured successfully.
+        pub async fn new(conn: &Connection, ty: QueueType) -> Result<Self> {
+            let chan = conn.create_channel().await?;
+            ty.info().init_producer(&chan).await?;
+
+            let replies = chan
+                .basic_consume(
+                    DIRECT_REPLY_TO,
+                    "",
+                    BasicConsumeOptions {
+                        no_ack: true,
+                        ..BasicConsumeOptions::default()
+                    },
+                    FieldTable::default(),
+                )
+                .await?;
+
+            Ok(Self { chan, ty, replies })
+        }
+
+        /// Send a request and wait for the first reply to it.  When several
+        /// producers share a network, the remaining replies are discarded.
+        /// Returns `None` if the reply consumer was closed.
+        ///
+        /// # Errors
+        /// This function fails if the request cannot be sent or a reply
+        /// cannot be received and deserialized.
+        pub async fn request(&mut self, req: &Request) -> Result<Option<Reply>> {
+            let id = format!("{:016x}", rand::thread_rng().gen::<u64>());
+
+            let mut vec = Vec::new();
+            serialize(&mut vec, req)?;
+
+            self.ty
+                .info()
+                .publish(
+                    &self.chan,
+                    None,
+                    &vec,
+                    BasicProperties::default()
+                        .with_reply_to(DIRECT_REPLY_TO.into())
+                        .with_correlation_id(id.clone().into()),
+                )
+                .await?
+                .await?;
+
+            while let Some(delivery) = self.replies.next().await {
+                let delivery = delivery?;
+
+                if delivery
+                    .properties
+                    .correlation_id()
+                    .as_ref()
+                    .map(ShortString::as_str)
+                    == Some(id.as_str())
+                {
+                    return Ok(Some(deserialize(std::io::Cursor::new(delivery.data))?));
+                }
+            }
+
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(all(feature = "consumer", feature = "producer"))]
+pub use client::{reply, Client};
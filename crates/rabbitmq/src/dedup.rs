@@ -0,0 +1,39 @@
+//! Duplicate suppression for consumers of exchanges shared by several Geyser
+//! producers.
+//!
+//! When multiple validators run the Geyser plugin against the same exchange,
+//! every on-chain event is delivered once per validator.  Each message can
+//! be published with a deterministic AMQP `message_id` (see
+//! [`Message::message_id`]) and carries identity headers for its producer (see
+//! [`ProducerId`](crate::geyser::ProducerId)), so a consumer can discard
+//! copies it has already seen by keying a [`ReplayGuard`] on
+//! [`message_key`]:
+//!
+//! ```ignore
+//! let mut guard = ReplayGuard::new(consumer, MemoryStore::new(100_000, Some(ttl)))
+//!     .with_key(dedup::message_key);
+//!
+//! while let Some((msg, acker)) = guard.read().await? {
+//!     handle(msg).await?;
+//!
+//!     acker.ack(BasicAckOptions::default()).await?;
+//! }
+//! ```
+//!
+//! [`ReplayGuard`]: crate::replay::ReplayGuard
+
+use lapin::BasicProperties;
+
+use crate::geyser::Message;
+
+/// Identify a Geyser delivery by its AMQP `message_id`, or by the ID derived
+/// from its contents if it was published without one
+#[must_use]
+pub fn message_key(msg: &Message, props: &BasicProperties) -> Option<String> {
+    Some(
+        props
+            .message_id()
+            .as_ref()
+            .map_or_else(|| msg.message_id(), |i| i.as_str().to_owned()),
+    )
+}
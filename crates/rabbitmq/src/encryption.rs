@@ -0,0 +1,173 @@
+//! Optional AES-256-GCM encryption of message payloads, for brokers shared
+//! with untrusted tenants.
+//!
+//! An encrypted payload consists of a random 96-bit nonce followed by the
+//! ciphertext and authentication tag.  The ID of the key used is carried in
+//! the [`KEY_ID_HEADER`] header so consumers can rotate keys without
+//! coordinating with every producer.  Only payloads are encrypted; AMQP
+//! properties such as the message ID remain visible to the broker.
+
+use std::fmt;
+
+use aes_gcm::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256Gcm, Nonce,
+};
+use lapin::{
+    types::{AMQPValue, FieldTable},
+    BasicProperties,
+};
+
+use crate::{Error, Result};
+
+/// The message header carrying the ID of the key a payload was encrypted with
+pub const KEY_ID_HEADER: &str = "x-holaplex-key-id";
+
+const NONCE_LEN: usize = 12;
+
+/// A named AES-256-GCM key
+#[derive(Clone)]
+pub struct Key {
+    id: String,
+    cipher: Aes256Gcm,
+}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Key")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Key {
+    /// Construct a key from its ID and 32 bytes of key material
+    ///
+    /// # Errors
+    /// This function fails if the key material is not 32 bytes long.
+    pub fn new(id: impl Into<String>, material: &[u8]) -> Result<Self> {
+        let cipher = Aes256Gcm::new_from_slice(material)
+            .map_err(|_| Error::Encryption("Key material must be 32 bytes"))?;
+
+        Ok(Self {
+            id: id.into(),
+            cipher,
+        })
+    }
+
+    /// Construct a key from its ID and base64-encoded key material
+    ///
+    /// # Errors
+    /// This function fails if the key material is not valid base64 encoding
+    /// 32 bytes.
+    pub fn from_base64(id: impl Into<String>, material: &str) -> Result<Self> {
+        let material = base64::decode(material.trim())
+            .map_err(|_| Error::Encryption("Key material is not valid base64"))?;
+
+        Self::new(id, &material)
+    }
+
+    /// The ID of this key
+    #[must_use]
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Encrypt a payload, tagging the given properties with this key's ID
+    ///
+    /// # Errors
+    /// This function fails if the payload cannot be encrypted.
+    pub fn seal(&self, data: &[u8], props: BasicProperties) -> Result<(Vec<u8>, BasicProperties)> {
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: data,
+                    aad: self.id.as_bytes(),
+                },
+            )
+            .map_err(|_| Error::Encryption("Failed to encrypt payload"))?;
+
+        let mut sealed = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+
+        let mut headers = props.headers().clone().unwrap_or_default();
+        headers.insert(
+            KEY_ID_HEADER.into(),
+            AMQPValue::LongString(self.id.clone().into()),
+        );
+
+        Ok((sealed, props.with_headers(headers)))
+    }
+
+    fn open(&self, data: &[u8]) -> Result<Vec<u8>> {
+        if data.len() < NONCE_LEN {
+            return Err(Error::Encryption("Encrypted payload is truncated"));
+        }
+
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+
+        self.cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: self.id.as_bytes(),
+                },
+            )
+            .map_err(|_| Error::Encryption("Failed to decrypt payload"))
+    }
+}
+
+/// The set of keys a consumer accepts encrypted payloads from
+#[derive(Debug, Clone, Default)]
+pub struct Keyring(Vec<Key>);
+
+impl Keyring {
+    /// Construct an empty keyring
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a key to this keyring, replacing any existing key with the same ID
+    pub fn insert(&mut self, key: Key) {
+        self.0.retain(|k| k.id != key.id);
+        self.0.push(key);
+    }
+
+    /// Read the key ID a delivery was encrypted with, if any
+    #[must_use]
+    pub fn key_id(props: &BasicProperties) -> Option<String> {
+        props
+            .headers()
+            .as_ref()
+            .map(FieldTable::inner)?
+            .get(KEY_ID_HEADER)?
+            .as_long_string()
+            .map(ToString::to_string)
+    }
+
+    /// Decrypt a delivered payload.  Payloads delivered without a key ID are
+    /// returned unchanged, so consumers can be configured with a keyring
+    /// before their producers begin encrypting.
+    ///
+    /// # Errors
+    /// This function fails if the payload was encrypted with a key not in
+    /// this keyring or cannot be decrypted.
+    pub fn open(&self, data: Vec<u8>, props: &BasicProperties) -> Result<Vec<u8>> {
+        let id = match Self::key_id(props) {
+            Some(i) => i,
+            None => return Ok(data),
+        };
+
+        self.0
+            .iter()
+            .find(|k| k.id == id)
+            .ok_or(Error::UnknownKey(id))?
+            .open(&data)
+    }
+}
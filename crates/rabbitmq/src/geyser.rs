@@ -1,17 +1,64 @@
 //! Queue configuration for Solana Geyser plugins intended to communicate
 //! with `holaplex-indexer`.
 
-use std::time::Duration;
+use std::{
+    fmt,
+    marker::PhantomData,
+    result::Result as StdResult,
+    sync::atomic::{AtomicI64, AtomicU64, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use serde::{Deserialize, Serialize};
-pub use solana_program::pubkey::Pubkey;
+use lapin::{
+    types::{AMQPValue, FieldTable},
+    BasicProperties,
+};
+use serde::{de, de::IntoDeserializer, Deserialize, Serialize};
+pub use solana_program::{hash::Hash, pubkey::Pubkey};
 
 use crate::{
-    queue_type::{Binding, QueueProps, RetryProps},
+    queue_type::{Binding, Overflow, QueueProps, RetryProps},
+    raw::Value,
     suffix::Suffix,
     Result,
 };
 
+/// Implement `Serialize` and `Deserialize` for an enum deriving them with
+/// `#[serde(remote = "Self")]`, decoding any variant not in the given list
+/// as its `Unknown` variant rather than failing
+macro_rules! tolerant_enum {
+    ($ty:ident, [$($variant:literal),*]) => {
+        impl Serialize for $ty {
+            fn serialize<S: serde::Serializer>(&self, ser: S) -> StdResult<S::Ok, S::Error> {
+                Self::serialize(self, ser)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $ty {
+            fn deserialize<D: serde::Deserializer<'de>>(de: D) -> StdResult<Self, D::Error> {
+                let value = Value::deserialize(de)?;
+
+                match variant_name(&value) {
+                    Some(name) if ![$($variant),*].contains(&name) => Ok(Self::Unknown),
+                    _ => Self::deserialize(value.into_deserializer()),
+                }
+            }
+        }
+    };
+}
+
+/// The variant name of an externally-tagged enum value, if it has one
+fn variant_name(value: &Value) -> Option<&str> {
+    match value {
+        Value::String(s) => Some(s),
+        Value::Map(m) => match m.as_slice() {
+            [(Value::String(s), _)] => Some(s),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 /// Message data for an account update
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AccountUpdate {
@@ -33,18 +80,137 @@ pub struct AccountUpdate {
     pub slot: u64,
     /// True if this update was triggered by a validator startup
     pub is_startup: bool,
+    /// True if this update was published in response to a snapshot request
+    /// rather than an on-chain write
+    #[serde(default)]
+    pub on_demand: bool,
+    /// Details of the mint of a token account, if mint enrichment is enabled
+    #[serde(default)]
+    pub mint_info: Option<MintInfo>,
+    /// SHA-256 hash of the account's data, set if the data was omitted from
+    /// this update, as it is by default for executable accounts
+    #[serde(default)]
+    pub data_hash: Option<Hash>,
+}
+
+/// Details of a token mint attached to updates for its token accounts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MintInfo {
+    /// The mint's public key
+    pub mint: Pubkey,
+    /// The number of base-10 digits to the right of the decimal place
+    pub decimals: u8,
+    /// The total supply of the token
+    pub supply: u64,
+    /// The raw data of the mint's Metaplex metadata account, if requested
+    /// and the account exists
+    #[serde(default)]
+    pub metadata: Option<Vec<u8>>,
+}
+
+/// A run of bytes to overwrite in an account's data
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DataPatch {
+    /// Offset of the first byte to overwrite
+    pub offset: u64,
+    /// The new bytes
+    pub bytes: Vec<u8>,
+}
+
+/// Message data for an account update published as a delta against the
+/// previous update for the same account.
+///
+/// Deltas form chains: version 0 of a chain is a keyframe carrying the full
+/// account data as a single patch, and every subsequent version applies to
+/// the data produced by the version before it.  A consumer which has not
+/// seen the previous version of a chain must wait for the next keyframe or
+/// request a full copy of the account.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountDiff {
+    /// The account's public key
+    pub key: Pubkey,
+    /// The lamport balance of the account
+    pub lamports: u64,
+    /// The Solana program controlling this account
+    pub owner: Pubkey,
+    /// True if the account's data is an executable smart contract
+    pub executable: bool,
+    /// The next epoch for which this account will owe rent
+    pub rent_epoch: u64,
+    /// Monotonic-increasing counter for sequencing on-chain writes
+    pub write_version: u64,
+    /// The slot in which this account was updated
+    pub slot: u64,
+    /// True if this update was triggered by a validator startup
+    pub is_startup: bool,
+    /// Identifier of the delta chain this update belongs to
+    pub chain: u64,
+    /// Position of this update in its chain
+    pub version: u64,
+    /// Patches to apply to the previous version's data
+    pub patches: Vec<DataPatch>,
+}
+
+/// Message data for an account being assigned to a new owner
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct AccountReassigned {
+    /// The account's public key
+    pub key: Pubkey,
+    /// The owner of the account as of its last published update
+    pub old_owner: Pubkey,
+    /// The new owner of the account
+    pub new_owner: Pubkey,
+    /// The slot in which the owner change was observed
+    pub slot: u64,
+}
+
+/// Message data for an account deleted while the producer was not observing
+/// it, detected by its absence from a startup snapshot
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct AccountDeleted {
+    /// The account's public key
+    pub key: Pubkey,
+    /// The owner of the account as of its last published update
+    pub owner: Pubkey,
+    /// The slot of the snapshot the account was absent from
+    pub slot: u64,
+}
+
+/// Message data for an update to an account holding no data, published in
+/// place of an [`AccountUpdate`] when compact zero-data updates are enabled
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct AccountBalance {
+    /// The account's public key
+    pub key: Pubkey,
+    /// The lamport balance of the account
+    pub lamports: u64,
+    /// The Solana program controlling this account
+    pub owner: Pubkey,
+    /// Monotonic-increasing counter for sequencing on-chain writes
+    pub write_version: u64,
+    /// The slot in which this account was updated
+    pub slot: u64,
+    /// True if this update was triggered by a validator startup
+    pub is_startup: bool,
 }
 
 /// The index of an instruction in a transaction
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(remote = "Self")]
 pub enum InstructionIndex {
     /// This instruction was included directly in the transaction message
     TopLevel(usize),
     /// This is a sub-instruction whose index is represented as
     /// `(parent, child)`
     Inner(u8, usize),
+    /// An index of a form unknown to this version.  Only produced when
+    /// decoding; attempting to encode it fails.
+    #[serde(skip)]
+    Unknown,
 }
 
+tolerant_enum!(InstructionIndex, ["TopLevel", "Inner"]);
+
 /// Message data for an instruction notification
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InstructionNotify {
@@ -63,16 +229,113 @@ pub struct InstructionNotify {
     pub index: InstructionIndex,
 }
 
+/// Message data for a successful transaction invoking a selected program
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignatureNotify {
+    /// Signature of the transaction
+    pub txn_signature: Vec<u8>,
+    /// The slot in which the transaction was reported
+    pub slot: u64,
+    /// The account paying the transaction's fees
+    pub fee_payer: Pubkey,
+    /// Every program invoked by the transaction, including by inner
+    /// instructions
+    pub program_ids: Vec<Pubkey>,
+}
+
+/// Message data summarizing the fee market for transactions invoking selected
+/// programs in a single slot
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct FeeStats {
+    /// The slot summarized
+    pub slot: u64,
+    /// The number of transactions summarized
+    pub txn_count: u64,
+    /// Median prioritization fee, in micro-lamports per compute unit
+    pub unit_price_p50: u64,
+    /// 75th-percentile prioritization fee
+    pub unit_price_p75: u64,
+    /// 90th-percentile prioritization fee
+    pub unit_price_p90: u64,
+    /// 99th-percentile prioritization fee
+    pub unit_price_p99: u64,
+    /// Highest prioritization fee
+    pub unit_price_max: u64,
+    /// Sum of the compute unit limits requested by the transactions
+    pub total_compute_limit: u64,
+    /// Sum of the fees paid by the transactions, in lamports
+    pub total_fees: u64,
+}
+
+/// Message data summarizing the activity of a single wallet in a single slot,
+/// computed from the successful transactions including it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletDigest {
+    /// The wallet summarized
+    pub wallet: Pubkey,
+    /// The slot summarized
+    pub slot: u64,
+    /// The number of transactions summarized
+    pub txn_count: u64,
+    /// Net change in the wallet's lamport balance, including fees paid
+    pub lamport_delta: i64,
+    /// Changes in the balances of token accounts owned by the wallet, one
+    /// entry per mint
+    pub token_deltas: Vec<TokenDelta>,
+    /// Programs invoked by the transactions, sorted and deduplicated
+    pub programs: Vec<Pubkey>,
+}
+
+/// Change in a wallet's balance of a single token within a
+/// [`WalletDigest`].  Increases and decreases are summed separately per
+/// token account, in base units.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TokenDelta {
+    /// The token's mint
+    pub mint: Pubkey,
+    /// Total increase in the balances of the wallet's token accounts
+    pub received: u64,
+    /// Total decrease in the balances of the wallet's token accounts
+    pub sent: u64,
+}
+
+/// Message data for a marketplace sale of a single NFT, recognized from the
+/// shape of a successful transaction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NftSale {
+    /// The mint of the token sold
+    pub mint: Pubkey,
+    /// The price paid by the buyer, in lamports
+    pub price: u64,
+    /// The wallet receiving the token
+    pub buyer: Pubkey,
+    /// The wallet the token was transferred from
+    pub seller: Pubkey,
+    /// The program executing the sale
+    pub marketplace: Pubkey,
+    /// The slot in which the transaction was reported
+    pub slot: u64,
+    /// Signature of the transaction
+    pub txn_signature: Vec<u8>,
+}
+
 /// Solana slot status, corresponding to the Geyser interface's enumeration of
 /// the same name.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(remote = "Self")]
 #[allow(missing_docs)]
 pub enum SlotStatus {
     Processed,
     Rooted,
     Confirmed,
+    /// A status unknown to this version.  Only produced when decoding;
+    /// attempting to encode it fails.
+    #[serde(skip)]
+    Unknown,
 }
 
+tolerant_enum!(SlotStatus, ["Processed", "Rooted", "Confirmed"]);
+
 /// Message data for a block status update
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct SlotStatusUpdate {
@@ -82,10 +345,267 @@ pub struct SlotStatusUpdate {
     pub parent: Option<u64>,
     /// The status of the slot
     pub status: SlotStatus,
+    /// The producer's estimate of when the slot was produced, as a Unix
+    /// timestamp in milliseconds.  This is derived from the local slot
+    /// cadence and is only approximate.
+    #[serde(default)]
+    pub estimated_time: Option<i64>,
 }
 
-/// A message transmitted by a Geyser plugin
+/// Message data for a rooted-slot watermark
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RootedWatermark {
+    /// The highest rooted slot for which every message has been published.
+    /// Consumers can safely resume from or prune reorg buffers up to this
+    /// slot.
+    pub slot: u64,
+}
+
+/// Header containing the hostname of the producer that published a message
+pub const PRODUCER_HOST_HEADER: &str = "x-producer-host";
+/// Header containing the startup epoch of the producer that published a
+/// message
+pub const PRODUCER_EPOCH_HEADER: &str = "x-producer-epoch";
+
+/// Unique identity of a single run of a Geyser producer
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ProducerId {
+    /// The hostname of the validator running the producer
+    pub host: String,
+    /// A random UUID generated when the producer started
+    pub epoch: String,
+}
+
+impl ProducerId {
+    /// Construct a new producer ID for the given host with a fresh epoch
+    #[must_use]
+    pub fn new(host: String) -> Self {
+        let n = u128::from_be_bytes(rand::random());
+        // Set the version (4) and variant (RFC 4122) bits
+        let n = (n & !(0xf << 76) & !(0x3 << 62)) | (0x4 << 76) | (0x2 << 62);
+
+        Self {
+            host,
+            epoch: format!(
+                "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+                n >> 96,
+                (n >> 80) & 0xffff,
+                (n >> 64) & 0xffff,
+                (n >> 48) & 0xffff,
+                n & 0xffff_ffff_ffff,
+            ),
+        }
+    }
+
+    /// Read the producer ID from the headers of a delivered message
+    #[must_use]
+    pub fn from_properties(props: &BasicProperties) -> Option<Self> {
+        let headers = props.headers().as_ref()?.inner();
+        let get = |k: &str| Some(headers.get(k)?.as_long_string()?.to_string());
+
+        Some(Self {
+            host: get(PRODUCER_HOST_HEADER)?,
+            epoch: get(PRODUCER_EPOCH_HEADER)?,
+        })
+    }
+
+    /// Construct message headers identifying this producer
+    #[must_use]
+    pub fn to_headers(&self) -> FieldTable {
+        let mut headers = FieldTable::default();
+
+        headers.insert(
+            PRODUCER_HOST_HEADER.into(),
+            AMQPValue::LongString(self.host.clone().into()),
+        );
+        headers.insert(
+            PRODUCER_EPOCH_HEADER.into(),
+            AMQPValue::LongString(self.epoch.clone().into()),
+        );
+
+        headers
+    }
+}
+
+impl fmt::Display for ProducerId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.host, self.epoch)
+    }
+}
+
+/// Header containing the time a message was published by its producer, as a
+/// Unix timestamp in microseconds
+pub const PUBLISH_TIME_HEADER: &str = "x-producer-time-us";
+
+static LAST_PUBLISH_TIME: AtomicI64 = AtomicI64::new(0);
+
+/// Stamp message properties with the current time as the publish time.
+/// Timestamps are read from the wall clock so consumers on other hosts can
+/// compare them against their own, but never decrease within a producer
+/// process, even if the wall clock steps backwards.
+#[must_use]
+pub fn stamp_publish_time(props: BasicProperties) -> BasicProperties {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_micros().try_into().unwrap_or(i64::MAX));
+    let prev = LAST_PUBLISH_TIME.fetch_max(now, Ordering::Relaxed);
+
+    let mut headers = props.headers().clone().unwrap_or_default();
+    headers.insert(
+        PUBLISH_TIME_HEADER.into(),
+        AMQPValue::LongLongInt(now.max(prev)),
+    );
+
+    props.with_headers(headers)
+}
+
+/// Read the publish time of a delivered message, as a Unix timestamp in
+/// microseconds, if its producer stamped one
+#[must_use]
+pub fn publish_time(props: &BasicProperties) -> Option<i64> {
+    props
+        .headers()
+        .as_ref()?
+        .inner()
+        .get(PUBLISH_TIME_HEADER)?
+        .as_long_long_int()
+}
+
+/// Header describing the delivery guarantee a message was published with,
+/// one of the strings returned by [`DeliveryGuarantee::as_str`]
+pub const DELIVERY_GUARANTEE_HEADER: &str = "x-delivery-guarantee";
+
+/// The delivery guarantee of a published message, determined by whether its
+/// producer waits for publisher confirms
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryGuarantee {
+    /// Published without confirms.  Messages lost in transit to the broker
+    /// are not detected, so a message is delivered at most once.
+    AtMostOnce,
+    /// Published with confirms.  Messages nacked by the broker are
+    /// republished, so a message may be delivered more than once.  Retries
+    /// are bounded, so a broker that keeps refusing messages still causes
+    /// drops, which the producer reports in its metrics.
+    AtLeastOnce,
+}
+
+impl DeliveryGuarantee {
+    /// The value of this guarantee in the [`DELIVERY_GUARANTEE_HEADER`]
+    /// header
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::AtMostOnce => "at-most-once",
+            Self::AtLeastOnce => "at-least-once",
+        }
+    }
+
+    /// Add this guarantee to a set of message headers
+    pub fn stamp(self, headers: &mut FieldTable) {
+        headers.insert(
+            DELIVERY_GUARANTEE_HEADER.into(),
+            AMQPValue::LongString(self.as_str().into()),
+        );
+    }
+
+    /// Read the delivery guarantee of a delivered message, if its producer
+    /// recorded one
+    #[must_use]
+    pub fn from_properties(props: &BasicProperties) -> Option<Self> {
+        let value = props
+            .headers()
+            .as_ref()?
+            .inner()
+            .get(DELIVERY_GUARANTEE_HEADER)?
+            .as_long_string()?
+            .to_string();
+
+        [Self::AtMostOnce, Self::AtLeastOnce]
+            .into_iter()
+            .find(|g| g.as_str() == value)
+    }
+}
+
+/// Compile-time details of the build a producer is running
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildInfo {
+    /// The version string of the compiler used
+    pub rustc: String,
+    /// The Cargo profile built with
+    pub profile: String,
+    /// The target triple built for
+    pub target: String,
+    /// The host triple built on
+    pub host: String,
+    /// The pointer width, endianness, and target features built with
+    pub platform: String,
+    /// The abbreviated commit hash built from, suffixed with `-DIRTY` if the
+    /// working tree had uncommitted changes
+    pub git_head: Option<String>,
+    /// The URL of the Git remote of the branch built from
+    pub git_remote: Option<String>,
+}
+
+/// Message data for a producer starting or stopping
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProducerLifecycle {
+    /// The identity of the producer
+    pub id: ProducerId,
+    /// The version string of the producer
+    pub version: String,
+    /// Details of the producer's build, if it reported them
+    #[serde(default)]
+    pub build: Option<BuildInfo>,
+}
+
+/// Message data for a periodic summary of a producer's health.  Counts cover
+/// the interval since the previous summary.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Stats {
+    /// The identity of the producer
+    pub producer: ProducerId,
+    /// Unix timestamp of the end of the interval, in seconds
+    pub timestamp: i64,
+    /// Length of the interval, in seconds
+    pub interval_secs: u64,
+    /// The newest slot observed by the producer
+    pub slot: u64,
+    /// Number of account update and reassignment messages published
+    pub account_msgs: u64,
+    /// Number of instruction and signature messages published
+    pub instruction_msgs: u64,
+    /// Number of slot status messages published
+    pub slot_status_msgs: u64,
+    /// Total size of the serialized messages published
+    pub bytes: u64,
+    /// Number of messages dropped or refused by the broker
+    pub drops: u64,
+    /// Number of times the producer reconnected to the broker
+    pub reconnects: u64,
+    /// Number of messages waiting to be published at the end of the interval
+    pub backlog: u64,
+    /// Details of the producer's build, if it reported them
+    #[serde(default)]
+    pub build: Option<BuildInfo>,
+    /// Names of the experimental features enabled on the producer
+    #[serde(default)]
+    pub features: Vec<String>,
+}
+
+/// A message transmitted by a Geyser plugin
+///
+/// Messages are encoded adjacently-tagged, as a map of the form
+/// `{"type": <variant>, "data": <content>}`, so consumers can identify a
+/// variant before decoding its content.  For compatibility with producers
+/// predating this encoding, the externally-tagged form `{<variant>:
+/// <content>}` is also accepted when decoding; consumers should therefore be
+/// upgraded before the producers they read from.
+///
+/// Decoding tolerates messages from newer producers: unrecognized fields of
+/// known variants are ignored, and variants unknown to this version are
+/// decoded as [`Message::Unknown`] rather than failing the delivery.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", content = "data")]
 pub enum Message {
     /// Indicates an account should be updated
     AccountUpdate(AccountUpdate),
@@ -93,6 +613,397 @@ pub enum Message {
     InstructionNotify(InstructionNotify),
     /// Indicates the status of a slot changed
     SlotStatusUpdate(SlotStatusUpdate),
+    /// Indicates all messages up to and including a rooted slot have been
+    /// published
+    RootedWatermark(RootedWatermark),
+    /// Indicates an account previously published with one owner is now
+    /// owned by another program
+    AccountReassigned(AccountReassigned),
+    /// Indicates a producer has started and is about to publish messages
+    ProducerStarted(ProducerLifecycle),
+    /// Indicates a producer is shutting down and will publish no more messages
+    ProducerStopped(ProducerLifecycle),
+    /// Indicates a **successful** transaction invoked a selected program
+    SignatureNotify(SignatureNotify),
+    /// Summarizes the fees paid to invoke selected programs in a slot
+    FeeStats(FeeStats),
+    /// Indicates an account should be updated by applying a delta
+    AccountDiff(AccountDiff),
+    /// Summarizes a producer's health over the last interval
+    Stats(Stats),
+    /// Indicates a previously-published account no longer exists
+    AccountDeleted(AccountDeleted),
+    /// Indicates the balance of an account holding no data changed
+    AccountBalance(AccountBalance),
+    /// Summarizes the activity of a tracked wallet in a slot
+    WalletDigest(WalletDigest),
+    /// Indicates a **successful** transaction sold an NFT on a recognized
+    /// marketplace
+    NftSale(NftSale),
+    /// A message of a variant unknown to this version, retained undecoded.
+    /// Only produced when decoding; attempting to encode it fails.
+    #[serde(skip_serializing)]
+    Unknown {
+        /// The variant name the message was tagged with
+        tag: String,
+        /// The message's content
+        raw: Value,
+    },
+}
+
+/// Number of messages decoded as [`Message::Unknown`] by this process
+static UNKNOWN_MESSAGES: AtomicU64 = AtomicU64::new(0);
+
+struct MessageVisitor;
+
+impl<'de> de::Visitor<'de> for MessageVisitor {
+    type Value = Message;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a tagged Geyser message")
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> StdResult<Message, A::Error> {
+        let key: String = map
+            .next_key()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+
+        let msg = if key == "type" {
+            let tag: String = map.next_value()?;
+
+            match map.next_key::<String>()? {
+                Some(k) if k == "data" => (),
+                _ => return Err(de::Error::missing_field("data")),
+            }
+
+            Message::decode_content(&tag, MapContent(&mut map))?
+        } else if key == "data" {
+            // Adjacently-tagged with the keys swapped; the content can only
+            // be interpreted once the tag is known
+            let data: Value = map.next_value()?;
+
+            match map.next_key::<String>()? {
+                Some(k) if k == "type" => (),
+                _ => return Err(de::Error::missing_field("type")),
+            }
+
+            let tag: String = map.next_value()?;
+
+            Message::decode_content(&tag, BufferedContent(Some(data), PhantomData))?
+        } else {
+            // Legacy externally-tagged encoding
+            Message::decode_content(&key, MapContent(&mut map))?
+        };
+
+        while map
+            .next_entry::<de::IgnoredAny, de::IgnoredAny>()?
+            .is_some()
+        {}
+
+        Ok(msg)
+    }
+
+    /// Compact encoding, with the tag and content as a two-element array
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> StdResult<Message, A::Error> {
+        let tag: String = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+
+        let msg = Message::decode_content(&tag, SeqContent(&mut seq))?;
+
+        while seq.next_element::<de::IgnoredAny>()?.is_some() {}
+
+        Ok(msg)
+    }
+}
+
+/// Source of the content of a message whose tag has been decoded
+trait ContentAccess<'de> {
+    type Error: de::Error;
+
+    fn next_content<T: Deserialize<'de>>(&mut self) -> StdResult<T, Self::Error>;
+}
+
+struct MapContent<'a, A>(&'a mut A);
+
+impl<'a, 'de, A: de::MapAccess<'de>> ContentAccess<'de> for MapContent<'a, A> {
+    type Error = A::Error;
+
+    fn next_content<T: Deserialize<'de>>(&mut self) -> StdResult<T, A::Error> {
+        self.0.next_value()
+    }
+}
+
+struct SeqContent<'a, A>(&'a mut A);
+
+impl<'a, 'de, A: de::SeqAccess<'de>> ContentAccess<'de> for SeqContent<'a, A> {
+    type Error = A::Error;
+
+    /// Compact content is buffered so trailing fields appended by a newer
+    /// version can be ignored, which decoding the array in place rejects
+    fn next_content<T: Deserialize<'de>>(&mut self) -> StdResult<T, A::Error> {
+        let value: Value = self
+            .0
+            .next_element()?
+            .ok_or_else(|| de::Error::missing_field("data"))?;
+
+        T::deserialize(value.into_deserializer())
+    }
+}
+
+struct BufferedContent<E>(Option<Value>, PhantomData<E>);
+
+impl<'de, E: de::Error> ContentAccess<'de> for BufferedContent<E> {
+    type Error = E;
+
+    fn next_content<T: Deserialize<'de>>(&mut self) -> StdResult<T, E> {
+        let value = self
+            .0
+            .take()
+            .ok_or_else(|| de::Error::missing_field("data"))?;
+
+        T::deserialize(value.into_deserializer())
+    }
+}
+
+impl<'de> Deserialize<'de> for Message {
+    fn deserialize<D: serde::Deserializer<'de>>(de: D) -> StdResult<Self, D::Error> {
+        de.deserialize_any(MessageVisitor)
+    }
+}
+
+impl Message {
+    fn decode_content<'de, A: ContentAccess<'de>>(
+        tag: &str,
+        mut content: A,
+    ) -> StdResult<Self, A::Error> {
+        Ok(match tag {
+            "AccountUpdate" => Self::AccountUpdate(content.next_content()?),
+            "InstructionNotify" => Self::InstructionNotify(content.next_content()?),
+            "SlotStatusUpdate" => Self::SlotStatusUpdate(content.next_content()?),
+            "RootedWatermark" => Self::RootedWatermark(content.next_content()?),
+            "AccountReassigned" => Self::AccountReassigned(content.next_content()?),
+            "ProducerStarted" => Self::ProducerStarted(content.next_content()?),
+            "ProducerStopped" => Self::ProducerStopped(content.next_content()?),
+            "SignatureNotify" => Self::SignatureNotify(content.next_content()?),
+            "FeeStats" => Self::FeeStats(content.next_content()?),
+            "AccountDiff" => Self::AccountDiff(content.next_content()?),
+            "Stats" => Self::Stats(content.next_content()?),
+            "AccountDeleted" => Self::AccountDeleted(content.next_content()?),
+            "AccountBalance" => Self::AccountBalance(content.next_content()?),
+            "WalletDigest" => Self::WalletDigest(content.next_content()?),
+            "NftSale" => Self::NftSale(content.next_content()?),
+            t => {
+                UNKNOWN_MESSAGES.fetch_add(1, Ordering::Relaxed);
+
+                Self::Unknown {
+                    tag: t.to_owned(),
+                    raw: content.next_content()?,
+                }
+            },
+        })
+    }
+
+    /// The number of messages of unknown variants decoded by this process
+    #[must_use]
+    pub fn unknown_count() -> u64 {
+        UNKNOWN_MESSAGES.load(Ordering::Relaxed)
+    }
+
+    /// The name of this message's variant, in camel case
+    #[must_use]
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::AccountUpdate(_) => "accountUpdate",
+            Self::InstructionNotify(_) => "instructionNotify",
+            Self::SlotStatusUpdate(_) => "slotStatusUpdate",
+            Self::RootedWatermark(_) => "rootedWatermark",
+            Self::AccountReassigned(_) => "accountReassigned",
+            Self::ProducerStarted(_) => "producerStarted",
+            Self::ProducerStopped(_) => "producerStopped",
+            Self::SignatureNotify(_) => "signatureNotify",
+            Self::FeeStats(_) => "feeStats",
+            Self::AccountDiff(_) => "accountDiff",
+            Self::Stats(_) => "stats",
+            Self::AccountDeleted(_) => "accountDeleted",
+            Self::AccountBalance(_) => "accountBalance",
+            Self::WalletDigest(_) => "walletDigest",
+            Self::NftSale(_) => "nftSale",
+            Self::Unknown { .. } => "unknown",
+        }
+    }
+
+    /// The slot this message pertains to, if any
+    #[must_use]
+    pub fn slot(&self) -> Option<u64> {
+        match self {
+            Self::AccountUpdate(AccountUpdate { slot, .. })
+            | Self::InstructionNotify(InstructionNotify { slot, .. })
+            | Self::SlotStatusUpdate(SlotStatusUpdate { slot, .. })
+            | Self::RootedWatermark(RootedWatermark { slot })
+            | Self::AccountReassigned(AccountReassigned { slot, .. })
+            | Self::SignatureNotify(SignatureNotify { slot, .. })
+            | Self::FeeStats(FeeStats { slot, .. })
+            | Self::AccountDiff(AccountDiff { slot, .. })
+            | Self::AccountDeleted(AccountDeleted { slot, .. })
+            | Self::AccountBalance(AccountBalance { slot, .. })
+            | Self::WalletDigest(WalletDigest { slot, .. })
+            | Self::NftSale(NftSale { slot, .. }) => Some(*slot),
+            Self::ProducerStarted(_)
+            | Self::ProducerStopped(_)
+            | Self::Stats(_)
+            | Self::Unknown { .. } => None,
+        }
+    }
+
+    /// A deterministic identifier for this message.  Two producers observing
+    /// the same on-chain event produce the same ID, allowing consumers of
+    /// exchanges shared by several validators to discard duplicates.
+    ///
+    /// Watermarks and lifecycle events describe a single producer, so their
+    /// IDs are only unique per producer.  The ID of an account update hashes
+    /// the account's data, so it is not free to compute for large accounts.
+    #[must_use]
+    pub fn message_id(&self) -> String {
+        match self {
+            Self::AccountUpdate(AccountUpdate {
+                key,
+                lamports,
+                owner,
+                executable,
+                data,
+                slot,
+                data_hash,
+                ..
+            }) => {
+                // write_version is local to each validator, so identify the
+                // write by its contents instead
+                let hash = solana_program::hash::hashv(&[
+                    &lamports.to_le_bytes()[..],
+                    owner.as_ref(),
+                    &[u8::from(*executable)],
+                    data.as_slice(),
+                    data_hash.as_ref().map_or(&[][..], AsRef::as_ref),
+                ]);
+
+                format!("acct:{}:{}:{}", key, slot, hash)
+            },
+            Self::InstructionNotify(InstructionNotify {
+                txn_signature,
+                index,
+                ..
+            }) => format!(
+                "ins:{}:{}",
+                bs58::encode(txn_signature).into_string(),
+                match index {
+                    InstructionIndex::TopLevel(i) => i.to_string(),
+                    InstructionIndex::Inner(p, i) => format!("{}.{}", p, i),
+                    InstructionIndex::Unknown => "unknown".into(),
+                }
+            ),
+            Self::SlotStatusUpdate(SlotStatusUpdate { slot, status, .. }) => {
+                format!("slot:{}:{:?}", slot, status)
+            },
+            Self::RootedWatermark(RootedWatermark { slot }) => format!("watermark:{}", slot),
+            Self::AccountReassigned(AccountReassigned {
+                key,
+                new_owner,
+                slot,
+                ..
+            }) => format!("reassign:{}:{}:{}", key, slot, new_owner),
+            Self::ProducerStarted(ProducerLifecycle { id, .. }) => format!("started:{}", id),
+            Self::ProducerStopped(ProducerLifecycle { id, .. }) => format!("stopped:{}", id),
+            Self::SignatureNotify(SignatureNotify { txn_signature, .. }) => {
+                format!("sig:{}", bs58::encode(txn_signature).into_string())
+            },
+            Self::FeeStats(FeeStats { slot, .. }) => format!("fees:{}", slot),
+            Self::AccountDiff(AccountDiff {
+                key,
+                chain,
+                version,
+                ..
+            }) => format!("diff:{}:{:x}:{}", key, chain, version),
+            Self::Stats(Stats {
+                producer,
+                timestamp,
+                ..
+            }) => format!("stats:{}:{}", producer, timestamp),
+            Self::AccountDeleted(AccountDeleted { key, slot, .. }) => {
+                format!("deleted:{}:{}", key, slot)
+            },
+            Self::AccountBalance(AccountBalance {
+                key,
+                lamports,
+                owner,
+                slot,
+                ..
+            }) => format!("balance:{}:{}:{}:{}", key, slot, owner, lamports),
+            Self::WalletDigest(WalletDigest { wallet, slot, .. }) => {
+                format!("digest:{}:{}", wallet, slot)
+            },
+            Self::NftSale(NftSale {
+                mint,
+                txn_signature,
+                ..
+            }) => format!(
+                "sale:{}:{}",
+                bs58::encode(txn_signature).into_string(),
+                mint
+            ),
+            Self::Unknown { tag, raw } => format!(
+                "unknown:{}:{}",
+                tag,
+                solana_program::hash::hash(&rmp_serde::to_vec(raw).unwrap_or_default())
+            ),
+        }
+    }
+}
+
+/// A class of messages which can be published to a dedicated exchange
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    strum::EnumString,
+    strum::Display,
+)]
+#[strum(serialize_all = "kebab-case")]
+pub enum MessageClass {
+    /// Account updates and reassignments
+    AccountUpdates,
+    /// Instruction and signature notifications
+    Instructions,
+    /// Slot status updates, watermarks, and per-slot statistics
+    SlotStatuses,
+    /// Periodic producer health statistics
+    Stats,
+    /// SPL token account updates, routed by mint with a topic exchange (see
+    /// [`token_shard_key`])
+    TokenAccounts,
+}
+
+/// The routing key prefix of token account updates published to the
+/// [`TokenAccounts`](MessageClass::TokenAccounts) exchange
+pub const TOKEN_SHARD_PREFIX: &str = "tokens";
+
+/// The routing key for a token account update, formed from the first `bytes`
+/// bytes of its mint as dot-separated hex words, e.g. `tokens.c6.fa`.
+/// Consumers may bind to a prefix of the key (e.g. `tokens.c6.#`) to receive
+/// a coarser partition.
+#[must_use]
+pub fn token_shard_key(mint: &Pubkey, bytes: usize) -> String {
+    mint.as_ref()
+        .iter()
+        .take(bytes)
+        .fold(TOKEN_SHARD_PREFIX.to_owned(), |mut key, byte| {
+            key.push_str(&format!(".{:02x}", byte));
+            key
+        })
 }
 
 /// AMQP configuration for Geyser plugins
@@ -166,24 +1077,77 @@ impl QueueType {
     /// # Errors
     /// This function fails if the given queue suffix is invalid.
     pub fn new(network: Network, startup_type: StartupType, suffix: &Suffix) -> Result<Self> {
+        Self::new_named(network, startup_type, suffix, "indexer")
+    }
+
+    /// Construct a new queue configuration as with [`new`](Self::new), but
+    /// with a custom name for the consumer queue.  This allows services other
+    /// than the indexer to receive their own copy of every message.
+    ///
+    /// # Errors
+    /// This function fails if the given queue suffix is invalid.
+    pub fn new_named(
+        network: Network,
+        startup_type: StartupType,
+        suffix: &Suffix,
+        name: &str,
+    ) -> Result<Self> {
+        Self::build(network, startup_type, suffix, None, name)
+    }
+
+    /// Construct a new queue configuration as with
+    /// [`new_named`](Self::new_named), but for the dedicated exchange of a
+    /// single class of messages.  This is used by producers configured to
+    /// split their messages across several exchanges.
+    ///
+    /// # Errors
+    /// This function fails if the given queue suffix is invalid.
+    pub fn new_class(
+        network: Network,
+        startup_type: StartupType,
+        suffix: &Suffix,
+        class: MessageClass,
+        name: &str,
+    ) -> Result<Self> {
+        Self::build(network, startup_type, suffix, Some(class), name)
+    }
+
+    fn build(
+        network: Network,
+        startup_type: StartupType,
+        suffix: &Suffix,
+        class: Option<MessageClass>,
+        name: &str,
+    ) -> Result<Self> {
         let exchange = format!(
-            "{}{}.accounts",
+            "{}{}.{}",
             network,
             match startup_type {
                 StartupType::Normal => "",
                 StartupType::Startup => ".startup",
                 StartupType::All => ".startup-all",
-            }
+            },
+            class.map_or_else(|| "accounts".into(), |c| c.to_string()),
         );
-        let queue = suffix.format(format!("{}.indexer", exchange))?;
+        let queue = suffix.format(format!("{}.{}", exchange, name))?;
 
         Ok(Self {
             props: QueueProps {
                 exchange,
                 queue,
-                binding: Binding::Fanout,
+                binding: if class == Some(MessageClass::TokenAccounts) {
+                    Binding::Topic(vec!["#".into()])
+                } else {
+                    Binding::Fanout
+                },
                 prefetch: 4096,
                 auto_delete: suffix.is_debug(),
+                max_len: None,
+                max_priority: None,
+                overflow: None,
+                confirm: false,
+                mandatory: false,
+                fallback_queue: None,
                 retry: Some(RetryProps {
                     max_tries: 3,
                     delay_hint: Duration::from_millis(500),
@@ -192,6 +1156,137 @@ impl QueueType {
             },
         })
     }
+
+    /// Limit the consumer queue to the given number of messages, discarding
+    /// the oldest messages when it is full
+    #[must_use]
+    pub fn with_max_len(mut self, max_len: u32) -> Self {
+        self.props.max_len = Some(max_len);
+        self
+    }
+
+    /// Declare the consumer queue as a priority queue supporting priorities
+    /// up to and including `max_priority`
+    #[must_use]
+    pub fn with_max_priority(mut self, max_priority: u8) -> Self {
+        self.props.max_priority = Some(max_priority);
+        self
+    }
+
+    /// Set the behavior of the consumer queue when it reaches its maximum
+    /// length
+    #[must_use]
+    pub fn with_overflow(mut self, overflow: Overflow) -> Self {
+        self.props.overflow = Some(overflow);
+        self
+    }
+
+    /// Set the number of unacknowledged messages delivered to each consumer
+    /// of the queue at once
+    #[must_use]
+    pub fn with_prefetch(mut self, prefetch: u16) -> Self {
+        self.props.prefetch = prefetch;
+        self
+    }
+
+    /// Consume from a queue of the given name in place of the default one,
+    /// e.g. to give each member of a consumer group its own partition
+    #[must_use]
+    pub fn with_queue_name(mut self, queue: String) -> Self {
+        self.props.queue = queue;
+        self
+    }
+
+    /// Enable publisher confirms, causing writes rejected by the broker to
+    /// fail with [`Error::PublishNacked`](crate::Error::PublishNacked)
+    #[must_use]
+    pub fn with_confirms(mut self) -> Self {
+        self.props.confirm = true;
+        self
+    }
+
+    /// Query the number of consumers of this configuration's queue, returning
+    /// `None` if the queue has not been declared yet
+    ///
+    /// # Errors
+    /// This function fails if a channel cannot be opened or the broker
+    /// cannot be queried.
+    #[cfg(feature = "producer")]
+    pub async fn consumer_count(&self, conn: &lapin::Connection) -> Result<Option<u32>> {
+        crate::QueueType::info(self).consumer_count(conn).await
+    }
+
+    /// Query the message and consumer counts of this configuration's queue,
+    /// returning `None` if the queue has not been declared yet
+    ///
+    /// # Errors
+    /// This function fails if a channel cannot be opened or the broker
+    /// cannot be queried.
+    #[cfg(feature = "producer")]
+    pub async fn status(&self, conn: &lapin::Connection) -> Result<Option<crate::QueueStatus>> {
+        crate::QueueType::info(self).status(conn).await
+    }
+
+    /// Bind the consumer queue to a single shard of the
+    /// [`TokenAccounts`](MessageClass::TokenAccounts) exchange, given as a
+    /// topic pattern such as `tokens.c6.#`
+    ///
+    /// # Errors
+    /// This function fails if this queue type is not for the token account
+    /// exchange.
+    pub fn with_token_shard(self, pattern: impl Into<String>) -> Result<Self> {
+        self.with_token_shards(vec![pattern.into()])
+    }
+
+    /// Bind the consumer queue to several shards of the
+    /// [`TokenAccounts`](MessageClass::TokenAccounts) exchange, each given as
+    /// a topic pattern such as `tokens.c6.#`
+    ///
+    /// # Errors
+    /// This function fails if this queue type is not for the token account
+    /// exchange or no patterns are given.
+    pub fn with_token_shards(mut self, patterns: Vec<String>) -> Result<Self> {
+        if patterns.is_empty() {
+            return Err(crate::Error::InvalidQueueType(
+                "At least one token shard must be bound",
+            ));
+        }
+
+        match self.props.binding {
+            Binding::Topic(ref mut p) => *p = patterns,
+            _ => {
+                return Err(crate::Error::InvalidQueueType(
+                    "Only token account queues can be bound to a shard",
+                ))
+            },
+        }
+
+        Ok(self)
+    }
+
+    /// Declare a versioned exchange name, e.g. `mainnet.accounts.v2`, in
+    /// place of the default one.  This allows producers to fall back to a
+    /// fresh exchange when the default one already exists with
+    /// [different arguments](crate::Error::DeclarationMismatch).  Consumers
+    /// must be configured with the same version to receive messages from it.
+    #[must_use]
+    pub fn with_exchange_version(mut self, version: u32) -> Self {
+        self.props.exchange = format!("{}.v{}", self.props.exchange, version);
+        self
+    }
+
+    /// Publish messages with the `mandatory` flag, causing writes of
+    /// unroutable messages to fail with
+    /// [`Error::Unroutable`](crate::Error::Unroutable).  If a fallback queue
+    /// is given, unroutable messages are diverted to it.  This enables
+    /// publisher confirms.
+    #[must_use]
+    pub fn with_mandatory(mut self, fallback_queue: Option<String>) -> Self {
+        self.props.confirm = true;
+        self.props.mandatory = true;
+        self.props.fallback_queue = fallback_queue;
+        self
+    }
 }
 
 impl crate::QueueType for QueueType {
@@ -201,6 +1296,15 @@ impl crate::QueueType for QueueType {
     fn info(&self) -> crate::queue_type::QueueInfo {
         (&self.props).into()
     }
+
+    /// Account updates are keyed by their owner, for per-owner encodings and
+    /// compression dictionaries
+    fn message_key(&self, msg: &Message) -> Option<String> {
+        match msg {
+            Message::AccountUpdate(u) => Some(u.owner.to_string()),
+            _ => None,
+        }
+    }
 }
 
 /// The type of a Geyser producer
@@ -0,0 +1,271 @@
+//! Helpers for running several competing consumers of one Geyser queue.
+//!
+//! Each consumer joins the group as a [`Member`] with its index and the
+//! group's size, taking its share of the group's prefetch budget.  Members
+//! of a token account queue can instead consume partitions of their own, so
+//! every update of a given mint is handled by the same member:
+//!
+//! ```ignore
+//! let member = Member::new(index, size)?;
+//! let ty = member.partition(member.share(ty, 4096))?;
+//! let (mut consumer, watchdog) = member.join(&conn, ty, "indexer", STALL).await?;
+//! let heartbeat = watchdog.heartbeat();
+//!
+//! tokio::spawn(watchdog.run());
+//!
+//! while let Some((msg, acker)) = consumer.read().await? {
+//!     heartbeat.begin();
+//!     handle(msg).await?;
+//!     acker.ack(BasicAckOptions::default()).await?;
+//!     heartbeat.end();
+//! }
+//! ```
+//!
+//! The broker requeues a member's unacknowledged messages once its channel
+//! closes.  A crashed member is only detected after the connection's AMQP
+//! heartbeat times out, so the `heartbeat` query parameter of the broker
+//! URI should be kept short.  A member whose processing loop hangs keeps
+//! its connection alive, so the [`Watchdog`] closes its channel instead
+//! once the loop stops beating.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use lapin::{Channel, Connection};
+
+use crate::{
+    consumer::Consumer,
+    geyser::{QueueType, TOKEN_SHARD_PREFIX},
+    QueueType as _, Result,
+};
+
+/// A single consumer of a group of competing consumers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Member {
+    index: u16,
+    size: u16,
+}
+
+impl Member {
+    /// Construct the member with the given zero-based index of a group of
+    /// `size` consumers
+    ///
+    /// # Errors
+    /// This function fails if the index is not less than the group size.
+    pub fn new(index: u16, size: u16) -> Result<Self> {
+        if index >= size {
+            return Err(crate::Error::InvalidQueueType(
+                "Group member index must be less than the group size",
+            ));
+        }
+
+        Ok(Self { index, size })
+    }
+
+    /// The zero-based index of this member
+    #[must_use]
+    pub fn index(self) -> u16 {
+        self.index
+    }
+
+    /// The number of members of this member's group
+    #[must_use]
+    pub fn size(self) -> u16 {
+        self.size
+    }
+
+    /// This member's share of a prefetch budget for the whole group,
+    /// distributing any remainder over the lowest indices
+    #[must_use]
+    pub fn prefetch(self, total: u16) -> u16 {
+        let share = total / self.size + u16::from(self.index < total % self.size);
+
+        share.max(1)
+    }
+
+    /// Limit the prefetch of the given queue type to this member's share of
+    /// a budget of `total` unacknowledged messages for the whole group
+    #[must_use]
+    pub fn share(self, ty: QueueType, total: u16) -> QueueType {
+        ty.with_prefetch(self.prefetch(total))
+    }
+
+    /// The patterns of the token account shards assigned to this member,
+    /// partitioning mints by the first byte of their
+    /// [shard key](crate::geyser::token_shard_key)
+    #[must_use]
+    pub fn token_shards(self) -> Vec<String> {
+        (0..=u8::MAX)
+            .filter(|b| u16::from(*b) % self.size == self.index)
+            .map(|b| format!("{}.{:02x}.#", TOKEN_SHARD_PREFIX, b))
+            .collect()
+    }
+
+    /// Consume this member's partition of a token account queue from a queue
+    /// of its own, named after the member's index and the group's size.
+    /// Producers must shard token accounts by at least one byte of their
+    /// mint.  Queues of previous group sizes are not removed, and should be
+    /// deleted once drained.
+    ///
+    /// # Errors
+    /// This function fails if the group has more than 256 members, or the
+    /// queue type is not for the token account exchange.
+    pub fn partition(self, ty: QueueType) -> Result<QueueType> {
+        if self.size > 256 {
+            return Err(crate::Error::InvalidQueueType(
+                "Token account partitions support at most 256 group members",
+            ));
+        }
+
+        let queue = format!("{}.{}-of-{}", ty.info().queue(), self.index, self.size);
+
+        Ok(ty
+            .with_token_shards(self.token_shards())?
+            .with_queue_name(queue))
+    }
+
+    /// Start consuming the given queue type, returning the consumer and a
+    /// watchdog closing its channel if its processing loop stalls for
+    /// longer than `stall_timeout`
+    ///
+    /// # Errors
+    /// This function fails if the channel cannot be opened or the consumer
+    /// cannot be configured successfully.
+    pub async fn join(
+        self,
+        conn: &Connection,
+        ty: QueueType,
+        tag: impl AsRef<str>,
+        stall_timeout: Duration,
+    ) -> Result<(Consumer<QueueType>, Watchdog)> {
+        let chan = conn.create_channel().await?;
+        let tag = format!("{}-{}-of-{}", tag.as_ref(), self.index, self.size);
+        let consumer = ty.info().init_consumer(&chan, tag).await?;
+
+        Ok((
+            Consumer::from_subscriber(consumer),
+            Watchdog {
+                chan,
+                heartbeat: Heartbeat::new(),
+                timeout: stall_timeout,
+            },
+        ))
+    }
+}
+
+#[derive(Debug)]
+struct Beat {
+    start: Instant,
+    /// Milliseconds since `start` of the last beat
+    last: AtomicU64,
+    /// Number of deliveries being handled
+    pending: AtomicUsize,
+}
+
+/// Handle signalling that a member's processing loop is making progress.
+/// Only a member handling deliveries can stall, so a member waiting on an
+/// idle queue is never considered stalled.
+#[derive(Debug, Clone)]
+pub struct Heartbeat(Arc<Beat>);
+
+impl Heartbeat {
+    fn new() -> Self {
+        Self(Arc::new(Beat {
+            start: Instant::now(),
+            last: AtomicU64::new(0),
+            pending: AtomicUsize::new(0),
+        }))
+    }
+
+    fn beat(&self) {
+        let ms = self
+            .0
+            .start
+            .elapsed()
+            .as_millis()
+            .try_into()
+            .unwrap_or(u64::MAX);
+
+        self.0.last.store(ms, Ordering::Relaxed);
+    }
+
+    /// Record that a delivery was received and is being handled
+    pub fn begin(&self) {
+        self.beat();
+        self.0.pending.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record that a delivery was acknowledged or rejected
+    pub fn end(&self) {
+        self.beat();
+        self.0
+            .pending
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |p| p.checked_sub(1))
+            .ok();
+    }
+
+    /// How long deliveries have been pending without progress, if any are
+    fn stalled_for(&self) -> Option<Duration> {
+        if self.0.pending.load(Ordering::Relaxed) == 0 {
+            return None;
+        }
+
+        Some(
+            self.0
+                .start
+                .elapsed()
+                .saturating_sub(Duration::from_millis(self.0.last.load(Ordering::Relaxed))),
+        )
+    }
+}
+
+/// Monitor closing a member's channel once its [`Heartbeat`] stops, so the
+/// broker redelivers its unacknowledged messages to the rest of the group
+#[derive(Debug)]
+pub struct Watchdog {
+    chan: Channel,
+    heartbeat: Heartbeat,
+    timeout: Duration,
+}
+
+impl Watchdog {
+    /// A handle to the heartbeat monitored by this watchdog
+    #[must_use]
+    pub fn heartbeat(&self) -> Heartbeat {
+        self.heartbeat.clone()
+    }
+
+    /// Monitor the heartbeat until it stalls or the channel closes.  A
+    /// stalled member's consumer stops receiving deliveries and should be
+    /// rejoined.
+    ///
+    /// # Errors
+    /// This function fails if the stalled channel cannot be closed.
+    pub async fn run(self) -> Result<()> {
+        let period = (self.timeout / 4).max(Duration::from_millis(100));
+
+        while self.chan.status().connected() {
+            tokio::time::sleep(period).await;
+
+            let stalled = match self.heartbeat.stalled_for() {
+                Some(d) if d >= self.timeout => d,
+                _ => continue,
+            };
+
+            log::warn!(
+                "Consumer stalled for {:?}, releasing its unacknowledged messages",
+                stalled
+            );
+
+            self.chan.close(200, "Consumer stalled").await?;
+            break;
+        }
+
+        Ok(())
+    }
+}
@@ -101,6 +101,12 @@ impl<E: Entity> QueueType<E> {
                 binding: Binding::Fanout,
                 prefetch: 1024,
                 auto_delete: suffix.is_debug(),
+                max_len: None,
+                max_priority: None,
+                overflow: None,
+                confirm: false,
+                mandatory: false,
+                fallback_queue: None,
                 retry: Some(RetryProps {
                     max_tries: 8,
                     delay_hint: Duration::from_secs(2),
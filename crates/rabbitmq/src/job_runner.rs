@@ -5,7 +5,7 @@ use std::time::Duration;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    geyser::StartupType,
+    geyser::{Pubkey, StartupType},
     queue_type::{Binding, QueueProps, RetryProps},
     suffix::Suffix,
     Result,
@@ -20,6 +20,13 @@ pub struct SlotReindex {
     pub startup: StartupType,
 }
 
+/// Message data for an account snapshot request
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct AccountSnapshotRequest {
+    /// The account to publish the current state of
+    pub pubkey: Pubkey,
+}
+
 /// Message data for a job dispatch request
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Message {
@@ -27,6 +34,10 @@ pub enum Message {
     RefreshTable(String),
     /// Reindex a given slot
     ReindexSlot(SlotReindex),
+    /// Request that a Geyser producer publish the current state of an
+    /// account.  Only answered when published with
+    /// [`QueueType::snapshot_requests`].
+    RequestAccountSnapshot(AccountSnapshotRequest),
 }
 
 /// AMQP configuration for job runners
@@ -45,20 +56,44 @@ impl QueueType {
         let exchange = format!("{}.jobs", sender);
         let queue = suffix.format(format!("{}.runner", exchange))?;
 
-        Ok(Self {
+        Ok(Self::with_names(exchange, queue, suffix))
+    }
+
+    /// Construct the queue configuration for account snapshot requests to
+    /// the Geyser producers of the given sender.  Snapshot requests have an
+    /// exchange of their own, so producers answering them never consume the
+    /// jobs meant for the job runner.
+    ///
+    /// # Errors
+    /// This function fails if the given queue suffix is invalid.
+    pub fn snapshot_requests(sender: &str, suffix: &Suffix) -> Result<Self> {
+        let exchange = format!("{}.snapshots", sender);
+        let queue = suffix.format(format!("{}.geyser", exchange))?;
+
+        Ok(Self::with_names(exchange, queue, suffix))
+    }
+
+    fn with_names(exchange: String, queue: String, suffix: &Suffix) -> Self {
+        Self {
             props: QueueProps {
                 exchange,
                 queue,
                 binding: Binding::Fanout,
                 prefetch: 1,
                 auto_delete: suffix.is_debug(),
+                max_len: None,
+                max_priority: None,
+                overflow: None,
+                confirm: false,
+                mandatory: false,
+                fallback_queue: None,
                 retry: Some(RetryProps {
                     max_tries: 5,
                     delay_hint: Duration::from_secs(5),
                     max_delay: Duration::from_secs(10 * 60),
                 }),
             },
-        })
+        }
     }
 }
 
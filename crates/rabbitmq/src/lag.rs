@@ -0,0 +1,155 @@
+//! Delivery lag measurement for consumers of Geyser messages.
+//!
+//! Producers stamp each message with the time it was published in the
+//! [`PUBLISH_TIME_HEADER`](crate::geyser::PUBLISH_TIME_HEADER) header.  A
+//! consumer observes the lag of each delivery against its own clock and
+//! periodically reports percentiles over the deliveries since the last
+//! report:
+//!
+//! ```ignore
+//! let mut lag = LagTracker::new(10_000);
+//!
+//! while let Some((msg, props, acker)) = consumer.read_with_properties().await? {
+//!     lag.observe(&props);
+//!     handle(msg).await?;
+//!     acker.ack(BasicAckOptions::default()).await?;
+//!
+//!     if let Some(report) = lag.report_every(Duration::from_secs(60)) {
+//!         info!("Delivery lag: {}", report);
+//!     }
+//! }
+//! ```
+//!
+//! Lag is measured across hosts, so it is only as accurate as the
+//! synchronization of the producer's and consumer's clocks.
+
+use std::{
+    fmt,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use lapin::BasicProperties;
+
+use crate::geyser::publish_time;
+
+/// Delivery lag percentiles over an interval
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(clippy::module_name_repetitions)]
+pub struct LagReport {
+    /// Number of stamped deliveries observed in the interval
+    pub count: u64,
+    /// Median lag
+    pub p50: Duration,
+    /// 90th percentile lag
+    pub p90: Duration,
+    /// 99th percentile lag
+    pub p99: Duration,
+    /// Highest lag observed
+    pub max: Duration,
+}
+
+impl fmt::Display for LagReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "p50={:?} p90={:?} p99={:?} max={:?} ({} deliveries)",
+            self.p50, self.p90, self.p99, self.max, self.count
+        )
+    }
+}
+
+/// Reservoir of delivery lag samples, bounded in size regardless of the
+/// delivery rate
+#[derive(Debug)]
+#[allow(clippy::module_name_repetitions)]
+pub struct LagTracker {
+    capacity: usize,
+    samples: Vec<u64>,
+    count: u64,
+    max: u64,
+    last_report: Instant,
+}
+
+impl LagTracker {
+    /// Construct a tracker keeping at most `capacity` samples per report
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            samples: Vec::new(),
+            count: 0,
+            max: 0,
+            last_report: Instant::now(),
+        }
+    }
+
+    /// Record the lag of a delivery, returning it if the delivery was
+    /// stamped by its producer.  Deliveries appearing to arrive before they
+    /// were published, due to clock skew, are recorded with no lag.
+    pub fn observe(&mut self, props: &BasicProperties) -> Option<Duration> {
+        let published = publish_time(props)?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()?
+            .as_micros()
+            .try_into()
+            .unwrap_or(i64::MAX);
+        let lag = u64::try_from(now.saturating_sub(published)).unwrap_or(0);
+
+        self.count += 1;
+        self.max = self.max.max(lag);
+
+        if self.samples.len() < self.capacity {
+            self.samples.push(lag);
+        } else {
+            let i = rand::random::<u64>() % self.count;
+
+            if let Some(s) = usize::try_from(i)
+                .ok()
+                .and_then(|i| self.samples.get_mut(i))
+            {
+                *s = lag;
+            }
+        }
+
+        Some(Duration::from_micros(lag))
+    }
+
+    /// Summarize the deliveries observed since the last report and start a
+    /// new interval.  Returns `None` if no stamped deliveries were observed.
+    pub fn report(&mut self) -> Option<LagReport> {
+        let mut samples = std::mem::take(&mut self.samples);
+        let count = std::mem::replace(&mut self.count, 0);
+        let max = std::mem::replace(&mut self.max, 0);
+        self.last_report = Instant::now();
+
+        if samples.is_empty() {
+            return None;
+        }
+
+        samples.sort_unstable();
+
+        let pct = |p: usize| {
+            let i = (samples.len() * p / 100).min(samples.len() - 1);
+            Duration::from_micros(samples[i])
+        };
+
+        Some(LagReport {
+            count,
+            p50: pct(50),
+            p90: pct(90),
+            p99: pct(99),
+            max: Duration::from_micros(max),
+        })
+    }
+
+    /// Report as with [`report`](Self::report) if at least `interval` has
+    /// passed since the last report
+    pub fn report_every(&mut self, interval: Duration) -> Option<LagReport> {
+        if self.last_report.elapsed() < interval {
+            return None;
+        }
+
+        self.report()
+    }
+}
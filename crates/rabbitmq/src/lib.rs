@@ -30,31 +30,122 @@ pub enum Error {
     /// An error propagated from [`rmp_serde`] during decoding
     #[error("MsgPack decode error: {0:?}")]
     MsgDecode(#[from] rmp_serde::decode::Error),
+    /// An error propagated from [`serde_json`] while encoding or decoding
+    #[cfg(feature = "json")]
+    #[error("JSON error: {0:?}")]
+    Json(#[from] serde_json::Error),
+    /// A delivery was published with a content type this consumer cannot
+    /// decode
+    #[error("Unsupported content type {0:?}")]
+    UnsupportedContentType(String),
+    /// A published message was negatively acknowledged by the broker, e.g.
+    /// because the destination queue is full
+    #[error("Message was rejected by the broker")]
+    PublishNacked,
+    /// A message published with the `mandatory` flag could not be routed to
+    /// any queue
+    #[error("Message could not be routed (diverted to fallback: {diverted})")]
+    Unroutable {
+        /// True if the message was published to the fallback queue instead
+        diverted: bool,
+    },
+    /// An exchange or queue already exists on the broker with arguments
+    /// differing from the ones it was declared with
+    #[error(
+        "{kind} {name:?} already exists with different arguments: {differing} (declared with \
+         {declared})"
+    )]
+    DeclarationMismatch {
+        /// The kind of resource being declared, e.g. `"exchange"`
+        kind: &'static str,
+        /// The name of the resource being declared
+        name: String,
+        /// The arguments reported by the broker as differing
+        differing: String,
+        /// The full set of arguments the resource was declared with
+        declared: String,
+    },
     /// An error occurred related to a queue's configuration
     #[error("Invalid queue type for operation: {0}")]
     InvalidQueueType(&'static str),
+    /// A payload could not be compressed or decompressed
+    #[cfg(feature = "compression")]
+    #[error("Compression error: {0}")]
+    Compression(#[source] std::io::Error),
+    /// A payload was compressed with a dictionary the consumer does not hold
+    #[cfg(feature = "compression")]
+    #[error("Payload was compressed with unknown dictionary {0:?}")]
+    UnknownDictionary(String),
+    /// A payload could not be encrypted or decrypted
+    #[cfg(feature = "encryption")]
+    #[error("Encryption error: {0}")]
+    Encryption(&'static str),
+    /// A payload was encrypted with a key the consumer does not hold
+    #[cfg(feature = "encryption")]
+    #[error("Payload was encrypted with unknown key {0:?}")]
+    UnknownKey(String),
+    /// An error occurred reading or writing a persisted watermark
+    #[cfg(feature = "resume")]
+    #[error("Watermark store error: {0}")]
+    WatermarkStore(#[source] Box<dyn std::error::Error + Send + Sync + 'static>),
 }
 
 #[allow(dead_code)]
 pub(crate) type Result<T, E = Error> = std::result::Result<T, E>;
 
+#[cfg(feature = "compression")]
+pub mod compression;
 #[cfg(feature = "consumer")]
 pub mod consumer;
+#[cfg(feature = "control")]
+pub mod control;
+#[cfg(all(feature = "consumer", feature = "geyser"))]
+pub mod dedup;
 #[cfg(feature = "consumer")]
 pub mod dl_consumer;
+#[cfg(feature = "encryption")]
+pub mod encryption;
 #[cfg(feature = "geyser")]
 pub mod geyser;
+#[cfg(feature = "group")]
+pub mod group;
 #[cfg(feature = "http-indexer")]
 pub mod http_indexer;
 #[cfg(feature = "job-runner")]
 pub mod job_runner;
+#[cfg(all(feature = "consumer", feature = "geyser"))]
+pub mod lag;
+#[cfg(any(
+    feature = "test-util",
+    all(test, feature = "consumer", feature = "producer")
+))]
+pub mod memory;
+#[cfg(all(test, feature = "consumer", feature = "producer"))]
+mod memory_tests;
 #[cfg(feature = "producer")]
 pub mod producer;
 mod queue_type;
+#[cfg(all(feature = "consumer", feature = "geyser"))]
+pub mod quorum;
+#[cfg(feature = "geyser")]
+pub mod raw;
+#[cfg(feature = "consumer")]
+pub mod replay;
+#[cfg(feature = "resume")]
+pub mod resume;
 #[cfg(feature = "search-indexer")]
 pub mod search_indexer;
 mod serialize;
 #[cfg(feature = "suffix")]
 pub mod suffix;
+#[cfg(any(feature = "producer", feature = "consumer"))]
+pub mod transport;
+#[cfg(all(test, feature = "consumer", feature = "geyser", feature = "producer"))]
+mod wire_tests;
 
-pub use queue_type::QueueType;
+#[cfg(feature = "producer")]
+pub use queue_type::QueueStatus;
+pub use queue_type::{Overflow, QueueInfo, QueueType};
+#[cfg(feature = "producer")]
+pub use serialize::Encoding;
+pub use serialize::{JSON_CONTENT_TYPE, MSGPACK_CONTENT_TYPE};
@@ -0,0 +1,344 @@
+//! An in-memory transport backend for testing producers and consumers
+//! without a broker.
+//!
+//! A [`Broker`] routes messages from exchanges to queues the same way
+//! RabbitMQ routes them for this crate's queue types: fanout bindings
+//! receive every message, direct bindings receive messages whose routing key
+//! equals their key, and topic bindings receive messages whose routing key
+//! matches one of their patterns.  Queues are declared by
+//! subscribing to them, so messages published before a consumer is created
+//! are not delivered to it.
+//!
+//! ```ignore
+//! let broker = Broker::default();
+//! let mut consumer = broker.consumer(&queue_type);
+//! let producer = broker.producer(queue_type).await?;
+//!
+//! producer.write(msg).await?;
+//! let (msg, acker) = consumer.read().await?.unwrap();
+//! ```
+//!
+//! Messages rejected without requeueing are redelivered up to the retry
+//! limit of their queue type, after which they are dead-lettered and can be
+//! inspected with [`Broker::dead_letters`].
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use futures_util::future::BoxFuture;
+use lapin::BasicProperties;
+use tokio::sync::{mpsc, Mutex as AsyncMutex};
+
+use crate::{
+    consumer::Consumer,
+    producer::Producer,
+    queue_type::{Binding, QueueInfo},
+    transport::{Ack, Delivery, Outcome, Publisher, Subscriber},
+    QueueType, Result,
+};
+
+#[derive(Debug, Clone)]
+struct Envelope {
+    data: Vec<u8>,
+    properties: BasicProperties,
+    /// The number of times this message has been rejected
+    rejections: u64,
+}
+
+#[derive(Debug)]
+struct Queue {
+    tx: mpsc::UnboundedSender<Envelope>,
+    rx: Arc<AsyncMutex<mpsc::UnboundedReceiver<Envelope>>>,
+    max_tries: Option<u64>,
+    dead: Vec<Envelope>,
+}
+
+/// A binding key, matched against routing keys the way its exchange type
+/// matches them
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum BindKey {
+    Fanout,
+    Direct(String),
+    Topic(String),
+}
+
+impl BindKey {
+    fn matches(&self, key: &str) -> bool {
+        match self {
+            Self::Fanout => true,
+            Self::Direct(k) => k == key,
+            Self::Topic(pattern) => topic_matches(pattern, key),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Bindings of each exchange, as pairs of binding key and queue name
+    exchanges: HashMap<String, Vec<(BindKey, String)>>,
+    queues: HashMap<String, Queue>,
+}
+
+/// An in-memory message broker implementing [`Publisher`] and handing out
+/// [`Subscription`]s.  Clones share the same exchanges and queues.
+#[derive(Debug, Clone, Default)]
+pub struct Broker(Arc<Mutex<State>>);
+
+/// Returns true if the given routing key matches an AMQP topic pattern
+fn topic_matches(pattern: &str, key: &str) -> bool {
+    fn rec(pat: &[&str], key: &[&str]) -> bool {
+        match (pat.split_first(), key.split_first()) {
+            (None, None) => true,
+            (Some((&"#", rest)), _) => {
+                rec(rest, key) || key.split_first().map_or(false, |(_, k)| rec(pat, k))
+            },
+            (Some((&p, pat_rest)), Some((&k, key_rest))) => {
+                (p == "*" || p == k) && rec(pat_rest, key_rest)
+            },
+            _ => false,
+        }
+    }
+
+    let pat: Vec<_> = pattern.split('.').collect();
+    let key: Vec<_> = key.split('.').collect();
+
+    rec(&pat, &key)
+}
+
+impl Broker {
+    fn state(&self) -> MutexGuard<State> {
+        self.0
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    fn enqueue(&self, queue: &str, env: Envelope) -> bool {
+        self.state()
+            .queues
+            .get(queue)
+            .map_or(false, |q| q.tx.send(env).is_ok())
+    }
+
+    /// Declare the queue of the given queue configuration, bind it to its
+    /// exchange, and subscribe to it.  Subscriptions to the same queue
+    /// compete for its messages.
+    #[must_use]
+    pub fn subscribe(&self, info: QueueInfo) -> Subscription {
+        let mut state = self.state();
+        let name = info.queue().to_owned();
+
+        let bindings = state.exchanges.entry(info.exchange().into()).or_default();
+
+        for key in info.binding().binding_keys() {
+            let key = match info.binding() {
+                Binding::Fanout => BindKey::Fanout,
+                Binding::Direct(_) => BindKey::Direct(key.to_owned()),
+                Binding::Topic(_) => BindKey::Topic(key.to_owned()),
+            };
+            let binding = (key, name.clone());
+
+            if !bindings.contains(&binding) {
+                bindings.push(binding);
+            }
+        }
+
+        let queue = state.queues.entry(name.clone()).or_insert_with(|| {
+            let (tx, rx) = mpsc::unbounded_channel();
+
+            Queue {
+                tx,
+                rx: Arc::new(AsyncMutex::new(rx)),
+                max_tries: info.max_tries(),
+                dead: vec![],
+            }
+        });
+
+        Subscription {
+            broker: self.clone(),
+            queue: name,
+            rx: Arc::clone(&queue.rx),
+        }
+    }
+
+    /// Construct a producer for the given queue type publishing to this
+    /// broker
+    ///
+    /// # Errors
+    /// This function never fails, but returns a result for parity with
+    /// [`Producer::new`].
+    pub async fn producer<Q: QueueType>(&self, ty: Q) -> Result<Producer<Q, Self>>
+    where
+        Q::Message: serde::Serialize,
+    {
+        Producer::from_publisher(self.clone(), ty).await
+    }
+
+    /// Construct a consumer for the given queue type subscribed to this
+    /// broker
+    #[must_use]
+    pub fn consumer<Q: QueueType>(&self, ty: &Q) -> Consumer<Q, Subscription>
+    where
+        Q::Message: for<'a> serde::Deserialize<'a>,
+    {
+        Consumer::from_subscriber(self.subscribe(ty.info()))
+    }
+
+    /// Get the payloads and properties of every message dead-lettered from
+    /// the given queue
+    #[must_use]
+    pub fn dead_letters(&self, queue: &str) -> Vec<(Vec<u8>, BasicProperties)> {
+        self.state().queues.get(queue).map_or_else(Vec::new, |q| {
+            q.dead
+                .iter()
+                .map(|e| (e.data.clone(), e.properties.clone()))
+                .collect()
+        })
+    }
+}
+
+impl Publisher for Broker {
+    fn declare<'a>(&'a self, info: QueueInfo<'a>) -> BoxFuture<'a, Result<()>> {
+        self.state()
+            .exchanges
+            .entry(info.exchange().into())
+            .or_default();
+
+        Box::pin(async { Ok(()) })
+    }
+
+    fn publish<'a>(
+        &'a self,
+        info: QueueInfo<'a>,
+        routing_key: Option<&'a str>,
+        data: &'a [u8],
+        props: BasicProperties,
+    ) -> BoxFuture<'a, Result<Outcome>> {
+        let key = info.routing_key(routing_key);
+        let queues: Vec<_> = self
+            .state()
+            .exchanges
+            .get(info.exchange())
+            .into_iter()
+            .flatten()
+            .filter(|(binding, _)| binding.matches(key))
+            .map(|(_, queue)| queue.clone())
+            .collect();
+
+        let mut routed = false;
+
+        for queue in queues {
+            routed |= self.enqueue(
+                &queue,
+                Envelope {
+                    data: data.to_vec(),
+                    properties: props.clone(),
+                    rejections: 0,
+                },
+            );
+        }
+
+        let outcome = if routed || !info.is_mandatory() {
+            Outcome::Delivered
+        } else {
+            Outcome::Returned
+        };
+
+        Box::pin(async move { Ok(outcome) })
+    }
+
+    fn divert<'a>(
+        &'a self,
+        info: QueueInfo<'a>,
+        data: &'a [u8],
+        props: BasicProperties,
+    ) -> BoxFuture<'a, Result<bool>> {
+        let diverted = info.fallback_queue().map_or(false, |q| {
+            self.enqueue(
+                q,
+                Envelope {
+                    data: data.to_vec(),
+                    properties: props,
+                    rejections: 0,
+                },
+            )
+        });
+
+        Box::pin(async move { Ok(diverted) })
+    }
+
+    fn is_connected(&self) -> bool {
+        true
+    }
+}
+
+/// A subscription to a single queue of a [`Broker`]
+#[derive(Debug, Clone)]
+pub struct Subscription {
+    broker: Broker,
+    queue: String,
+    rx: Arc<AsyncMutex<mpsc::UnboundedReceiver<Envelope>>>,
+}
+
+impl Subscriber for Subscription {
+    type Acker = Acker;
+
+    fn next_delivery(&mut self) -> BoxFuture<'_, Result<Option<Delivery<Acker>>>> {
+        Box::pin(async move {
+            let env = self.rx.lock().await.recv().await;
+
+            Ok(env.map(|env| Delivery {
+                data: env.data.clone(),
+                properties: env.properties.clone(),
+                acker: Acker {
+                    broker: self.broker.clone(),
+                    queue: self.queue.clone(),
+                    env,
+                },
+            }))
+        })
+    }
+}
+
+/// Acknowledgement handle for a message delivered by a [`Subscription`]
+#[derive(Debug)]
+pub struct Acker {
+    broker: Broker,
+    queue: String,
+    env: Envelope,
+}
+
+impl Ack for Acker {
+    fn ack_delivery(self) -> BoxFuture<'static, Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn reject_delivery(self, requeue: bool) -> BoxFuture<'static, Result<()>> {
+        let Self {
+            broker,
+            queue,
+            mut env,
+        } = self;
+
+        if requeue {
+            broker.enqueue(&queue, env);
+        } else {
+            env.rejections += 1;
+
+            let mut state = broker.state();
+
+            if let Some(q) = state.queues.get_mut(&queue) {
+                if q.max_tries.map_or(false, |m| env.rejections <= m) {
+                    // A failed send means the broker is gone, so there is
+                    // nowhere left to dead-letter to either
+                    q.tx.send(env).ok();
+                } else {
+                    q.dead.push(env);
+                }
+            }
+        }
+
+        Box::pin(async { Ok(()) })
+    }
+}
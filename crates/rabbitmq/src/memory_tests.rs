@@ -0,0 +1,192 @@
+//! Routing and acknowledgement semantics of the in-memory [`Broker`], which
+//! tests of producers and consumers elsewhere rely on behaving like
+//! RabbitMQ.
+
+use std::{future::Future, time::Duration};
+
+use lapin::BasicProperties;
+
+use crate::{
+    memory::Broker,
+    queue_type::{Binding, QueueInfo, QueueProps, RetryProps},
+    transport::{Ack, Publisher},
+    QueueType,
+};
+
+#[derive(Debug)]
+struct Test(QueueProps);
+
+impl QueueType for Test {
+    type Message = String;
+
+    fn info(&self) -> QueueInfo {
+        (&self.0).into()
+    }
+}
+
+fn queue(queue: &str, binding: Binding, max_tries: Option<u64>) -> Test {
+    Test(QueueProps {
+        exchange: "test".into(),
+        queue: queue.into(),
+        binding,
+        prefetch: 1,
+        auto_delete: true,
+        max_len: None,
+        max_priority: None,
+        overflow: None,
+        confirm: false,
+        mandatory: false,
+        fallback_queue: None,
+        retry: max_tries.map(|max_tries| RetryProps {
+            max_tries,
+            delay_hint: Duration::ZERO,
+            max_delay: Duration::ZERO,
+        }),
+    })
+}
+
+fn run(test: impl Future<Output = ()>) {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .unwrap()
+        .block_on(test);
+}
+
+#[test]
+fn publish_and_consume() {
+    run(async {
+        let broker = Broker::default();
+        let ty = queue("q", Binding::Fanout, None);
+        let mut consumer = broker.consumer(&ty);
+        let producer = broker.producer(ty).await.unwrap();
+
+        producer.write("hello".to_owned()).await.unwrap();
+        let (msg, acker) = consumer.read().await.unwrap().unwrap();
+        acker.ack_delivery().await.unwrap();
+
+        assert_eq!(msg, "hello");
+    });
+}
+
+#[test]
+fn direct_bindings_match_exactly() {
+    run(async {
+        let broker = Broker::default();
+        let mut direct = broker.consumer(&queue("direct", Binding::Direct("a.*".into()), None));
+        let mut exact = broker.consumer(&queue("exact", Binding::Direct("a.b".into()), None));
+        let producer = broker
+            .producer(queue("producer", Binding::Direct("a.b".into()), None))
+            .await
+            .unwrap();
+
+        producer.write("routed".to_owned()).await.unwrap();
+
+        let (msg, _) = exact.read().await.unwrap().unwrap();
+        assert_eq!(msg, "routed");
+        assert!(
+            tokio::time::timeout(Duration::from_millis(10), direct.read())
+                .await
+                .is_err()
+        );
+    });
+}
+
+#[test]
+fn topic_bindings_match_wildcards() {
+    run(async {
+        let broker = Broker::default();
+        let ty = queue(
+            "topic",
+            Binding::Topic(vec!["x.#".into(), "a.*".into()]),
+            None,
+        );
+        let mut consumer = broker.consumer(&ty);
+        let producer = broker.producer(ty).await.unwrap();
+
+        for key in ["a.b", "x.y.z", "b.a"] {
+            producer
+                .write_routed(&key.to_owned(), Some(key), BasicProperties::default())
+                .await
+                .unwrap();
+        }
+
+        for key in ["a.b", "x.y.z"] {
+            let (msg, _) = consumer.read().await.unwrap().unwrap();
+            assert_eq!(msg, key);
+        }
+
+        assert!(
+            tokio::time::timeout(Duration::from_millis(10), consumer.read())
+                .await
+                .is_err()
+        );
+    });
+}
+
+#[test]
+fn nack_requeue_redelivers() {
+    run(async {
+        let broker = Broker::default();
+        let ty = queue("requeue", Binding::Fanout, None);
+        let mut consumer = broker.consumer(&ty);
+        let producer = broker.producer(ty).await.unwrap();
+
+        producer.write("again".to_owned()).await.unwrap();
+
+        let (_, acker) = consumer.read().await.unwrap().unwrap();
+        acker.reject_delivery(true).await.unwrap();
+
+        let (msg, acker) = consumer.read().await.unwrap().unwrap();
+        acker.ack_delivery().await.unwrap();
+
+        assert_eq!(msg, "again");
+        assert!(broker.dead_letters("requeue").is_empty());
+    });
+}
+
+#[test]
+fn nack_retries_then_dead_letters() {
+    run(async {
+        let broker = Broker::default();
+        let ty = queue("retry", Binding::Fanout, Some(1));
+        let mut consumer = broker.consumer(&ty);
+        let producer = broker.producer(ty).await.unwrap();
+
+        producer.write("doomed".to_owned()).await.unwrap();
+
+        for _ in 0..2 {
+            let (msg, acker) = consumer.read().await.unwrap().unwrap();
+            assert_eq!(msg, "doomed");
+            acker.reject_delivery(false).await.unwrap();
+        }
+
+        assert_eq!(broker.dead_letters("retry").len(), 1);
+    });
+}
+
+#[test]
+fn undecodable_delivery_dead_letters() {
+    run(async {
+        let broker = Broker::default();
+        let ty = queue("garbage", Binding::Fanout, None);
+        let mut consumer = broker.consumer(&ty);
+
+        // 0xc1 is never valid MessagePack
+        broker
+            .publish(ty.info(), None, &[0xc1], BasicProperties::default())
+            .await
+            .unwrap();
+
+        let producer = broker.producer(ty).await.unwrap();
+        producer.write("fine".to_owned()).await.unwrap();
+
+        assert!(consumer.read().await.is_err());
+
+        let (msg, acker) = consumer.read().await.unwrap().unwrap();
+        acker.ack_delivery().await.unwrap();
+
+        assert_eq!(msg, "fine");
+        assert_eq!(broker.dead_letters("garbage").len(), 1);
+    });
+}
@@ -1,14 +1,28 @@
 //! An AMQP producer configured from a [`QueueType`]
 
-use lapin::{Channel, Connection};
+use std::collections::HashMap;
 
-use crate::{serialize::serialize, QueueType, Result};
+use lapin::{BasicProperties, Channel, Connection};
 
-/// A producer consisting of a configured channel and additional queue config
+use crate::{
+    serialize::{serialize_with, Encoding},
+    transport::{Outcome, Publisher},
+    Error, QueueType, Result,
+};
+
+/// A producer consisting of a configured publisher and additional queue
+/// config.  Publishes over AMQP unless constructed with
+/// [`from_publisher`](Self::from_publisher).
 #[derive(Debug)]
-pub struct Producer<Q> {
-    chan: Channel,
+pub struct Producer<Q, P = Channel> {
+    publisher: P,
     ty: Q,
+    encoding: Encoding,
+    encodings: HashMap<String, Encoding>,
+    #[cfg(feature = "compression")]
+    dictionaries: Option<std::sync::Arc<crate::compression::Dictionaries>>,
+    #[cfg(feature = "encryption")]
+    key: Option<std::sync::Arc<crate::encryption::Key>>,
 }
 
 impl<Q: QueueType> Producer<Q>
@@ -30,10 +44,78 @@ where
     ///
     /// # Errors
     /// This fucntion fails if the producer cannot be configured successfully.
+    #[inline]
     pub async fn from_channel(chan: Channel, ty: Q) -> Result<Self> {
-        ty.info().init_producer(&chan).await?;
+        Self::from_publisher(chan, ty).await
+    }
+}
+
+impl<Q: QueueType, P: Publisher> Producer<Q, P>
+where
+    Q::Message: serde::Serialize,
+{
+    /// Construct a new producer from a [`QueueType`] and any transport
+    /// backend
+    ///
+    /// # Errors
+    /// This function fails if the publisher cannot declare the resources
+    /// required by the queue configuration.
+    pub async fn from_publisher(publisher: P, ty: Q) -> Result<Self> {
+        publisher.declare(ty.info()).await?;
+
+        Ok(Self {
+            publisher,
+            ty,
+            encoding: Encoding::default(),
+            encodings: HashMap::new(),
+            #[cfg(feature = "compression")]
+            dictionaries: None,
+            #[cfg(feature = "encryption")]
+            key: None,
+        })
+    }
+
+    /// Encrypt every payload written to this producer with the given key
+    #[cfg(feature = "encryption")]
+    #[must_use]
+    pub fn with_encryption(self, key: std::sync::Arc<crate::encryption::Key>) -> Self {
+        Self {
+            key: Some(key),
+            ..self
+        }
+    }
+
+    /// Compress payloads written to this producer with the given
+    /// dictionaries, before encrypting them if encryption is enabled
+    #[cfg(feature = "compression")]
+    #[must_use]
+    pub fn with_compression(
+        self,
+        dictionaries: std::sync::Arc<crate::compression::Dictionaries>,
+    ) -> Self {
+        Self {
+            dictionaries: Some(dictionaries),
+            ..self
+        }
+    }
+
+    /// Encode every payload written to this producer with the given layout
+    #[must_use]
+    pub fn with_encoding(self, encoding: Encoding) -> Self {
+        Self { encoding, ..self }
+    }
+
+    /// Encode payloads with the given encodings in place of the default,
+    /// keyed by the [message key](QueueType::message_key) of each payload
+    #[must_use]
+    pub fn with_encodings(self, encodings: HashMap<String, Encoding>) -> Self {
+        Self { encodings, ..self }
+    }
 
-        Ok(Self { chan, ty })
+    /// Returns true if this producer's publisher is connected
+    #[must_use]
+    pub fn is_connected(&self) -> bool {
+        self.publisher.is_connected()
     }
 
     /// Write a single message to this producer
@@ -41,14 +123,87 @@ where
     /// # Errors
     /// This function fails if the value cannot be serialized or the serialized
     /// payload cannot be transmitted.
+    #[inline]
     pub async fn write(&self, val: impl std::borrow::Borrow<Q::Message>) -> Result<()> {
+        self.write_with_properties(val, BasicProperties::default())
+            .await
+    }
+
+    /// Write a single message to this producer, attaching the given AMQP
+    /// properties
+    ///
+    /// # Errors
+    /// This function fails if the value cannot be serialized, the serialized
+    /// payload cannot be transmitted, or publisher confirms are enabled and
+    /// the broker rejects or returns the message.
+    #[inline]
+    pub async fn write_with_properties(
+        &self,
+        val: impl std::borrow::Borrow<Q::Message>,
+        props: BasicProperties,
+    ) -> Result<()> {
+        self.write_routed(val, None, props).await.map(|_| ())
+    }
+
+    /// Write a single message to this producer with the given AMQP
+    /// properties, overriding the routing key of the queue's binding if one
+    /// is given.  Returns the size of the published payload.
+    ///
+    /// # Errors
+    /// This function fails if the value cannot be serialized, the serialized
+    /// payload cannot be transmitted, or publisher confirms are enabled and
+    /// the broker rejects or returns the message.
+    pub async fn write_routed(
+        &self,
+        val: impl std::borrow::Borrow<Q::Message>,
+        routing_key: Option<&str>,
+        props: BasicProperties,
+    ) -> Result<usize> {
         let val = val.borrow();
 
+        let encoding = if self.encodings.is_empty() {
+            self.encoding
+        } else {
+            self.ty
+                .message_key(val)
+                .and_then(|k| self.encodings.get(&k).copied())
+                .unwrap_or(self.encoding)
+        };
+
         let mut vec = Vec::new();
-        serialize(&mut vec, val)?;
+        serialize_with(&mut vec, val, encoding)?;
+        let props = props.with_content_type(encoding.content_type().into());
+
+        #[cfg(feature = "compression")]
+        let (vec, props) = match self.dictionaries {
+            Some(ref d) => d.compress(self.ty.dictionary_key(val).as_deref(), vec, props)?,
+            None => (vec, props),
+        };
+
+        #[cfg(feature = "encryption")]
+        let (vec, props) = match self.key {
+            Some(ref k) => k.seal(&vec, props)?,
+            None => (vec, props),
+        };
+
+        let info = self.ty.info();
+        let fallback_props = info.fallback_queue().is_some().then(|| props.clone());
 
-        self.ty.info().publish(&self.chan, &vec).await?.await?;
+        match self
+            .publisher
+            .publish(info, routing_key, &vec, props)
+            .await?
+        {
+            Outcome::Returned => {
+                let diverted = match fallback_props {
+                    Some(props) => self.publisher.divert(info, &vec, props).await?,
+                    None => false,
+                };
 
-        Ok(())
+                Err(Error::Unroutable { diverted })
+            },
+            Outcome::Nacked => Err(Error::PublishNacked),
+            Outcome::Delivered => Ok(vec.len()),
+        }
     }
 }
@@ -1,7 +1,12 @@
 use std::time::Duration;
 
 #[cfg(feature = "producer")]
-use lapin::{options::BasicPublishOptions, publisher_confirm::PublisherConfirm, BasicProperties};
+use lapin::{
+    options::{BasicPublishOptions, ConfirmSelectOptions, QueueDeclareOptions as PassiveOptions},
+    publisher_confirm::PublisherConfirm,
+    BasicProperties, Connection,
+};
+use serde::{Deserialize, Serialize};
 #[cfg(feature = "consumer")]
 use {
     crate::Error,
@@ -15,7 +20,12 @@ use {
 #[cfg(any(feature = "producer", feature = "consumer"))]
 use {
     crate::Result,
-    lapin::{options::ExchangeDeclareOptions, types::FieldTable, Channel, ExchangeKind},
+    lapin::{
+        options::ExchangeDeclareOptions,
+        protocol::{AMQPErrorKind, AMQPSoftError},
+        types::FieldTable,
+        Channel, ExchangeKind,
+    },
 };
 
 /// A trait representing an AMQP queue with a specific message type and AMQP
@@ -26,20 +36,49 @@ pub trait QueueType {
 
     /// Expose the underlying queue info for this queue
     fn info(&self) -> QueueInfo;
+
+    /// A key identifying the family of the given message, e.g. the owner of
+    /// an updated account, used to select per-key encodings and compression
+    /// dictionaries
+    fn message_key(&self, msg: &Self::Message) -> Option<String> {
+        let _ = msg;
+
+        None
+    }
+
+    /// The key of the [dictionary](crate::compression::Dictionary) to
+    /// compress the given message with, if any
+    #[cfg(feature = "compression")]
+    fn dictionary_key(&self, msg: &Self::Message) -> Option<String> {
+        self.message_key(msg)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum Binding {
     Fanout,
     Direct(String),
+    /// A topic exchange, binding the consumer queue with each of the given
+    /// patterns
+    Topic(Vec<String>),
 }
 
-#[cfg(feature = "consumer")]
 impl Binding {
     fn routing_key(&self) -> &str {
         match self {
             Self::Fanout => "",
             Self::Direct(k) => k.as_ref(),
+            Self::Topic(p) => p.first().map_or("", AsRef::as_ref),
+        }
+    }
+
+    /// The keys to bind the consumer queue with
+    #[cfg(feature = "consumer")]
+    pub(crate) fn binding_keys(&self) -> Vec<&str> {
+        match self {
+            Self::Fanout => vec![""],
+            Self::Direct(k) => vec![k.as_ref()],
+            Self::Topic(p) => p.iter().map(AsRef::as_ref).collect(),
         }
     }
 }
@@ -71,6 +110,40 @@ impl RetryProps {
     }
 }
 
+/// Behavior of a length-limited queue when it is full
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    strum::EnumString,
+    strum::Display,
+)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum Overflow {
+    /// Discard the oldest message in the queue
+    DropHead,
+    /// Refuse new messages, negatively acknowledging them to the publisher
+    RejectPublish,
+    /// Refuse new messages as with [`RejectPublish`](Self::RejectPublish),
+    /// and dead-letter them
+    RejectPublishDlx,
+}
+
+impl Overflow {
+    /// Returns true if publishers are notified of messages dropped under this
+    /// policy
+    #[must_use]
+    pub fn rejects_publish(self) -> bool {
+        matches!(self, Self::RejectPublish | Self::RejectPublishDlx)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct QueueProps {
     pub exchange: String,
@@ -78,19 +151,90 @@ pub struct QueueProps {
     pub binding: Binding,
     pub prefetch: u16,
     pub auto_delete: bool,
+    pub max_len: Option<u32>,
+    pub max_priority: Option<u8>,
+    pub overflow: Option<Overflow>,
+    /// Enable publisher confirms on producer channels
+    pub confirm: bool,
+    /// Publish with the `mandatory` flag, requiring publisher confirms to
+    /// detect returned messages
+    pub mandatory: bool,
+    /// Queue to divert unroutable messages to
+    pub fallback_queue: Option<String>,
     pub retry: Option<RetryProps>,
 }
 
+/// A read-only view of a queue type's queue and exchange configuration
 #[derive(Debug, Clone, Copy)]
 #[repr(transparent)]
 pub struct QueueInfo<'a>(&'a QueueProps);
 
+/// Counts reported by the broker for a declared queue
+#[cfg(feature = "producer")]
+#[derive(Debug, Clone, Copy)]
+pub struct QueueStatus {
+    /// Number of messages ready to be delivered
+    pub messages: u32,
+    /// Number of consumers reading the queue
+    pub consumers: u32,
+}
+
 impl<'a> From<&'a QueueProps> for QueueInfo<'a> {
     fn from(props: &'a QueueProps) -> Self {
         Self(props)
     }
 }
 
+impl<'a> QueueInfo<'a> {
+    /// The name of the exchange messages are published to
+    #[must_use]
+    pub fn exchange(self) -> &'a str {
+        &self.0.exchange
+    }
+
+    /// The name of the queue messages are consumed from
+    #[must_use]
+    pub fn queue(self) -> &'a str {
+        &self.0.queue
+    }
+
+    /// The routing key to publish with, given an optional override of the
+    /// binding's key
+    #[must_use]
+    pub fn routing_key(self, key: Option<&'a str>) -> &'a str {
+        key.unwrap_or_else(|| self.0.binding.routing_key())
+    }
+
+    /// Returns true if messages are published with the `mandatory` flag
+    #[must_use]
+    pub fn is_mandatory(self) -> bool {
+        self.0.mandatory
+    }
+
+    /// The queue unroutable messages are diverted to, if any
+    #[must_use]
+    pub fn fallback_queue(self) -> Option<&'a str> {
+        self.0.fallback_queue.as_deref()
+    }
+
+    /// The number of times a rejected message is retried before it is
+    /// dead-lettered, or `None` if retries are disabled
+    #[must_use]
+    pub fn max_tries(self) -> Option<u64> {
+        self.0.retry.map(|r| r.max_tries)
+    }
+
+    /// How the consumer queue is bound to the exchange
+    #[cfg(any(
+        feature = "test-util",
+        all(test, feature = "consumer", feature = "producer")
+    ))]
+    #[must_use]
+    pub(crate) fn binding(self) -> &'a Binding {
+        &self.0.binding
+    }
+}
+
 #[cfg(feature = "consumer")]
 pub const DLX_DEAD_KEY: &str = "dead";
 #[cfg(feature = "consumer")]
@@ -101,18 +245,68 @@ pub const DLX_TRIAGE_KEY: &str = "triage";
 #[cfg(any(feature = "producer", feature = "consumer"))]
 impl<'a> QueueInfo<'a> {
     async fn exchange_declare(self, chan: &Channel) -> Result<()> {
-        chan.exchange_declare(
-            self.0.exchange.as_ref(),
-            match self.0.binding {
-                Binding::Fanout => ExchangeKind::Fanout,
-                Binding::Direct(_) => ExchangeKind::Direct,
+        let kind = match self.0.binding {
+            Binding::Fanout => ExchangeKind::Fanout,
+            Binding::Direct(_) => ExchangeKind::Direct,
+            Binding::Topic(_) => ExchangeKind::Topic,
+        };
+        let opts = ExchangeDeclareOptions::default();
+        let ExchangeDeclareOptions {
+            durable,
+            auto_delete,
+            internal,
+            ..
+        } = opts;
+
+        match chan
+            .exchange_declare(
+                self.0.exchange.as_ref(),
+                kind.clone(),
+                opts,
+                FieldTable::default(),
+            )
+            .await
+        {
+            Ok(()) => Ok(()),
+            Err(lapin::Error::ProtocolError(e))
+                if *e.kind() == AMQPErrorKind::Soft(AMQPSoftError::PRECONDITIONFAILED) =>
+            {
+                Err(crate::Error::DeclarationMismatch {
+                    kind: "exchange",
+                    name: self.0.exchange.clone(),
+                    differing: differing_args(e.get_message().as_str()),
+                    declared: format!(
+                        "type={:?}, durable={}, auto_delete={}, internal={}, arguments={{}}",
+                        kind, durable, auto_delete, internal,
+                    ),
+                })
             },
-            ExchangeDeclareOptions::default(),
-            FieldTable::default(),
-        )
-        .await?;
+            Err(e) => Err(e.into()),
+        }
+    }
+}
 
-        Ok(())
+/// Extract the differing arguments from a broker's `PRECONDITION_FAILED`
+/// reply.  RabbitMQ reports mismatches in the form ``inequivalent arg 'type'
+/// for exchange 'x' in vhost '/': received 'topic' but current is 'fanout'``,
+/// listing only the first differing argument; if the reply is in any other
+/// form it is returned verbatim.
+#[cfg(any(feature = "producer", feature = "consumer"))]
+fn differing_args(reply: &str) -> String {
+    let reply = reply.split_once(" - ").map_or(reply, |(_, r)| r);
+    let arg = reply
+        .strip_prefix("inequivalent arg '")
+        .and_then(|s| s.split_once('\''))
+        .map(|(a, _)| a);
+    let values = reply
+        .split_once(": received ")
+        .and_then(|(_, s)| s.split_once(" but current is "));
+
+    match (arg, values) {
+        (Some(arg), Some((requested, current))) => {
+            format!("{} (requested {}, existing {})", arg, requested, current)
+        },
+        _ => reply.to_owned(),
     }
 }
 
@@ -121,23 +315,88 @@ impl<'a> QueueInfo<'a> {
     pub(crate) async fn init_producer(self, chan: &Channel) -> Result<()> {
         self.exchange_declare(chan).await?;
 
+        if self.0.confirm {
+            chan.confirm_select(ConfirmSelectOptions::default()).await?;
+        }
+
         Ok(())
     }
 
-    pub(crate) async fn publish(self, chan: &Channel, data: &[u8]) -> Result<PublisherConfirm> {
+    pub(crate) async fn publish(
+        self,
+        chan: &Channel,
+        routing_key: Option<&str>,
+        data: &[u8],
+        props: BasicProperties,
+    ) -> Result<PublisherConfirm> {
         chan.basic_publish(
-            self.0.exchange.as_ref(),
-            match self.0.binding {
-                Binding::Fanout => "",
-                Binding::Direct(ref s) => s,
+            self.exchange(),
+            self.routing_key(routing_key),
+            BasicPublishOptions {
+                mandatory: self.0.mandatory,
+                ..BasicPublishOptions::default()
             },
-            BasicPublishOptions::default(),
             data,
-            BasicProperties::default(),
+            props,
         )
         .await
         .map_err(Into::into)
     }
+
+    /// Publish a returned message to the fallback queue, if one is configured
+    pub(crate) async fn publish_fallback(
+        self,
+        chan: &Channel,
+        data: &[u8],
+        props: BasicProperties,
+    ) -> Result<bool> {
+        let queue = match self.0.fallback_queue {
+            Some(ref q) => q,
+            None => return Ok(false),
+        };
+
+        chan.basic_publish("", queue, BasicPublishOptions::default(), data, props)
+            .await?
+            .await?;
+
+        Ok(true)
+    }
+
+    /// Returns the number of consumers of the queue, or `None` if it has not
+    /// been declared
+    pub(crate) async fn consumer_count(self, conn: &Connection) -> Result<Option<u32>> {
+        self.status(conn).await.map(|s| s.map(|s| s.consumers))
+    }
+
+    /// Returns the message and consumer counts of the queue, or `None` if it
+    /// has not been declared
+    pub(crate) async fn status(self, conn: &Connection) -> Result<Option<QueueStatus>> {
+        // A failed passive declare closes the channel, so use a throwaway one
+        let chan = conn.create_channel().await?;
+
+        match chan
+            .queue_declare(
+                self.0.queue.as_ref(),
+                PassiveOptions {
+                    passive: true,
+                    ..PassiveOptions::default()
+                },
+                FieldTable::default(),
+            )
+            .await
+        {
+            Ok(queue) => Ok(Some(QueueStatus {
+                messages: queue.message_count(),
+                consumers: queue.consumer_count(),
+            })),
+            Err(lapin::Error::ProtocolError(e))
+                if *e.kind() == AMQPErrorKind::Soft(AMQPSoftError::NOTFOUND) =>
+            {
+                Ok(None)
+            },
+            Err(e) => Err(e.into()),
+        }
+    }
 }
 
 #[cfg(feature = "consumer")]
@@ -157,6 +416,24 @@ impl<'a> QueueInfo<'a> {
             );
         }
 
+        if let Some(max_len) = self.0.max_len {
+            queue_fields.insert("x-max-length".into(), AMQPValue::LongUInt(max_len));
+        }
+
+        if let Some(overflow) = self.0.overflow {
+            queue_fields.insert(
+                "x-overflow".into(),
+                AMQPValue::LongString(overflow.to_string().into()),
+            );
+        }
+
+        if let Some(max_priority) = self.0.max_priority {
+            queue_fields.insert(
+                "x-max-priority".into(),
+                AMQPValue::ShortShortUInt(max_priority),
+            );
+        }
+
         chan.queue_declare(
             self.0.queue.as_ref(),
             QueueDeclareOptions {
@@ -218,14 +495,16 @@ impl<'a> QueueInfo<'a> {
         self.exchange_declare(chan).await?;
         self.queue_declare(chan).await?;
 
-        chan.queue_bind(
-            self.0.queue.as_ref(),
-            self.0.exchange.as_ref(),
-            self.0.binding.routing_key(),
-            QueueBindOptions::default(),
-            FieldTable::default(),
-        )
-        .await?;
+        for key in self.0.binding.binding_keys() {
+            chan.queue_bind(
+                self.0.queue.as_ref(),
+                self.0.exchange.as_ref(),
+                key,
+                QueueBindOptions::default(),
+                FieldTable::default(),
+            )
+            .await?;
+        }
 
         chan.basic_qos(self.0.prefetch, BasicQosOptions::default())
             .await?;
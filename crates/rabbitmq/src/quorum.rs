@@ -0,0 +1,131 @@
+//! Quorum aggregation of slot statuses from several Geyser producers.
+//!
+//! When redundant validators run the Geyser plugin against the same
+//! exchange, each reports its own view of every slot's status.  A consumer
+//! can merge these reports, keyed by the identity headers of each producer
+//! (see [`ProducerId`]), into a single stream of statuses confirmed by a
+//! minimum number of producers:
+//!
+//! ```ignore
+//! let mut quorum = Quorum::new(2, 150);
+//!
+//! while let Some((msg, props, acker)) = consumer.read_with_properties().await? {
+//!     if let (Message::SlotStatusUpdate(update), Some(producer)) =
+//!         (&msg, ProducerId::from_properties(&props))
+//!     {
+//!         for confirmed in quorum.observe(&producer, update) {
+//!             handle(confirmed).await?;
+//!         }
+//!     }
+//!
+//!     acker.ack(BasicAckOptions::default()).await?;
+//! }
+//! ```
+//!
+//! A producer reporting a slot as rooted also counts towards its confirmed
+//! and processed quorums, so statuses are emitted in order for each slot
+//! even if a producer skips one.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::geyser::{ProducerId, SlotStatus, SlotStatusUpdate};
+
+/// Slot statuses in order of increasing commitment
+const LEVELS: [SlotStatus; 3] = [
+    SlotStatus::Processed,
+    SlotStatus::Confirmed,
+    SlotStatus::Rooted,
+];
+
+fn level(status: SlotStatus) -> Option<usize> {
+    match status {
+        SlotStatus::Processed => Some(0),
+        SlotStatus::Confirmed => Some(1),
+        SlotStatus::Rooted => Some(2),
+        SlotStatus::Unknown => None,
+    }
+}
+
+/// The reports received for a single slot
+#[derive(Debug, Default)]
+struct Votes {
+    parent: Option<u64>,
+    /// Highest commitment level reported by each producer
+    producers: HashMap<ProducerId, usize>,
+    /// Number of levels already emitted
+    emitted: usize,
+}
+
+/// Sliding-window aggregator of slot statuses, keyed by slot
+#[derive(Debug)]
+pub struct Quorum {
+    threshold: usize,
+    window: u64,
+    slots: BTreeMap<u64, Votes>,
+}
+
+impl Quorum {
+    /// Construct a new aggregator emitting a status once `threshold`
+    /// producers have reported it, and remembering reports for `window`
+    /// slots behind the newest slot observed
+    #[must_use]
+    pub fn new(threshold: usize, window: u64) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            window,
+            slots: BTreeMap::new(),
+        }
+    }
+
+    /// Record a producer's status report, returning the statuses of its slot
+    /// which reached quorum as a result, in order of increasing commitment.
+    /// Reports for slots that have already left the window, or of statuses
+    /// unknown to this version, are ignored.
+    pub fn observe(
+        &mut self,
+        producer: &ProducerId,
+        update: &SlotStatusUpdate,
+    ) -> Vec<SlotStatusUpdate> {
+        let slot = update.slot;
+        let level = match level(update.status) {
+            Some(l) => l,
+            None => return vec![],
+        };
+        let newest = self.slots.keys().next_back().copied().unwrap_or(slot);
+
+        if slot.saturating_add(self.window) < newest {
+            return vec![];
+        }
+
+        if slot > newest {
+            self.slots = self.slots.split_off(&slot.saturating_sub(self.window));
+        }
+
+        let votes = self.slots.entry(slot).or_default();
+        votes.parent = votes.parent.or(update.parent);
+
+        let reported = votes.producers.entry(producer.clone()).or_default();
+        *reported = (*reported).max(level);
+
+        let mut confirmed = vec![];
+
+        while votes.emitted < LEVELS.len()
+            && votes
+                .producers
+                .values()
+                .filter(|l| **l >= votes.emitted)
+                .count()
+                >= self.threshold
+        {
+            confirmed.push(SlotStatusUpdate {
+                slot,
+                parent: votes.parent,
+                status: LEVELS[votes.emitted],
+                estimated_time: update.estimated_time,
+            });
+            votes.emitted += 1;
+        }
+
+        confirmed
+    }
+}
@@ -0,0 +1,230 @@
+//! A schemaless value capturing data this crate cannot interpret, such as
+//! the content of a message variant added by a newer producer
+
+use std::{fmt, marker::PhantomData};
+
+use serde::{
+    de::{
+        self,
+        value::{MapAccessDeserializer, MapDeserializer, SeqDeserializer},
+        IntoDeserializer, MapAccess, SeqAccess, Visitor,
+    },
+    ser::{SerializeMap, SerializeSeq},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+/// Any value representable in the wire formats used by this crate
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// A null or unit value
+    Nil,
+    /// A boolean
+    Bool(bool),
+    /// A non-negative integer
+    UInt(u64),
+    /// A negative integer
+    Int(i64),
+    /// A floating-point number
+    Float(f64),
+    /// A string
+    String(String),
+    /// A byte array
+    Bytes(Vec<u8>),
+    /// A sequence of values
+    Array(Vec<Value>),
+    /// A map of values, in encoded order
+    Map(Vec<(Value, Value)>),
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any value")
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Nil)
+    }
+
+    fn visit_none<E>(self) -> Result<Value, E> {
+        Ok(Value::Nil)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, de: D) -> Result<Value, D::Error> {
+        Value::deserialize(de)
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Bool(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Value, E> {
+        Ok(Value::UInt(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Value, E> {
+        Ok(u64::try_from(v).map_or(Value::Int(v), Value::UInt))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Value, E> {
+        Ok(Value::Float(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::String(v.to_owned()))
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Value, E> {
+        Ok(Value::String(v))
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Value, E> {
+        Ok(Value::Bytes(v.to_owned()))
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Value, E> {
+        Ok(Value::Bytes(v))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut vec = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+
+        while let Some(el) = seq.next_element()? {
+            vec.push(el);
+        }
+
+        Ok(Value::Array(vec))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut vec = Vec::with_capacity(map.size_hint().unwrap_or(0));
+
+        while let Some(entry) = map.next_entry()? {
+            vec.push(entry);
+        }
+
+        Ok(Value::Map(vec))
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        de.deserialize_any(ValueVisitor)
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Nil => ser.serialize_unit(),
+            Self::Bool(b) => ser.serialize_bool(*b),
+            Self::UInt(u) => ser.serialize_u64(*u),
+            Self::Int(i) => ser.serialize_i64(*i),
+            Self::Float(f) => ser.serialize_f64(*f),
+            Self::String(s) => ser.serialize_str(s),
+            Self::Bytes(b) => ser.serialize_bytes(b),
+            Self::Array(a) => {
+                let mut arr = ser.serialize_seq(Some(a.len()))?;
+
+                for el in a {
+                    arr.serialize_element(el)?;
+                }
+
+                arr.end()
+            },
+            Self::Map(m) => {
+                let mut map = ser.serialize_map(Some(m.len()))?;
+
+                for (k, v) in m {
+                    map.serialize_entry(k, v)?;
+                }
+
+                map.end()
+            },
+        }
+    }
+}
+
+/// Deserializer reading a type back out of a decoded [`Value`].  Elements
+/// of an array left over once its visitor is done are ignored, so structs
+/// encoded as arrays by a newer version with more fields still decode.
+#[derive(Debug)]
+pub struct ValueDeserializer<E> {
+    value: Value,
+    error: PhantomData<E>,
+}
+
+impl<'de, E: de::Error> IntoDeserializer<'de, E> for Value {
+    type Deserializer = ValueDeserializer<E>;
+
+    fn into_deserializer(self) -> ValueDeserializer<E> {
+        ValueDeserializer {
+            value: self,
+            error: PhantomData::default(),
+        }
+    }
+}
+
+impl<'de, E: de::Error> Deserializer<'de> for ValueDeserializer<E> {
+    type Error = E;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, E> {
+        match self.value {
+            Value::Nil => visitor.visit_unit(),
+            Value::Bool(b) => visitor.visit_bool(b),
+            Value::UInt(u) => visitor.visit_u64(u),
+            Value::Int(i) => visitor.visit_i64(i),
+            Value::Float(f) => visitor.visit_f64(f),
+            Value::String(s) => visitor.visit_string(s),
+            Value::Bytes(b) => visitor.visit_byte_buf(b),
+            Value::Array(a) => visitor.visit_seq(SeqDeserializer::new(a.into_iter())),
+            Value::Map(m) => {
+                let mut map = MapDeserializer::new(m.into_iter());
+                let val = visitor.visit_map(&mut map)?;
+                map.end()?;
+
+                Ok(val)
+            },
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, E> {
+        match self.value {
+            Value::Nil => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, E> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, E> {
+        match self.value {
+            Value::String(s) => visitor.visit_enum(s.into_deserializer()),
+            Value::Map(m) if m.len() == 1 => {
+                MapAccessDeserializer::new(MapDeserializer::new(m.into_iter()))
+                    .deserialize_enum(name, variants, visitor)
+            },
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
@@ -0,0 +1,214 @@
+//! Replay protection for consumers, discarding redeliveries and duplicate
+//! publishes of messages already handled.
+//!
+//! Messages are identified by an idempotency key, by default their AMQP
+//! `message_id`.  A [`ReplayGuard`] wraps a [`Consumer`] and acknowledges
+//! any delivery whose key was seen within the window of its
+//! [`ReplayStore`] without returning it.  Keys are recorded as deliveries
+//! are returned, so a redelivery of a message whose handler failed and
+//! requeued it is also discarded; such messages should be dead-lettered
+//! instead.  Deliveries carrying an `x-death` header are dead-letter retries
+//! and are always passed through without consulting the store.
+//!
+//! Consumers of exchanges shared by several Geyser producers can key the
+//! guard on [`dedup::message_key`](crate::dedup::message_key) to discard the
+//! copies published by each producer as well.
+//!
+//! ```ignore
+//! let mut guard = ReplayGuard::new(consumer, MemoryStore::new(100_000, Some(ttl)));
+//!
+//! while let Some((msg, acker)) = guard.read().await? {
+//!     handle(msg).await?;
+//!
+//!     acker.ack(BasicAckOptions::default()).await?;
+//! }
+//! ```
+
+use std::{
+    collections::{HashSet, VecDeque},
+    fmt,
+    time::{Duration, Instant},
+};
+
+use futures_util::future::BoxFuture;
+use lapin::BasicProperties;
+
+use crate::{
+    consumer::Consumer,
+    transport::{Ack, Subscriber},
+    QueueType, Result,
+};
+
+/// A record of recently seen idempotency keys
+#[allow(clippy::module_name_repetitions)]
+pub trait ReplayStore: Send {
+    /// Record the given key, returning true if it was not already present
+    /// within the store's window
+    ///
+    /// # Errors
+    /// This function fails if the store cannot be read or written.
+    fn insert(&mut self, key: String) -> BoxFuture<'_, Result<bool>>;
+}
+
+/// A [`ReplayStore`] held in memory, remembering up to a fixed number of
+/// keys for an optional maximum duration
+#[derive(Debug)]
+pub struct MemoryStore {
+    capacity: usize,
+    ttl: Option<Duration>,
+    order: VecDeque<(Instant, String)>,
+    keys: HashSet<String>,
+}
+
+impl MemoryStore {
+    /// Construct a new store remembering at most `capacity` keys, each for
+    /// no longer than `ttl` if given
+    #[must_use]
+    pub fn new(capacity: usize, ttl: Option<Duration>) -> Self {
+        Self {
+            capacity,
+            ttl,
+            order: VecDeque::new(),
+            keys: HashSet::new(),
+        }
+    }
+
+    fn evict(&mut self, now: Instant) {
+        while let Some((seen, _)) = self.order.front() {
+            let expired = self
+                .ttl
+                .map_or(false, |t| now.saturating_duration_since(*seen) > t);
+
+            if !expired && self.order.len() < self.capacity {
+                break;
+            }
+
+            if let Some((_, key)) = self.order.pop_front() {
+                self.keys.remove(&key);
+            }
+        }
+    }
+}
+
+impl ReplayStore for MemoryStore {
+    fn insert(&mut self, key: String) -> BoxFuture<'_, Result<bool>> {
+        Box::pin(async move {
+            if self.capacity == 0 {
+                return Ok(true);
+            }
+
+            let now = Instant::now();
+            self.evict(now);
+
+            if self.keys.contains(&key) {
+                return Ok(false);
+            }
+
+            self.keys.insert(key.clone());
+            self.order.push_back((now, key));
+
+            Ok(true)
+        })
+    }
+}
+
+/// Extracts the idempotency key of a delivery, or `None` if it has none
+pub type KeyFn<M> = fn(&M, &BasicProperties) -> Option<String>;
+
+/// A consumer which transparently acknowledges and skips deliveries whose
+/// idempotency key was recently seen
+#[allow(clippy::module_name_repetitions)]
+pub struct ReplayGuard<Q: QueueType, S, B = lapin::Consumer> {
+    consumer: Consumer<Q, B>,
+    store: S,
+    key: KeyFn<Q::Message>,
+    skipped: u64,
+}
+
+impl<Q: QueueType, S: fmt::Debug, B> fmt::Debug for ReplayGuard<Q, S, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReplayGuard")
+            .field("store", &self.store)
+            .field("skipped", &self.skipped)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Q: QueueType, S: ReplayStore, B: Subscriber> ReplayGuard<Q, S, B>
+where
+    Q::Message: for<'a> serde::Deserialize<'a>,
+{
+    /// Wrap a consumer, identifying deliveries by their AMQP `message_id`.
+    /// Deliveries without one are always passed through.
+    #[must_use]
+    pub fn new(consumer: Consumer<Q, B>, store: S) -> Self {
+        Self {
+            consumer,
+            store,
+            key: |_, props| props.message_id().as_ref().map(|i| i.as_str().to_owned()),
+            skipped: 0,
+        }
+    }
+
+    /// Identify deliveries using the given function rather than their AMQP
+    /// `message_id`
+    #[must_use]
+    pub fn with_key(self, key: KeyFn<Q::Message>) -> Self {
+        Self { key, ..self }
+    }
+
+    /// Get the number of duplicate deliveries skipped so far
+    #[must_use]
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    /// Receive the next message not seen within the replay window
+    ///
+    /// # Errors
+    /// This function fails if the delivery cannot be successfully performed,
+    /// the payload cannot be deserialized, a duplicate cannot be
+    /// acknowledged, or the store fails.
+    pub async fn read(&mut self) -> Result<Option<(Q::Message, B::Acker)>> {
+        Ok(self
+            .read_with_properties()
+            .await?
+            .map(|(data, _, acker)| (data, acker)))
+    }
+
+    /// Receive the next message not seen within the replay window, along
+    /// with the AMQP properties it was published with
+    ///
+    /// # Errors
+    /// This function fails if the delivery cannot be successfully performed,
+    /// the payload cannot be deserialized, a duplicate cannot be
+    /// acknowledged, or the store fails.
+    pub async fn read_with_properties(
+        &mut self,
+    ) -> Result<Option<(Q::Message, BasicProperties, B::Acker)>> {
+        while let Some((msg, props, acker)) = self.consumer.read_with_properties().await? {
+            let retried = props
+                .headers()
+                .as_ref()
+                .map_or(false, |h| h.inner().contains_key("x-death"));
+
+            if retried {
+                return Ok(Some((msg, props, acker)));
+            }
+
+            let key = match (self.key)(&msg, &props) {
+                Some(k) => k,
+                None => return Ok(Some((msg, props, acker))),
+            };
+
+            if self.store.insert(key).await? {
+                return Ok(Some((msg, props, acker)));
+            }
+
+            self.skipped += 1;
+            acker.ack_delivery().await?;
+        }
+
+        Ok(None)
+    }
+}
@@ -0,0 +1,201 @@
+//! Helpers for resuming a Geyser consumer from a persisted rooted-slot
+//! watermark
+
+use std::path::{Path, PathBuf};
+
+use futures_util::future::BoxFuture;
+use log::{info, warn};
+use tokio::fs;
+
+use crate::{
+    geyser::{Message, RootedWatermark, StartupType},
+    job_runner::{self, SlotReindex},
+    Error, Result,
+};
+
+/// A persistent location for the last watermark processed by a consumer
+pub trait WatermarkStore: Send {
+    /// Load the stored watermark, if any
+    ///
+    /// # Errors
+    /// This function fails if the store cannot be read.
+    fn load(&mut self) -> BoxFuture<'_, Result<Option<u64>>>;
+
+    /// Replace the stored watermark
+    ///
+    /// # Errors
+    /// This function fails if the store cannot be written.
+    fn store(&mut self, slot: u64) -> BoxFuture<'_, Result<()>>;
+}
+
+/// A [`WatermarkStore`] backed by a plain-text file on the local disk
+#[derive(Debug, Clone)]
+pub struct FileStore {
+    path: PathBuf,
+}
+
+impl FileStore {
+    /// Construct a new file store at the given path.  The file is created on
+    /// the first write.
+    #[must_use]
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_owned(),
+        }
+    }
+}
+
+fn store_err(e: impl std::error::Error + Send + Sync + 'static) -> Error {
+    Error::WatermarkStore(e.into())
+}
+
+impl WatermarkStore for FileStore {
+    fn load(&mut self) -> BoxFuture<'_, Result<Option<u64>>> {
+        Box::pin(async move {
+            match fs::read_to_string(&self.path).await {
+                Ok(s) => s.trim().parse().map(Some).map_err(store_err),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(store_err(e)),
+            }
+        })
+    }
+
+    fn store(&mut self, slot: u64) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            // Write-then-rename so a crash never leaves a truncated file
+            let tmp = self.path.with_extension("tmp");
+            fs::write(&tmp, slot.to_string()).await.map_err(store_err)?;
+            fs::rename(&tmp, &self.path).await.map_err(store_err)
+        })
+    }
+}
+
+/// The default maximum number of slots [`Resume::backfill`] schedules, about
+/// half a day of slots
+pub const DEFAULT_MAX_BACKFILL: u64 = 100_000;
+
+/// Tracks the watermark processed by a consumer and schedules reindex jobs
+/// for slots missed while it was offline
+#[derive(Debug)]
+pub struct Resume<S> {
+    store: S,
+    last: Option<u64>,
+    max_backfill: u64,
+}
+
+impl<S: WatermarkStore> Resume<S> {
+    /// Construct a new resume helper, loading the last watermark from the
+    /// given store
+    ///
+    /// # Errors
+    /// This function fails if the store cannot be read.
+    pub async fn new(mut store: S) -> Result<Self> {
+        let last = store.load().await?;
+
+        Ok(Self {
+            store,
+            last,
+            max_backfill: DEFAULT_MAX_BACKFILL,
+        })
+    }
+
+    /// Schedule at most `max` slots per backfill rather than
+    /// [`DEFAULT_MAX_BACKFILL`]
+    #[must_use]
+    pub fn with_max_backfill(self, max: u64) -> Self {
+        Self {
+            max_backfill: max,
+            ..self
+        }
+    }
+
+    /// The last watermark committed to the store
+    #[must_use]
+    pub fn last(&self) -> Option<u64> {
+        self.last
+    }
+
+    /// Dispatch a [`ReindexSlot`](job_runner::Message::ReindexSlot) job for
+    /// every slot after the stored watermark and before `live_slot`, the
+    /// first slot observed on the live stream.  If more slots than the
+    /// configured maximum were missed, only the most recent ones are
+    /// scheduled.  Returns the number of jobs dispatched.
+    ///
+    /// # Errors
+    /// This function fails if a job cannot be published.
+    pub async fn backfill(
+        &self,
+        jobs: &job_runner::Producer,
+        live_slot: u64,
+        startup: StartupType,
+    ) -> Result<u64> {
+        let last = if let Some(last) = self.last {
+            last
+        } else {
+            info!("No stored watermark, skipping backfill");
+            return Ok(0);
+        };
+
+        if live_slot <= last {
+            return Ok(0);
+        }
+
+        let gap = live_slot - last - 1;
+        let scheduled = gap.min(self.max_backfill);
+
+        if scheduled < gap {
+            warn!(
+                "{} slot(s) missed since watermark {} exceed the backfill limit, skipping the \
+                 oldest {}",
+                gap,
+                last,
+                gap - scheduled
+            );
+        }
+
+        if scheduled > 0 {
+            warn!(
+                "Scheduling reindex of {} slot(s) before live slot {}",
+                scheduled, live_slot
+            );
+        }
+
+        for slot in (live_slot - scheduled)..live_slot {
+            jobs.write(job_runner::Message::ReindexSlot(SlotReindex {
+                slot,
+                startup,
+            }))
+            .await?;
+        }
+
+        Ok(scheduled)
+    }
+
+    /// Persist a new watermark if it is ahead of the stored one
+    ///
+    /// # Errors
+    /// This function fails if the store cannot be written.
+    pub async fn commit(&mut self, slot: u64) -> Result<()> {
+        if self.last.map_or(false, |l| slot <= l) {
+            return Ok(());
+        }
+
+        self.store.store(slot).await?;
+        self.last = Some(slot);
+
+        Ok(())
+    }
+
+    /// Commit the watermark carried by a message once the consumer has
+    /// finished processing it.  Messages other than
+    /// [`RootedWatermark`](Message::RootedWatermark) are ignored.
+    ///
+    /// # Errors
+    /// This function fails if the store cannot be written.
+    pub async fn processed(&mut self, msg: &Message) -> Result<()> {
+        match *msg {
+            Message::RootedWatermark(RootedWatermark { slot }) => self.commit(slot).await,
+            _ => Ok(()),
+        }
+    }
+}
@@ -65,6 +65,12 @@ impl QueueType {
                 binding: Binding::Fanout,
                 prefetch: 4096,
                 auto_delete: suffix.is_debug(),
+                max_len: None,
+                max_priority: None,
+                overflow: None,
+                confirm: false,
+                mandatory: false,
+                fallback_queue: None,
                 retry: Some(RetryProps {
                     max_tries: 3,
                     delay_hint: Duration::from_millis(500),
@@ -3,6 +3,53 @@ use std::io::Read;
 #[cfg(feature = "producer")]
 use std::io::Write;
 
+/// The AMQP content type of MessagePack-encoded payloads.  Deliveries
+/// without a content type are assumed to be MessagePack.
+pub const MSGPACK_CONTENT_TYPE: &str = "application/msgpack";
+
+/// The AMQP content type of JSON-encoded payloads
+pub const JSON_CONTENT_TYPE: &str = "application/json";
+
+/// The MessagePack layout used to encode message structs.  Consumers decode
+/// either layout, but compact payloads identify fields by position, so they
+/// are only decoded reliably by consumers built with the same message
+/// definitions as the producer; payloads carrying fields added by newer
+/// producers are rejected rather than skipped.
+#[cfg(feature = "producer")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Encoding {
+    /// Encode structs as maps keyed by field name
+    StructMap,
+    /// Encode structs as arrays of field values, omitting field names
+    Compact,
+    /// Encode messages as JSON, e.g. for consumption by scripts without a
+    /// MessagePack decoder.  Not a MessagePack layout; payloads are tagged
+    /// with [`JSON_CONTENT_TYPE`] instead.
+    #[cfg(feature = "json")]
+    Json,
+}
+
+#[cfg(feature = "producer")]
+impl Encoding {
+    /// The AMQP content type of payloads written with this encoding
+    #[must_use]
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::StructMap | Self::Compact => MSGPACK_CONTENT_TYPE,
+            #[cfg(feature = "json")]
+            Self::Json => JSON_CONTENT_TYPE,
+        }
+    }
+}
+
+#[cfg(feature = "producer")]
+impl Default for Encoding {
+    fn default() -> Self {
+        Self::StructMap
+    }
+}
+
 /// Serialize a message into a [`Write`] stream
 ///
 /// # Errors
@@ -12,11 +59,34 @@ pub fn serialize<M: serde::Serialize>(
     w: impl Write,
     msg: &M,
 ) -> Result<(), rmp_serde::encode::Error> {
-    let mut ser = rmp_serde::Serializer::new(w)
-        .with_binary()
-        .with_struct_map();
+    msg.serialize(
+        &mut rmp_serde::Serializer::new(w)
+            .with_binary()
+            .with_struct_map(),
+    )
+}
+
+/// Serialize a message into a [`Write`] stream using the given encoding
+///
+/// # Errors
+/// This function fails if an I/O error occurs or a wire format error occurs.
+#[cfg(feature = "producer")]
+#[allow(clippy::module_name_repetitions)]
+pub fn serialize_with<M: serde::Serialize>(
+    w: impl Write,
+    msg: &M,
+    encoding: Encoding,
+) -> crate::Result<()> {
+    let ser = rmp_serde::Serializer::new(w).with_binary();
+
+    match encoding {
+        Encoding::StructMap => msg.serialize(&mut ser.with_struct_map())?,
+        Encoding::Compact => msg.serialize(&mut ser.with_struct_tuple())?,
+        #[cfg(feature = "json")]
+        Encoding::Json => serde_json::to_writer(ser.into_inner(), msg)?,
+    }
 
-    msg.serialize(&mut ser)
+    Ok(())
 }
 
 /// Deserialize a message from a [`Read`] stream
@@ -31,3 +101,26 @@ pub fn deserialize<M: for<'a> serde::Deserialize<'a>>(
 
     M::deserialize(&mut de)
 }
+
+/// Deserialize a message from a [`Read`] stream, decoding it according to
+/// the content type of the given AMQP properties
+///
+/// # Errors
+/// This function fails if an I/O error occurs, a wire format error occurs,
+/// or the content type is not supported.
+#[cfg(feature = "consumer")]
+pub fn deserialize_content<M: for<'a> serde::Deserialize<'a>>(
+    r: impl Read,
+    props: &lapin::BasicProperties,
+) -> crate::Result<M> {
+    match props
+        .content_type()
+        .as_ref()
+        .map(lapin::types::ShortString::as_str)
+    {
+        None | Some(MSGPACK_CONTENT_TYPE) => Ok(deserialize(r)?),
+        #[cfg(feature = "json")]
+        Some(JSON_CONTENT_TYPE) => Ok(serde_json::from_reader(r)?),
+        Some(other) => Err(crate::Error::UnsupportedContentType(other.into())),
+    }
+}
@@ -0,0 +1,202 @@
+//! Broker-agnostic interfaces underlying [`Producer`](crate::producer::Producer)
+//! and [`Consumer`](crate::consumer::Consumer).
+//!
+//! Queue semantics (naming, suffixes, routing, serialization, encryption,
+//! and unroutable-message handling) are implemented once on top of these
+//! traits, so a backend only has to move payloads.  Message metadata is
+//! carried as AMQP [`BasicProperties`] regardless of the backend.  The
+//! RabbitMQ backend, implemented for [`lapin`] channels and consumers, is
+//! the default for both producers and consumers.
+
+use futures_util::future::BoxFuture;
+use lapin::BasicProperties;
+
+#[cfg(feature = "producer")]
+use crate::queue_type::QueueInfo;
+use crate::Result;
+
+/// The result of publishing a single message
+#[cfg(feature = "producer")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// The message was accepted, or confirms are not enabled
+    Delivered,
+    /// The message was rejected by the backend, e.g. because the destination
+    /// queue is full
+    Nacked,
+    /// The message was published with the `mandatory` flag and could not be
+    /// routed to any queue
+    Returned,
+}
+
+/// The publishing half of a transport backend
+#[cfg(feature = "producer")]
+pub trait Publisher: Send + Sync {
+    /// Declare any resources required to publish to the given queue
+    /// configuration
+    ///
+    /// # Errors
+    /// This function fails if the resources cannot be declared.
+    fn declare<'a>(&'a self, info: QueueInfo<'a>) -> BoxFuture<'a, Result<()>>;
+
+    /// Publish a serialized payload according to the given queue
+    /// configuration, overriding the routing key of its binding if one is
+    /// given
+    ///
+    /// # Errors
+    /// This function fails if the payload cannot be transmitted.
+    fn publish<'a>(
+        &'a self,
+        info: QueueInfo<'a>,
+        routing_key: Option<&'a str>,
+        data: &'a [u8],
+        props: BasicProperties,
+    ) -> BoxFuture<'a, Result<Outcome>>;
+
+    /// Publish a [returned](Outcome::Returned) payload directly to the
+    /// fallback queue of the given queue configuration, returning false if
+    /// it has none
+    ///
+    /// # Errors
+    /// This function fails if the payload cannot be transmitted.
+    fn divert<'a>(
+        &'a self,
+        info: QueueInfo<'a>,
+        data: &'a [u8],
+        props: BasicProperties,
+    ) -> BoxFuture<'a, Result<bool>>;
+
+    /// Returns true if this publisher can currently transmit messages
+    fn is_connected(&self) -> bool;
+}
+
+/// A single message received from a [`Subscriber`]
+#[cfg(feature = "consumer")]
+#[derive(Debug)]
+pub struct Delivery<A> {
+    /// The raw payload of the message
+    pub data: Vec<u8>,
+    /// The properties the message was published with
+    pub properties: BasicProperties,
+    /// A handle for acknowledging the message
+    pub acker: A,
+}
+
+/// A handle for settling a single received message
+#[cfg(feature = "consumer")]
+pub trait Ack: Send + Sized {
+    /// Acknowledge the message, removing it from its queue
+    ///
+    /// # Errors
+    /// This function fails if the acknowledgement cannot be transmitted.
+    fn ack_delivery(self) -> BoxFuture<'static, Result<()>>;
+
+    /// Reject the message, returning it to its queue if `requeue` is set or
+    /// dead-lettering it otherwise
+    ///
+    /// # Errors
+    /// This function fails if the rejection cannot be transmitted.
+    fn reject_delivery(self, requeue: bool) -> BoxFuture<'static, Result<()>>;
+}
+
+/// The consuming half of a transport backend
+#[cfg(feature = "consumer")]
+pub trait Subscriber: Send {
+    /// The acknowledgement handle of this backend's deliveries
+    type Acker: Ack;
+
+    /// Receive the next message, or `None` if the subscription has ended
+    ///
+    /// # Errors
+    /// This function fails if the message cannot be received.
+    fn next_delivery(&mut self) -> BoxFuture<'_, Result<Option<Delivery<Self::Acker>>>>;
+}
+
+#[cfg(feature = "producer")]
+mod amqp_publisher {
+    use futures_util::future::BoxFuture;
+    use lapin::{publisher_confirm::Confirmation, BasicProperties, Channel};
+
+    use super::{Outcome, Publisher};
+    use crate::{queue_type::QueueInfo, Result};
+
+    impl Publisher for Channel {
+        fn declare<'a>(&'a self, info: QueueInfo<'a>) -> BoxFuture<'a, Result<()>> {
+            Box::pin(info.init_producer(self))
+        }
+
+        fn publish<'a>(
+            &'a self,
+            info: QueueInfo<'a>,
+            routing_key: Option<&'a str>,
+            data: &'a [u8],
+            props: BasicProperties,
+        ) -> BoxFuture<'a, Result<Outcome>> {
+            Box::pin(async move {
+                let confirm = info.publish(self, routing_key, data, props).await?.await?;
+
+                Ok(match confirm {
+                    Confirmation::Ack(Some(_)) | Confirmation::Nack(Some(_)) => Outcome::Returned,
+                    Confirmation::Nack(None) => Outcome::Nacked,
+                    Confirmation::Ack(None) | Confirmation::NotRequested => Outcome::Delivered,
+                })
+            })
+        }
+
+        fn divert<'a>(
+            &'a self,
+            info: QueueInfo<'a>,
+            data: &'a [u8],
+            props: BasicProperties,
+        ) -> BoxFuture<'a, Result<bool>> {
+            Box::pin(info.publish_fallback(self, data, props))
+        }
+
+        fn is_connected(&self) -> bool {
+            self.status().connected()
+        }
+    }
+}
+
+#[cfg(feature = "consumer")]
+mod amqp_subscriber {
+    use futures_util::{future::BoxFuture, StreamExt};
+    use lapin::{
+        acker::Acker,
+        options::{BasicAckOptions, BasicRejectOptions},
+    };
+
+    use super::{Ack, Delivery, Subscriber};
+    use crate::Result;
+
+    impl Ack for Acker {
+        fn ack_delivery(self) -> BoxFuture<'static, Result<()>> {
+            Box::pin(async move { Ok(self.ack(BasicAckOptions::default()).await?) })
+        }
+
+        fn reject_delivery(self, requeue: bool) -> BoxFuture<'static, Result<()>> {
+            Box::pin(async move { Ok(self.reject(BasicRejectOptions { requeue }).await?) })
+        }
+    }
+
+    impl Subscriber for lapin::Consumer {
+        type Acker = Acker;
+
+        fn next_delivery(&mut self) -> BoxFuture<'_, Result<Option<Delivery<Acker>>>> {
+            Box::pin(async move {
+                Ok(match StreamExt::next(self).await {
+                    Some(d) => {
+                        let d = d?;
+
+                        Some(Delivery {
+                            data: d.data,
+                            properties: d.properties,
+                            acker: d.acker,
+                        })
+                    },
+                    None => None,
+                })
+            })
+        }
+    }
+}
@@ -0,0 +1,572 @@
+//! Golden wire-format fixtures for every Geyser [`Message`] variant.
+//!
+//! Each sample message is serialized to MessagePack and JSON and compared
+//! byte-for-byte against the fixtures under `fixtures/wire`, then decoded
+//! from the fixture and re-encoded to check the round trip.  A failure here
+//! means consumers built against the committed fixtures can no longer read
+//! what producers publish.
+//!
+//! To intentionally change the wire format, regenerate the fixtures and
+//! review the diff:
+//!
+//! ```sh
+//! WIRE_TESTS_REGENERATE=1 cargo test -p holaplex-indexer-rabbitmq \
+//!     --features consumer,geyser,producer wire_tests
+//! ```
+//!
+//! A fixture missing from the tree fails its test, so a new variant needs its
+//! sample added to [`samples`] and its fixtures generated the same way.
+
+use std::{fs, path::PathBuf};
+
+use crate::{
+    geyser::{
+        AccountBalance, AccountDeleted, AccountDiff, AccountReassigned, AccountUpdate, BuildInfo,
+        DataPatch, FeeStats, InstructionIndex, InstructionNotify, Message, MintInfo, NftSale,
+        ProducerId, ProducerLifecycle, Pubkey, RootedWatermark, SignatureNotify, SlotStatus,
+        SlotStatusUpdate, Stats, TokenDelta, WalletDigest,
+    },
+    raw::Value,
+    serialize::{deserialize, serialize, serialize_with, Encoding},
+};
+
+const REGENERATE_VAR: &str = "WIRE_TESTS_REGENERATE";
+
+fn key(n: u8) -> Pubkey {
+    Pubkey::new_from_array([n; 32])
+}
+
+fn producer() -> ProducerId {
+    ProducerId {
+        host: "validator-1".into(),
+        epoch: "00000000000000000000000000000001".into(),
+    }
+}
+
+fn build() -> BuildInfo {
+    BuildInfo {
+        rustc: "rustc 1.65.0 (897e37553 2022-11-02)".into(),
+        profile: "release".into(),
+        target: "x86_64-unknown-linux-gnu".into(),
+        host: "x86_64-unknown-linux-gnu".into(),
+        platform: "ptr64,little,fxsr,sse,sse2".into(),
+        git_head: Some("0123abc".into()),
+        git_remote: Some("https://github.com/holaplex/indexer-geyser-plugin".into()),
+    }
+}
+
+/// One deterministic sample of every message variant, keyed by fixture name
+#[allow(clippy::too_many_lines)]
+fn samples() -> Vec<(&'static str, Message)> {
+    vec![
+        (
+            "account_update",
+            Message::AccountUpdate(AccountUpdate {
+                key: key(1),
+                lamports: 2_039_280,
+                owner: key(2),
+                executable: false,
+                rent_epoch: 361,
+                data: vec![0, 1, 2, 3, 254, 255],
+                write_version: 1_234_567,
+                slot: 150_000_000,
+                is_startup: false,
+                on_demand: false,
+                mint_info: Some(MintInfo {
+                    mint: key(3),
+                    decimals: 9,
+                    supply: 1_000_000_000,
+                    metadata: Some(vec![4, 5, 6]),
+                }),
+                data_hash: None,
+            }),
+        ),
+        (
+            "instruction_notify",
+            Message::InstructionNotify(InstructionNotify {
+                program: key(4),
+                data: vec![7, 8, 9],
+                accounts: vec![key(5), key(6)],
+                slot: 150_000_001,
+                txn_signature: vec![10; 64],
+                index: InstructionIndex::Inner(1, 2),
+            }),
+        ),
+        (
+            "slot_status_update",
+            Message::SlotStatusUpdate(SlotStatusUpdate {
+                slot: 150_000_002,
+                parent: Some(150_000_001),
+                status: SlotStatus::Confirmed,
+                estimated_time: Some(1_660_000_000_000),
+            }),
+        ),
+        (
+            "rooted_watermark",
+            Message::RootedWatermark(RootedWatermark { slot: 150_000_003 }),
+        ),
+        (
+            "account_reassigned",
+            Message::AccountReassigned(AccountReassigned {
+                key: key(7),
+                old_owner: key(8),
+                new_owner: key(9),
+                slot: 150_000_004,
+            }),
+        ),
+        (
+            "producer_started",
+            Message::ProducerStarted(ProducerLifecycle {
+                id: producer(),
+                version: "v0.8.1+git.0123abc".into(),
+                build: Some(build()),
+            }),
+        ),
+        (
+            "producer_stopped",
+            Message::ProducerStopped(ProducerLifecycle {
+                id: producer(),
+                version: "v0.8.1+git.0123abc".into(),
+                build: None,
+            }),
+        ),
+        (
+            "signature_notify",
+            Message::SignatureNotify(SignatureNotify {
+                txn_signature: vec![11; 64],
+                slot: 150_000_005,
+                fee_payer: key(10),
+                program_ids: vec![key(11), key(12)],
+            }),
+        ),
+        (
+            "fee_stats",
+            Message::FeeStats(FeeStats {
+                slot: 150_000_006,
+                txn_count: 42,
+                unit_price_p50: 1_000,
+                unit_price_p75: 2_500,
+                unit_price_p90: 10_000,
+                unit_price_p99: 250_000,
+                unit_price_max: 1_000_000,
+                total_compute_limit: 8_400_000,
+                total_fees: 210_000,
+            }),
+        ),
+        (
+            "account_diff",
+            Message::AccountDiff(AccountDiff {
+                key: key(13),
+                lamports: 1_461_600,
+                owner: key(14),
+                executable: false,
+                rent_epoch: 361,
+                write_version: 1_234_568,
+                slot: 150_000_007,
+                is_startup: false,
+                chain: 0xdead_beef_0000_0001,
+                version: 3,
+                patches: vec![DataPatch {
+                    offset: 64,
+                    bytes: vec![12, 13, 14],
+                }],
+            }),
+        ),
+        (
+            "stats",
+            Message::Stats(Stats {
+                producer: producer(),
+                timestamp: 1_660_000_000,
+                interval_secs: 60,
+                slot: 150_000_008,
+                account_msgs: 120_000,
+                instruction_msgs: 30_000,
+                slot_status_msgs: 450,
+                bytes: 98_765_432,
+                drops: 7,
+                reconnects: 1,
+                backlog: 12,
+                build: Some(build()),
+                features: vec!["coalescing".into()],
+            }),
+        ),
+        (
+            "account_deleted",
+            Message::AccountDeleted(AccountDeleted {
+                key: key(10),
+                owner: key(11),
+                slot: 150_000_009,
+            }),
+        ),
+        (
+            "account_balance",
+            Message::AccountBalance(AccountBalance {
+                key: key(12),
+                lamports: 2_039_280,
+                owner: key(13),
+                write_version: 987_654_322,
+                slot: 150_000_010,
+                is_startup: false,
+            }),
+        ),
+        (
+            "wallet_digest",
+            Message::WalletDigest(WalletDigest {
+                wallet: key(14),
+                slot: 150_000_011,
+                txn_count: 3,
+                lamport_delta: -1_015_000,
+                token_deltas: vec![TokenDelta {
+                    mint: key(15),
+                    received: 5_000_000,
+                    sent: 1_250_000,
+                }],
+                programs: vec![key(16), key(17)],
+            }),
+        ),
+        (
+            "nft_sale",
+            Message::NftSale(NftSale {
+                mint: key(18),
+                price: 12_500_000_000,
+                buyer: key(19),
+                seller: key(20),
+                marketplace: key(21),
+                slot: 150_000_012,
+                txn_signature: vec![22; 64],
+            }),
+        ),
+    ]
+}
+
+fn fixture_path(name: &str, ext: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "fixtures", "wire"]
+        .iter()
+        .collect::<PathBuf>()
+        .join(format!("{}.{}", name, ext))
+}
+
+/// Compare encoded bytes against a fixture, recording the fixture instead if
+/// it is missing or regeneration was requested
+fn check_fixture(name: &str, ext: &str, encoded: &[u8]) -> Vec<u8> {
+    let path = fixture_path(name, ext);
+
+    if std::env::var_os(REGENERATE_VAR).is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, encoded).unwrap();
+        eprintln!("Recorded wire fixture {}", path.display());
+    }
+
+    let expected = fs::read(&path).unwrap_or_else(|e| {
+        panic!(
+            "Missing wire fixture {} ({}) - to record it, rerun with {}=1",
+            path.display(),
+            e,
+            REGENERATE_VAR,
+        )
+    });
+    assert!(
+        expected == encoded,
+        "{} encoding of {:?} no longer matches {} - if this change is intentional, rerun with \
+         {}=1",
+        ext,
+        name,
+        path.display(),
+        REGENERATE_VAR,
+    );
+
+    expected
+}
+
+#[test]
+fn msgpack_fixtures() {
+    for (name, msg) in samples() {
+        let mut encoded = vec![];
+        serialize(&mut encoded, &msg).unwrap();
+
+        let fixture = check_fixture(name, "msgpack", &encoded);
+
+        let decoded: Message = deserialize(std::io::Cursor::new(&fixture)).unwrap();
+        let mut reencoded = vec![];
+        serialize(&mut reencoded, &decoded).unwrap();
+
+        assert_eq!(
+            fixture, reencoded,
+            "msgpack round trip of {:?} failed",
+            name
+        );
+    }
+}
+
+#[test]
+fn json_fixtures() {
+    for (name, msg) in samples() {
+        let encoded = serde_json::to_vec_pretty(&msg).unwrap();
+        let fixture = check_fixture(name, "json", &encoded);
+
+        let decoded: Message = serde_json::from_slice(&fixture).unwrap();
+
+        assert_eq!(
+            fixture,
+            serde_json::to_vec_pretty(&decoded).unwrap(),
+            "JSON round trip of {:?} failed",
+            name
+        );
+    }
+}
+
+#[test]
+fn samples_cover_every_variant() {
+    let mut kinds: Vec<_> = samples().iter().map(|(_, m)| m.kind()).collect();
+    let len = kinds.len();
+    kinds.sort_unstable();
+    kinds.dedup();
+
+    assert_eq!(kinds.len(), len, "Duplicate sample variant");
+
+    // Fails to compile when a variant is added without a sample
+    for (_, msg) in samples() {
+        match msg {
+            Message::AccountUpdate(_)
+            | Message::InstructionNotify(_)
+            | Message::SlotStatusUpdate(_)
+            | Message::RootedWatermark(_)
+            | Message::AccountReassigned(_)
+            | Message::ProducerStarted(_)
+            | Message::ProducerStopped(_)
+            | Message::SignatureNotify(_)
+            | Message::FeeStats(_)
+            | Message::AccountDiff(_)
+            | Message::Stats(_)
+            | Message::AccountDeleted(_)
+            | Message::AccountBalance(_)
+            | Message::WalletDigest(_)
+            | Message::NftSale(_) => (),
+            // Decode-only, so it has no fixture
+            Message::Unknown { .. } => unreachable!(),
+        }
+    }
+}
+
+#[test]
+fn unknown_variant_decodes() {
+    #[derive(serde::Serialize)]
+    #[serde(tag = "type", content = "data")]
+    enum Future {
+        FromTheFuture { slot: u64, extra: Vec<u8> },
+    }
+
+    let future = Future::FromTheFuture {
+        slot: 1,
+        extra: vec![2, 3],
+    };
+
+    let mut encoded = vec![];
+    serialize(&mut encoded, &future).unwrap();
+    let decoded: Message = deserialize(std::io::Cursor::new(&encoded)).unwrap();
+    assert!(matches!(decoded, Message::Unknown { ref tag, .. } if tag == "FromTheFuture"));
+
+    let encoded = serde_json::to_vec(&future).unwrap();
+    let decoded: Message = serde_json::from_slice(&encoded).unwrap();
+    assert!(matches!(decoded, Message::Unknown { ref tag, .. } if tag == "FromTheFuture"));
+    assert!(Message::unknown_count() >= 2);
+}
+
+#[test]
+fn legacy_encoding_decodes() {
+    /// The externally-tagged encoding used before messages were adjacently
+    /// tagged
+    #[derive(serde::Serialize)]
+    enum Legacy {
+        RootedWatermark(RootedWatermark),
+    }
+
+    let legacy = Legacy::RootedWatermark(RootedWatermark { slot: 150_000_003 });
+
+    let mut encoded = vec![];
+    serialize(&mut encoded, &legacy).unwrap();
+    let decoded: Message = deserialize(std::io::Cursor::new(&encoded)).unwrap();
+    assert!(matches!(
+        decoded,
+        Message::RootedWatermark(RootedWatermark { slot: 150_000_003 })
+    ));
+
+    let encoded = serde_json::to_vec(&legacy).unwrap();
+    let decoded: Message = serde_json::from_slice(&encoded).unwrap();
+    assert!(matches!(
+        decoded,
+        Message::RootedWatermark(RootedWatermark { slot: 150_000_003 })
+    ));
+}
+
+#[test]
+fn compact_round_trip() {
+    for (name, msg) in samples() {
+        let mut compact = vec![];
+        serialize_with(&mut compact, &msg, Encoding::Compact).unwrap();
+
+        let decoded: Message = deserialize(std::io::Cursor::new(&compact)).unwrap();
+        let mut expected = vec![];
+        serialize(&mut expected, &msg).unwrap();
+        let mut reencoded = vec![];
+        serialize(&mut reencoded, &decoded).unwrap();
+
+        assert_eq!(
+            expected, reencoded,
+            "compact round trip of {:?} failed",
+            name
+        );
+    }
+}
+
+#[test]
+fn data_before_type_decodes() {
+    for (name, msg) in samples() {
+        let mut expected = vec![];
+        serialize(&mut expected, &msg).unwrap();
+
+        let mut value: Value = deserialize(std::io::Cursor::new(&expected)).unwrap();
+        match value {
+            Value::Map(ref mut entries) => entries.reverse(),
+            _ => panic!("{:?} did not encode as a map", name),
+        }
+        let mut swapped = vec![];
+        serialize(&mut swapped, &value).unwrap();
+
+        let decoded: Message = deserialize(std::io::Cursor::new(&swapped)).unwrap();
+        let mut reencoded = vec![];
+        serialize(&mut reencoded, &decoded).unwrap();
+
+        assert_eq!(
+            expected, reencoded,
+            "msgpack with data first of {:?} failed",
+            name
+        );
+
+        // serde_json's maps are sorted, putting "data" before "type"
+        let json = serde_json::to_value(&msg).unwrap().to_string();
+        assert!(json.starts_with("{\"data\":"));
+        let decoded: Message = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(
+            serde_json::to_vec(&msg).unwrap(),
+            serde_json::to_vec(&decoded).unwrap(),
+            "JSON with data first of {:?} failed",
+            name
+        );
+    }
+}
+
+#[test]
+fn unknown_enum_variants_decode() {
+    #[derive(serde::Serialize)]
+    enum FutureStatus {
+        Finalized,
+    }
+
+    #[derive(serde::Serialize)]
+    enum FutureIndex {
+        Nested(u8, u8, usize),
+    }
+
+    let mut encoded = vec![];
+    serialize(&mut encoded, &FutureStatus::Finalized).unwrap();
+    let decoded: SlotStatus = deserialize(std::io::Cursor::new(&encoded)).unwrap();
+    assert_eq!(decoded, SlotStatus::Unknown);
+
+    let mut encoded = vec![];
+    serialize(&mut encoded, &FutureIndex::Nested(1, 2, 3)).unwrap();
+    let decoded: InstructionIndex = deserialize(std::io::Cursor::new(&encoded)).unwrap();
+    assert_eq!(decoded, InstructionIndex::Unknown);
+
+    let encoded = serde_json::to_vec(&FutureIndex::Nested(1, 2, 3)).unwrap();
+    let decoded: InstructionIndex = serde_json::from_slice(&encoded).unwrap();
+    assert_eq!(decoded, InstructionIndex::Unknown);
+
+    let json = serde_json::to_string(&Message::SlotStatusUpdate(SlotStatusUpdate {
+        slot: 1,
+        parent: None,
+        status: SlotStatus::Confirmed,
+        estimated_time: None,
+    }))
+    .unwrap()
+    .replace("\"Confirmed\"", "\"Finalized\"");
+    let decoded: Message = serde_json::from_str(&json).unwrap();
+    assert!(matches!(
+        decoded,
+        Message::SlotStatusUpdate(SlotStatusUpdate {
+            status: SlotStatus::Unknown,
+            ..
+        })
+    ));
+
+    // Known variants that fail to decode are still errors
+    assert!(serde_json::from_str::<InstructionIndex>(r#"{"Inner":"x"}"#).is_err());
+    assert!(serialize(&mut vec![], &SlotStatus::Unknown).is_err());
+}
+
+#[test]
+fn unknown_fields_ignored() {
+    let (name, msg) = samples()
+        .into_iter()
+        .find(|(_, m)| matches!(m, Message::AccountUpdate(_)))
+        .unwrap();
+
+    let mut expected = vec![];
+    serialize(&mut expected, &msg).unwrap();
+
+    let check = |encoded: &[u8], form: &str| {
+        let decoded: Message = deserialize(std::io::Cursor::new(encoded)).unwrap();
+        let mut reencoded = vec![];
+        serialize(&mut reencoded, &decoded).unwrap();
+
+        assert_eq!(
+            expected, reencoded,
+            "{} {:?} with an unknown field failed",
+            form, name
+        );
+    };
+
+    // Struct-map MessagePack: an extra named field
+    let mut value: Value = deserialize(std::io::Cursor::new(&expected)).unwrap();
+    match value {
+        Value::Map(ref mut entries) => match entries.get_mut(1) {
+            Some((_, Value::Map(fields))) => fields.push((
+                Value::String("from_the_future".into()),
+                Value::Array(vec![Value::UInt(1)]),
+            )),
+            _ => panic!("{:?} content is not a map", name),
+        },
+        _ => panic!("{:?} did not encode as a map", name),
+    }
+    let mut encoded = vec![];
+    serialize(&mut encoded, &value).unwrap();
+    check(&encoded, "msgpack");
+
+    // Compact MessagePack: an extra trailing element
+    let mut compact = vec![];
+    serialize_with(&mut compact, &msg, Encoding::Compact).unwrap();
+    let mut value: Value = deserialize(std::io::Cursor::new(&compact)).unwrap();
+    match value {
+        Value::Array(ref mut els) => match els.get_mut(1) {
+            Some(Value::Array(fields)) => fields.push(Value::String("from the future".into())),
+            _ => panic!("{:?} compact content is not an array", name),
+        },
+        _ => panic!("{:?} did not encode compactly as an array", name),
+    }
+    let mut encoded = vec![];
+    serialize(&mut encoded, &value).unwrap();
+    check(&encoded, "compact");
+
+    // JSON: an extra named field
+    let mut json = serde_json::to_value(&msg).unwrap();
+    json["data"]["from_the_future"] = serde_json::json!([1]);
+    let decoded: Message = serde_json::from_value(json).unwrap();
+    let mut reencoded = vec![];
+    serialize(&mut reencoded, &decoded).unwrap();
+    assert_eq!(
+        expected, reencoded,
+        "JSON {:?} with an unknown field failed",
+        name
+    );
+}
@@ -0,0 +1,144 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use once_cell::sync::Lazy;
+use selector::{
+    config::{Accounts, Filter, FilterHint, PdaFamily, Seed},
+    AccountInfo, AccountSelector,
+};
+use solana_program::{pubkey, pubkey::Pubkey};
+
+const TOKEN: Pubkey = pubkey!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+const TOKEN_2022: Pubkey = pubkey!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+const METADATA: Pubkey = pubkey!("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+const PDA_PROGRAM: Pubkey = Pubkey::new_from_array([6; 32]);
+
+/// A selector exercising every data-inspecting path: token mint selection,
+/// exclusion, and heuristics, delegate and close authority selection,
+/// collection decoding, and PDA verification with and without a stored bump
+/// through a small PDA cache
+static SELECTOR: Lazy<AccountSelector> = Lazy::new(|| {
+    let mut sel = AccountSelector::from_config(Accounts {
+        owners: [TOKEN, TOKEN_2022, METADATA]
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+        pubkeys: [Pubkey::new_from_array([1; 32]).to_string()]
+            .into_iter()
+            .collect(),
+        mints: [Pubkey::new_from_array([2; 32]).to_string()]
+            .into_iter()
+            .collect(),
+        exclude_mints: [Pubkey::new_from_array([3; 32]).to_string()]
+            .into_iter()
+            .collect(),
+        delegates: [Pubkey::new_from_array([7; 32]).to_string()]
+            .into_iter()
+            .collect(),
+        close_authorities: [Pubkey::new_from_array([8; 32]).to_string()]
+            .into_iter()
+            .collect(),
+        collections: [Pubkey::new_from_array([4; 32]).to_string()]
+            .into_iter()
+            .collect(),
+        prescreen: true,
+        pdas: vec![
+            PdaFamily {
+                program: PDA_PROGRAM.to_string(),
+                seeds: vec![
+                    Seed::Utf8("escrow".into()),
+                    Seed::Data { offset: 8, len: 32 },
+                ],
+                bump_offset: Some(40),
+            },
+            PdaFamily {
+                program: PDA_PROGRAM.to_string(),
+                seeds: vec![Seed::Data { offset: 0, len: 8 }],
+                bump_offset: None,
+            },
+        ],
+        pda_cache_capacity: Some(16),
+        plan_hints: [(
+            Filter::Pdas,
+            FilterHint {
+                cost: Some(0.1),
+                selectivity: None,
+            },
+        )]
+        .into_iter()
+        .collect(),
+        ..Accounts::default()
+    })
+    .unwrap();
+
+    sel.set_token_registry([Pubkey::new_from_array([5; 32])].into_iter().collect())
+        .unwrap();
+
+    sel
+});
+
+/// Owners are mostly chosen from the programs whose data the selector
+/// decodes, since arbitrary keys would almost never match one
+#[derive(Debug, Arbitrary)]
+enum Owner {
+    Token,
+    Token2022,
+    Metadata,
+    Pda,
+    Other([u8; 32]),
+}
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    owner: Owner,
+    pubkey: Vec<u8>,
+    data: Vec<u8>,
+    is_startup: bool,
+}
+
+struct Account {
+    owner: [u8; 32],
+    pubkey: Vec<u8>,
+    data: Vec<u8>,
+}
+
+impl AccountInfo for Account {
+    fn owner(&self) -> &[u8] {
+        &self.owner
+    }
+
+    fn pubkey(&self) -> &[u8] {
+        &self.pubkey
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+fuzz_target!(|input: Input| {
+    let Input {
+        owner,
+        pubkey,
+        data,
+        is_startup,
+    } = input;
+
+    let owner = match owner {
+        Owner::Token => TOKEN.to_bytes(),
+        Owner::Token2022 => TOKEN_2022.to_bytes(),
+        Owner::Metadata => METADATA.to_bytes(),
+        Owner::Pda => PDA_PROGRAM.to_bytes(),
+        Owner::Other(o) => o,
+    };
+
+    let acct = Account {
+        owner,
+        pubkey,
+        data,
+    };
+
+    SELECTOR.inspects_data(&acct.owner);
+    SELECTOR.is_selected(&acct, is_startup);
+});
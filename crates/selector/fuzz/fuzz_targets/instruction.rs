@@ -0,0 +1,67 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use once_cell::sync::Lazy;
+use selector::{config::Instructions, InstructionInfo, InstructionSelector};
+use solana_program::{pubkey, pubkey::Pubkey};
+
+const TOKEN: Pubkey = pubkey!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+
+/// Account keys resolvable by instructions, led by the screened token
+/// program.  Indices past the end exercise the missing-account path.
+static KEYS: Lazy<Vec<Pubkey>> = Lazy::new(|| {
+    vec![
+        TOKEN,
+        Pubkey::new_from_array([1; 32]),
+        Pubkey::new_from_array([2; 32]),
+    ]
+});
+
+/// A selector screening token instructions with the default screen
+static SELECTOR: Lazy<InstructionSelector> = Lazy::new(|| {
+    InstructionSelector::from_config(Instructions {
+        programs: KEYS[..2].iter().map(ToString::to_string).collect(),
+        ..Instructions::default()
+    })
+    .unwrap()
+});
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    program_index: u8,
+    accounts: Vec<u8>,
+    data: Vec<u8>,
+}
+
+impl<'a> InstructionInfo<'a> for Input {
+    type AccountIndices = std::iter::Copied<std::slice::Iter<'a, u8>>;
+
+    fn program_index(&self) -> u8 {
+        self.program_index
+    }
+
+    fn account_indices(&'a self) -> Self::AccountIndices {
+        self.accounts.iter().copied()
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+fuzz_target!(|input: Input| {
+    SELECTOR
+        .is_selected(|i| KEYS.get(usize::from(i)), &input)
+        .ok();
+
+    let accounts: Vec<_> = input
+        .accounts
+        .iter()
+        .filter_map(|i| KEYS.get(usize::from(*i)).copied())
+        .collect();
+
+    if let Some(program) = KEYS.get(usize::from(input.program_index)) {
+        SELECTOR.is_selected_resolved(program, &accounts, &input.data);
+    }
+});
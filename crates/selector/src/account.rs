@@ -1,9 +1,22 @@
+#[cfg(feature = "token")]
 use hashbrown::HashSet;
+#[cfg(feature = "rabbitmq")]
 use indexer_rabbitmq::geyser::StartupType;
-use solana_program::{program_pack::Pack, pubkey::Pubkey};
-use spl_token::state::Account as TokenAccount;
+use solana_program::pubkey::Pubkey;
 
-use crate::{config::Accounts, Error, Heuristic, Result};
+#[cfg(feature = "token")]
+use crate::{
+    collection::Collections,
+    token::{self, Tokens},
+};
+use crate::{
+    config::{Accounts, Filter},
+    keys::KeySet,
+    pda::Pdas,
+    plan::Plan,
+    usage::{Unused, Usage},
+    Decision, Error, Result,
+};
 
 /// Abstraction over a Solana account container
 #[allow(clippy::module_name_repetitions)]
@@ -41,76 +54,198 @@ impl<'a> AccountInfo
 /// Helper for performing screening logic on Solana accounts
 #[derive(Debug)]
 pub struct Selector {
-    owners: HashSet<[u8; 32]>,
-    pubkeys: HashSet<[u8; 32]>,
-    mints: HashSet<Pubkey>,
+    owners: KeySet,
+    pubkeys: KeySet,
     startup: Option<bool>,
-    token_reg: Heuristic<Option<HashSet<Pubkey>>>,
+    pdas: Pdas,
+    /// Evaluation order of the filters which inspect account data
+    plan: Plan,
+    /// Match flags for the `owners` and `pubkeys` entries, if tracked
+    usage: Option<[Usage; 2]>,
+    #[cfg(feature = "token")]
+    tokens: Tokens,
+    #[cfg(feature = "token")]
+    collections: Collections,
 }
 
 impl Selector {
     /// Construct a new selector from the given configuration block
     ///
     /// # Errors
-    /// Fails if an owner, public-key, or mint address is incorrectly specified
+    /// Fails if an owner, public-key, mint address, PDA seed, or plan hint is
+    /// incorrectly specified, or if mints are specified without the `token`
+    /// feature enabled
+    #[allow(clippy::too_many_lines)]
     pub fn from_config(config: Accounts) -> Result<Self> {
         let Accounts {
             owners,
             all_tokens,
             pubkeys,
             mints,
+            exclude_mints,
+            delegates,
+            close_authorities,
+            collections,
+            token_programs,
+            prescreen,
+            pdas,
+            pda_cache_capacity,
+            plan_hints,
             startup,
         } = config;
 
-        let owners = owners
+        let filters = [
+            (Filter::Mints, !mints.is_empty()),
+            (
+                Filter::Authorities,
+                !delegates.is_empty() || !close_authorities.is_empty(),
+            ),
+            (Filter::Collections, !collections.is_empty()),
+            (Filter::Pdas, !pdas.is_empty()),
+        ];
+
+        let owners: KeySet = owners
             .into_iter()
             .map(|s| s.parse().map(Pubkey::to_bytes))
             .collect::<Result<_, _>>()
             .map_err(|e| Error::AccountConfig("owners", e.into()))?;
 
-        let pubkeys = pubkeys
+        let pubkeys: KeySet = pubkeys
             .into_iter()
             .map(|s| s.parse().map(Pubkey::to_bytes))
             .collect::<Result<_, _>>()
             .map_err(|e| Error::AccountConfig("pubkeys", e.into()))?;
+        let pubkeys = if prescreen {
+            pubkeys.with_prescreen()
+        } else {
+            pubkeys
+        };
+        owners.report("account owners");
+        pubkeys.report("account pubkeys");
 
-        let mints = mints
-            .into_iter()
-            .map(|s| s.parse::<Pubkey>())
-            .collect::<Result<_, _>>()
-            .map_err(|e| Error::AccountConfig("pubkeys", e.into()))?;
+        let pdas = Pdas::from_config(pdas, pda_cache_capacity)?;
+
+        let plan = Plan::new(
+            filters
+                .into_iter()
+                .filter(|(_, configured)| *configured)
+                .map(|(filter, _)| {
+                    let cost = match filter {
+                        Filter::Mints => 0.5,
+                        Filter::Authorities => 1.0,
+                        Filter::Collections => 1.5,
+                        Filter::Pdas => pdas.estimated_cost(),
+                    };
+
+                    (filter, cost)
+                }),
+            &plan_hints,
+        )?;
+
+        #[cfg(feature = "token")]
+        let tokens = Tokens::from_config(
+            mints,
+            exclude_mints,
+            all_tokens,
+            token_programs,
+            prescreen,
+            &owners,
+        )?
+        .with_authorities(delegates, close_authorities)?;
+        #[cfg(feature = "token")]
+        let collections = Collections::from_config(collections)?;
+
+        #[cfg(not(feature = "token"))]
+        {
+            let _ = (all_tokens, prescreen);
+
+            if !mints.is_empty() {
+                return Err(Error::AccountConfig(
+                    "mints",
+                    "mint filtering requires the token feature".into(),
+                ));
+            }
+
+            if !exclude_mints.is_empty() {
+                return Err(Error::AccountConfig(
+                    "exclude_mints",
+                    "mint filtering requires the token feature".into(),
+                ));
+            }
+
+            if !delegates.is_empty() {
+                return Err(Error::AccountConfig(
+                    "delegates",
+                    "delegate filtering requires the token feature".into(),
+                ));
+            }
+
+            if !close_authorities.is_empty() {
+                return Err(Error::AccountConfig(
+                    "close_authorities",
+                    "close authority filtering requires the token feature".into(),
+                ));
+            }
+
+            if !collections.is_empty() {
+                return Err(Error::AccountConfig(
+                    "collections",
+                    "collection filtering requires the token feature".into(),
+                ));
+            }
 
-        let mut ret = Self {
+            if !token_programs.is_empty() {
+                return Err(Error::AccountConfig(
+                    "token_programs",
+                    "token heuristics require the token feature".into(),
+                ));
+            }
+        }
+
+        Ok(Self {
             owners,
             pubkeys,
-            mints,
             startup,
-            token_reg: Heuristic::Used(if all_tokens {
-                None
-            } else {
-                Some(HashSet::new())
-            }),
-        };
+            pdas,
+            plan,
+            usage: None,
+            #[cfg(feature = "token")]
+            tokens,
+            #[cfg(feature = "token")]
+            collections,
+        })
+    }
 
-        // Don't screen tokens if we're never going to return them
-        if !ret.owners.contains(spl_token::id().as_ref()) {
-            ret.token_reg = Heuristic::Unused;
-        }
+    /// Start recording which `owners` and `pubkeys` entries match any
+    /// account, for reporting with [`unused`](Self::unused)
+    pub fn track_usage(&mut self) {
+        self.usage = Some([
+            Usage::new("owners", self.owners.iter().copied()),
+            Usage::new("pubkeys", self.pubkeys.iter().copied()),
+        ]);
+    }
 
-        Ok(ret)
+    /// Returns the `owners` and `pubkeys` entries which have not matched any
+    /// account since [`track_usage`](Self::track_usage) was called, or an
+    /// empty list if usage is not tracked
+    #[must_use]
+    pub fn unused(&self) -> Vec<Unused> {
+        self.usage.iter().flatten().map(Usage::unused).collect()
     }
 
-    /// Lazy-load the token addresses
+    /// Load or replace the token addresses, returning the previously-loaded
+    /// set (empty if none was loaded)
     ///
-    /// # Panics
-    /// This method panics if token addresses are not wanted or if they have
-    /// already been loaded
-    pub fn init_token_registry(&mut self, addrs: HashSet<Pubkey>) {
-        assert!(self.token_reg.get().as_ref().unwrap().is_empty());
-        *self.token_reg.get_mut() = Some(addrs);
+    /// # Errors
+    /// This method fails with [`Error::HeuristicUnavailable`] if token
+    /// addresses are not wanted by this selector's configuration
+    #[cfg(feature = "token")]
+    pub fn set_token_registry(&mut self, addrs: HashSet<Pubkey>) -> Result<HashSet<Pubkey>> {
+        self.tokens.set_token_registry(addrs)
     }
 
     /// Returns the startup-based selector configuration
+    #[cfg(feature = "rabbitmq")]
     #[inline]
     #[must_use]
     pub fn startup(&self) -> StartupType {
@@ -119,53 +254,111 @@ impl Selector {
 
     /// Returns whether tokens from the Solana token registry should be loaded
     /// for exclusion
+    #[cfg(feature = "token")]
     #[inline]
     pub fn screen_token_registry(&self) -> bool {
-        self.token_reg.try_get().map_or(false, Option::is_some)
+        self.tokens.screen_token_registry()
+    }
+
+    /// Returns true if the given public key is in this selector's explicit
+    /// allowlist
+    #[inline]
+    #[must_use]
+    pub fn is_allowlisted(&self, pubkey: &[u8]) -> bool {
+        self.pubkeys.contains(pubkey)
+    }
+
+    /// Returns true if selecting an account with the given owner may require
+    /// decoding its data, making selection cost more than a few key lookups
+    #[cfg(feature = "token")]
+    #[inline]
+    #[must_use]
+    pub fn inspects_data(&self, owner: &[u8]) -> bool {
+        self.tokens.inspects_data(owner)
+            || self.pdas.inspects_data(owner)
+            || (!self.collections.is_empty()
+                && (owner == crate::collection::METADATA_ID.as_ref()
+                    || crate::token::is_token_program(owner)))
+    }
+
+    /// Returns true if selecting an account with the given owner may require
+    /// decoding its data, making selection cost more than a few key lookups
+    #[cfg(not(feature = "token"))]
+    #[inline]
+    #[must_use]
+    pub fn inspects_data(&self, owner: &[u8]) -> bool {
+        self.pdas.inspects_data(owner)
     }
 
     /// Returns true if the given account associated with the given startup flag
     /// has been requested by this selector's configuration
     #[inline]
     pub fn is_selected(&self, acct: &impl AccountInfo, is_startup: bool) -> bool {
+        self.decide(acct, is_startup).is_selected()
+    }
+
+    /// Returns the rule deciding whether the given account associated with the
+    /// given startup flag is selected
+    #[inline]
+    #[must_use]
+    pub fn decide(&self, acct: &impl AccountInfo, is_startup: bool) -> Decision {
         let owner = acct.owner();
         let pubkey = acct.pubkey();
         let data = acct.data();
 
         if self.startup.map_or(false, |s| is_startup != s) {
-            return false;
+            return Decision::StartupMismatch { is_startup };
         }
 
         if self.pubkeys.contains(pubkey) {
-            return true;
+            if let Some([_, ref pubkeys]) = self.usage {
+                pubkeys.hit(pubkey);
+            }
+
+            return Decision::Pubkey;
         }
 
-        let token = once_cell::unsync::Lazy::new(|| {
-            if owner == spl_token::id().as_ref() && data.len() == TokenAccount::get_packed_len() {
-                TokenAccount::unpack_from_slice(data).ok()
-            } else {
-                None
-            }
-        });
+        let wanted_owner = self.owners.contains(owner);
 
-        if !self.mints.is_empty() && token.map_or(false, |t| self.mints.contains(&t.mint)) {
-            return true;
+        // No filter can select an unwanted owner without inspecting its data
+        if !wanted_owner && !self.inspects_data(owner) {
+            return Decision::UnselectedOwner;
         }
 
-        if !self.owners.contains(owner) {
-            return false;
+        #[cfg(feature = "token")]
+        let token = once_cell::unsync::Lazy::new(|| token::token_info(owner, data));
+
+        for filter in self.plan.iter() {
+            let decision = match filter {
+                #[cfg(feature = "token")]
+                Filter::Mints => self.tokens.select_mint(token.as_ref()),
+                #[cfg(feature = "token")]
+                Filter::Authorities => self.tokens.select_authority(token.as_ref()),
+                #[cfg(feature = "token")]
+                Filter::Collections => self.collections.select(owner, data, token.as_ref()),
+                Filter::Pdas => self.pdas.select(pubkey, owner, data),
+                #[cfg(not(feature = "token"))]
+                Filter::Mints | Filter::Authorities | Filter::Collections => None,
+            };
+
+            if let Some(decision) = decision {
+                return decision;
+            }
         }
 
-        let maybe_not_nft = self.token_reg.get().as_ref().and_then(|reg| {
-            let token = token.as_ref()?;
+        if !wanted_owner {
+            return Decision::UnselectedOwner;
+        }
 
-            Some(token.amount > 1 || reg.contains(&token.mint))
-        });
+        if let Some([ref owners, _]) = self.usage {
+            owners.hit(owner);
+        }
 
-        if maybe_not_nft.unwrap_or(false) {
-            return false;
+        #[cfg(feature = "token")]
+        if let Some(decision) = self.tokens.screen(owner, token.as_ref()) {
+            return decision;
         }
 
-        true
+        Decision::Owner
     }
 }
@@ -0,0 +1,65 @@
+//! Bounded caches of per-account facts consulted by the selectors
+
+use std::sync::Mutex;
+
+use hashbrown::HashMap;
+
+type Generation<V> = HashMap<[u8; 32], V>;
+
+/// Approximate LRU map keyed by account public key, made of two generations
+/// with the older generation discarded whenever the newer one fills up
+#[derive(Debug)]
+pub(crate) struct KeyCache<V> {
+    cap: usize,
+    gens: Mutex<(Generation<V>, Generation<V>)>,
+}
+
+impl<V: Copy> KeyCache<V> {
+    pub fn new(cap: usize) -> Self {
+        Self {
+            cap: (cap / 2).max(1),
+            gens: Mutex::new((HashMap::new(), HashMap::new())),
+        }
+    }
+
+    pub fn get(&self, key: &[u8; 32]) -> Option<V> {
+        let mut gens = self
+            .gens
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let (curr, prev) = &mut *gens;
+
+        if let Some(val) = curr.get(key) {
+            return Some(*val);
+        }
+
+        let val = prev.remove(key)?;
+        Self::insert_locked(self.cap, curr, prev, *key, val);
+
+        Some(val)
+    }
+
+    pub fn insert(&self, key: [u8; 32], val: V) {
+        let mut gens = self
+            .gens
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let (curr, prev) = &mut *gens;
+
+        Self::insert_locked(self.cap, curr, prev, key, val);
+    }
+
+    fn insert_locked(
+        cap: usize,
+        curr: &mut Generation<V>,
+        prev: &mut Generation<V>,
+        key: [u8; 32],
+        val: V,
+    ) {
+        if curr.len() >= cap && !curr.contains_key(&key) {
+            *prev = std::mem::take(curr);
+        }
+
+        curr.insert(key, val);
+    }
+}
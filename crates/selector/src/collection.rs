@@ -0,0 +1,165 @@
+//! Selection of NFTs by verified Metaplex collection.  Metadata accounts are
+//! decoded as they are updated, and the mints of those belonging to a
+//! requested collection are remembered so the token accounts holding them
+//! can be selected as well.  Token accounts are therefore only selected once
+//! the metadata of their mint has been observed.
+
+use std::sync::RwLock;
+
+use hashbrown::{HashMap, HashSet};
+use solana_program::{pubkey, pubkey::Pubkey};
+
+use crate::{token::TokenInfo, Decision, Error, Result};
+
+/// The Metaplex token metadata program
+pub(crate) const METADATA_ID: Pubkey = pubkey!("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+
+/// The account key tag of a `MetadataV1` account
+const METADATA_V1_KEY: u8 = 4;
+
+/// Sequential reader over Borsh-encoded account data
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+
+        Some(head)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4)?.try_into().ok().map(u32::from_le_bytes)
+    }
+
+    fn pubkey(&mut self) -> Option<Pubkey> {
+        self.take(32)?.try_into().ok().map(Pubkey::new_from_array)
+    }
+
+    fn skip_string(&mut self) -> Option<()> {
+        let len = self.u32()?;
+        self.take(len.try_into().ok()?).map(|_| ())
+    }
+
+    /// Skip an `Option<T>` whose `Some` payload is `len` bytes long
+    fn skip_option(&mut self, len: usize) -> Option<()> {
+        match self.u8()? {
+            0 => Some(()),
+            _ => self.take(len).map(|_| ()),
+        }
+    }
+}
+
+/// Decode the mint and verified collection of a `MetadataV1` account.  The
+/// collection is `None` if the metadata has no verified collection.
+fn parse_metadata(data: &[u8]) -> Option<(Pubkey, Option<Pubkey>)> {
+    let mut r = Reader(data);
+
+    if r.u8()? != METADATA_V1_KEY {
+        return None;
+    }
+
+    r.take(32)?; // update_authority
+    let mint = r.pubkey()?;
+
+    r.skip_string()?; // name
+    r.skip_string()?; // symbol
+    r.skip_string()?; // uri
+    r.take(2)?; // seller_fee_basis_points
+
+    if r.u8()? != 0 {
+        // creators, each an address followed by verified and share bytes
+        let len: usize = r.u32()?.try_into().ok()?;
+        r.take(len.checked_mul(34)?)?;
+    }
+
+    r.take(2)?; // primary_sale_happened, is_mutable
+    r.skip_option(1)?; // edition_nonce
+    r.skip_option(1)?; // token_standard
+
+    let collection = match r.u8() {
+        Some(1) => {
+            let verified = r.u8()? != 0;
+            let key = r.pubkey()?;
+
+            verified.then_some(key)
+        },
+        _ => None,
+    };
+
+    Some((mint, collection))
+}
+
+/// Collection-based account selection state
+#[derive(Debug)]
+pub(crate) struct Collections {
+    collections: HashSet<Pubkey>,
+    /// Collection of each observed mint belonging to a requested collection
+    mints: RwLock<HashMap<Pubkey, Pubkey>>,
+}
+
+impl Collections {
+    pub fn from_config(collections: HashSet<String>) -> Result<Self> {
+        let collections = collections
+            .into_iter()
+            .map(|s| s.parse::<Pubkey>())
+            .collect::<Result<_, _>>()
+            .map_err(|e| Error::AccountConfig("collections", e.into()))?;
+
+        Ok(Self {
+            collections,
+            mints: RwLock::default(),
+        })
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.collections.is_empty()
+    }
+
+    /// Selects the account if it is the metadata of a requested collection's
+    /// NFT, or a token account holding such an NFT
+    pub fn select(&self, owner: &[u8], data: &[u8], token: Option<&TokenInfo>) -> Option<Decision> {
+        if self.is_empty() {
+            return None;
+        }
+
+        if owner == METADATA_ID.as_ref() {
+            let (mint, collection) = parse_metadata(data)?;
+            let collection = collection.filter(|c| self.collections.contains(c));
+
+            let mut mints = self
+                .mints
+                .write()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+            // The collection of a mutable NFT can be unverified or changed
+            return if let Some(c) = collection {
+                mints.insert(mint, c);
+
+                Some(Decision::Collection(c))
+            } else {
+                mints.remove(&mint);
+
+                None
+            };
+        }
+
+        let token = token?;
+        let collection = *self
+            .mints
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .get(&token.mint)?;
+
+        Some(Decision::Collection(collection))
+    }
+}
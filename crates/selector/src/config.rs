@@ -1,11 +1,11 @@
 //! Configuration blocks for the Geyser selectors
 
-use hashbrown::HashSet;
+use hashbrown::{HashMap, HashSet};
 use serde;
 use serde::Deserialize;
 
 /// Configuration block for [`AccountSelector`](crate::AccountSelector)
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct Accounts {
     /// The set of account owners to filter by
@@ -17,7 +17,8 @@ pub struct Accounts {
     #[serde(default)]
     pub pubkeys: HashSet<String>,
 
-    /// The set of token mints to filter accounts belonging to [`spl_token`] by
+    /// The set of token mints to filter accounts belonging to the SPL token
+    /// program by.  Requires the `token` feature.
     #[serde(default)]
     pub mints: HashSet<String>,
 
@@ -31,15 +32,143 @@ pub struct Accounts {
     pub startup: Option<bool>,
 
     /// Set to true to disable heuristics to reduce the number of incoming
-    /// token account updates.  Has no effect if the spl-token pubkey is not in
-    /// the owners list.
+    /// token account updates.  Has no effect if no token program is in the
+    /// owners list.
     #[serde(default)]
     pub all_tokens: bool,
+
+    /// Per-program overrides of the token heuristics, keyed by the ID of a
+    /// token program (spl-token or Token-2022).  Token programs not listed
+    /// here use `all_tokens`.  Requires the `token` feature.
+    #[serde(default)]
+    pub token_programs: HashMap<String, TokenProgram>,
+
+    /// A set of token mints whose token accounts are never selected by the
+    /// owners filter, e.g. to drop high-churn stablecoin accounts when
+    /// `all_tokens` is set.  Mints listed in `mints` are still selected.
+    /// Requires the `token` feature.
+    #[serde(default)]
+    pub exclude_mints: HashSet<String>,
+
+    /// A set of delegates, such as marketplace escrow authorities, whose
+    /// delegated token accounts are selected.  Requires the `token` feature.
+    #[serde(default)]
+    pub delegates: HashSet<String>,
+
+    /// A set of close authorities whose closable token accounts are
+    /// selected.  Requires the `token` feature.
+    #[serde(default)]
+    pub close_authorities: HashSet<String>,
+
+    /// A set of verified Metaplex collections whose NFTs are selected: their
+    /// metadata accounts, and the token accounts holding them once their
+    /// metadata has been seen.  Requires the `token` feature.
+    #[serde(default)]
+    pub collections: HashSet<String>,
+
+    /// Set to true to check a bloom filter before looking up accounts in the
+    /// `pubkeys` and `mints` sets, costing about 10 bits per key.  Worthwhile
+    /// only when these sets hold hundreds of thousands of keys and most
+    /// updates match neither.  The size of each set is logged at load.
+    #[serde(default)]
+    pub prescreen: bool,
+
+    /// Families of program-derived addresses to select, e.g. every escrow
+    /// account of a marketplace.  An account owned by a family's program is
+    /// selected if its address derives from the family's seeds.
+    #[serde(default)]
+    pub pdas: Vec<PdaFamily>,
+
+    /// The approximate number of accounts whose PDA verification results are
+    /// remembered to avoid rehashing their seeds on every update.  Defaults
+    /// to 65,536; set to 0 to disable the cache.
+    #[serde(default)]
+    pub pda_cache_capacity: Option<usize>,
+
+    /// Hints for ordering the filters which decode account data, overriding
+    /// the estimated cost and selectivity of each.  The chosen order is
+    /// logged at load.
+    #[serde(default)]
+    pub plan_hints: HashMap<Filter, FilterHint>,
 }
 
-/// Configuration block for [`InstructionSelector`](crate::InstructionSelector)
+/// A filter of [`Accounts`] which decodes or hashes account data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Filter {
+    /// Token accounts of the `mints` set
+    Mints,
+    /// Token accounts of the `delegates` and `close_authorities` sets
+    Authorities,
+    /// Metadata and token accounts of the `collections` set
+    Collections,
+    /// Program-derived addresses of the `pdas` families
+    Pdas,
+}
+
+/// Operator-supplied estimates for a single [`Filter`]
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct FilterHint {
+    /// The relative cost of evaluating the filter for a candidate account,
+    /// where decoding a token account costs about 2
+    #[serde(default)]
+    pub cost: Option<f64>,
+
+    /// The fraction of candidate accounts the filter selects, between 0 and
+    /// 1
+    #[serde(default)]
+    pub selectivity: Option<f64>,
+}
+
+/// A family of program-derived addresses sharing a seed pattern
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct PdaFamily {
+    /// The program the addresses are derived from, which must also own the
+    /// accounts
+    pub program: String,
+
+    /// The seeds of each address in order, excluding the bump seed
+    pub seeds: Vec<Seed>,
+
+    /// The offset of the bump seed stored in each account's data.  If unset
+    /// the canonical bump is searched for, costing up to 255 hashes for each
+    /// account verified.
+    #[serde(default)]
+    pub bump_offset: Option<usize>,
+}
+
+/// A single seed of a [`PdaFamily`]
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub enum Seed {
+    /// A constant UTF-8 string, such as `"escrow"`
+    Utf8(String),
+    /// A constant public key
+    Pubkey(String),
+    /// Bytes read from the data of the account being verified, such as the
+    /// key of a seller recorded by the program
+    Data {
+        /// The offset of the seed in the account data
+        offset: usize,
+        /// The length of the seed in bytes
+        len: usize,
+    },
+}
+
+/// Token heuristic settings for a single token program
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct TokenProgram {
+    /// Set to true to disable heuristics for this program's token accounts
+    #[serde(default)]
+    pub all_tokens: bool,
+}
+
+/// Configuration block for [`InstructionSelector`](crate::InstructionSelector)
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct Instructions {
     /// The set of target programs to filter by
     #[serde(default)]
@@ -52,4 +181,18 @@ pub struct Instructions {
     /// 1.
     #[serde(default)]
     pub all_token_calls: bool,
+
+    /// Named screens applied to the instructions of each program, keyed by
+    /// program ID.  An instruction is selected if it passes any screen for
+    /// its program.  Available screens are `burn-nft`, `transfer-nft`, and
+    /// `mint-single`.  Overrides the default spl-token screen.  Requires the
+    /// `token` feature.
+    #[serde(default)]
+    pub screens: HashMap<String, Vec<String>>,
+
+    /// Wallets whose transactions are selected in full.  Every instruction
+    /// of a transaction signed by one of these wallets, whether as fee payer
+    /// or as an additional signer, is selected regardless of its program.
+    #[serde(default)]
+    pub signers: HashSet<String>,
 }
@@ -0,0 +1,112 @@
+//! Explanations of selector decisions, for tracing why an account or
+//! instruction was or was not selected
+
+use std::fmt;
+
+use solana_program::pubkey::Pubkey;
+
+/// The rule which decided whether an account or instruction was selected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// Rejected because the update's startup flag did not match the
+    /// `startup` filter
+    StartupMismatch {
+        /// The startup flag of the update
+        is_startup: bool,
+    },
+    /// Selected because the account is in the `pubkeys` allowlist
+    Pubkey,
+    /// Selected because the account is a token account for a mint in
+    /// `mints`
+    Mint(Pubkey),
+    /// Selected because the account is a token account delegated to an
+    /// authority in `delegates`
+    Delegate(Pubkey),
+    /// Selected because the account is a token account closable by an
+    /// authority in `closeAuthorities`
+    CloseAuthority(Pubkey),
+    /// Selected because the account is the metadata of, or a token account
+    /// holding, an NFT verified as part of a collection in `collections`
+    Collection(Pubkey),
+    /// Selected because the account's address derives from the seeds of a
+    /// family in `pdas` for the given program
+    Pda(Pubkey),
+    /// Rejected because the account's owner is not in `owners`
+    UnselectedOwner,
+    /// Rejected because the account is a token account for a mint in
+    /// `excludeMints`
+    ExcludedMint(Pubkey),
+    /// Rejected by the token heuristics as probably not an NFT
+    TokenHeuristic {
+        /// The mint of the token account
+        mint: Pubkey,
+        /// The balance of the token account
+        amount: u64,
+        /// True if the mint is listed in the token registry
+        registered: bool,
+    },
+    /// Selected because the account's owner is in `owners`
+    Owner,
+    /// Rejected because the instruction's program is not in `programs`
+    UnselectedProgram,
+    /// Rejected because the instruction passed none of its program's screens
+    Screened,
+    /// Selected because the instruction's program is in `programs`
+    Program,
+    /// Selected because the instruction's transaction was signed by a wallet
+    /// in `signers`
+    Signer(Pubkey),
+}
+
+impl Decision {
+    /// Returns true if this decision selects the account or instruction
+    #[must_use]
+    pub fn is_selected(self) -> bool {
+        matches!(
+            self,
+            Self::Pubkey
+                | Self::Mint(_)
+                | Self::Delegate(_)
+                | Self::CloseAuthority(_)
+                | Self::Collection(_)
+                | Self::Pda(_)
+                | Self::Owner
+                | Self::Program
+                | Self::Signer(_)
+        )
+    }
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::StartupMismatch { is_startup } => {
+                write!(f, "rejected: startup filter (is_startup={})", is_startup)
+            },
+            Self::Pubkey => f.write_str("selected: pubkeys"),
+            Self::Mint(m) => write!(f, "selected: mints (mint={})", m),
+            Self::Delegate(d) => write!(f, "selected: delegates (delegate={})", d),
+            Self::CloseAuthority(c) => {
+                write!(f, "selected: closeAuthorities (authority={})", c)
+            },
+            Self::Collection(c) => write!(f, "selected: collections (collection={})", c),
+            Self::Pda(p) => write!(f, "selected: pdas (program={})", p),
+            Self::UnselectedOwner => f.write_str("rejected: owner not in owners"),
+            Self::ExcludedMint(m) => write!(f, "rejected: excludeMints (mint={})", m),
+            Self::TokenHeuristic {
+                mint,
+                amount,
+                registered,
+            } => write!(
+                f,
+                "rejected: token heuristics (mint={}, amount={}, registered={})",
+                mint, amount, registered
+            ),
+            Self::Owner => f.write_str("selected: owners"),
+            Self::UnselectedProgram => f.write_str("rejected: program not in programs"),
+            Self::Screened => f.write_str("rejected: instruction screens"),
+            Self::Program => f.write_str("selected: programs"),
+            Self::Signer(s) => write!(f, "selected: signers (signer={})", s),
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! C ABI for evaluating account selectors outside Rust, e.g. against
+//! historical account dumps.  Build the library with
+//! `cargo rustc -p holaplex-indexer-geyser-selector --features ffi --crate-type cdylib`
+//! and declare:
+//!
+//! ```c
+//! typedef struct Selector Selector;
+//!
+//! Selector *selector_new_from_json(const char *json);
+//! bool selector_account_is_selected(const Selector *sel, const uint8_t *owner,
+//!     const uint8_t *pubkey, const uint8_t *data, size_t len);
+//! const char *selector_last_error(void);
+//! void selector_free(Selector *sel);
+//! ```
+//!
+//! Accounts are evaluated as non-startup updates.
+
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString},
+    os::raw::c_char,
+    panic::{catch_unwind, AssertUnwindSafe},
+    ptr, slice,
+};
+
+use crate::{config::Accounts, AccountInfo, AccountSelector};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+fn set_last_error(msg: impl ToString) {
+    let msg = CString::new(msg.to_string().replace('\0', " ")).unwrap_or_default();
+
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(msg));
+}
+
+struct Account<'a> {
+    owner: &'a [u8],
+    pubkey: &'a [u8],
+    data: &'a [u8],
+}
+
+impl<'a> AccountInfo for Account<'a> {
+    fn owner(&self) -> &[u8] {
+        self.owner
+    }
+
+    fn pubkey(&self) -> &[u8] {
+        self.pubkey
+    }
+
+    fn data(&self) -> &[u8] {
+        self.data
+    }
+}
+
+/// Construct an account selector from a JSON account selector configuration
+/// block, returning null on failure.  The reason for a failure can be read
+/// with [`selector_last_error`].
+///
+/// # Safety
+/// `json` must be null or point to a valid null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn selector_new_from_json(json: *const c_char) -> *mut AccountSelector {
+    if json.is_null() {
+        set_last_error("Configuration is null");
+        return ptr::null_mut();
+    }
+
+    let json = CStr::from_ptr(json);
+
+    let res = catch_unwind(|| {
+        let json = json
+            .to_str()
+            .map_err(|e| format!("Configuration is not valid UTF-8: {}", e))?;
+        let config: Accounts = serde_json::from_str(json)
+            .map_err(|e| format!("Invalid account selector configuration: {}", e))?;
+
+        AccountSelector::from_config(config).map_err(|e| e.to_string())
+    });
+
+    match res {
+        Ok(Ok(sel)) => Box::into_raw(Box::new(sel)),
+        Ok(Err(e)) => {
+            set_last_error(e);
+            ptr::null_mut()
+        },
+        Err(_) => {
+            set_last_error("Panicked while constructing the selector");
+            ptr::null_mut()
+        },
+    }
+}
+
+/// Returns true if an account with the given 32-byte owner and public key
+/// and `len` bytes of data is selected.  Returns false if `sel`, `owner`,
+/// or `pubkey` is null.
+///
+/// # Safety
+/// `sel` must be null or a selector returned by [`selector_new_from_json`]
+/// not yet freed, `owner` and `pubkey` must be null or point to 32 readable
+/// bytes, and `data` must point to `len` readable bytes unless `len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn selector_account_is_selected(
+    sel: *const AccountSelector,
+    owner: *const u8,
+    pubkey: *const u8,
+    data: *const u8,
+    len: usize,
+) -> bool {
+    if sel.is_null() || owner.is_null() || pubkey.is_null() || (data.is_null() && len != 0) {
+        return false;
+    }
+
+    let acct = Account {
+        owner: slice::from_raw_parts(owner, 32),
+        pubkey: slice::from_raw_parts(pubkey, 32),
+        data: if len == 0 {
+            &[]
+        } else {
+            slice::from_raw_parts(data, len)
+        },
+    };
+
+    let sel = &*sel;
+
+    catch_unwind(AssertUnwindSafe(|| sel.is_selected(&acct, false))).unwrap_or_else(|_| {
+        set_last_error("Panicked while evaluating the selector");
+        false
+    })
+}
+
+/// Returns the error message of the last failed call on this thread, or
+/// null if no call has failed.  The message is valid until the next failed
+/// call on this thread.
+#[no_mangle]
+pub extern "C" fn selector_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+/// Free a selector returned by [`selector_new_from_json`]
+///
+/// # Safety
+/// `sel` must be null or a selector returned by [`selector_new_from_json`]
+/// not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn selector_free(sel: *mut AccountSelector) {
+    if !sel.is_null() {
+        drop(Box::from_raw(sel));
+    }
+}
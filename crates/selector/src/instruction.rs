@@ -1,7 +1,15 @@
+#[cfg(feature = "token")]
+use hashbrown::HashMap;
 use hashbrown::HashSet;
-use solana_program::pubkey::Pubkey;
+use solana_program::{instruction::Instruction, pubkey::Pubkey};
 
-use crate::{config::Instructions, Error, Heuristic, Result};
+#[cfg(feature = "token")]
+use crate::screen::{self, Screen};
+use crate::{
+    config::Instructions,
+    usage::{Unused, Usage},
+    Decision, Error, Result,
+};
 
 /// Abstraction over a Solana instruction container
 #[allow(clippy::module_name_repetitions)]
@@ -23,48 +31,152 @@ pub trait InstructionInfo<'a>: 'a {
 #[derive(Debug)]
 pub struct Selector {
     programs: HashSet<Pubkey>,
-    screen_tokens: Heuristic<bool>,
+    signers: HashSet<Pubkey>,
+    /// Match flags for the `programs` entries, if tracked
+    usage: Option<Usage>,
+    /// Screens applied to the instructions of each screened program
+    #[cfg(feature = "token")]
+    screens: HashMap<Pubkey, Vec<Screen>>,
 }
 
 impl Selector {
     /// Construct a new selector from the given configuration block
     ///
     /// # Errors
-    /// Fails if a program address is incorrectly specified
+    /// Fails if a program or signer address or a screen name is incorrectly
+    /// specified
     pub fn from_config(config: Instructions) -> Result<Self> {
         let Instructions {
             programs,
             all_token_calls,
+            screens,
+            signers,
         } = config;
 
-        let programs = programs
+        let programs: HashSet<Pubkey> = programs
             .into_iter()
             .map(|s| s.parse::<Pubkey>())
             .collect::<Result<_, _>>()
             .map_err(|e| Error::InstructionConfig("programs", e.into()))?;
 
-        let mut ret = Self {
-            programs,
-            screen_tokens: Heuristic::Used(!all_token_calls),
+        let signers = signers
+            .into_iter()
+            .map(|s| s.parse::<Pubkey>())
+            .collect::<Result<_, _>>()
+            .map_err(|e| Error::InstructionConfig("signers", e.into()))?;
+
+        #[cfg(feature = "token")]
+        let screens = {
+            let mut screens = screens
+                .into_iter()
+                .map(|(program, names)| {
+                    let program = program
+                        .parse::<Pubkey>()
+                        .map_err(|e| Error::InstructionConfig("screens", e.into()))?;
+                    let screens = names
+                        .iter()
+                        .map(|n| {
+                            Screen::get(n).ok_or_else(|| {
+                                Error::InstructionConfig(
+                                    "screens",
+                                    format!("Unknown screen {:?}", n).into(),
+                                )
+                            })
+                        })
+                        .collect::<Result<_>>()?;
+
+                    Ok((program, screens))
+                })
+                .collect::<Result<HashMap<_, _>>>()?;
+
+            if !all_token_calls {
+                screens.entry(spl_token::id()).or_insert_with(|| {
+                    Screen::get(screen::DEFAULT_TOKEN_SCREEN)
+                        .into_iter()
+                        .collect()
+                });
+            }
+
+            // Don't screen calls if we're never going to return them
+            screens.retain(|p, _| programs.contains(p));
+
+            screens
         };
 
-        // Don't screen token calls if we're never going to return them
-        if !ret.programs.contains(&spl_token::id()) {
-            ret.screen_tokens = Heuristic::Unused;
+        #[cfg(not(feature = "token"))]
+        {
+            let _ = all_token_calls;
+
+            if !screens.is_empty() {
+                return Err(Error::InstructionConfig(
+                    "screens",
+                    "instruction screens require the token feature".into(),
+                ));
+            }
         }
 
-        Ok(ret)
+        Ok(Self {
+            programs,
+            signers,
+            usage: None,
+            #[cfg(feature = "token")]
+            screens,
+        })
+    }
+
+    /// Start recording which `programs` entries match any instruction, for
+    /// reporting with [`unused`](Self::unused)
+    pub fn track_usage(&mut self) {
+        self.usage = Some(Usage::new(
+            "programs",
+            self.programs.iter().map(|p| p.to_bytes()),
+        ));
+    }
+
+    /// Returns the `programs` entries which have not matched any instruction
+    /// since [`track_usage`](Self::track_usage) was called, or an empty list
+    /// if usage is not tracked
+    #[must_use]
+    pub fn unused(&self) -> Vec<Unused> {
+        self.usage.iter().map(Usage::unused).collect()
     }
 
     /// Returns true if this selector will never select anything
     #[inline]
     #[must_use]
     pub fn is_empty(&self) -> bool {
-        self.programs.is_empty()
+        self.programs.is_empty() && self.signers.is_empty()
+    }
+
+    /// Returns true if the given program is in this selector's program list,
+    /// regardless of any instruction heuristics
+    #[inline]
+    #[must_use]
+    pub fn contains_program(&self, program: &Pubkey) -> bool {
+        self.programs.contains(program)
+    }
+
+    /// Returns the decision selecting every instruction of a transaction
+    /// signed by the given wallets, if any of them is in this selector's
+    /// signer list
+    #[must_use]
+    pub fn decide_signers<'a>(
+        &self,
+        signers: impl IntoIterator<Item = &'a Pubkey>,
+    ) -> Option<Decision> {
+        if self.signers.is_empty() {
+            return None;
+        }
+
+        signers
+            .into_iter()
+            .find(|s| self.signers.contains(*s))
+            .map(|s| Decision::Signer(*s))
     }
 
     /// Returns true if the given instruction addressed to the given program
-    /// has been requested by this selector's configuration
+    /// has been requested by this selector's configuration.  Signer filters
+    /// are not considered; see [`decide_signers`](Self::decide_signers).
     ///
     /// # Errors
     /// This function fails if an input account or program address cannot be
@@ -75,30 +187,67 @@ impl Selector {
         get_acct: impl Fn(u8) -> Option<&'a Pubkey>,
         ins: &impl InstructionInfo<'a>,
     ) -> Result<bool> {
+        self.decide(get_acct, ins).map(Decision::is_selected)
+    }
+
+    /// Returns the rule deciding whether the given instruction addressed to
+    /// the given program is selected
+    ///
+    /// # Errors
+    /// This function fails if an input account or program address cannot be
+    /// retrieved
+    #[inline]
+    pub fn decide<'a>(
+        &self,
+        get_acct: impl Fn(u8) -> Option<&'a Pubkey>,
+        ins: &impl InstructionInfo<'a>,
+    ) -> Result<Decision> {
         let pgm = ins.program_index();
         let pgm = get_acct(pgm).ok_or(Error::InstructionMissingAccount(pgm))?;
+
+        Ok(self.decide_program(pgm, ins.data()))
+    }
+
+    /// Returns true if the given instruction, with its program and input
+    /// accounts already resolved to public keys, has been requested by this
+    /// selector's configuration
+    #[inline]
+    #[must_use]
+    pub fn is_selected_resolved(&self, program: &Pubkey, accounts: &[Pubkey], data: &[u8]) -> bool {
+        // No filter currently inspects the input accounts
+        let _ = accounts;
+
+        self.decide_program(program, data).is_selected()
+    }
+
+    /// Returns true if the given decoded instruction has been requested by this
+    /// selector's configuration
+    #[inline]
+    #[must_use]
+    pub fn is_selected_instruction(&self, ins: &Instruction) -> bool {
+        self.decide_program(&ins.program_id, &ins.data)
+            .is_selected()
+    }
+
+    fn decide_program(&self, pgm: &Pubkey, data: &[u8]) -> Decision {
         if !self.programs.contains(pgm) {
-            return Ok(false);
+            return Decision::UnselectedProgram;
         }
 
-        if self.screen_tokens.into_inner() && *pgm == spl_token::id() {
-            let data = ins.data();
-            if let [8, rest @ ..] = data {
-                let amt = rest.try_into().map(u64::from_le_bytes);
-
-                if !matches!(amt, Ok(1)) {
-                    return Ok(false);
-                }
-
-                debug_assert_eq!(
-                    data,
-                    spl_token::instruction::TokenInstruction::Burn { amount: 1_u64 }.pack(),
-                );
-            } else {
-                return Ok(false);
+        if let Some(ref usage) = self.usage {
+            usage.hit(pgm.as_ref());
+        }
+
+        #[cfg(feature = "token")]
+        if let Some(screens) = self.screens.get(pgm) {
+            if !screens.iter().any(|s| s.passes(data)) {
+                return Decision::Screened;
             }
         }
 
-        Ok(true)
+        #[cfg(not(feature = "token"))]
+        let _ = data;
+
+        Decision::Program
     }
 }
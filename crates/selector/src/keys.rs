@@ -0,0 +1,184 @@
+//! Lookup structure for large sets of public keys, such as wallet-tracking
+//! allowlists with hundreds of thousands of entries
+
+/// Sets with at least this many keys are indexed by key prefix
+const INDEX_THRESHOLD: usize = 4096;
+
+/// The number of buckets in the prefix index, one per 16-bit key prefix
+const BUCKETS: usize = 1 << 16;
+
+/// Bloom filter bits allocated per key, giving a false-positive rate of
+/// roughly 1% with [`BLOOM_HASHES`] probes
+const BLOOM_BITS_PER_KEY: usize = 10;
+
+/// The number of bits probed per bloom filter lookup
+const BLOOM_HASHES: u64 = 7;
+
+/// A bloom filter over 32-byte public keys.  Public keys are already
+/// uniformly distributed, so probe positions are derived directly from key
+/// bytes rather than rehashing the key.
+#[derive(Debug, Clone)]
+struct Bloom {
+    bits: Vec<u64>,
+}
+
+impl Bloom {
+    fn new(keys: &[[u8; 32]]) -> Self {
+        let words = (keys.len() * BLOOM_BITS_PER_KEY / 64).max(1);
+        let mut bloom = Self {
+            bits: vec![0; words],
+        };
+
+        for key in keys {
+            for bit in bloom.probes(key) {
+                bloom.bits[bit / 64] |= 1 << (bit % 64);
+            }
+        }
+
+        bloom
+    }
+
+    /// Double-hashed probe positions, using key bytes disjoint from those
+    /// consulted by the prefix index
+    // Truncation is fine, probe positions are less than the number of bits
+    #[allow(clippy::cast_possible_truncation)]
+    fn probes(&self, key: &[u8; 32]) -> impl Iterator<Item = usize> {
+        fn word(key: &[u8; 32], at: usize) -> u64 {
+            let mut word = [0; 8];
+            word.copy_from_slice(&key[at..at + 8]);
+            u64::from_le_bytes(word)
+        }
+
+        let h1 = word(key, 16);
+        let h2 = word(key, 24) | 1;
+        let len = self.bits.len() as u64 * 64;
+
+        (0..BLOOM_HASHES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % len) as usize)
+    }
+
+    #[inline]
+    fn may_contain(&self, key: &[u8; 32]) -> bool {
+        self.probes(key)
+            .all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+}
+
+/// An immutable set of 32-byte keys stored as a sorted array.  Large sets are
+/// additionally partitioned by the first two bytes of each key, so a lookup
+/// is a single index into the bucket table followed by a binary search of a
+/// handful of contiguous keys rather than hashing the full key.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct KeySet {
+    keys: Vec<[u8; 32]>,
+    /// Start offset into `keys` of each prefix bucket, followed by the total
+    /// length.  Empty if the set is too small to benefit from an index.
+    index: Vec<u32>,
+    /// Optional pre-screen checked before the exact lookup
+    bloom: Option<Bloom>,
+}
+
+impl KeySet {
+    /// Check a bloom filter before each exact lookup, so lookups for keys
+    /// not in the set usually touch a few bits instead of the key array
+    #[must_use]
+    pub fn with_prescreen(self) -> Self {
+        let bloom = (!self.keys.is_empty()).then(|| Bloom::new(&self.keys));
+
+        Self { bloom, ..self }
+    }
+
+    /// Returns true if this set contains no keys
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Iterate over the keys of this set in sorted order
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = &[u8; 32]> {
+        self.keys.iter()
+    }
+
+    /// Returns the approximate heap memory used by this set, in bytes
+    pub fn heap_size(&self) -> usize {
+        std::mem::size_of_val(&self.keys[..])
+            + std::mem::size_of_val(&self.index[..])
+            + self
+                .bloom
+                .as_ref()
+                .map_or(0, |b| std::mem::size_of_val(&b.bits[..]))
+    }
+
+    /// Log the size and memory usage of this set
+    pub fn report(&self, name: &str) {
+        if self.is_empty() {
+            return;
+        }
+
+        log::info!(
+            "Loaded {} {} ({} KiB, prefix index {}, bloom pre-screen {})",
+            self.keys.len(),
+            name,
+            self.heap_size() / 1024,
+            if self.index.is_empty() { "off" } else { "on" },
+            if self.bloom.is_some() { "on" } else { "off" },
+        );
+    }
+
+    /// Returns true if this set contains the given key.  Keys not 32 bytes
+    /// long are never contained.
+    #[inline]
+    pub fn contains(&self, key: &[u8]) -> bool {
+        let key: &[u8; 32] = match key.try_into() {
+            Ok(k) => k,
+            Err(_) => return false,
+        };
+
+        if self.bloom.as_ref().map_or(false, |b| !b.may_contain(key)) {
+            return false;
+        }
+
+        let keys = if self.index.is_empty() {
+            &self.keys[..]
+        } else {
+            let bucket = usize::from(u16::from_be_bytes([key[0], key[1]]));
+
+            &self.keys[self.index[bucket] as usize..self.index[bucket + 1] as usize]
+        };
+
+        keys.binary_search(key).is_ok()
+    }
+}
+
+impl FromIterator<[u8; 32]> for KeySet {
+    fn from_iter<I: IntoIterator<Item = [u8; 32]>>(iter: I) -> Self {
+        let mut keys: Vec<_> = iter.into_iter().collect();
+        keys.sort_unstable();
+        keys.dedup();
+        keys.shrink_to_fit();
+
+        let index = if keys.len() >= INDEX_THRESHOLD {
+            let mut index = vec![0_u32; BUCKETS + 1];
+
+            for key in &keys {
+                index[usize::from(u16::from_be_bytes([key[0], key[1]])) + 1] += 1;
+            }
+
+            let mut start = 0;
+            for offset in &mut index {
+                start += *offset;
+                *offset = start;
+            }
+
+            index
+        } else {
+            vec![]
+        };
+
+        Self {
+            keys,
+            index,
+            bloom: None,
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! [`KeySet`] checked against a [`HashSet`] at the sizes it is built for.
+//! The lookup cost comparison is ignored by default since it only means
+//! something in an optimized build:
+//!
+//! ```sh
+//! cargo test --release -p holaplex-indexer-geyser-selector keys_tests -- --ignored --nocapture
+//! ```
+
+use std::time::Instant;
+
+use hashbrown::HashSet;
+use solana_program::hash::hashv;
+
+use crate::keys::KeySet;
+
+const LEN: usize = 200_000;
+
+/// Uniformly distributed keys, like real public keys, disjoint between seeds
+fn keys(seed: &str, len: usize) -> Vec<[u8; 32]> {
+    (0..len as u64)
+        .map(|i| hashv(&[seed.as_bytes(), &i.to_le_bytes()]).to_bytes())
+        .collect()
+}
+
+#[test]
+fn agrees_with_hash_set() {
+    let present = keys("present", LEN);
+    let absent = keys("absent", LEN);
+    let expected: HashSet<_> = present.iter().copied().collect();
+
+    for set in [
+        present.iter().copied().collect::<KeySet>(),
+        present.iter().copied().collect::<KeySet>().with_prescreen(),
+    ] {
+        for key in present.iter().chain(&absent) {
+            assert_eq!(set.contains(key), expected.contains(key));
+        }
+
+        assert!(!set.contains(&[0; 31]));
+        assert_eq!(set.iter().count(), LEN);
+    }
+}
+
+#[test]
+#[ignore]
+fn lookup_cost_against_hash_set() {
+    let present = keys("present", LEN);
+    let absent = keys("absent", LEN);
+    let hash_set: HashSet<_> = present.iter().copied().collect();
+    let key_set: KeySet = present.iter().copied().collect();
+    let prescreened = key_set.clone().with_prescreen();
+
+    let time = |name: &str, contains: &dyn Fn(&[u8; 32]) -> bool| {
+        for (label, probes) in [("hits", &present), ("misses", &absent)] {
+            let start = Instant::now();
+            let found = probes.iter().filter(|k| contains(k)).count();
+            let per = start.elapsed() / u32::try_from(probes.len()).unwrap();
+
+            println!(
+                "{:<20} {:<6} {:>8?}/lookup ({} found)",
+                name, label, per, found
+            );
+        }
+    };
+
+    time("HashSet", &|k| hash_set.contains(k));
+    time("KeySet", &|k| key_set.contains(k));
+    time("KeySet (prescreen)", &|k| prescreened.contains(k));
+
+    println!(
+        "HashSet ~{} KiB, KeySet {} KiB, KeySet (prescreen) {} KiB",
+        hash_set.capacity() * 33 / 1024,
+        key_set.heap_size() / 1024,
+        prescreened.heap_size() / 1024,
+    );
+}
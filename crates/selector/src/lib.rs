@@ -1,4 +1,13 @@
 //! Solana validator selector components for `holaplex-indexer`.
+//!
+//! With default features disabled the selectors only depend on
+//! `solana-program`, allowing them to be compiled for targets such as WASM.
+//! The following features add heavier functionality:
+//!  - `token`: SPL token mint filtering and NFT screening heuristics
+//!  - `rabbitmq`: conversion of selector settings to RabbitMQ queue types
+//!  - `ffi`: a C ABI for evaluating account selectors, see [`ffi`]
+//!  - `solana-geyser-plugin-interface`: [`AccountInfo`] implementations for
+//!    the Geyser plugin interface types
 
 #![deny(
     clippy::disallowed_methods,
@@ -10,11 +19,31 @@
 #![warn(clippy::pedantic, clippy::cargo, missing_docs)]
 
 mod account;
+mod cache;
+#[cfg(feature = "token")]
+mod collection;
 pub mod config;
+mod decision;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 mod instruction;
+mod keys;
+#[cfg(test)]
+mod keys_tests;
+mod pda;
+mod plan;
+#[cfg(feature = "token")]
+mod screen;
+#[cfg(feature = "token")]
+mod token;
+mod usage;
 
 pub use account::{AccountInfo, Selector as AccountSelector};
+pub use decision::Decision;
 pub use instruction::{InstructionInfo, Selector as InstructionSelector};
+#[cfg(feature = "token")]
+pub use token::{is_token_program, token_info, TokenInfo, TOKEN_2022_ID};
+pub use usage::Unused;
 
 /// Helper traits exported by this crate
 pub mod prelude {
@@ -39,16 +68,22 @@ pub enum Error {
     /// An error occurred fetching an account for an instruction
     #[error("Error reading instruction: no account with index {0}")]
     InstructionMissingAccount(u8),
+    /// A heuristic was requested which is not in use by the selector's
+    /// configuration
+    #[error("Heuristic {0:?} is not in use by this selector")]
+    HeuristicUnavailable(&'static str),
 }
 
 pub(crate) type Result<T, E = Error> = std::result::Result<T, E>;
 
+#[cfg(feature = "token")]
 #[derive(Debug, Clone, Copy)]
 pub(crate) enum Heuristic<T> {
     Used(T),
     Unused,
 }
 
+#[cfg(feature = "token")]
 impl<T> Heuristic<T> {
     fn try_get(&self) -> Option<&T> {
         match self {
@@ -57,24 +92,10 @@ impl<T> Heuristic<T> {
         }
     }
 
-    fn get(&self) -> &T {
+    fn get_mut(&mut self, name: &'static str) -> Result<&mut T> {
         match self {
-            Self::Used(v) => v,
-            Self::Unused => panic!("Attempted to use heuristic marked as unused"),
-        }
-    }
-
-    fn get_mut(&mut self) -> &mut T {
-        match self {
-            Self::Used(v) => v,
-            Self::Unused => panic!("Attempted to use heuristic marked as unused"),
-        }
-    }
-
-    fn into_inner(self) -> T {
-        match self {
-            Self::Used(v) => v,
-            Self::Unused => panic!("Attempted to use heuristic marked as unused"),
+            Self::Used(v) => Ok(v),
+            Self::Unused => Err(Error::HeuristicUnavailable(name)),
         }
     }
 }
@@ -0,0 +1,245 @@
+//! Selection of program-derived addresses by seed pattern, e.g. every escrow
+//! account of a marketplace, without enumerating their addresses.  Accounts
+//! owned by a family's program are selected if their address re-derives from
+//! the family's seeds, whose variable parts are read from the account's own
+//! data.  Each verification costs one or more SHA-256 hashes, so verdicts
+//! are cached per account.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use solana_program::pubkey::{Pubkey, MAX_SEEDS, MAX_SEED_LEN};
+
+use crate::{
+    cache::KeyCache,
+    config::{PdaFamily, Seed as SeedConfig},
+    Decision, Error, Result,
+};
+
+/// The default capacity of the PDA verdict cache
+const DEFAULT_PDA_CACHE_CAPACITY: usize = 65_536;
+/// The estimated cost of deriving a single program address, relative to
+/// unpacking a token account
+const DERIVE_COST: f64 = 20.0;
+
+#[derive(Debug)]
+enum Seed {
+    Bytes(Vec<u8>),
+    Data { offset: usize, len: usize },
+}
+
+impl Seed {
+    fn get<'a>(&'a self, data: &'a [u8]) -> Option<&'a [u8]> {
+        match self {
+            Self::Bytes(b) => Some(b),
+            Self::Data { offset, len } => data.get(*offset..offset.checked_add(*len)?),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Family {
+    program: Pubkey,
+    seeds: Vec<Seed>,
+    bump_offset: Option<usize>,
+}
+
+impl Family {
+    fn from_config(config: PdaFamily) -> Result<Self> {
+        let PdaFamily {
+            program,
+            seeds,
+            bump_offset,
+        } = config;
+
+        let program = program
+            .parse::<Pubkey>()
+            .map_err(|e| Error::AccountConfig("pdas", e.into()))?;
+
+        if seeds.len() >= MAX_SEEDS {
+            return Err(Error::AccountConfig(
+                "pdas",
+                format!("at most {} seeds are allowed", MAX_SEEDS - 1).into(),
+            ));
+        }
+
+        let seeds = seeds
+            .into_iter()
+            .map(|s| {
+                let seed = match s {
+                    SeedConfig::Utf8(s) => Seed::Bytes(s.into_bytes()),
+                    SeedConfig::Pubkey(k) => Seed::Bytes(
+                        k.parse::<Pubkey>()
+                            .map_err(|e| Error::AccountConfig("pdas", e.into()))?
+                            .to_bytes()
+                            .to_vec(),
+                    ),
+                    SeedConfig::Data { offset, len } => Seed::Data { offset, len },
+                };
+
+                let len = match seed {
+                    Seed::Bytes(ref b) => b.len(),
+                    Seed::Data { len, .. } => len,
+                };
+
+                if len > MAX_SEED_LEN {
+                    return Err(Error::AccountConfig(
+                        "pdas",
+                        format!("seeds may be at most {} bytes long", MAX_SEED_LEN).into(),
+                    ));
+                }
+
+                Ok(seed)
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            program,
+            seeds,
+            bump_offset,
+        })
+    }
+
+    /// Returns true if the given address derives from this family's seeds
+    /// as read from the given account data
+    fn derives(&self, pubkey: &[u8], data: &[u8]) -> bool {
+        let seeds = match self
+            .seeds
+            .iter()
+            .map(|s| s.get(data))
+            .collect::<Option<Vec<_>>>()
+        {
+            Some(s) => s,
+            None => return false,
+        };
+
+        let addr = match self.bump_offset {
+            Some(off) => {
+                let bump = match data.get(off..=off) {
+                    Some(b) => b,
+                    None => return false,
+                };
+                let seeds: Vec<_> = seeds.into_iter().chain([bump]).collect();
+
+                Pubkey::create_program_address(&seeds, &self.program).ok()
+            },
+            None => Pubkey::try_find_program_address(&seeds, &self.program).map(|(a, _)| a),
+        };
+
+        addr.map_or(false, |a| a.as_ref() == pubkey)
+    }
+}
+
+/// The cached result of verifying an account against every family of its
+/// owner
+#[derive(Debug, Clone, Copy)]
+struct Verdict {
+    /// Index of the matching family, if any
+    family: Option<usize>,
+    /// Fingerprint of the account data seeds the verdict was computed from
+    seeds: u64,
+}
+
+/// PDA-based account selection state
+#[derive(Debug)]
+pub(crate) struct Pdas {
+    families: Vec<Family>,
+    cache: Option<KeyCache<Verdict>>,
+}
+
+impl Pdas {
+    pub fn from_config(families: Vec<PdaFamily>, cache_capacity: Option<usize>) -> Result<Self> {
+        let families = families
+            .into_iter()
+            .map(Family::from_config)
+            .collect::<Result<_>>()?;
+
+        let cache = match cache_capacity.unwrap_or(DEFAULT_PDA_CACHE_CAPACITY) {
+            0 => None,
+            n => Some(KeyCache::new(n)),
+        };
+
+        Ok(Self { families, cache })
+    }
+
+    /// Returns true if accounts owned by the given program are candidates
+    /// for PDA verification
+    #[inline]
+    pub fn inspects_data(&self, owner: &[u8]) -> bool {
+        self.families.iter().any(|f| f.program.as_ref() == owner)
+    }
+
+    /// Estimate the cost of verifying a candidate account against every
+    /// family, ignoring the verdict cache.  Searching for the canonical bump
+    /// takes two derivations on average.
+    pub fn estimated_cost(&self) -> f64 {
+        self.families
+            .iter()
+            .map(|f| match f.bump_offset {
+                Some(_) => DERIVE_COST,
+                None => 2.0 * DERIVE_COST,
+            })
+            .sum()
+    }
+
+    /// Hash the account data read as seeds by the candidate families, so a
+    /// negative verdict can be reused until those bytes change, e.g. when a
+    /// newly-created account is initialized
+    fn fingerprint(&self, owner: &[u8], data: &[u8]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+
+        for family in self.families.iter().filter(|f| f.program.as_ref() == owner) {
+            for seed in &family.seeds {
+                if let Seed::Data { .. } = seed {
+                    seed.get(data).hash(&mut hasher);
+                }
+            }
+
+            family.bump_offset.map(|o| data.get(o)).hash(&mut hasher);
+        }
+
+        hasher.finish()
+    }
+
+    /// Selects the account if its address derives from the seeds of a
+    /// family of its owner
+    pub fn select(&self, pubkey: &[u8], owner: &[u8], data: &[u8]) -> Option<Decision> {
+        if !self.inspects_data(owner) {
+            return None;
+        }
+
+        let key: Option<[u8; 32]> = pubkey.try_into().ok();
+        let cache = self.cache.as_ref().zip(key);
+        let cached = cache.and_then(|(c, k)| c.get(&k));
+
+        // An address derived from a family's seeds stays in the family
+        if let Some(Verdict {
+            family: Some(i), ..
+        }) = cached
+        {
+            return Some(Decision::Pda(self.families[i].program));
+        }
+
+        let seeds = self.fingerprint(owner, data);
+
+        let family = match cached {
+            Some(v) if v.seeds == seeds => None,
+            _ => {
+                let family = self
+                    .families
+                    .iter()
+                    .position(|f| f.program.as_ref() == owner && f.derives(pubkey, data));
+
+                if let Some((cache, key)) = cache {
+                    cache.insert(key, Verdict { family, seeds });
+                }
+
+                family
+            },
+        };
+
+        family.map(|i| Decision::Pda(self.families[i].program))
+    }
+}
@@ -0,0 +1,161 @@
+//! Load-time ordering of the account selector's data-inspecting filters.
+//! Any of these filters selects an account on its own, so they are
+//! evaluated in order of increasing cost per selected account, letting the
+//! cheapest likely match short-circuit the rest.  Token filters share a
+//! single decode of the account, which only the first of them pays for.
+
+use hashbrown::HashMap;
+
+use crate::{
+    config::{Filter, FilterHint},
+    Error, Result,
+};
+
+/// The estimated cost of unpacking an SPL token account
+const TOKEN_DECODE_COST: f64 = 2.0;
+/// The estimated fraction of candidate accounts selected by a filter
+const DEFAULT_SELECTIVITY: f64 = 0.01;
+
+impl Filter {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Mints => "mints",
+            Self::Authorities => "authorities",
+            Self::Collections => "collections",
+            Self::Pdas => "pdas",
+        }
+    }
+
+    fn decodes_token(self) -> bool {
+        matches!(self, Self::Mints | Self::Authorities | Self::Collections)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Estimate {
+    filter: Filter,
+    cost: f64,
+    selectivity: f64,
+    /// Whether the cost was supplied by the operator, and so includes any
+    /// token decoding
+    hinted: bool,
+}
+
+impl Estimate {
+    fn cost(&self, decoded: bool) -> f64 {
+        if !self.hinted && !decoded && self.filter.decodes_token() {
+            self.cost + TOKEN_DECODE_COST
+        } else {
+            self.cost
+        }
+    }
+
+    fn rank(&self, decoded: bool) -> f64 {
+        self.cost(decoded) / self.selectivity
+    }
+}
+
+/// The order in which the data-inspecting filters of an account selector
+/// are evaluated
+#[derive(Debug)]
+pub(crate) struct Plan(Vec<Filter>);
+
+impl Plan {
+    /// Order the given configured filters, each paired with its estimated
+    /// cost excluding token decoding, applying any operator hints
+    pub fn new(
+        filters: impl IntoIterator<Item = (Filter, f64)>,
+        hints: &HashMap<Filter, FilterHint>,
+    ) -> Result<Self> {
+        for hint in hints.values() {
+            if hint.cost.map_or(false, |c| !c.is_finite() || c <= 0.0) {
+                return Err(Error::AccountConfig(
+                    "plan_hints",
+                    "costs must be positive".into(),
+                ));
+            }
+
+            if hint
+                .selectivity
+                .map_or(false, |s| s.is_nan() || s <= 0.0 || s > 1.0)
+            {
+                return Err(Error::AccountConfig(
+                    "plan_hints",
+                    "selectivities must be greater than 0 and at most 1".into(),
+                ));
+            }
+        }
+
+        let mut remaining: Vec<_> = filters
+            .into_iter()
+            .map(|(filter, cost)| {
+                let hint = hints.get(&filter).copied().unwrap_or_default();
+
+                Estimate {
+                    filter,
+                    cost: hint.cost.unwrap_or(cost),
+                    selectivity: hint.selectivity.unwrap_or(DEFAULT_SELECTIVITY),
+                    hinted: hint.cost.is_some(),
+                }
+            })
+            .collect();
+
+        for filter in hints.keys() {
+            if !remaining.iter().any(|e| e.filter == *filter) {
+                log::warn!(
+                    "Ignoring plan hint for unconfigured filter {:?}",
+                    filter.name()
+                );
+            }
+        }
+
+        let mut decoded = false;
+        let mut order = Vec::with_capacity(remaining.len());
+
+        while let Some(i) = remaining
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.rank(decoded).total_cmp(&b.rank(decoded)))
+            .map(|(i, _)| i)
+        {
+            let est = remaining.remove(i);
+            order.push((est, est.cost(decoded)));
+            decoded |= est.filter.decodes_token();
+        }
+
+        // Collection membership is learned from metadata updates, which a
+        // PDA family of the metadata program must not hide
+        let pdas = order.iter().position(|(e, _)| e.filter == Filter::Pdas);
+        let collections = order
+            .iter()
+            .position(|(e, _)| e.filter == Filter::Collections);
+
+        if let (Some(p), Some(c)) = (pdas, collections) {
+            if p < c {
+                let est = order.remove(c);
+                order.insert(p, est);
+            }
+        }
+
+        log::info!(
+            "Account selector plan: pubkeys, owner prefilter, {}owners",
+            order
+                .iter()
+                .map(|(e, cost)| format!(
+                    "{} (cost {:.1}, selectivity {}), ",
+                    e.filter.name(),
+                    cost,
+                    e.selectivity
+                ))
+                .collect::<String>(),
+        );
+
+        Ok(Self(order.into_iter().map(|(e, _)| e.filter).collect()))
+    }
+
+    /// Iterate over the filters in evaluation order
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = Filter> + '_ {
+        self.0.iter().copied()
+    }
+}
@@ -0,0 +1,74 @@
+//! Registry of named instruction screens.  A screen inspects the data of an
+//! instruction addressed to a selected program and passes only instructions
+//! matching a program-specific pattern.  Adding a screen only requires a new
+//! entry in [`REGISTRY`].
+
+use std::fmt;
+
+/// SPL token instruction tags, see `spl_token::instruction::TokenInstruction`
+mod tag {
+    pub const TRANSFER: u8 = 3;
+    pub const MINT_TO: u8 = 7;
+    pub const BURN: u8 = 8;
+    pub const TRANSFER_CHECKED: u8 = 12;
+    pub const MINT_TO_CHECKED: u8 = 14;
+    pub const BURN_CHECKED: u8 = 15;
+}
+
+/// A named predicate over instruction data
+#[derive(Clone, Copy)]
+pub(crate) struct Screen {
+    name: &'static str,
+    passes: fn(&[u8]) -> bool,
+}
+
+impl fmt::Debug for Screen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Screen").field(&self.name).finish()
+    }
+}
+
+impl Screen {
+    /// Look up a screen by name
+    pub fn get(name: &str) -> Option<Self> {
+        REGISTRY.iter().find(|s| s.name == name).copied()
+    }
+
+    #[inline]
+    pub fn passes(&self, data: &[u8]) -> bool {
+        (self.passes)(data)
+    }
+}
+
+/// Every screen selectable from configuration
+const REGISTRY: &[Screen] = &[
+    Screen {
+        name: "burn-nft",
+        passes: |d| is_single(d, tag::BURN, tag::BURN_CHECKED),
+    },
+    Screen {
+        name: "transfer-nft",
+        passes: |d| is_single(d, tag::TRANSFER, tag::TRANSFER_CHECKED),
+    },
+    Screen {
+        name: "mint-single",
+        passes: |d| is_single(d, tag::MINT_TO, tag::MINT_TO_CHECKED),
+    },
+];
+
+/// The screen applied to spl-token instructions unless `all_token_calls` is
+/// set or other screens are configured
+pub(crate) const DEFAULT_TOKEN_SCREEN: &str = "burn-nft";
+
+/// Returns true if the data encodes an spl-token instruction with one of the
+/// given tags moving an amount of exactly 1, with 0 decimals for the checked
+/// variant
+fn is_single(data: &[u8], unchecked: u8, checked: u8) -> bool {
+    match data {
+        [t, amt @ ..] if *t == unchecked => matches!(amt.try_into().map(u64::from_le_bytes), Ok(1)),
+        [t, amt @ .., decimals] if *t == checked => {
+            *decimals == 0 && matches!(amt.try_into().map(u64::from_le_bytes), Ok(1))
+        },
+        _ => false,
+    }
+}
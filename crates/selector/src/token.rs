@@ -0,0 +1,298 @@
+use hashbrown::{HashMap, HashSet};
+use solana_program::{program_pack::Pack, pubkey, pubkey::Pubkey};
+use spl_token::state::Account as TokenAccount;
+
+use crate::{config::TokenProgram, keys::KeySet, Decision, Error, Heuristic, Result};
+
+/// The Token-2022 program, whose accounts share the spl-token layout
+/// followed by optional extensions
+pub const TOKEN_2022_ID: Pubkey = pubkey!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+
+/// The Token-2022 account type tag following the base layout of an account
+/// with extensions
+const TOKEN_2022_ACCOUNT_TYPE: u8 = 2;
+
+/// Returns true if the given program is spl-token or Token-2022
+#[inline]
+#[must_use]
+pub fn is_token_program(program: &[u8]) -> bool {
+    program == spl_token::id().as_ref() || program == TOKEN_2022_ID.as_ref()
+}
+
+/// The offset of the amount field in the spl-token account layout
+const AMOUNT_OFFSET: usize = 64;
+
+/// The offset of the optional delegate in the spl-token account layout
+const DELEGATE_OFFSET: usize = 72;
+
+/// The offset of the optional close authority in the spl-token account
+/// layout
+const CLOSE_AUTHORITY_OFFSET: usize = 129;
+
+/// Read a `COption<Pubkey>` field of the spl-token account layout
+#[inline]
+fn coption_key(base: &[u8], offset: usize) -> Option<Pubkey> {
+    let field = base.get(offset..offset + 36)?;
+
+    if field[..4] != [1, 0, 0, 0] {
+        return None;
+    }
+
+    field[4..].try_into().ok().map(Pubkey::new_from_array)
+}
+
+/// Returns the base spl-token layout of an account, if it is owned by a token
+/// program and has a valid length
+#[inline]
+fn token_layout<'a>(owner: &[u8], data: &'a [u8]) -> Option<&'a [u8]> {
+    let len = TokenAccount::get_packed_len();
+
+    if owner == spl_token::id().as_ref() {
+        (data.len() == len).then_some(data)
+    } else if owner == TOKEN_2022_ID.as_ref() {
+        match data.len().cmp(&len) {
+            std::cmp::Ordering::Equal => Some(data),
+            std::cmp::Ordering::Greater if data[len] == TOKEN_2022_ACCOUNT_TYPE => {
+                Some(&data[..len])
+            },
+            _ => None,
+        }
+    } else {
+        None
+    }
+}
+
+/// The fields of a token account consulted by the selector
+#[derive(Debug, Clone, Copy)]
+#[allow(clippy::module_name_repetitions)]
+pub struct TokenInfo {
+    /// The mint of the tokens held
+    pub mint: Pubkey,
+    /// The number of tokens held
+    pub amount: u64,
+    /// The delegate of the account, if any
+    pub delegate: Option<Pubkey>,
+    /// The close authority of the account, if any
+    pub close_authority: Option<Pubkey>,
+}
+
+/// Decode the mint, amount, and authorities of an account if it is an
+/// spl-token or Token-2022 account.  The fields are read in place rather than
+/// unpacking the whole account, which is cheaper than any cache lookup.
+#[inline]
+#[must_use]
+#[allow(clippy::module_name_repetitions)]
+pub fn token_info(owner: &[u8], data: &[u8]) -> Option<TokenInfo> {
+    let base = token_layout(owner, data)?;
+
+    Some(TokenInfo {
+        mint: base[..32].try_into().ok().map(Pubkey::new_from_array)?,
+        amount: base[AMOUNT_OFFSET..AMOUNT_OFFSET + 8]
+            .try_into()
+            .map(u64::from_le_bytes)
+            .ok()?,
+        delegate: coption_key(base, DELEGATE_OFFSET),
+        close_authority: coption_key(base, CLOSE_AUTHORITY_OFFSET),
+    })
+}
+
+/// Token-specific account selection state
+#[derive(Debug)]
+pub(crate) struct Tokens {
+    mints: KeySet,
+    exclude_mints: KeySet,
+    delegates: KeySet,
+    close_authorities: KeySet,
+    /// Tracked token programs whose accounts are screened by the heuristics
+    screened: HashSet<[u8; 32]>,
+    token_reg: Heuristic<Option<HashSet<Pubkey>>>,
+}
+
+impl Tokens {
+    pub fn from_config(
+        mints: HashSet<String>,
+        exclude_mints: HashSet<String>,
+        all_tokens: bool,
+        token_programs: HashMap<String, TokenProgram>,
+        prescreen: bool,
+        owners: &KeySet,
+    ) -> Result<Self> {
+        let mints: KeySet = mints
+            .into_iter()
+            .map(|s| s.parse().map(Pubkey::to_bytes))
+            .collect::<Result<_, _>>()
+            .map_err(|e| Error::AccountConfig("mints", e.into()))?;
+        let mints = if prescreen {
+            mints.with_prescreen()
+        } else {
+            mints
+        };
+        mints.report("token mints");
+
+        let exclude_mints: KeySet = exclude_mints
+            .into_iter()
+            .map(|s| s.parse().map(Pubkey::to_bytes))
+            .collect::<Result<_, _>>()
+            .map_err(|e| Error::AccountConfig("exclude_mints", e.into()))?;
+        exclude_mints.report("excluded token mints");
+
+        let mut all_tokens_by_program = token_programs
+            .into_iter()
+            .map(|(program, TokenProgram { all_tokens })| {
+                let program = program
+                    .parse::<Pubkey>()
+                    .map_err(|e| Error::AccountConfig("token_programs", e.into()))?;
+
+                if !is_token_program(program.as_ref()) {
+                    return Err(Error::AccountConfig(
+                        "token_programs",
+                        format!("{} is not a token program", program).into(),
+                    ));
+                }
+
+                Ok((program.to_bytes(), all_tokens))
+            })
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        // Don't screen tokens if we're never going to return them
+        let tracked: Vec<_> = [spl_token::id().to_bytes(), TOKEN_2022_ID.to_bytes()]
+            .into_iter()
+            .filter(|p| owners.contains(p))
+            .collect();
+
+        let screened: HashSet<_> = tracked
+            .iter()
+            .copied()
+            .filter(|p| !all_tokens_by_program.remove(p).unwrap_or(all_tokens))
+            .collect();
+
+        let token_reg = if tracked.is_empty() {
+            Heuristic::Unused
+        } else {
+            Heuristic::Used((!screened.is_empty()).then(HashSet::new))
+        };
+
+        Ok(Self {
+            mints,
+            exclude_mints,
+            delegates: KeySet::default(),
+            close_authorities: KeySet::default(),
+            screened,
+            token_reg,
+        })
+    }
+
+    /// Select token accounts delegated to, or closable by, the given
+    /// authorities
+    pub fn with_authorities(
+        self,
+        delegates: HashSet<String>,
+        close_authorities: HashSet<String>,
+    ) -> Result<Self> {
+        let delegates: KeySet = delegates
+            .into_iter()
+            .map(|s| s.parse().map(Pubkey::to_bytes))
+            .collect::<Result<_, _>>()
+            .map_err(|e| Error::AccountConfig("delegates", e.into()))?;
+        delegates.report("token delegates");
+
+        let close_authorities: KeySet = close_authorities
+            .into_iter()
+            .map(|s| s.parse().map(Pubkey::to_bytes))
+            .collect::<Result<_, _>>()
+            .map_err(|e| Error::AccountConfig("close_authorities", e.into()))?;
+        close_authorities.report("token close authorities");
+
+        Ok(Self {
+            delegates,
+            close_authorities,
+            ..self
+        })
+    }
+
+    pub fn set_token_registry(&mut self, addrs: HashSet<Pubkey>) -> Result<HashSet<Pubkey>> {
+        let reg = self
+            .token_reg
+            .get_mut("token_reg")?
+            .as_mut()
+            .ok_or(Error::HeuristicUnavailable("token_reg"))?;
+
+        Ok(std::mem::replace(reg, addrs))
+    }
+
+    #[inline]
+    pub fn screen_token_registry(&self) -> bool {
+        self.token_reg.try_get().map_or(false, Option::is_some)
+    }
+
+    /// Returns true if selecting an account with the given owner requires
+    /// decoding its data as a token account
+    #[inline]
+    pub fn inspects_data(&self, owner: &[u8]) -> bool {
+        is_token_program(owner)
+            && (!self.mints.is_empty()
+                || !self.exclude_mints.is_empty()
+                || !self.delegates.is_empty()
+                || !self.close_authorities.is_empty()
+                || self.screened.contains(owner))
+    }
+
+    /// Selects the account if it is a token account for a requested mint
+    #[inline]
+    pub fn select_mint(&self, token: Option<&TokenInfo>) -> Option<Decision> {
+        if self.mints.is_empty() {
+            return None;
+        }
+
+        token
+            .filter(|t| self.mints.contains(t.mint.as_ref()))
+            .map(|t| Decision::Mint(t.mint))
+    }
+
+    /// Selects the account if it is a token account delegated to, or closable
+    /// by, a requested authority
+    #[inline]
+    pub fn select_authority(&self, token: Option<&TokenInfo>) -> Option<Decision> {
+        if self.delegates.is_empty() && self.close_authorities.is_empty() {
+            return None;
+        }
+
+        let token = token?;
+
+        if let Some(d) = token
+            .delegate
+            .filter(|d| self.delegates.contains(d.as_ref()))
+        {
+            return Some(Decision::Delegate(d));
+        }
+
+        token
+            .close_authority
+            .filter(|c| self.close_authorities.contains(c.as_ref()))
+            .map(Decision::CloseAuthority)
+    }
+
+    /// Rejects the account if it is a token account for an excluded mint or
+    /// which is probably not an NFT, if its token program is screened
+    #[inline]
+    pub fn screen(&self, owner: &[u8], token: Option<&TokenInfo>) -> Option<Decision> {
+        let token = token?;
+
+        if !self.exclude_mints.is_empty() && self.exclude_mints.contains(token.mint.as_ref()) {
+            return Some(Decision::ExcludedMint(token.mint));
+        }
+
+        if !self.screened.contains(owner) {
+            return None;
+        }
+
+        let reg = self.token_reg.try_get().and_then(Option::as_ref)?;
+        let registered = token.amount <= 1 && reg.contains(&token.mint);
+
+        (token.amount > 1 || registered).then_some(Decision::TokenHeuristic {
+            mint: token.mint,
+            amount: token.amount,
+            registered,
+        })
+    }
+}
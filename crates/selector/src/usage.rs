@@ -0,0 +1,67 @@
+//! Tracking of configuration entries which never match anything, so stale
+//! entries can be pruned from long-lived configurations
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use hashbrown::HashMap;
+use solana_program::pubkey::Pubkey;
+
+/// Entries of a single selector configuration field which have not matched
+/// any account or instruction since usage tracking was enabled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unused {
+    /// The name of the configuration field
+    pub field: &'static str,
+    /// The entries of the field which never matched
+    pub keys: Vec<Pubkey>,
+}
+
+/// Match flags for the entries of a single configuration field
+#[derive(Debug)]
+pub(crate) struct Usage {
+    field: &'static str,
+    hits: HashMap<[u8; 32], AtomicBool>,
+}
+
+impl Usage {
+    pub fn new(field: &'static str, keys: impl IntoIterator<Item = [u8; 32]>) -> Self {
+        Self {
+            field,
+            hits: keys
+                .into_iter()
+                .map(|k| (k, AtomicBool::new(false)))
+                .collect(),
+        }
+    }
+
+    /// Record a match of the given entry
+    #[inline]
+    pub fn hit(&self, key: &[u8]) {
+        let flag = <&[u8; 32]>::try_from(key)
+            .ok()
+            .and_then(|k| self.hits.get(k));
+
+        // Skip the store once set to avoid contending on the cache line
+        if let Some(flag) = flag {
+            if !flag.load(Ordering::Relaxed) {
+                flag.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Collect the entries which never matched, in sorted order
+    pub fn unused(&self) -> Unused {
+        let mut keys: Vec<_> = self
+            .hits
+            .iter()
+            .filter(|(_, f)| !f.load(Ordering::Relaxed))
+            .map(|(k, _)| Pubkey::new_from_array(*k))
+            .collect();
+        keys.sort_unstable();
+
+        Unused {
+            field: self.field,
+            keys,
+        }
+    }
+}